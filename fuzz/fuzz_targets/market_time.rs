@@ -0,0 +1,53 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mmatamm_interface::market::{Event, MarketTime};
+
+const EVENTS: [Event; 5] = [
+    Event::Tick,
+    Event::PreMarketStart,
+    Event::RegularMarketStart,
+    Event::RegularMarketEnd,
+    Event::PostMarketEnd,
+];
+
+/// The market time a session event must occur in, and the one it leads to
+fn transition(event: &Event) -> Option<(MarketTime, MarketTime)> {
+    match event {
+        Event::PreMarketStart => Some((MarketTime::NotTrading, MarketTime::PreMarket)),
+        Event::RegularMarketStart => Some((MarketTime::PreMarket, MarketTime::Regular)),
+        Event::RegularMarketEnd => Some((MarketTime::Regular, MarketTime::PostMarket)),
+        Event::PostMarketEnd => Some((MarketTime::PostMarket, MarketTime::NotTrading)),
+        _ => None,
+    }
+}
+
+// Feeds arbitrary event sequences through the session state machine, checking
+// every step against its specification
+fuzz_target!(|data: &[u8]| {
+    let mut market_time = MarketTime::Unknown;
+
+    for byte in data {
+        let event = &EVENTS[*byte as usize % EVENTS.len()];
+        let before = market_time;
+        let result = market_time.update(event);
+
+        match transition(event) {
+            Some((from, to)) if before == from || before == MarketTime::Unknown => {
+                assert!(result.is_ok());
+                assert_eq!(to, market_time);
+            }
+            Some(_) => {
+                assert!(result.is_err());
+                assert_eq!(before, market_time);
+            }
+            None => {
+                assert!(result.is_ok());
+                assert_eq!(before, market_time);
+            }
+        }
+
+        // The state machine never returns to an unknown state
+        assert!(before == MarketTime::Unknown || market_time != MarketTime::Unknown);
+    }
+});
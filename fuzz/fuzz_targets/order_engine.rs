@@ -0,0 +1,397 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, TimeZone as _, Utc};
+use libfuzzer_sys::fuzz_target;
+use mmatamm_interface::{
+    account::{AccountError, SimulatedAccount},
+    fill::BarPrices,
+    instrument::{Currency, InstrumentRegistry},
+    market::{Event, MarketTime},
+    money::Money,
+    order::{
+        Amendment, FillContext, ImpactCurve, MarketFill, MarketOrderFill, Order, OrderEngine,
+        OrderKind, PendingOrder, PriceImpact, Remainder, Side, TimeInForce, Trades, Trail,
+        VolumeLimit,
+    },
+};
+
+const SYMBOLS: [&str; 2] = ["AAA", "BBB"];
+
+const SESSION_EVENTS: [Event; 4] = [
+    Event::PreMarketStart,
+    Event::RegularMarketStart,
+    Event::RegularMarketEnd,
+    Event::PostMarketEnd,
+];
+
+const CASH: f64 = 10_000.0;
+
+/// The fuzzer's bytes, read as the orders, bars and sessions to simulate.
+/// Once they run out, every read is zero.
+struct Input<'a>(std::slice::Iter<'a, u8>);
+
+impl Input<'_> {
+    fn byte(&mut self) -> u8 {
+        self.0.next().copied().unwrap_or_default()
+    }
+
+    fn flag(&mut self) -> bool {
+        self.byte() % 2 == 1
+    }
+
+    fn pick<'t, T>(&mut self, items: &'t [T]) -> &'t T {
+        &items[self.byte() as usize % items.len()]
+    }
+
+    fn price(&mut self) -> f64 {
+        1.0 + self.byte() as f64 / 8.0
+    }
+
+    fn quantity(&mut self) -> f64 {
+        1.0 + (self.byte() % 20) as f64
+    }
+
+    fn side(&mut self) -> Side {
+        if self.flag() {
+            Side::Buy
+        } else {
+            Side::Sell
+        }
+    }
+
+    fn bar(&mut self) -> BarPrices {
+        let low = self.price();
+        let high = low + self.byte() as f64 / 16.0;
+        let within = |fraction: u8| low + (high - low) * fraction as f64 / 255.0;
+
+        BarPrices {
+            open: within(self.byte()),
+            high,
+            low,
+            close: within(self.byte()),
+        }
+    }
+
+    fn kind(&mut self, price: f64) -> OrderKind {
+        let offset = self.byte() as f64 / 32.0 - 4.0;
+        match self.byte() % 5 {
+            0 => OrderKind::Limit {
+                limit_price: price + offset,
+            },
+            1 => OrderKind::Stop {
+                stop_price: price + offset,
+            },
+            2 => OrderKind::StopLimit {
+                stop_price: price + offset,
+                limit_price: price + offset + self.byte() as f64 / 32.0 - 4.0,
+            },
+            3 => OrderKind::TrailingStop {
+                trail: Trail::Amount(offset.abs()),
+            },
+            _ => OrderKind::TrailingStop {
+                trail: Trail::Percent(offset.abs()),
+            },
+        }
+    }
+}
+
+/// A simulated market reduced to its order engine and account, stepped the
+/// way the simulated markets step theirs
+struct Simulation {
+    engine: OrderEngine,
+    account: SimulatedAccount,
+    instruments: InstrumentRegistry,
+    currency: Currency,
+    time: DateTime<Utc>,
+    market_time: MarketTime,
+    /// The last bar of each symbol, whose close is its current price
+    bars: HashMap<&'static str, (BarPrices, Option<f64>)>,
+    /// The shares reported as traded by receipts and events
+    reported: f64,
+}
+
+impl Simulation {
+    fn context(&mut self) -> (&mut OrderEngine, FillContext<'_>) {
+        (
+            &mut self.engine,
+            FillContext {
+                account: &mut self.account,
+                instruments: &self.instruments,
+                currency: &self.currency,
+                time: self.time,
+                market_time: self.market_time,
+            },
+        )
+    }
+
+    fn session(&mut self, event: &Event) {
+        if self.market_time.update(event).is_err() {
+            return;
+        }
+
+        self.time += TimeDelta::minutes(1);
+        self.engine.expire_orders(event);
+    }
+
+    /// Enters a bar of `symbol`, filling the queued market orders at its open
+    /// and matching the pending orders against its trades
+    fn bar(&mut self, symbol: &'static str, prices: BarPrices, volume: Option<f64>) {
+        let start = self.time;
+        self.time += TimeDelta::minutes(1);
+
+        let fills: HashMap<_, _> = self
+            .engine
+            .queued_market_orders(self.market_time)
+            .iter()
+            .filter(|queued| queued.symbol == symbol && queued.submitted_at <= start)
+            .map(|queued| {
+                let fill = MarketOrderFill {
+                    price: prices.open,
+                    volume,
+                    bar_time: start,
+                };
+                (queued.id, fill)
+            })
+            .collect();
+        let trades = [(symbol.to_string(), Trades { prices, volume })].into();
+
+        let (engine, mut context) = self.context();
+        engine.fill_market_orders(&mut context, &fills);
+        engine.match_orders(&mut context, &trades);
+        self.bars.insert(symbol, (prices, volume));
+    }
+
+    fn market_order(&mut self, symbol: &'static str, side: Side, quantity: f64) {
+        let Some(&(prices, volume)) = self.bars.get(symbol) else {
+            return;
+        };
+        if !self.engine.session_policy().allows(self.market_time, false) {
+            return;
+        }
+
+        let (engine, mut context) = self.context();
+        let receipt =
+            engine.submit_market_order(&mut context, symbol, side, quantity, prices.close, volume);
+        match receipt {
+            Ok(receipt) => {
+                if receipt.fill_price.is_some() {
+                    self.reported += receipt.quantity;
+                }
+            }
+            Err(error) => assert_rejected(error),
+        }
+    }
+
+    fn submit(&mut self, order: Order) {
+        let Some(&(prices, volume)) = self.bars.get(order.symbol.as_str()) else {
+            return;
+        };
+        let price = order.reference_price().unwrap_or(prices.close);
+        if let Err(error) =
+            self.account
+                .check(&order.symbol, order.side, order.quantity, price, self.time)
+        {
+            return assert_rejected(error);
+        }
+
+        let id = self.engine.new_order_id();
+        let prepared = (PendingOrder::new(id, order), prices.close, volume);
+        let (engine, mut context) = self.context();
+        if let Err(error) = engine.submit(&mut context, prepared) {
+            assert_rejected(error);
+        }
+    }
+
+    fn amend(&mut self, index: u8, amendment: Amendment) {
+        let pending = self.engine.pending_orders();
+        if pending.is_empty() {
+            return;
+        }
+        let pending = &pending[index as usize % pending.len()];
+        let (id, amended) = (pending.id, pending.order.amended(&amendment));
+        let Some(amended) = amended else {
+            return;
+        };
+        let Some(&(prices, volume)) = self.bars.get(amended.symbol.as_str()) else {
+            return;
+        };
+
+        // Validated like a new order, or canceled if nothing is left of it
+        let price = amended.reference_price().unwrap_or(prices.close);
+        if let Err(error) = self.account.check(
+            &amended.symbol,
+            amended.side,
+            amended.quantity,
+            price,
+            self.time,
+        ) {
+            return assert_rejected(error);
+        }
+        let prepared = (amended.quantity > 0.0)
+            .then(|| (PendingOrder::new(id, amended), prices.close, volume));
+        let (engine, mut context) = self.context();
+        if let Err(error) = engine.amend(&mut context, id, prepared) {
+            assert_rejected(error);
+        }
+    }
+
+    fn cancel(&mut self, index: u8) {
+        let pending = self.engine.pending_orders();
+        if pending.is_empty() {
+            return;
+        }
+
+        let id = pending[index as usize % pending.len()].id;
+        assert!(self.engine.cancel(id));
+    }
+
+    /// Checks the account against the trades reported by the engine
+    fn check_invariants(&mut self) {
+        for event in self.engine.take_events() {
+            if let Event::OrderFilled { quantity, .. }
+            | Event::OrderPartiallyFilled { quantity, .. } = event
+            {
+                self.reported += quantity;
+            }
+        }
+
+        let transactions = self.account.transactions();
+        let traded: f64 = transactions.iter().map(|t| t.quantity).sum();
+        assert!((traded - self.reported).abs() < 1e-6, "unreported trades");
+        assert!(transactions
+            .windows(2)
+            .all(|pair| pair[0].time <= pair[1].time));
+
+        // Without margin, the cash and the shares only move with the trades,
+        // and never below zero
+        let spent: Money = transactions
+            .iter()
+            .map(|t| match t.side {
+                Side::Buy => Money::worth(t.quantity, t.price),
+                Side::Sell => -Money::worth(t.quantity, t.price),
+            })
+            .sum();
+        assert_eq!(Money::from_f64(CASH) - spent, self.account.cash());
+        assert!(!self.account.cash().is_negative(), "negative cash");
+
+        for symbol in SYMBOLS {
+            let bought: f64 = transactions
+                .iter()
+                .filter(|t| t.symbol == symbol)
+                .map(|t| match t.side {
+                    Side::Buy => t.quantity,
+                    Side::Sell => -t.quantity,
+                })
+                .sum();
+            let shares = self.account.shares_of(symbol);
+            assert!((bought - shares).abs() < 1e-6);
+            assert!(shares >= 0.0, "negative shares");
+        }
+    }
+}
+
+/// The only errors orders may fail with, since the account has neither
+/// margin, settlement nor a day trade limit
+fn assert_rejected(error: AccountError) {
+    assert!(
+        matches!(
+            error,
+            AccountError::InsufficientCash { .. } | AccountError::InsufficientShares { .. }
+        ),
+        "unexpected error {error:?}"
+    );
+}
+
+// Drives arbitrary sequences of orders, bars and session events through the
+// order engine, checking that the account only ever changes by the trades
+// it reports
+fuzz_target!(|data: &[u8]| {
+    let mut input = Input(data.iter());
+
+    let mut engine = OrderEngine::new();
+    let config = input.byte();
+    if config & 1 != 0 {
+        engine = engine.with_market_fill(MarketFill::NextBarOpen);
+    }
+    if config & 2 != 0 {
+        let remainder = if config & 4 != 0 {
+            Remainder::Cancel
+        } else {
+            Remainder::RollOver
+        };
+        engine = engine.with_volume_limit(VolumeLimit {
+            fraction: 0.1,
+            remainder,
+        });
+    }
+    if config & 8 != 0 {
+        engine = engine.with_price_impact(PriceImpact {
+            threshold: 0.01,
+            coefficient: 0.1,
+            curve: ImpactCurve::SquareRoot,
+        });
+    }
+    engine = engine.with_end_of_day_cancellation(config & 16 != 0);
+
+    let mut simulation = Simulation {
+        engine,
+        account: SimulatedAccount::new(Money::from_f64(CASH)),
+        instruments: InstrumentRegistry::default(),
+        currency: Currency::default(),
+        time: Utc.with_ymd_and_hms(2024, 3, 4, 9, 0, 0).unwrap(),
+        market_time: MarketTime::Unknown,
+        bars: HashMap::new(),
+        reported: 0.0,
+    };
+
+    while !input.0.as_slice().is_empty() {
+        match input.byte() % 6 {
+            0 => {
+                let event = input.pick(&SESSION_EVENTS);
+                simulation.session(event);
+            }
+            1 => {
+                let symbol = *input.pick(&SYMBOLS);
+                let prices = input.bar();
+                let volume = input.flag().then(|| input.byte() as f64 * 10.0);
+                simulation.bar(symbol, prices, volume);
+            }
+            2 => {
+                let symbol = *input.pick(&SYMBOLS);
+                let (side, quantity) = (input.side(), input.quantity());
+                simulation.market_order(symbol, side, quantity);
+            }
+            3 => {
+                let symbol = *input.pick(&SYMBOLS);
+                let price = simulation
+                    .bars
+                    .get(symbol)
+                    .map_or(1.0, |(prices, _)| prices.close);
+                let (side, quantity) = (input.side(), input.quantity());
+                let order = Order::new(symbol, side, quantity, input.kind(price))
+                    .with_time_in_force(*input.pick(&[
+                        TimeInForce::Day,
+                        TimeInForce::GoodTillCanceled,
+                        TimeInForce::ImmediateOrCancel,
+                        TimeInForce::FillOrKill,
+                    ]))
+                    .with_extended_hours(input.flag());
+                simulation.submit(order);
+            }
+            4 => {
+                let index = input.byte();
+                let amendment = Amendment {
+                    quantity: input.flag().then(|| (input.byte() % 20) as f64),
+                    limit_price: input.flag().then(|| input.price()),
+                    stop_price: input.flag().then(|| input.price()),
+                };
+                simulation.amend(index, amendment);
+            }
+            _ => simulation.cancel(input.byte()),
+        }
+
+        simulation.check_invariants();
+    }
+});
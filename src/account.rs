@@ -0,0 +1,643 @@
+//! The cash and positions of a simulated account, shared by the simulated
+//! markets so a new backend only has to implement data access.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike as _, NaiveDate, TimeDelta, Utc, Weekday};
+use chrono_tz::America::New_York;
+use thiserror::Error;
+
+use crate::{money::Money, order::Side};
+
+/// The rounding error of fractional quantities, e.g. that selling 0.1 and
+/// then 0.2 of 0.3 shares leaves behind
+pub(crate) const QUANTITY_TOLERANCE: f64 = 1e-9;
+
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum AccountError {
+    #[error("Cannot buy {quantity} shares of {symbol} for {total_price} with {cash} in cash")]
+    InsufficientCash {
+        quantity: f64,
+        symbol: String,
+        total_price: Money,
+        cash: Money,
+    },
+
+    #[error("Cannot sell {quantity} shares of {symbol} because only {owned} shares are owned")]
+    InsufficientShares {
+        quantity: f64,
+        symbol: String,
+        owned: f64,
+    },
+
+    #[error(
+        "Cannot buy {quantity} shares of {symbol} for {total_price} with {settled_cash} in \
+         settled cash, the rest of the cash is not settled yet"
+    )]
+    UnsettledFunds {
+        quantity: f64,
+        symbol: String,
+        total_price: Money,
+        settled_cash: Money,
+    },
+
+    #[error(
+        "Selling {symbol} would be the day trade {day_trades} within {period_days} trading days, \
+         with an equity of {equity} below {min_equity}"
+    )]
+    DayTradeLimit {
+        symbol: String,
+        day_trades: usize,
+        period_days: usize,
+        equity: Money,
+        min_equity: Money,
+    },
+}
+
+/// Borrowing against the account to buy more than its cash does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Margin {
+    /// The largest debit balance (i.e. negative cash) allowed
+    pub limit: Money,
+    /// The yearly interest on the debit balance, e.g. 0.08 for 8%, accrued
+    /// daily over a 360-day year as brokers do
+    pub annual_rate: f64,
+}
+
+/// The US pattern day trader rule: accounts with less equity than
+/// `min_equity` (see `SimulatedAccount::equity`) may only make
+/// `max_day_trades` day trades (sales of an equity bought the same day)
+/// within `period_days` trading days
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DayTradeLimit {
+    pub max_day_trades: usize,
+    pub period_days: usize,
+    pub min_equity: Money,
+}
+
+impl Default for DayTradeLimit {
+    /// FINRA's rule for margin accounts
+    fn default() -> Self {
+        DayTradeLimit {
+            max_day_trades: 3,
+            period_days: 5,
+            min_equity: Money::from_f64(25_000.0),
+        }
+    }
+}
+
+/// Whether a date is a weekday. Market holidays are not skipped.
+fn is_trading_day(date: &NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// The date `days` trading days after `date`
+fn add_trading_days(date: NaiveDate, days: usize) -> NaiveDate {
+    if days == 0 {
+        return date;
+    }
+
+    date.iter_days()
+        .skip(1)
+        .filter(is_trading_day)
+        .nth(days - 1)
+        .unwrap()
+}
+
+/// A held position in an equity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub quantity: f64,
+    /// The price paid for the shares still held. Sales take their shares'
+    /// share of it, leaving the average cost unchanged.
+    pub cost_basis: Money,
+    /// When the position was opened, by its first purchase
+    pub opened_at: DateTime<Utc>,
+}
+
+impl Position {
+    /// The average price paid per share
+    pub fn avg_cost(&self) -> f64 {
+        self.cost_basis.to_f64() / self.quantity
+    }
+
+    /// The profit of the shares still held if they were sold at `price`
+    pub fn unrealized_pnl(&self, price: f64) -> Money {
+        Money::worth(self.quantity, price) - self.cost_basis
+    }
+}
+
+/// Identifies a tax lot within its account
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LotId(pub u64);
+
+/// The shares of a single purchase that are still held
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lot {
+    pub id: LotId,
+    pub quantity: f64,
+    /// The price paid per share
+    pub cost: f64,
+    pub acquired_at: DateTime<Utc>,
+}
+
+/// The shares of a lot that a sale closed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosedLot {
+    pub lot: LotId,
+    pub symbol: String,
+    pub quantity: f64,
+    /// The price paid per share
+    pub cost: f64,
+    /// The price sold at per share
+    pub price: f64,
+    pub acquired_at: DateTime<Utc>,
+    pub sold_at: DateTime<Utc>,
+}
+
+impl ClosedLot {
+    pub fn realized_gain(&self) -> Money {
+        Money::worth(self.quantity, self.price) - Money::worth(self.quantity, self.cost)
+    }
+
+    /// How long the shares were held, e.g. to tell short-term gains from
+    /// long-term ones
+    pub fn holding_period(&self) -> TimeDelta {
+        self.sold_at - self.acquired_at
+    }
+}
+
+/// Which lots sales close first, unless specific lots were designated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LotSelection {
+    /// The oldest lots first
+    #[default]
+    Fifo,
+    /// The newest lots first
+    Lifo,
+}
+
+/// A trade executed by the account
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub time: DateTime<Utc>,
+    pub symbol: String,
+    pub side: Side,
+    pub quantity: f64,
+    pub price: f64,
+    /// The fees charged for the trade. Simulated accounts charge none yet.
+    pub fees: Money,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimulatedAccount {
+    // TODO seperate `cash` to `available_cash` and `locked_cash` (or some other name). =
+    // available_cash will be subtracted from when submitting an order, and added to
+    // locked_cash. Upon trade complete, this will be updated.
+    /// The amount of cash on hand, exact so it does not drift over many
+    /// trades
+    cash: Money,
+    /// The currently held positions, by symbol
+    positions: HashMap<String, Position>,
+    margin: Option<Margin>,
+    day_trade_limit: Option<DayTradeLimit>,
+    /// The New York dates of the day trades made so far
+    day_trades: Vec<NaiveDate>,
+    /// The number of trading days sale proceeds take to settle, if they do
+    settlement_days: Option<usize>,
+    /// The proceeds of sales not settled yet, with the New York date they
+    /// settle on
+    unsettled: Vec<(NaiveDate, Money)>,
+    /// When interest was last charged, as of which it is paid
+    interest_charged_at: Option<DateTime<Utc>>,
+    interest_paid: Money,
+    /// The profit of the shares sold so far, over their average cost
+    realized_pnl: Money,
+    /// Every trade executed, in order
+    transactions: Vec<Transaction>,
+    /// The lots of each held equity, from the oldest
+    lots: HashMap<String, Vec<Lot>>,
+    next_lot_id: u64,
+    lot_selection: LotSelection,
+    /// The lots the next sales of each equity close first
+    designated_lots: HashMap<String, Vec<LotId>>,
+    closed_lots: Vec<ClosedLot>,
+    /// The latest price of each held equity, from its fills and `mark`
+    marks: HashMap<String, f64>,
+}
+
+impl SimulatedAccount {
+    pub fn new(cash: Money) -> Self {
+        SimulatedAccount {
+            cash,
+            ..Default::default()
+        }
+    }
+
+    /// Lets purchases borrow up to the margin's limit
+    pub fn with_margin(mut self, margin: Margin) -> Self {
+        self.margin = Some(margin);
+        self
+    }
+
+    /// Limits the day trades of the account while its equity is small
+    pub fn with_day_trade_limit(mut self, limit: DayTradeLimit) -> Self {
+        self.day_trade_limit = Some(limit);
+        self
+    }
+
+    pub fn day_trade_limit(&self) -> Option<DayTradeLimit> {
+        self.day_trade_limit
+    }
+
+    /// Makes the proceeds of sales unavailable to purchases until they
+    /// settle, `trading_days` after the sale (e.g. 1 for T+1), as in a cash
+    /// account
+    pub fn with_settlement_delay(mut self, trading_days: usize) -> Self {
+        self.settlement_days = Some(trading_days);
+        self
+    }
+
+    /// Chooses which lots sales close first
+    pub fn with_lot_selection(mut self, lot_selection: LotSelection) -> Self {
+        self.lot_selection = lot_selection;
+        self
+    }
+
+    /// The amount of cash on hand, negative while borrowing on margin
+    pub fn cash(&self) -> Money {
+        self.cash
+    }
+
+    /// The cash that purchases may use as of `time`, i.e. without the
+    /// proceeds of sales that did not settle yet
+    pub fn settled_cash(&self, time: DateTime<Utc>) -> Money {
+        let today = time.with_timezone(&New_York).date_naive();
+        let unsettled: Money = self
+            .unsettled
+            .iter()
+            .filter(|(settles_on, _)| *settles_on > today)
+            .map(|(_, amount)| *amount)
+            .sum();
+
+        self.cash - unsettled
+    }
+
+    /// The amount borrowed on margin
+    pub fn debit_balance(&self) -> Money {
+        (-self.cash).max(Money::ZERO)
+    }
+
+    /// The margin interest charged so far
+    pub fn interest_paid(&self) -> Money {
+        self.interest_paid
+    }
+
+    /// The profit of the shares sold so far, over their average cost
+    pub fn realized_pnl(&self) -> Money {
+        self.realized_pnl
+    }
+
+    /// Every trade executed so far, in order
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn shares_of(&self, symbol: &str) -> f64 {
+        self.positions
+            .get(symbol)
+            .map_or(0.0, |position| position.quantity)
+    }
+
+    /// How many shares of each held equity are owned, by symbol
+    pub fn holdings(&self) -> impl Iterator<Item = (&String, &f64)> {
+        self.positions
+            .iter()
+            .map(|(symbol, position)| (symbol, &position.quantity))
+    }
+
+    /// The current position in an equity, if one is held
+    pub fn position(&self, symbol: &str) -> Option<&Position> {
+        self.positions.get(symbol)
+    }
+
+    pub fn positions(&self) -> &HashMap<String, Position> {
+        &self.positions
+    }
+
+    /// Values a held equity at its current price from now on, e.g. to value
+    /// the account for its day trade limit. Fills mark their equities too.
+    pub fn mark(&mut self, symbol: &str, price: f64) {
+        if self.positions.contains_key(symbol) {
+            self.marks.insert(symbol.to_string(), price);
+        }
+    }
+
+    /// The cash plus the held positions at their latest marks, or at cost
+    /// where they were never marked
+    pub fn equity(&self) -> Money {
+        self.cash
+            + self
+                .positions
+                .keys()
+                .map(|symbol| self.marked_value(symbol))
+                .sum::<Money>()
+    }
+
+    /// The value of the position in an equity at its latest mark
+    fn marked_value(&self, symbol: &str) -> Money {
+        self.positions.get(symbol).map_or(Money::ZERO, |position| {
+            self.marks.get(symbol).map_or(position.cost_basis, |price| {
+                Money::worth(position.quantity, *price)
+            })
+        })
+    }
+
+    /// The lots of an equity that are still held, from the oldest
+    pub fn lots(&self, symbol: &str) -> &[Lot] {
+        self.lots.get(symbol).map_or(&[], Vec::as_slice)
+    }
+
+    /// The lots closed by sales so far, in order, with their realized gains
+    pub fn closed_lots(&self) -> &[ClosedLot] {
+        &self.closed_lots
+    }
+
+    /// Makes the next sales of an equity close specific lots first, in the
+    /// given order, before the lot selection applies. Lots that are not
+    /// held are ignored.
+    pub fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.designated_lots
+            .insert(symbol.to_string(), lots.to_vec());
+    }
+
+    /// Ensures a trade could be executed at a price, without executing it
+    pub fn check(
+        &self,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        time: DateTime<Utc>,
+    ) -> Result<(), AccountError> {
+        match side {
+            Side::Buy => {
+                let total_price = Money::worth(quantity, price);
+                let limit = self.margin.map_or(Money::ZERO, |margin| margin.limit);
+                if total_price > self.cash() + limit {
+                    return Err(AccountError::InsufficientCash {
+                        quantity,
+                        symbol: symbol.to_string(),
+                        total_price,
+                        cash: self.cash(),
+                    });
+                }
+                let settled_cash = self.settled_cash(time);
+                if total_price > settled_cash + limit {
+                    return Err(AccountError::UnsettledFunds {
+                        quantity,
+                        symbol: symbol.to_string(),
+                        total_price,
+                        settled_cash,
+                    });
+                }
+            }
+            Side::Sell => {
+                let owned = self.shares_of(symbol);
+                if quantity > owned + QUANTITY_TOLERANCE {
+                    return Err(AccountError::InsufficientShares {
+                        quantity,
+                        symbol: symbol.to_string(),
+                        owned,
+                    });
+                }
+                self.check_day_trade_limit(symbol, price, time)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The number of day trades made within the last `period_days` trading
+    /// days, as of `time`. Weekends are skipped, market holidays are not.
+    pub fn day_trades_within(&self, period_days: usize, time: DateTime<Utc>) -> usize {
+        let today = time.with_timezone(&New_York).date_naive();
+        let first_day = today
+            .iter_days()
+            .rev()
+            .filter(is_trading_day)
+            .take(period_days)
+            .last()
+            .unwrap_or(today);
+
+        self.day_trades
+            .iter()
+            .filter(|date| (first_day..=today).contains(date))
+            .count()
+    }
+
+    /// Whether selling an equity at `time` would be a day trade, i.e. some of
+    /// the held shares were bought the same (New York) day
+    fn is_day_trade(&self, symbol: &str, time: DateTime<Utc>) -> bool {
+        let today = time.with_timezone(&New_York).date_naive();
+
+        self.lots(symbol)
+            .iter()
+            .any(|lot| lot.acquired_at.with_timezone(&New_York).date_naive() == today)
+    }
+
+    /// Checks a sale of `symbol` at `price` against the day trade limit,
+    /// valuing the account at the sale's price and the other positions'
+    /// marks
+    fn check_day_trade_limit(
+        &self,
+        symbol: &str,
+        price: f64,
+        time: DateTime<Utc>,
+    ) -> Result<(), AccountError> {
+        let Some(limit) = self.day_trade_limit else {
+            return Ok(());
+        };
+        if !self.is_day_trade(symbol, time) {
+            return Ok(());
+        }
+
+        let equity =
+            self.equity() - self.marked_value(symbol) + Money::worth(self.shares_of(symbol), price);
+        let day_trades = self.day_trades_within(limit.period_days, time) + 1;
+        if equity < limit.min_equity && day_trades > limit.max_day_trades {
+            return Err(AccountError::DayTradeLimit {
+                symbol: symbol.to_string(),
+                day_trades,
+                period_days: limit.period_days,
+                equity,
+                min_equity: limit.min_equity,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Executes a trade at a price, updating the cash and the holdings
+    pub fn fill(
+        &mut self,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        time: DateTime<Utc>,
+    ) -> Result<(), AccountError> {
+        self.check(symbol, side, quantity, price, time)?;
+        if side == Side::Sell && self.is_day_trade(symbol, time) {
+            self.day_trades
+                .push(time.with_timezone(&New_York).date_naive());
+        }
+
+        let total_price = Money::worth(quantity, price);
+        match side {
+            Side::Buy => {
+                self.cash -= total_price;
+                let position = self
+                    .positions
+                    .entry(symbol.to_string())
+                    .or_insert(Position {
+                        quantity: 0.0,
+                        cost_basis: Money::ZERO,
+                        opened_at: time,
+                    });
+                position.cost_basis += total_price;
+                position.quantity += quantity;
+
+                self.lots.entry(symbol.to_string()).or_default().push(Lot {
+                    id: LotId(self.next_lot_id),
+                    quantity,
+                    cost: price,
+                    acquired_at: time,
+                });
+                self.next_lot_id += 1;
+            }
+            Side::Sell => {
+                let Some(position) = self.positions.get_mut(symbol) else {
+                    return Err(AccountError::InsufficientShares {
+                        quantity,
+                        symbol: symbol.to_string(),
+                        owned: 0.0,
+                    });
+                };
+                self.cash += total_price;
+                if let Some(days) = self.settlement_days {
+                    let today = time.with_timezone(&New_York).date_naive();
+                    self.unsettled.retain(|(settles_on, _)| *settles_on > today);
+                    self.unsettled
+                        .push((add_trading_days(today, days), total_price));
+                }
+                // The last shares sold take what is left of the cost basis
+                let cost = if position.quantity - quantity < QUANTITY_TOLERANCE {
+                    position.cost_basis
+                } else {
+                    Money::worth(quantity, position.avg_cost())
+                };
+                self.realized_pnl += total_price - cost;
+                position.cost_basis -= cost;
+                position.quantity -= quantity;
+                // Fractional sales may leave a rounding error behind
+                if position.quantity.abs() < QUANTITY_TOLERANCE {
+                    self.positions.remove(symbol);
+                }
+                self.close_lots(symbol, quantity, price, time);
+            }
+        }
+        if self.positions.contains_key(symbol) {
+            self.marks.insert(symbol.to_string(), price);
+        } else {
+            self.marks.remove(symbol);
+        }
+        self.transactions.push(Transaction {
+            time,
+            symbol: symbol.to_string(),
+            side,
+            quantity,
+            price,
+            fees: Money::ZERO,
+        });
+
+        Ok(())
+    }
+
+    /// Adds `amount` to the cash, or takes it if negative, e.g. for funding
+    /// payments
+    pub fn credit(&mut self, amount: Money) {
+        self.cash += amount;
+    }
+
+    /// Charges the interest on the debit balance for every day since the
+    /// previous charge (or for a day, at the first one), e.g. at the end of
+    /// each trading day, returning the amount charged
+    pub fn charge_interest(&mut self, time: DateTime<Utc>) -> Money {
+        let days = match self.interest_charged_at {
+            Some(charged_at) => (time.date_naive() - charged_at.date_naive()).num_days(),
+            None => 1,
+        };
+        self.interest_charged_at = Some(time);
+
+        let Some(margin) = self.margin else {
+            return Money::ZERO;
+        };
+        let interest = Money::from_f64(
+            self.debit_balance().to_f64() * margin.annual_rate / 360.0 * days as f64,
+        );
+        self.cash -= interest;
+        self.interest_paid += interest;
+
+        interest
+    }
+
+    /// Closes the lots a sale sold, designated ones first
+    fn close_lots(&mut self, symbol: &str, quantity: f64, price: f64, time: DateTime<Utc>) {
+        let Some(lots) = self.lots.get_mut(symbol) else {
+            return;
+        };
+        let mut designated = self.designated_lots.remove(symbol).unwrap_or_default();
+
+        let mut order: Vec<LotId> = designated.clone();
+        let remaining = lots
+            .iter()
+            .map(|lot| lot.id)
+            .filter(|id| !designated.contains(id));
+        match self.lot_selection {
+            LotSelection::Fifo => order.extend(remaining),
+            LotSelection::Lifo => order.extend(remaining.rev()),
+        }
+
+        let mut unsold = quantity;
+        for id in order {
+            if unsold < QUANTITY_TOLERANCE {
+                break;
+            }
+            let Some(lot) = lots.iter_mut().find(|lot| lot.id == id) else {
+                continue;
+            };
+            let sold = unsold.min(lot.quantity);
+            lot.quantity -= sold;
+            unsold -= sold;
+
+            self.closed_lots.push(ClosedLot {
+                lot: id,
+                symbol: symbol.to_string(),
+                quantity: sold,
+                cost: lot.cost,
+                price,
+                acquired_at: lot.acquired_at,
+                sold_at: time,
+            });
+        }
+
+        lots.retain(|lot| lot.quantity >= QUANTITY_TOLERANCE);
+        designated.retain(|id| lots.iter().any(|lot| lot.id == *id));
+        if lots.is_empty() {
+            self.lots.remove(symbol);
+        } else if !designated.is_empty() {
+            self.designated_lots.insert(symbol.to_string(), designated);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Helpers for aligning time series of different resolutions (e.g. daily
+//! fundamentals and minute prices) onto a common tick grid.
+//!
+//! Every series is a slice of `(time, value)` pairs sorted by time, where
+//! `time` is when the value became known. A value is only ever visible at or
+//! after its time, so aligning never looks ahead.
+
+use chrono::{DateTime, DurationRound as _, TimeDelta, Utc};
+
+/// Returns the latest value known at `time`, if any.
+pub fn as_of<T>(series: &[(DateTime<Utc>, T)], time: DateTime<Utc>) -> Option<&T> {
+    let known = series.partition_point(|(value_time, _)| value_time <= &time);
+
+    known.checked_sub(1).map(|index| &series[index].1)
+}
+
+/// Returns the ticks between `start` and `end` (inclusive), aligned to
+/// multiples of `tick` the same way `Market::next_event_or_tick` aligns them.
+pub fn tick_grid(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    tick: TimeDelta,
+) -> impl Iterator<Item = DateTime<Utc>> {
+    let first_tick = start.duration_trunc(tick).unwrap();
+    let first_tick = if first_tick < start {
+        first_tick + tick
+    } else {
+        first_tick
+    };
+
+    std::iter::successors(Some(first_tick), move |time| Some(*time + tick))
+        .take_while(move |time| time <= &end)
+}
+
+/// Samples a series at every time of a grid, carrying the latest known value
+/// forward. Grid times before the first value are `None`.
+pub fn forward_fill<T>(
+    series: &[(DateTime<Utc>, T)],
+    grid: impl IntoIterator<Item = DateTime<Utc>>,
+) -> Vec<(DateTime<Utc>, Option<&T>)> {
+    grid.into_iter()
+        .map(|time| (time, as_of(series, time)))
+        .collect()
+}
+
+/// Pairs every value of `left` with the latest value of `right` known at the
+/// same time.
+pub fn asof_join<'a, L, R>(
+    left: &'a [(DateTime<Utc>, L)],
+    right: &'a [(DateTime<Utc>, R)],
+) -> Vec<(DateTime<Utc>, &'a L, Option<&'a R>)> {
+    left.iter()
+        .map(|(time, value)| (*time, value, as_of(right, *time)))
+        .collect()
+}
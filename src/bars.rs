@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, DurationRound as _, TimeDelta, Utc};
+
+use crate::market::{Candle, Event};
+
+/// Aggregates a stream of trades into bars in memory, reporting each bar as
+/// an `Event::BarClosed` once it is complete. This gives bar-based
+/// algorithms the same events from a live trade feed as backtest markets
+/// report from historical candles (see `BarEvents`).
+pub struct BarAggregator {
+    interval: TimeDelta,
+    /// The bar currently being built, by symbol
+    open_bars: HashMap<String, Candle>,
+    /// The end of the last bar closed, by symbol
+    closed_until: HashMap<String, DateTime<Utc>>,
+}
+
+impl BarAggregator {
+    pub fn new(interval: TimeDelta) -> Self {
+        BarAggregator {
+            interval,
+            open_bars: HashMap::new(),
+            closed_until: HashMap::new(),
+        }
+    }
+
+    /// Adds a trade to its symbol's bar. If the trade starts a new bar, the
+    /// previous one is closed and returned.
+    ///
+    /// Trades from before the current bar arrived too late to be counted in
+    /// theirs, which was already closed, and are dropped.
+    pub fn push_trade(
+        &mut self,
+        symbol: &str,
+        time: DateTime<Utc>,
+        price: f64,
+        size: f64,
+    ) -> Option<Event> {
+        let start = time.duration_trunc(self.interval).unwrap();
+        let late = self
+            .closed_until
+            .get(symbol)
+            .is_some_and(|until| time < *until)
+            || self
+                .open_bars
+                .get(symbol)
+                .is_some_and(|bar| start < bar.start);
+        if late {
+            log::debug!("dropping a late trade of {symbol} at {time}");
+            return None;
+        }
+
+        if let Some(bar) = self.open_bars.get_mut(symbol) {
+            if bar.start == start {
+                bar.high = bar.high.max(price);
+                bar.low = bar.low.min(price);
+                bar.close = price;
+                bar.volume += size;
+
+                return None;
+            }
+        }
+
+        let new_bar = Candle {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: size,
+        };
+
+        self.open_bars
+            .insert(symbol.to_string(), new_bar)
+            .map(|closed| self.bar_closed(symbol, closed))
+    }
+
+    /// Closes every bar that ended by `time`, e.g. on a tick when no trade
+    /// arrived to close it.
+    pub fn close_until(&mut self, time: DateTime<Utc>) -> Vec<Event> {
+        let mut closed_symbols: Vec<String> = self
+            .open_bars
+            .iter()
+            .filter(|(_, bar)| bar.start + self.interval <= time)
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        closed_symbols.sort();
+
+        closed_symbols
+            .into_iter()
+            .map(|symbol| {
+                let closed = self.open_bars.remove(&symbol).unwrap();
+                self.bar_closed(&symbol, closed)
+            })
+            .collect()
+    }
+
+    fn bar_closed(&mut self, symbol: &str, candle: Candle) -> Event {
+        self.closed_until
+            .insert(symbol.to_string(), candle.start + self.interval);
+
+        Event::BarClosed {
+            symbol: symbol.to_string(),
+            interval: self.interval,
+            candle,
+        }
+    }
+}
+
+/// Which bars a backtest market reports as `Event::BarClosed` events once
+/// they close, like a `BarAggregator` does for a live feed
+#[derive(Clone, Debug, PartialEq)]
+pub struct BarEvents {
+    pub universe: Vec<String>,
+    pub interval: TimeDelta,
+}
+
+/// How bars are constructed from time-based candles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BarType {
+    /// The candles themselves
+    Time,
+    /// Smoothed candles, each averaging itself with the previous one
+    HeikinAshi,
+    /// Fixed-size bricks, added whenever the close moves a whole brick
+    Renko { brick_size: f64 },
+    /// Bars of consecutive candles that together trade `threshold` shares
+    Volume { threshold: f64 },
+    /// Bars of consecutive candles that together trade `threshold` in value
+    Dollar { threshold: f64 },
+}
+
+impl BarType {
+    pub fn build(&self, candles: &[Candle]) -> Vec<Candle> {
+        match *self {
+            BarType::Time => candles.to_vec(),
+            BarType::HeikinAshi => heikin_ashi(candles),
+            BarType::Renko { brick_size } => renko(candles, brick_size),
+            BarType::Volume { threshold } => {
+                merge_by_activity(candles, threshold, |candle| candle.volume)
+            }
+            BarType::Dollar { threshold } => {
+                merge_by_activity(candles, threshold, |candle| candle.volume * candle.close)
+            }
+        }
+    }
+}
+
+fn heikin_ashi(candles: &[Candle]) -> Vec<Candle> {
+    let mut bars: Vec<Candle> = Vec::with_capacity(candles.len());
+
+    for candle in candles {
+        let close = (candle.open + candle.high + candle.low + candle.close) / 4.0;
+        let open = match bars.last() {
+            Some(previous) => (previous.open + previous.close) / 2.0,
+            None => (candle.open + candle.close) / 2.0,
+        };
+
+        bars.push(Candle {
+            start: candle.start,
+            open,
+            high: candle.high.max(open).max(close),
+            low: candle.low.min(open).min(close),
+            close,
+            volume: candle.volume,
+        });
+    }
+
+    bars
+}
+
+/// Bricks start when the candle whose close completed them starts
+fn renko(candles: &[Candle], brick_size: f64) -> Vec<Candle> {
+    assert!(brick_size > 0.0);
+
+    let Some(first) = candles.first() else {
+        return Vec::new();
+    };
+
+    let mut bricks = Vec::new();
+    let mut level = first.close;
+    let mut volume = 0.0;
+
+    for candle in candles {
+        volume += candle.volume;
+
+        while (candle.close - level).abs() >= brick_size {
+            let next_level = level + brick_size.copysign(candle.close - level);
+
+            bricks.push(Candle {
+                start: candle.start,
+                open: level,
+                high: level.max(next_level),
+                low: level.min(next_level),
+                close: next_level,
+                volume,
+            });
+
+            level = next_level;
+            volume = 0.0;
+        }
+    }
+
+    bricks
+}
+
+/// Merges consecutive candles until their combined activity reaches the
+/// threshold. A trailing bar below the threshold is left out.
+fn merge_by_activity(
+    candles: &[Candle],
+    threshold: f64,
+    activity: impl Fn(&Candle) -> f64,
+) -> Vec<Candle> {
+    let mut bars = Vec::new();
+    let mut current: Option<Candle> = None;
+    let mut current_activity = 0.0;
+
+    for candle in candles {
+        let bar = current.get_or_insert(Candle {
+            volume: 0.0,
+            ..*candle
+        });
+        bar.high = bar.high.max(candle.high);
+        bar.low = bar.low.min(candle.low);
+        bar.close = candle.close;
+        bar.volume += candle.volume;
+
+        current_activity += activity(candle);
+        if current_activity >= threshold {
+            bars.extend(current.take());
+            current_activity = 0.0;
+        }
+    }
+
+    bars
+}
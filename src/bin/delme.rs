@@ -4,7 +4,9 @@ use std::{collections::VecDeque, error::Error};
 
 use chrono::{DateTime, TimeDelta, Utc};
 use mmatamm_interface::{
+    ext::MarketExt,
     market::{Event, Market, MarketTime},
+    money::Money,
     questdb_market::QuestDbMarket,
     Algorithm,
 };
@@ -88,20 +90,16 @@ impl Algorithm for CrossMovingAverageStrategy {
                 if short_ma > long_ma {
                     if !self.last_bought {
                         // buy
-                        // TODO add a market extender function for this
-                        let quantity = market.cash() / current_price;
-                        market.buy_at_market(&self.symbol, quantity as u32).await?;
-                        println!("buying {} shares", quantity as u32);
+                        let receipt = market.buy_max(&self.symbol).await?;
+                        println!("buying {} shares", receipt.quantity);
 
                         self.last_bought = true;
                         self.last_sold = false;
                     }
                 } else if !self.last_sold {
                     // sell
-                    // TODO add a market extender function for this
-                    let quantity = market.shares_of(&self.symbol);
-                    market.sell_at_market(&self.symbol, quantity).await?;
-                    println!("selling {} shares", quantity);
+                    let receipt = market.sell_all(&self.symbol).await?;
+                    println!("selling {} shares", receipt.quantity);
 
                     self.last_bought = false;
                     self.last_sold = true;
@@ -112,8 +110,10 @@ impl Algorithm for CrossMovingAverageStrategy {
         println!(
             "net worth: {}",
             market.cash()
-                + (market.shares_of(&self.symbol) as f64)
-                    * market.current_price(&self.symbol).await?
+                + Money::worth(
+                    market.shares_of(&self.symbol),
+                    market.current_price(&self.symbol).await?
+                )
         );
         // println!("{:?}", market.time());
 
@@ -143,7 +143,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let mut market = QuestDbMarket::new(
         &client,
         "2024-06-25T13:00:00Z".parse::<DateTime<Utc>>()?,
-        10_000.0,
+        Money::from_f64(10_000.0),
     )
     .await?;
 
@@ -0,0 +1,244 @@
+//! Opens a market at a chosen virtual time and lets the user query it
+//! interactively, e.g. `explore 2024-06-25T13:00:00Z 10000`.
+
+use std::{
+    error::Error,
+    io::{BufRead as _, Write as _},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use mmatamm_interface::{
+    breakpoint::{BreakContext, Breakpoints},
+    market::{Candle, Market},
+    money::Money,
+    questdb_market::{self, MarketSnapshot, QuestDbMarket},
+};
+use tokio_postgres::NoTls;
+
+const HELP: &str = "\
+commands:
+  price SYMBOL                       the latest price of an equity
+  candles SYMBOL MINUTES COUNT       the last COUNT candles of an equity
+  sma SYMBOL MINUTES COUNT           the moving average of the last COUNT closes
+  buy SYMBOL QUANTITY                buy at market
+  sell SYMBOL QUANTITY               sell at market
+  undo                               revert the last order
+  next                               advance to the next event
+  tick MINUTES                       advance to the next event or tick
+  run MINUTES COUNT                  tick up to COUNT times, until a breakpoint
+  break PERCENT                      break when net worth drops PERCENT% in a day
+  status                             the time, cash, holdings and net worth
+  help                               show this message
+  quit                               exit";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    flexi_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let start = match args.next() {
+        Some(start) => start.parse::<DateTime<Utc>>()?,
+        None => {
+            eprintln!("usage: explore START_TIME [CASH]");
+            std::process::exit(2);
+        }
+    };
+    let cash = match args.next() {
+        Some(cash) => Money::from_f64(cash.parse()?),
+        None => Money::from_f64(10_000.0),
+    };
+
+    // Connect to the database
+    let (client, connection) = tokio_postgres::connect(
+        "user=admin password=quest host=localhost port=8812 dbname=qdb",
+        NoTls,
+    )
+    .await?;
+
+    // The connection object performs the actual communication with the database,
+    // so spawn it off to run on its own.
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+
+    let market = QuestDbMarket::new(&client, start, cash).await?;
+    let mut market = Breakpoints::new(market).with_on_break(print_break);
+    let mut undo_stack: Vec<MarketSnapshot> = Vec::new();
+
+    println!("{HELP}");
+    let stdin = std::io::stdin();
+    loop {
+        print!("{} > ", market.time());
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => continue,
+            ["quit" | "exit"] => break,
+            ["help"] => println!("{HELP}"),
+            words => {
+                if let Err(error) = execute(&mut market, &mut undo_stack, words).await {
+                    println!("error: {error}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a single command against the market
+async fn execute(
+    market: &mut Breakpoints<QuestDbMarket<'_>>,
+    undo_stack: &mut Vec<MarketSnapshot>,
+    words: &[&str],
+) -> Result<(), Box<dyn Error>> {
+    match words {
+        ["price", symbol] => {
+            let quote = market.current_quote(symbol).await?;
+            println!(
+                "{symbol}: {} (as of {})",
+                market
+                    .market()
+                    .instruments()
+                    .format_price(symbol, quote.price),
+                quote.as_of
+            );
+        }
+        ["candles", symbol, minutes, count] => {
+            let candles =
+                last_candles(market.market(), symbol, minutes.parse()?, count.parse()?).await?;
+            for candle in candles {
+                let price = |price| market.market().instruments().format_price(symbol, price);
+                println!(
+                    "{}  o {}  h {}  l {}  c {}  v {}",
+                    candle.start,
+                    price(candle.open),
+                    price(candle.high),
+                    price(candle.low),
+                    price(candle.close),
+                    candle.volume
+                );
+            }
+        }
+        ["sma", symbol, minutes, count] => {
+            let candles =
+                last_candles(market.market(), symbol, minutes.parse()?, count.parse()?).await?;
+            if candles.is_empty() {
+                println!("no candles");
+            } else {
+                let sum: f64 = candles.iter().map(|candle| candle.close).sum();
+                println!(
+                    "sma over {} candles: {}",
+                    candles.len(),
+                    market
+                        .market()
+                        .instruments()
+                        .format_price(symbol, sum / candles.len() as f64)
+                );
+            }
+        }
+        ["buy", symbol, quantity] => {
+            let snapshot = market.market().snapshot();
+            market.buy_at_market(symbol, quantity.parse()?).await?;
+            undo_stack.push(snapshot);
+            println!("cash: {}", market.market().currency().format(market.cash()));
+        }
+        ["sell", symbol, quantity] => {
+            let snapshot = market.market().snapshot();
+            market.sell_at_market(symbol, quantity.parse()?).await?;
+            undo_stack.push(snapshot);
+            println!("cash: {}", market.market().currency().format(market.cash()));
+        }
+        ["undo"] => match undo_stack.pop() {
+            Some(snapshot) => {
+                market.market_mut().restore(snapshot);
+                println!("reverted to {}", market.time());
+            }
+            None => println!("nothing to undo"),
+        },
+        ["next"] => match market.next_event().await? {
+            Some((time, event)) => println!("{time}: {event:?}"),
+            None => println!("no more events"),
+        },
+        ["tick", minutes] => {
+            let (time, event) = market
+                .next_event_or_tick(TimeDelta::minutes(minutes.parse()?))
+                .await?;
+            println!("{time}: {event:?}");
+        }
+        ["run", minutes, count] => {
+            let tick = TimeDelta::minutes(minutes.parse()?);
+            let hits = market.hits().len();
+            for _ in 0..count.parse::<usize>()? {
+                market.next_event_or_tick(tick).await?;
+                if market.hits().len() > hits {
+                    break;
+                }
+            }
+            println!("stopped at {}", market.time());
+        }
+        ["break", percent] => {
+            let percent: f64 = percent.parse()?;
+            market.add(
+                &format!("net worth dropped {percent}% today"),
+                move |context| {
+                    context.net_worth < context.day_start_net_worth.times(1.0 - percent / 100.0)
+                },
+            );
+        }
+        ["status"] => {
+            println!("time: {} ({:?})", market.time(), market.market_time());
+            println!("cash: {}", market.market().currency().format(market.cash()));
+            for (symbol, quantity) in market.holdings() {
+                println!(
+                    "{symbol}: {}",
+                    market
+                        .market()
+                        .instruments()
+                        .format_quantity(symbol, *quantity)
+                );
+            }
+            let net_worth = market.net_worth().await?;
+            println!(
+                "net worth: {}",
+                market.market().currency().format(net_worth)
+            );
+        }
+        _ => println!("unknown command, try 'help'"),
+    }
+
+    Ok(())
+}
+
+/// Returns the (up to) `count` latest candles of `minutes` minutes each
+async fn last_candles(
+    market: &QuestDbMarket<'_>,
+    symbol: &str,
+    minutes: i64,
+    count: usize,
+) -> Result<Vec<Candle>, questdb_market::Error> {
+    let interval = TimeDelta::minutes(minutes.max(1));
+    let end = market.time() + interval;
+    let start = end - interval * (count as i32 + 1);
+
+    let candles = market.candles(symbol, interval, start, end).await?;
+    let skip = candles.len().saturating_sub(count);
+
+    Ok(candles.into_iter().skip(skip).collect())
+}
+
+/// Reports a breakpoint that fired, leaving the prompt as the pause
+fn print_break(name: &str, context: &BreakContext) {
+    println!(
+        "breakpoint '{name}' hit at {}: net worth {}, cash {}",
+        context.time, context.net_worth, context.cash
+    );
+}
@@ -0,0 +1,309 @@
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+use crate::{
+    account::{ClosedLot, Lot, LotId, Position, Transaction},
+    market::{Event, Market, MarketTime, PriceQuote},
+    money::Money,
+    order::{Amendment, OcoGroupId, Order, OrderId, OrderStatus, PendingOrder, TradeReceipt},
+};
+
+/// The state of a market when a breakpoint is evaluated
+#[derive(Clone, Debug)]
+pub struct BreakContext {
+    pub time: DateTime<Utc>,
+    /// The event that was just reported
+    pub event: Event,
+    pub market_time: MarketTime,
+    pub cash: Money,
+    pub net_worth: Money,
+    /// The net worth at the first event of the current (UTC) day
+    pub day_start_net_worth: Money,
+    /// Held shares by symbol, sorted by symbol
+    pub holdings: Vec<(String, f64)>,
+}
+
+impl BreakContext {
+    pub fn shares_of(&self, symbol: &str) -> f64 {
+        self.holdings
+            .iter()
+            .find(|(held, _)| held == symbol)
+            .map_or(0.0, |(_, quantity)| *quantity)
+    }
+}
+
+type Condition = Box<dyn Fn(&BreakContext) -> bool + Send + Sync>;
+
+/// Called with the name of a breakpoint and the context it fired at
+type OnBreak = Box<dyn FnMut(&str, &BreakContext) + Send + Sync>;
+
+struct Breakpoint {
+    name: String,
+    condition: Condition,
+    /// Whether the condition held at the previous event, so a breakpoint
+    /// only fires when its condition starts holding
+    active: bool,
+}
+
+/// Wraps a market, recording and logging the market's state whenever a
+/// registered condition starts to hold (e.g. "net worth dropped 5% today").
+///
+/// The backtest is paused for as long as the `with_on_break` callback runs,
+/// e.g. until a CLI reads Enter from stdin.
+pub struct Breakpoints<M: Market> {
+    market: M,
+    breakpoints: Vec<Breakpoint>,
+    on_break: Option<OnBreak>,
+
+    /// The current day and the net worth at its start
+    day_start: Option<(NaiveDate, Money)>,
+    /// Every context a breakpoint fired at, with the breakpoint's name
+    hits: Vec<(String, BreakContext)>,
+}
+
+impl<M: Market + Send> Breakpoints<M> {
+    pub fn new(market: M) -> Self {
+        Breakpoints {
+            market,
+            breakpoints: Vec::new(),
+            on_break: None,
+
+            day_start: None,
+            hits: Vec::new(),
+        }
+    }
+
+    /// Calls `on_break` whenever a breakpoint fires, before the event it
+    /// fired at is returned
+    pub fn with_on_break(
+        mut self,
+        on_break: impl FnMut(&str, &BreakContext) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_break = Some(Box::new(on_break));
+        self
+    }
+
+    pub fn add(
+        &mut self,
+        name: &str,
+        condition: impl Fn(&BreakContext) -> bool + Send + Sync + 'static,
+    ) {
+        self.breakpoints.push(Breakpoint {
+            name: name.to_string(),
+            condition: Box::new(condition),
+            active: false,
+        });
+    }
+
+    pub fn hits(&self) -> &[(String, BreakContext)] {
+        &self.hits
+    }
+
+    pub fn market(&self) -> &M {
+        &self.market
+    }
+
+    pub fn market_mut(&mut self) -> &mut M {
+        &mut self.market
+    }
+
+    pub fn into_inner(self) -> M {
+        self.market
+    }
+
+    async fn check(&mut self, event: &Event) -> Result<(), M::Error> {
+        if self.breakpoints.is_empty() {
+            return Ok(());
+        }
+
+        let net_worth = self.market.net_worth().await?;
+        let today = self.market.time().date_naive();
+        let day_start_net_worth = match self.day_start {
+            Some((day, day_start_net_worth)) if day == today => day_start_net_worth,
+            _ => {
+                self.day_start = Some((today, net_worth));
+                net_worth
+            }
+        };
+
+        let mut holdings: Vec<(String, f64)> = self
+            .market
+            .holdings()
+            .into_iter()
+            .map(|(symbol, quantity)| (symbol.clone(), *quantity))
+            .collect();
+        holdings.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let context = BreakContext {
+            time: self.market.time(),
+            event: event.clone(),
+            market_time: self.market.market_time(),
+            cash: self.market.cash(),
+            net_worth,
+            day_start_net_worth,
+            holdings,
+        };
+
+        for breakpoint in &mut self.breakpoints {
+            let holds = (breakpoint.condition)(&context);
+            let fired = holds && !breakpoint.active;
+            breakpoint.active = holds;
+
+            if fired {
+                log::info!("breakpoint '{}' hit: {context:#?}", breakpoint.name);
+                if let Some(on_break) = &mut self.on_break {
+                    on_break(&breakpoint.name, &context);
+                }
+                self.hits.push((breakpoint.name.clone(), context.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<M: Market + Send> Market for Breakpoints<M> {
+    type Error = M::Error;
+
+    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        let event = self.market.next_event().await?;
+        if let Some((_, event)) = &event {
+            self.check(event).await?;
+        }
+
+        Ok(event)
+    }
+
+    async fn next_event_or_tick(
+        &mut self,
+        tick: TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), M::Error> {
+        let event = self.market.next_event_or_tick(tick).await?;
+        self.check(&event.1).await?;
+
+        Ok(event)
+    }
+
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        let event = self.market.next_event_until(until).await?;
+        if let Some((_, event)) = &event {
+            self.check(event).await?;
+        }
+
+        Ok(event)
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.market.time()
+    }
+
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, M::Error> {
+        self.market.quote_at(symbol, time).await
+    }
+
+    async fn buy_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.buy_at_market(symbol, quantity).await
+    }
+
+    async fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.sell_at_market(symbol, quantity).await
+    }
+
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, M::Error> {
+        self.market.submit_order(order).await
+    }
+
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), M::Error> {
+        self.market.submit_oco(first, second).await
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), M::Error> {
+        self.market.amend_order(id, amendment).await
+    }
+
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), M::Error> {
+        self.market.cancel_order(id).await
+    }
+
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.market.order_status(id)
+    }
+
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.market.open_orders()
+    }
+
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        self.market.set_tradeable(symbol, tradeable)
+    }
+
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        self.market.is_tradeable(symbol)
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.market.lot_size(symbol)
+    }
+
+    fn market_time(&self) -> MarketTime {
+        self.market.market_time()
+    }
+
+    fn cash(&self) -> Money {
+        self.market.cash()
+    }
+
+    fn settled_cash(&self) -> Money {
+        self.market.settled_cash()
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.market.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.market.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.market.position(symbol)
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.market.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.market.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[Lot] {
+        self.market.lots(symbol)
+    }
+
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.market.closed_lots()
+    }
+
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.market.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, M::Error> {
+        self.market.position_high_water_mark(symbol).await
+    }
+}
@@ -0,0 +1,207 @@
+//! Exchange calendars, used to populate the `system_events` table instead of
+//! curating it by hand.
+
+use chrono::{DateTime, Datelike as _, NaiveDate, NaiveTime, TimeDelta, Utc, Weekday};
+use chrono_tz::America::New_York;
+
+use crate::market::Event;
+#[cfg(feature = "questdb")]
+use {crate::questdb_market::system_event_symbol, chrono::NaiveDateTime};
+
+/// A trading day of an exchange, with the bounds of its sessions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub date: NaiveDate,
+    pub pre_market_start: DateTime<Utc>,
+    pub regular_start: DateTime<Utc>,
+    pub regular_end: DateTime<Utc>,
+    pub post_market_end: DateTime<Utc>,
+}
+
+impl Session {
+    /// Whether the regular session closes before the usual time
+    pub fn is_early_close(&self) -> bool {
+        self.regular_end - self.regular_start < TimeDelta::hours(6)
+    }
+
+    /// The session events of this day, in order
+    pub fn events(&self) -> [(DateTime<Utc>, Event); 4] {
+        [
+            (self.pre_market_start, Event::PreMarketStart),
+            (self.regular_start, Event::RegularMarketStart),
+            (self.regular_end, Event::RegularMarketEnd),
+            (self.post_market_end, Event::PostMarketEnd),
+        ]
+    }
+}
+
+/// Returns the sessions of the US equity exchanges (NYSE and Nasdaq) between
+/// `start` and `end` (inclusive): pre-market from 4:00, regular hours from
+/// 9:30 to 16:00 and post-market until 20:00, New York time.
+///
+/// Full-day holidays and the 13:00 early closes are derived from the
+/// exchanges' rules; unscheduled closures (e.g. national days of mourning)
+/// and trading halts are not known to the calendar.
+pub fn us_equity_sessions(start: NaiveDate, end: NaiveDate) -> Vec<Session> {
+    let new_york_time = |date: NaiveDate, hour: u32, minute: u32| {
+        date.and_hms_opt(hour, minute, 0)
+            .unwrap()
+            .and_local_timezone(New_York)
+            .unwrap()
+            .with_timezone(&Utc)
+    };
+
+    start
+        .iter_days()
+        .take_while(|date| date <= &end)
+        .filter(|date| is_us_equity_trading_day(*date))
+        .map(|date| {
+            let (regular_end_hour, post_market_end_hour) = if is_us_equity_early_close(date) {
+                (13, 17)
+            } else {
+                (16, 20)
+            };
+
+            Session {
+                date,
+                pre_market_start: new_york_time(date, 4, 0),
+                regular_start: new_york_time(date, 9, 30),
+                regular_end: new_york_time(date, regular_end_hour, 0),
+                post_market_end: new_york_time(date, post_market_end_hour, 0),
+            }
+        })
+        .collect()
+}
+
+/// Returns the first time after `after` that is `at` (New York time) on a
+/// trading day of the US equity exchanges, e.g. to advance a daily backtest
+/// to its next decision
+pub fn next_us_equity_trading_time(after: DateTime<Utc>, at: NaiveTime) -> DateTime<Utc> {
+    after
+        .with_timezone(&New_York)
+        .date_naive()
+        .iter_days()
+        .filter(|date| is_us_equity_trading_day(*date))
+        .filter_map(|date| date.and_time(at).and_local_timezone(New_York).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .find(|time| *time > after)
+        .unwrap()
+}
+
+fn is_us_equity_trading_day(date: NaiveDate) -> bool {
+    let year = date.year();
+    let is_weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+
+    // New Year's Day falling on a Saturday is not observed on the Friday
+    // before, since that would close the previous year's last trading day
+    let mut holidays = vec![
+        observed_on_monday(NaiveDate::from_ymd_opt(year, 1, 1).unwrap()),
+        nth_weekday(year, 1, Weekday::Mon, 3),
+        nth_weekday(year, 2, Weekday::Mon, 3),
+        easter_sunday(year) - TimeDelta::days(2),
+        last_weekday(year, 5, Weekday::Mon),
+        observed(NaiveDate::from_ymd_opt(year, 7, 4).unwrap()),
+        nth_weekday(year, 9, Weekday::Mon, 1),
+        nth_weekday(year, 11, Weekday::Thu, 4),
+        observed(NaiveDate::from_ymd_opt(year, 12, 25).unwrap()),
+    ];
+    if year >= 2022 {
+        holidays.push(observed(NaiveDate::from_ymd_opt(year, 6, 19).unwrap()));
+    }
+
+    !is_weekend && !holidays.contains(&date)
+}
+
+fn is_us_equity_early_close(date: NaiveDate) -> bool {
+    let year = date.year();
+    let is_weekday = !matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+
+    let day_before_independence_day = NaiveDate::from_ymd_opt(year, 7, 3).unwrap();
+    let day_after_thanksgiving = nth_weekday(year, 11, Weekday::Thu, 4) + TimeDelta::days(1);
+    let christmas_eve = NaiveDate::from_ymd_opt(year, 12, 24).unwrap();
+
+    is_weekday
+        && is_us_equity_trading_day(date)
+        && (date == day_before_independence_day
+            || date == day_after_thanksgiving
+            || date == christmas_eve)
+}
+
+/// A holiday falling on a weekend is observed on the closest weekday
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - TimeDelta::days(1),
+        Weekday::Sun => date + TimeDelta::days(1),
+        _ => date,
+    }
+}
+
+fn observed_on_monday(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sun => date + TimeDelta::days(1),
+        _ => date,
+    }
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap()
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+        .unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+
+/// The date of (western) Easter, by the anonymous Gregorian algorithm
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+/// Inserts the events of sessions into the `system_events` table, returning
+/// the number of events written.
+///
+/// Only events later than the latest one already in the table are written,
+/// so generating the calendar ahead (e.g. every day) is idempotent.
+#[cfg(feature = "questdb")]
+pub async fn insert_sessions(
+    client: &tokio_postgres::Client,
+    sessions: &[Session],
+) -> Result<u64, tokio_postgres::Error> {
+    let latest = client
+        .query_one("SELECT max(timestamp) FROM system_events;", &[])
+        .await?
+        .get::<_, Option<NaiveDateTime>>(0)
+        .map(|timestamp| timestamp.and_utc());
+
+    let mut written = 0;
+    for (time, event) in sessions.iter().flat_map(Session::events) {
+        if latest.is_some_and(|latest| time <= latest) {
+            continue;
+        }
+
+        let symbol = system_event_symbol(&event).unwrap();
+        written += client
+            .execute(
+                "INSERT INTO system_events VALUES ($1::TEXT, $2::TIMESTAMP);",
+                &[&symbol, &(time.timestamp_micros() as f64)],
+            )
+            .await?;
+    }
+
+    Ok(written)
+}
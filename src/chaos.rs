@@ -0,0 +1,371 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
+use thiserror::Error;
+
+use crate::{
+    account::{ClosedLot, Lot, LotId, Position, Transaction},
+    market::{Event, Market, MarketError, MarketTime, PriceQuote},
+    money::Money,
+    order::{Amendment, OcoGroupId, Order, OrderId, OrderStatus, PendingOrder, TradeReceipt},
+};
+
+/// A failure injected by a `ChaosMarket`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    QueryTimeout,
+    OrderRejected,
+}
+
+#[derive(Error, Debug)]
+pub enum ChaosError<E> {
+    #[error("Injected fault: {0:?}")]
+    Injected(Fault),
+
+    #[error(transparent)]
+    Market(E),
+}
+
+/// Wraps a market, injecting failures (query timeouts, rejected orders,
+/// dropped events and late fill reports) at configurable rates, so strategies
+/// can be hardened against an unreliable broker before going live.
+///
+/// Failures are drawn from a seeded generator, so a chaotic backtest is
+/// reproducible.
+pub struct ChaosMarket<M: Market> {
+    market: M,
+    rng: Mutex<StdRng>,
+
+    query_failure_rate: f64,
+    order_rejection_rate: f64,
+    event_drop_rate: f64,
+    fill_delay_rate: f64,
+    fill_delay: TimeDelta,
+
+    /// Fill events held back, with the time they are reported at
+    delayed_fills: Vec<(DateTime<Utc>, Event)>,
+}
+
+impl<M: Market + Send> ChaosMarket<M> {
+    /// Wraps a market without injecting any failures yet
+    pub fn new(market: M, seed: u64) -> Self {
+        ChaosMarket {
+            market,
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+
+            query_failure_rate: 0.0,
+            order_rejection_rate: 0.0,
+            event_drop_rate: 0.0,
+            fill_delay_rate: 0.0,
+            fill_delay: TimeDelta::zero(),
+
+            delayed_fills: Vec::new(),
+        }
+    }
+
+    /// Fails price queries with `Fault::QueryTimeout` at a rate
+    pub fn with_query_failures(mut self, rate: f64) -> Self {
+        self.query_failure_rate = rate;
+        self
+    }
+
+    /// Rejects orders with `Fault::OrderRejected` at a rate, before they
+    /// reach the market
+    pub fn with_order_rejections(mut self, rate: f64) -> Self {
+        self.order_rejection_rate = rate;
+        self
+    }
+
+    /// Drops events at a rate. The market still processes dropped events
+    /// (e.g. session changes), the algorithm just never sees them. At most
+    /// one event is dropped per call.
+    pub fn with_dropped_events(mut self, rate: f64) -> Self {
+        self.event_drop_rate = rate;
+        self
+    }
+
+    /// Reports fills at a rate only `delay` after they happened. The fill
+    /// itself (cash and holdings) is not delayed.
+    pub fn with_delayed_fills(mut self, rate: f64, delay: TimeDelta) -> Self {
+        self.fill_delay_rate = rate;
+        self.fill_delay = delay;
+        self
+    }
+
+    pub fn into_inner(self) -> M {
+        self.market
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.lock().unwrap().gen_bool(rate.min(1.0))
+    }
+
+    fn fail(&self, rate: f64, fault: Fault) -> Result<(), ChaosError<M::Error>> {
+        if self.roll(rate) {
+            Err(ChaosError::Injected(fault))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Takes a held back fill that is due at the current time
+    fn take_due_fill(&mut self) -> Option<(DateTime<Utc>, Event)> {
+        let time = self.market.time();
+        let due = self
+            .delayed_fills
+            .iter()
+            .position(|(report_time, _)| report_time <= &time)?;
+
+        Some((time, self.delayed_fills.remove(due).1))
+    }
+
+    /// Whether an event is held back (or dropped) instead of being reported
+    fn withholds(&mut self, (time, event): &(DateTime<Utc>, Event), dropped: &mut bool) -> bool {
+        if matches!(event, Event::OrderFilled { .. }) && self.roll(self.fill_delay_rate) {
+            self.delayed_fills
+                .push((*time + self.fill_delay, event.clone()));
+            return true;
+        }
+
+        if !*dropped && self.roll(self.event_drop_rate) {
+            *dropped = true;
+            return true;
+        }
+
+        false
+    }
+}
+
+impl<E: MarketError> MarketError for ChaosError<E> {
+    fn is_insufficient_funds(&self) -> bool {
+        match self {
+            ChaosError::Injected(_) => false,
+            ChaosError::Market(error) => error.is_insufficient_funds(),
+        }
+    }
+}
+
+impl<M: Market + Send> Market for ChaosMarket<M> {
+    type Error = ChaosError<M::Error>;
+
+    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, Self::Error> {
+        let mut dropped = false;
+
+        loop {
+            if let Some(fill) = self.take_due_fill() {
+                return Ok(Some(fill));
+            }
+
+            let Some(event) = self.market.next_event().await.map_err(ChaosError::Market)? else {
+                // Report the held back fills before the end of the events
+                if self.delayed_fills.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some((self.market.time(), self.delayed_fills.remove(0).1)));
+            };
+
+            if !self.withholds(&event, &mut dropped) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    async fn next_event_or_tick(
+        &mut self,
+        tick: TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), Self::Error> {
+        let mut dropped = false;
+
+        loop {
+            if let Some(fill) = self.take_due_fill() {
+                return Ok(fill);
+            }
+
+            let event = self
+                .market
+                .next_event_or_tick(tick)
+                .await
+                .map_err(ChaosError::Market)?;
+
+            if !self.withholds(&event, &mut dropped) {
+                return Ok(event);
+            }
+        }
+    }
+
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, Self::Error> {
+        let mut dropped = false;
+
+        loop {
+            if let Some(fill) = self.take_due_fill() {
+                return Ok(Some(fill));
+            }
+
+            let Some(event) = self
+                .market
+                .next_event_until(until)
+                .await
+                .map_err(ChaosError::Market)?
+            else {
+                // Report the fills held back until the arrival
+                return Ok(self.take_due_fill());
+            };
+
+            if !self.withholds(&event, &mut dropped) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.market.time()
+    }
+
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, Self::Error> {
+        self.fail(self.query_failure_rate, Fault::QueryTimeout)?;
+
+        self.market
+            .quote_at(symbol, time)
+            .await
+            .map_err(ChaosError::Market)
+    }
+
+    async fn buy_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, Self::Error> {
+        self.fail(self.order_rejection_rate, Fault::OrderRejected)?;
+
+        self.market
+            .buy_at_market(symbol, quantity)
+            .await
+            .map_err(ChaosError::Market)
+    }
+
+    async fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, Self::Error> {
+        self.fail(self.order_rejection_rate, Fault::OrderRejected)?;
+
+        self.market
+            .sell_at_market(symbol, quantity)
+            .await
+            .map_err(ChaosError::Market)
+    }
+
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, Self::Error> {
+        self.fail(self.order_rejection_rate, Fault::OrderRejected)?;
+
+        self.market
+            .submit_order(order)
+            .await
+            .map_err(ChaosError::Market)
+    }
+
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), Self::Error> {
+        self.fail(self.order_rejection_rate, Fault::OrderRejected)?;
+
+        self.market
+            .submit_oco(first, second)
+            .await
+            .map_err(ChaosError::Market)
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), Self::Error> {
+        self.fail(self.order_rejection_rate, Fault::OrderRejected)?;
+
+        self.market
+            .amend_order(id, amendment)
+            .await
+            .map_err(ChaosError::Market)
+    }
+
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), Self::Error> {
+        self.market
+            .cancel_order(id)
+            .await
+            .map_err(ChaosError::Market)
+    }
+
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.market.order_status(id)
+    }
+
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.market.open_orders()
+    }
+
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        self.market.set_tradeable(symbol, tradeable)
+    }
+
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        self.market.is_tradeable(symbol)
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.market.lot_size(symbol)
+    }
+
+    fn market_time(&self) -> MarketTime {
+        self.market.market_time()
+    }
+
+    fn cash(&self) -> Money {
+        self.market.cash()
+    }
+
+    fn settled_cash(&self) -> Money {
+        self.market.settled_cash()
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.market.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.market.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.market.position(symbol)
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.market.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.market.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[Lot] {
+        self.market.lots(symbol)
+    }
+
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.market.closed_lots()
+    }
+
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.market.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, Self::Error> {
+        self.market
+            .position_high_water_mark(symbol)
+            .await
+            .map_err(ChaosError::Market)
+    }
+}
@@ -0,0 +1,129 @@
+//! Compares the fills of a strategy running live with the fills of the same
+//! strategy simulated on recorded data, to quantify how realistic the
+//! simulation is.
+
+use chrono::TimeDelta;
+
+use crate::export::Trade;
+
+/// How far a simulated fill may be from its live counterpart before it is
+/// considered divergent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerances {
+    /// As a fraction of the simulated price
+    pub relative_price: f64,
+    pub timing: TimeDelta,
+    /// How far apart two fills may be to be considered the same one at all
+    pub match_window: TimeDelta,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Tolerances {
+            relative_price: 0.001,
+            timing: TimeDelta::seconds(5),
+            match_window: TimeDelta::minutes(5),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Divergence {
+    /// A live fill that was not simulated
+    Unsimulated(Trade),
+    /// A simulated fill that did not happen live
+    Unfilled(Trade),
+    Price {
+        live: Trade,
+        simulated: Trade,
+    },
+    Quantity {
+        live: Trade,
+        simulated: Trade,
+    },
+    Timing {
+        live: Trade,
+        simulated: Trade,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DivergenceReport {
+    pub divergences: Vec<Divergence>,
+    /// How many live fills had a simulated counterpart
+    pub matched: usize,
+    /// The mean of `live / simulated - 1` over the matched fills' prices,
+    /// positive when live purchases cost more than simulated
+    pub mean_relative_price_difference: f64,
+}
+
+/// Pairs every live fill with the closest unpaired simulated fill of the same
+/// symbol and side within the match window, and reports how the pairs (and
+/// the fills left unpaired) diverge.
+pub fn compare_fills(
+    live: &[Trade],
+    simulated: &[Trade],
+    tolerances: Tolerances,
+) -> DivergenceReport {
+    let mut paired = vec![false; simulated.len()];
+    let mut report = DivergenceReport::default();
+    let mut price_difference_sum = 0.0;
+
+    for live_fill in live {
+        let counterpart = simulated
+            .iter()
+            .enumerate()
+            .filter(|(index, simulated_fill)| {
+                !paired[*index]
+                    && simulated_fill.symbol == live_fill.symbol
+                    && simulated_fill.quantity.signum() == live_fill.quantity.signum()
+                    && (simulated_fill.time - live_fill.time).abs() <= tolerances.match_window
+            })
+            .min_by_key(|(_, simulated_fill)| (simulated_fill.time - live_fill.time).abs());
+
+        let Some((index, simulated_fill)) = counterpart else {
+            report
+                .divergences
+                .push(Divergence::Unsimulated(live_fill.clone()));
+            continue;
+        };
+        paired[index] = true;
+        report.matched += 1;
+
+        let relative_price_difference = live_fill.price / simulated_fill.price - 1.0;
+        price_difference_sum += relative_price_difference;
+
+        let pair = || (live_fill.clone(), simulated_fill.clone());
+        if relative_price_difference.abs() > tolerances.relative_price {
+            let (live, simulated) = pair();
+            report
+                .divergences
+                .push(Divergence::Price { live, simulated });
+        }
+        if live_fill.quantity != simulated_fill.quantity {
+            let (live, simulated) = pair();
+            report
+                .divergences
+                .push(Divergence::Quantity { live, simulated });
+        }
+        if (live_fill.time - simulated_fill.time).abs() > tolerances.timing {
+            let (live, simulated) = pair();
+            report
+                .divergences
+                .push(Divergence::Timing { live, simulated });
+        }
+    }
+
+    report.divergences.extend(
+        simulated
+            .iter()
+            .zip(paired)
+            .filter(|(_, paired)| !paired)
+            .map(|(simulated_fill, _)| Divergence::Unfilled(simulated_fill.clone())),
+    );
+    if report.matched > 0 {
+        report.mean_relative_price_difference = price_difference_sum / report.matched as f64;
+    }
+
+    report
+}
@@ -0,0 +1,277 @@
+//! The domain model of markets: events, sessions, quotes and candles, and
+//! (in `order`) orders and their life cycle. Unlike `market`, these depend on
+//! neither async code nor a database, so they can be reused without them.
+
+use std::ops::BitOr;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use thiserror::Error;
+
+use crate::{
+    money::Money,
+    order::{CancelReason, OrderId, OrderKind, Side},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Tick,
+    PreMarketStart,
+    RegularMarketStart,
+    RegularMarketEnd,
+    PostMarketEnd,
+    /// An equity is reporting its earnings
+    Earnings {
+        symbol: String,
+    },
+    /// A scheduled macroeconomic announcement (e.g. FOMC, CPI) is coming up
+    /// at `time`
+    Macro {
+        name: String,
+        importance: Importance,
+        time: DateTime<Utc>,
+    },
+    /// A bar of an equity's trades has closed
+    BarClosed {
+        symbol: String,
+        interval: TimeDelta,
+        candle: Candle,
+    },
+    /// A resting (or queued market) order was executed completely
+    OrderFilled {
+        id: OrderId,
+        symbol: String,
+        side: Side,
+        quantity: f64,
+        price: f64,
+    },
+    /// Part of an order was executed, and `remaining` shares of it were not
+    /// (see `VolumeLimit`)
+    OrderPartiallyFilled {
+        id: OrderId,
+        symbol: String,
+        side: Side,
+        quantity: f64,
+        remaining: f64,
+        price: f64,
+    },
+    /// A resting order was changed in place
+    OrderAmended {
+        id: OrderId,
+        quantity: f64,
+        kind: OrderKind,
+    },
+    /// A resting order was canceled
+    OrderCanceled {
+        id: OrderId,
+        symbol: String,
+        side: Side,
+        quantity: f64,
+        reason: CancelReason,
+    },
+    /// A bar of an equity matched a rule of the scanner (see
+    /// `scanner::Scanner`)
+    ScannerHit {
+        symbol: String,
+        rule: String,
+    },
+    /// A perpetual swap's funding was settled at `rate`, moving `amount` of
+    /// cash into the account (negative when the position paid it). Reported
+    /// for every perpetual with a funding rate, with no amount where none
+    /// is held.
+    FundingPayment {
+        symbol: String,
+        rate: f64,
+        amount: Money,
+    },
+}
+
+/// A set of kinds of events, e.g. `EventMask::SESSIONS | EventMask::FILLS`
+/// for only the session events and the fills of orders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EventMask(u16);
+
+impl EventMask {
+    pub const NONE: EventMask = EventMask(0);
+    pub const TICKS: EventMask = EventMask(1 << 0);
+    /// The starts and ends of the market's sessions
+    pub const SESSIONS: EventMask = EventMask(1 << 1);
+    pub const EARNINGS: EventMask = EventMask(1 << 2);
+    pub const MACRO: EventMask = EventMask(1 << 3);
+    pub const BARS: EventMask = EventMask(1 << 4);
+    /// Complete and partial fills of orders
+    pub const FILLS: EventMask = EventMask(1 << 5);
+    /// Amendments and cancellations of orders
+    pub const ORDER_UPDATES: EventMask = EventMask(1 << 6);
+    pub const SCANNER_HITS: EventMask = EventMask(1 << 7);
+    pub const FUNDING: EventMask = EventMask(1 << 8);
+    pub const ALL: EventMask = EventMask(u16::MAX);
+
+    /// The kind of an event
+    pub fn of(event: &Event) -> EventMask {
+        match event {
+            Event::Tick => EventMask::TICKS,
+            Event::PreMarketStart
+            | Event::RegularMarketStart
+            | Event::RegularMarketEnd
+            | Event::PostMarketEnd => EventMask::SESSIONS,
+            Event::Earnings { .. } => EventMask::EARNINGS,
+            Event::Macro { .. } => EventMask::MACRO,
+            Event::BarClosed { .. } => EventMask::BARS,
+            Event::OrderFilled { .. } | Event::OrderPartiallyFilled { .. } => EventMask::FILLS,
+            Event::OrderAmended { .. } | Event::OrderCanceled { .. } => EventMask::ORDER_UPDATES,
+            Event::ScannerHit { .. } => EventMask::SCANNER_HITS,
+            Event::FundingPayment { .. } => EventMask::FUNDING,
+        }
+    }
+
+    pub fn contains(&self, other: EventMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        self.contains(EventMask::of(event))
+    }
+}
+
+impl Default for EventMask {
+    fn default() -> Self {
+        EventMask::ALL
+    }
+}
+
+impl BitOr for EventMask {
+    type Output = EventMask;
+
+    fn bitor(self, other: EventMask) -> EventMask {
+        EventMask(self.0 | other.0)
+    }
+}
+
+/// How much a macroeconomic announcement is expected to move the market
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Importance {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MarketTime {
+    NotTrading,
+    PreMarket,
+    Regular,
+    PostMarket,
+    #[default]
+    Unknown,
+}
+
+#[derive(Error, Debug)]
+pub enum ImpossibleEvent {
+    #[error("{event:?} reported during {market_time:?} market time")]
+    MarketTimeSkip {
+        event: Event,
+        market_time: MarketTime,
+    },
+}
+
+macro_rules! update_market_time {
+    ($self:ident, $event:ident, $current_state:expr, $next_state:expr) => {
+        if $self == &$current_state || $self == &MarketTime::Unknown {
+            *$self = $next_state;
+            Ok(())
+        } else {
+            Err(ImpossibleEvent::MarketTimeSkip {
+                event: $event.clone(),
+                market_time: $self.clone(),
+            })
+        }
+    };
+}
+
+impl MarketTime {
+    pub fn update(&mut self, event: &Event) -> Result<(), ImpossibleEvent> {
+        match event {
+            Event::PreMarketStart => {
+                update_market_time!(self, event, MarketTime::NotTrading, MarketTime::PreMarket)
+            }
+            Event::RegularMarketStart => {
+                update_market_time!(self, event, MarketTime::PreMarket, MarketTime::Regular)
+            }
+            Event::RegularMarketEnd => {
+                update_market_time!(self, event, MarketTime::Regular, MarketTime::PostMarket)
+            }
+            Event::PostMarketEnd => {
+                update_market_time!(self, event, MarketTime::PostMarket, MarketTime::NotTrading)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Determines if the market is currently open.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the market is open (Pre-Market, Regular, or Post-Market)
+    /// * `false` if the market is closed (any other state)
+    pub fn is_open(&self) -> bool {
+        self == &MarketTime::PreMarket
+            || self == &MarketTime::Regular
+            || self == &MarketTime::PostMarket
+    }
+}
+
+/// A price along with the time it was recorded at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceQuote {
+    pub price: f64,
+    /// When the price was recorded, which may be long before the time it
+    /// was queried for (e.g. outside of trading hours)
+    pub as_of: DateTime<Utc>,
+}
+
+/// What the price of an equity is derived from
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PriceSource {
+    /// The last trade
+    #[default]
+    Trade,
+    /// The middle of the latest bid and ask
+    Midpoint,
+    /// A weighted average of the two, e.g. 0.5 for the same weight on both
+    Blend { trade_weight: f64 },
+}
+
+impl PriceSource {
+    /// Derives a quote from the last trade and the middle of the latest bid
+    /// and ask, falling back on whichever of them is known. Blends are as old
+    /// as the older of the two.
+    pub fn quote(
+        &self,
+        trade: Option<PriceQuote>,
+        midpoint: Option<PriceQuote>,
+    ) -> Option<PriceQuote> {
+        match (self, trade, midpoint) {
+            (PriceSource::Trade, Some(trade), _) => Some(trade),
+            (PriceSource::Midpoint, _, Some(midpoint)) => Some(midpoint),
+            (PriceSource::Blend { trade_weight }, Some(trade), Some(midpoint)) => {
+                Some(PriceQuote {
+                    price: trade_weight * trade.price + (1.0 - trade_weight) * midpoint.price,
+                    as_of: trade.as_of.min(midpoint.as_of),
+                })
+            }
+            (_, trade, midpoint) => trade.or(midpoint),
+        }
+    }
+}
+
+/// The aggregated trades of an equity over an interval
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candle {
+    /// The start of the interval
+    pub start: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
@@ -0,0 +1,128 @@
+use chrono::{DateTime, DurationRound as _, NaiveDateTime, TimeDelta, Utc};
+use tokio::try_join;
+
+/// A resolution of persisted bar tables, aggregated from raw trades
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resolution {
+    OneSecond,
+    OneMinute,
+    FiveMinutes,
+    OneDay,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 4] = [
+        Resolution::OneSecond,
+        Resolution::OneMinute,
+        Resolution::FiveMinutes,
+        Resolution::OneDay,
+    ];
+
+    pub fn duration(&self) -> TimeDelta {
+        match self {
+            Resolution::OneSecond => TimeDelta::seconds(1),
+            Resolution::OneMinute => TimeDelta::minutes(1),
+            Resolution::FiveMinutes => TimeDelta::minutes(5),
+            Resolution::OneDay => TimeDelta::days(1),
+        }
+    }
+
+    /// The latest start of a bar of this resolution that is complete at
+    /// `time`. Bars are stamped with their start, so a bar stamped later
+    /// still contains trades after `time`.
+    pub fn last_complete_start(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        time - self.duration()
+    }
+
+    /// The table bars of this resolution are persisted in. It has the same
+    /// columns as the `prices` table.
+    pub fn table(&self) -> &'static str {
+        match self {
+            Resolution::OneSecond => "prices_1s",
+            Resolution::OneMinute => "prices_1m",
+            Resolution::FiveMinutes => "prices_5m",
+            Resolution::OneDay => "prices_1d",
+        }
+    }
+}
+
+/// Formats a duration as a QuestDB `SAMPLE BY` interval (e.g. `5m`)
+pub(crate) fn sample_by_interval(interval: TimeDelta) -> String {
+    let seconds = interval.num_seconds();
+
+    if seconds % 86_400 == 0 {
+        format!("{}d", seconds / 86_400)
+    } else if seconds % 3_600 == 0 {
+        format!("{}h", seconds / 3_600)
+    } else if seconds % 60 == 0 {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Aggregates a raw trades table (with `symbol`, `price`, `size` and
+/// `timestamp` columns) into the bar table of a resolution, returning the
+/// number of bars written.
+///
+/// Only bars that are already complete and newer than the latest persisted
+/// bar are written, so running this repeatedly (e.g. as new trades are
+/// ingested) is both incremental and idempotent.
+pub async fn downsample(
+    client: &tokio_postgres::Client,
+    trades_table: &str,
+    resolution: Resolution,
+) -> Result<u64, tokio_postgres::Error> {
+    let bar_table = resolution.table();
+    let interval = resolution.duration();
+
+    client
+        .batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {bar_table} (symbol SYMBOL, open DOUBLE, high DOUBLE, low DOUBLE, close DOUBLE, volume DOUBLE, timestamp TIMESTAMP) timestamp(timestamp) PARTITION BY DAY;"
+        ))
+        .await?;
+
+    let (last_bar, last_trade) = try_join!(
+        latest_timestamp(client, bar_table),
+        latest_timestamp(client, trades_table)
+    )?;
+    let Some(last_trade) = last_trade else {
+        return Ok(0);
+    };
+
+    // The bar the latest trade falls in may still be incomplete
+    let end = last_trade.duration_trunc(interval).unwrap();
+    let start = match last_bar {
+        Some(last_bar) => last_bar + interval,
+        None => DateTime::UNIX_EPOCH,
+    };
+    if start >= end {
+        return Ok(0);
+    }
+
+    client
+        .execute(
+            &format!(
+                "INSERT INTO {bar_table} SELECT symbol, first(price) open, max(price) high, min(price) low, last(price) close, sum(size) volume, timestamp FROM {trades_table} WHERE timestamp >= $1::TIMESTAMP AND timestamp < $2::TIMESTAMP SAMPLE BY {} ALIGN TO CALENDAR;",
+                sample_by_interval(interval)
+            ),
+            &[
+                &(start.timestamp_micros() as f64),
+                &(end.timestamp_micros() as f64),
+            ],
+        )
+        .await
+}
+
+async fn latest_timestamp(
+    client: &tokio_postgres::Client,
+    table: &str,
+) -> Result<Option<DateTime<Utc>>, tokio_postgres::Error> {
+    let row = client
+        .query_one(&format!("SELECT max(timestamp) FROM {table};"), &[])
+        .await?;
+
+    Ok(row
+        .get::<_, Option<NaiveDateTime>>(0)
+        .map(|timestamp| timestamp.and_utc()))
+}
@@ -0,0 +1,150 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use thiserror::Error;
+use tokio_postgres::NoTls;
+
+#[cfg(feature = "analytics")]
+use crate::{
+    ensemble::{run_ensemble, EnsembleReport, RunMetrics},
+    export::RecordingMarket,
+    fill::SeededRandom,
+};
+use crate::{money::Money, questdb_market::QuestDbMarket, Algorithm};
+
+/// Where an algorithm trades
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mode {
+    /// Replays the database's history from `start`, with `cash` to trade
+    Backtest { start: DateTime<Utc>, cash: Money },
+    /// Like `Backtest`, but advances once per trading day at `at` (New York
+    /// time) on daily bars, for long backtests of daily strategies
+    DailyBacktest {
+        start: DateTime<Utc>,
+        cash: Money,
+        at: NaiveTime,
+    },
+    /// Trades against a live broker
+    Live,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    /// A `tokio_postgres` connection string of the QuestDB instance
+    pub database: String,
+    pub mode: Mode,
+}
+
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("Cannot connect to the database")]
+    Connection(#[source] tokio_postgres::Error),
+
+    #[error("Market error")]
+    Market(#[from] crate::questdb_market::Error),
+
+    #[error("Live trading is not available yet")]
+    LiveTradingUnavailable,
+}
+
+/// Runs algorithms against the market selected by a configuration, so the
+/// same algorithm code runs in every mode
+pub struct Engine;
+
+impl Engine {
+    pub async fn run<A: Algorithm>(
+        config: &EngineConfig,
+        algorithm: &mut A,
+    ) -> Result<(), EngineError> {
+        let backtest = backtest(config)?;
+        let client = connect(config).await?;
+
+        let mut market = backtest.market(&client).await?;
+        algorithm.run(&mut market).await?;
+
+        Ok(())
+    }
+
+    /// Backtests fresh algorithms once per seed, with market orders filling
+    /// at random prices within their bars drawn from the seed, and reports
+    /// the distributions of the runs' metrics
+    #[cfg(feature = "analytics")]
+    pub async fn run_ensemble<A: Algorithm>(
+        config: &EngineConfig,
+        seeds: impl IntoIterator<Item = u64>,
+        mut algorithm: impl FnMut() -> A,
+    ) -> Result<EnsembleReport, EngineError> {
+        let backtest = backtest(config)?;
+        let client = connect(config).await?;
+        let client = &client;
+
+        run_ensemble(seeds, |seed| {
+            let mut algorithm = algorithm();
+            async move {
+                let market = backtest
+                    .market(client)
+                    .await?
+                    .with_fill_model(SeededRandom::new(seed));
+                let mut market = RecordingMarket::new(market);
+                algorithm.run(&mut market).await?;
+
+                Ok(RunMetrics::of(seed, &market))
+            }
+        })
+        .await
+    }
+}
+
+/// The parameters of a backtest configuration
+#[derive(Clone, Copy)]
+struct Backtest {
+    start: DateTime<Utc>,
+    cash: Money,
+    /// The time of day of daily backtests
+    daily: Option<NaiveTime>,
+}
+
+impl Backtest {
+    async fn market<'a>(
+        &self,
+        client: &'a tokio_postgres::Client,
+    ) -> Result<QuestDbMarket<'a>, EngineError> {
+        let market = QuestDbMarket::new(client, self.start, self.cash).await?;
+
+        Ok(match self.daily {
+            Some(at) => market.with_daily_resolution(at).await?,
+            None => market,
+        })
+    }
+}
+
+fn backtest(config: &EngineConfig) -> Result<Backtest, EngineError> {
+    match config.mode {
+        Mode::Backtest { start, cash } => Ok(Backtest {
+            start,
+            cash,
+            daily: None,
+        }),
+        Mode::DailyBacktest { start, cash, at } => Ok(Backtest {
+            start,
+            cash,
+            daily: Some(at),
+        }),
+        // TODO wire a live market once a broker adapter exists
+        Mode::Live => Err(EngineError::LiveTradingUnavailable),
+    }
+}
+
+async fn connect(config: &EngineConfig) -> Result<tokio_postgres::Client, EngineError> {
+    let (client, connection) = tokio_postgres::connect(&config.database, NoTls)
+        .await
+        .map_err(EngineError::Connection)?;
+
+    // The connection object performs the actual communication with the
+    // database, so spawn it off to run on its own.
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::error!("connection error: {}", e);
+        }
+    });
+
+    Ok(client)
+}
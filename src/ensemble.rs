@@ -0,0 +1,142 @@
+//! Repeated runs of the same backtest under different seeds, summarized as
+//! distributions of their metrics rather than single point estimates, since
+//! stochastic fills make any one run a sample.
+
+use std::future::Future;
+
+use crate::{export::RecordingMarket, market::Market, money::Money};
+
+/// The results of one run
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunMetrics {
+    pub seed: u64,
+    pub final_net_worth: Money,
+    /// Relative to the first recorded net worth
+    pub total_return: f64,
+    /// The largest fall from a peak of the equity curve, as a fraction of
+    /// the peak
+    pub max_drawdown: f64,
+    pub trades: usize,
+}
+
+impl RunMetrics {
+    /// The metrics of a recorded run, or `None` if it recorded no events
+    pub fn of<M: Market + Send>(seed: u64, market: &RecordingMarket<M>) -> Option<Self> {
+        let equity_curve = market.equity_curve();
+        let (_, first) = equity_curve.first()?;
+        let (_, last) = equity_curve.last()?;
+
+        let mut peak = Money::ZERO;
+        let mut max_drawdown: f64 = 0.0;
+        for (_, net_worth) in equity_curve {
+            peak = peak.max(*net_worth);
+            if peak > Money::ZERO {
+                max_drawdown = max_drawdown.max(1.0 - net_worth.to_f64() / peak.to_f64());
+            }
+        }
+
+        Some(RunMetrics {
+            seed,
+            final_net_worth: *last,
+            total_return: last.to_f64() / first.to_f64() - 1.0,
+            max_drawdown,
+            trades: market.trades().len(),
+        })
+    }
+}
+
+/// A summary of the values a metric took across runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Distribution {
+    pub mean: f64,
+    pub standard_deviation: f64,
+    pub min: f64,
+    /// The 5th percentile
+    pub p5: f64,
+    pub median: f64,
+    /// The 95th percentile
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl Distribution {
+    /// Summarizes values, or returns `None` if there are none
+    pub fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let count = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / count;
+        let variance = sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count;
+
+        Some(Distribution {
+            mean,
+            standard_deviation: variance.sqrt(),
+            min: sorted[0],
+            p5: percentile(&sorted, 0.05),
+            median: percentile(&sorted, 0.5),
+            p95: percentile(&sorted, 0.95),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// Linearly interpolates between the closest ranks of sorted values
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let rank = fraction * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+/// The metrics of every run of an ensemble
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EnsembleReport {
+    pub runs: Vec<RunMetrics>,
+}
+
+impl EnsembleReport {
+    pub fn final_net_worth(&self) -> Option<Distribution> {
+        self.distribution(|run| run.final_net_worth.to_f64())
+    }
+
+    pub fn total_return(&self) -> Option<Distribution> {
+        self.distribution(|run| run.total_return)
+    }
+
+    pub fn max_drawdown(&self) -> Option<Distribution> {
+        self.distribution(|run| run.max_drawdown)
+    }
+
+    pub fn trades(&self) -> Option<Distribution> {
+        self.distribution(|run| run.trades as f64)
+    }
+
+    fn distribution(&self, metric: impl Fn(&RunMetrics) -> f64) -> Option<Distribution> {
+        let values: Vec<_> = self.runs.iter().map(metric).collect();
+        Distribution::of(&values)
+    }
+}
+
+/// Runs a backtest once per seed, one after the other. Runs that recorded
+/// nothing are left out of the report.
+pub async fn run_ensemble<F, Fut, E>(
+    seeds: impl IntoIterator<Item = u64>,
+    mut run: F,
+) -> Result<EnsembleReport, E>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<Option<RunMetrics>, E>>,
+{
+    let mut report = EnsembleReport::default();
+    for seed in seeds {
+        if let Some(metrics) = run(seed).await? {
+            report.runs.push(metrics);
+        }
+    }
+
+    Ok(report)
+}
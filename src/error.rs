@@ -0,0 +1,110 @@
+//! The error of the simulated markets, shared by the QuestDB market and the
+//! test market (which runs without a database).
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::{
+    account::AccountError,
+    instrument::RoundingError,
+    market::{ImpossibleEvent, MarketError, MarketTime},
+    order::OrderId,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[cfg(feature = "questdb")]
+    #[error("PostgreSQL error")]
+    DatabaseError(#[from] tokio_postgres::Error),
+
+    #[error("Attempted to trade {0} at {1}, outside of trading hours")]
+    UntimelyTrade(String, DateTime<Utc>),
+
+    #[error("Attempted to trade {0} during {1:?}, which the session policy does not allow")]
+    OutsideSession(String, MarketTime),
+
+    #[error("Attempted to trade {0} while trading in it is disabled")]
+    UntradeableSymbol(String),
+
+    #[error("Attempted to trade {quantity} of {symbol}, which is not a positive quantity")]
+    InvalidQuantity { symbol: String, quantity: f64 },
+
+    #[error(
+        "Attempted to buy {symbol} during the blackout before its earnings report at {report_time}"
+    )]
+    EarningsBlackout {
+        symbol: String,
+        report_time: DateTime<Utc>,
+    },
+
+    #[error("Attempted to trade {0} yet the price is unknown")]
+    UnknownPrice(String),
+
+    #[error(transparent)]
+    Account(#[from] AccountError),
+
+    #[error(
+        "Symbol '{symbol}' found in database, which is not of the expected kind, {expected_kind}"
+    )]
+    UnexpectedDatabaseSymbol {
+        symbol: String,
+        expected_kind: String,
+    },
+
+    #[error("Order {0:?} is not open")]
+    OrderNotOpen(OrderId),
+
+    #[error("Order {0:?} has no such price to amend")]
+    InvalidAmendment(OrderId),
+
+    #[error("Order does not match the instrument's specification")]
+    MisalignedOrder(#[from] RoundingError),
+
+    #[error("Impossible event, internal logic fault")]
+    ImpossibleEvent(#[from] ImpossibleEvent),
+
+    #[error("The last price of {symbol} is from {as_of}, too long before {time}")]
+    StalePrice {
+        symbol: String,
+        as_of: DateTime<Utc>,
+        time: DateTime<Utc>,
+    },
+
+    #[error("Tried to query data from {future_time} at {current_time}")]
+    FutureQuery {
+        future_time: DateTime<Utc>,
+        current_time: DateTime<Utc>,
+    },
+
+    #[error("'{0}' is not a valid table name")]
+    InvalidTableName(String),
+
+    #[error("Cannot limit the tables of query '{0}' to the current virtual time")]
+    UnboundedQuery(String),
+}
+
+impl MarketError for Error {
+    fn is_insufficient_funds(&self) -> bool {
+        matches!(
+            self,
+            Error::Account(
+                AccountError::InsufficientCash { .. } | AccountError::UnsettledFunds { .. }
+            )
+        )
+    }
+}
+
+/// Ensures the quantity of an order is a positive number, before it is
+/// rounded to the instrument, so negative quantities cannot reverse a trade
+// Only the test market checks quantities without QuestDB
+#[cfg_attr(not(feature = "questdb"), allow(dead_code))]
+pub(crate) fn check_quantity(symbol: &str, quantity: f64) -> Result<(), Error> {
+    if quantity.is_finite() && quantity > 0.0 {
+        return Ok(());
+    }
+
+    Err(Error::InvalidQuantity {
+        symbol: symbol.to_string(),
+        quantity,
+    })
+}
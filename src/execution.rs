@@ -0,0 +1,178 @@
+//! A simulated level-2 order book for backtests on tick-level data. Resting
+//! limit orders join the back of the queue at their price, behind synthetic
+//! depth, and only fill once the volume traded at their price worked through
+//! the queue ahead of them, rather than as soon as their price is touched.
+
+use std::collections::HashMap;
+
+use crate::order::{OrderId, PendingOrder, Side};
+
+/// The shape of the synthetic depth around the last price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SyntheticDepth {
+    /// The distance between price levels
+    pub tick_size: f64,
+    /// The quantity resting at the level next to the last price
+    pub top: f64,
+    /// How much the quantity grows with every further level, as a fraction
+    /// of `top`
+    pub growth: f64,
+}
+
+/// The resting quantity at a price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    pub quantity: f64,
+}
+
+/// A snapshot of a book, best levels first
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderBook {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+impl SyntheticDepth {
+    /// The book around `reference` (e.g. the last trade price), `levels`
+    /// deep on each side
+    pub fn book(&self, reference: f64, levels: usize) -> OrderBook {
+        let level = |side: Side, n: usize| {
+            let offset = self.tick_size * n as f64;
+            let price = match side {
+                Side::Buy => reference - offset,
+                Side::Sell => reference + offset,
+            };
+
+            Level {
+                price,
+                quantity: self.quantity_at(side, price, reference),
+            }
+        };
+
+        OrderBook {
+            bids: (1..=levels).map(|n| level(Side::Buy, n)).collect(),
+            asks: (1..=levels).map(|n| level(Side::Sell, n)).collect(),
+        }
+    }
+
+    /// The quantity resting on a side of the book at a price. Nothing rests
+    /// at prices that improve on the last one.
+    pub fn quantity_at(&self, side: Side, price: f64, reference: f64) -> f64 {
+        let distance = match side {
+            Side::Buy => reference - price,
+            Side::Sell => price - reference,
+        };
+        let level = (distance / self.tick_size).round();
+        if level < 1.0 {
+            return 0.0;
+        }
+
+        self.top * (1.0 + self.growth * (level - 1.0))
+    }
+
+    /// The share of a bar's volume that traded at any one price, assuming it
+    /// spread evenly over the levels between its low and its high
+    fn volume_per_level(&self, low: f64, high: f64, volume: f64) -> f64 {
+        let levels = ((high - low) / self.tick_size).round().max(0.0) + 1.0;
+        volume / levels
+    }
+}
+
+/// Tracks the queue ahead of every resting limit order
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderBookSimulator {
+    depth: SyntheticDepth,
+    /// The quantity ahead of each order at its price
+    queues: HashMap<OrderId, f64>,
+}
+
+impl OrderBookSimulator {
+    pub fn new(depth: SyntheticDepth) -> Self {
+        OrderBookSimulator {
+            depth,
+            queues: HashMap::new(),
+        }
+    }
+
+    pub fn depth(&self) -> &SyntheticDepth {
+        &self.depth
+    }
+
+    /// The quantity ahead of an order in its queue, if it joined one
+    pub fn queue_ahead(&self, id: OrderId) -> Option<f64> {
+        self.queues.get(&id).copied()
+    }
+
+    /// Places an order at the back of the queue at its limit price, behind
+    /// the depth resting there when the last price is `reference`. Orders
+    /// without a limit price do not queue.
+    pub fn join(&mut self, pending: &PendingOrder, reference: f64) {
+        if let Some(limit_price) = pending.order.limit_price() {
+            let ahead = self
+                .depth
+                .quantity_at(pending.order.side, limit_price, reference);
+            self.queues.insert(pending.id, ahead);
+        }
+    }
+
+    /// Removes an order from its queue, once it is filled or canceled, or
+    /// loses its place by being amended
+    pub fn leave(&mut self, id: OrderId) {
+        self.queues.remove(&id);
+    }
+
+    /// Puts an order back in its queue behind `ahead` shares, e.g. once the
+    /// amendment that took it out of the queue was rejected
+    pub fn rejoin(&mut self, id: OrderId, ahead: f64) {
+        self.queues.insert(id, ahead);
+    }
+
+    /// How many shares of a limit order that trades of `volume` between
+    /// `low` and `high` reached fill. Trades through its price fill all of
+    /// them; trades at its price first work through the queue ahead, in
+    /// whole lots of `lot_size` shares. Orders that did not join a queue yet
+    /// join it behind the depth at `close`.
+    pub fn on_trades(
+        &mut self,
+        pending: &PendingOrder,
+        low: f64,
+        high: f64,
+        close: f64,
+        volume: f64,
+        lot_size: f64,
+    ) -> f64 {
+        let (side, quantity) = (pending.order.side, pending.order.quantity);
+        let Some(limit_price) = pending.order.limit_price() else {
+            return quantity;
+        };
+
+        let depth = self.depth;
+        let ahead = self
+            .queues
+            .entry(pending.id)
+            .or_insert_with(|| depth.quantity_at(side, limit_price, close));
+
+        let half_tick = depth.tick_size / 2.0;
+        let traded_through = match side {
+            Side::Buy => low < limit_price - half_tick,
+            Side::Sell => high > limit_price + half_tick,
+        };
+        if traded_through {
+            *ahead = 0.0;
+            return quantity;
+        }
+
+        let traded = depth.volume_per_level(low, high, volume);
+        let left = traded - *ahead;
+        *ahead = (*ahead - traded).max(0.0);
+
+        if left <= 0.0 {
+            0.0
+        } else {
+            // Without losing a lot to the rounding error of the division
+            let lots = (left / lot_size + 1e-9).floor();
+            (lots * lot_size).min(quantity)
+        }
+    }
+}
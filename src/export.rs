@@ -0,0 +1,595 @@
+//! Exporters of backtest results to the formats of common analysis tools,
+//! such as QuantStats and pyfolio.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, io,
+};
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    account::{self, ClosedLot, LotId, Position, Transaction},
+    market::{Event, Market, MarketTime, PriceQuote},
+    money::Money,
+    order::{Amendment, OcoGroupId, Order, OrderId, OrderStatus, PendingOrder, Side, TradeReceipt},
+};
+
+/// An executed trade. Sales have a negative quantity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trade {
+    pub time: DateTime<Utc>,
+    pub symbol: String,
+    pub quantity: f64,
+    /// The average price per share
+    pub price: f64,
+    /// The order the trade filled, if it is known
+    pub order: Option<OrderId>,
+    /// Why the strategy placed the order, if it recorded it
+    pub reason: Option<TradeReason>,
+}
+
+/// Why a strategy placed an order: the rule that triggered and the signal
+/// values it saw, so every trade can be explained after the fact
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradeReason {
+    pub rule: String,
+    pub signals: Vec<(String, f64)>,
+}
+
+impl TradeReason {
+    pub fn new(rule: &str) -> Self {
+        TradeReason {
+            rule: rule.to_string(),
+            signals: Vec::new(),
+        }
+    }
+
+    pub fn with_signal(mut self, name: &str, value: f64) -> Self {
+        self.signals.push((name.to_string(), value));
+        self
+    }
+}
+
+/// E.g. `breakout (close=10.5; high=10.2)`, without commas so it fits in a
+/// CSV field
+impl fmt::Display for TradeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rule)?;
+        if self.signals.is_empty() {
+            return Ok(());
+        }
+
+        let signals: Vec<_> = self
+            .signals
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        write!(f, " ({})", signals.join("; "))
+    }
+}
+
+/// Records the trades and the net worth after every event of a market
+pub struct RecordingMarket<M: Market> {
+    market: M,
+    trades: Vec<Trade>,
+    equity_curve: Vec<(DateTime<Utc>, Money)>,
+    /// The journal of why orders were placed
+    reasons: HashMap<OrderId, TradeReason>,
+}
+
+impl<M: Market> RecordingMarket<M> {
+    pub fn new(market: M) -> Self {
+        RecordingMarket {
+            market,
+            trades: Vec::new(),
+            equity_curve: Vec::new(),
+            reasons: HashMap::new(),
+        }
+    }
+
+    /// Records why an order was placed, attaching the reason to its trades,
+    /// including those it filled already
+    pub fn explain(&mut self, id: OrderId, reason: TradeReason) {
+        for trade in &mut self.trades {
+            if trade.order == Some(id) {
+                trade.reason = Some(reason.clone());
+            }
+        }
+        self.reasons.insert(id, reason);
+    }
+
+    pub fn reason(&self, id: OrderId) -> Option<&TradeReason> {
+        self.reasons.get(&id)
+    }
+
+    pub fn trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    /// The net worth after every event
+    pub fn equity_curve(&self) -> &[(DateTime<Utc>, Money)] {
+        &self.equity_curve
+    }
+
+    pub fn into_inner(self) -> M {
+        self.market
+    }
+
+    async fn record_equity(&mut self) -> Result<(), M::Error> {
+        let net_worth = self.market.net_worth().await?;
+        self.equity_curve.push((self.market.time(), net_worth));
+
+        Ok(())
+    }
+
+    /// Records the trade of a market order that was filled right away. Fills
+    /// that happen later are recorded from their events.
+    fn record_trade(&mut self, receipt: &TradeReceipt, quantity: f64, cash_before: Money) {
+        if receipt.fill_price.is_some() && quantity != 0.0 {
+            let price = (cash_before - self.market.cash()).to_f64() / quantity;
+            self.trades.push(Trade {
+                time: receipt.timestamp,
+                symbol: receipt.symbol.clone(),
+                quantity,
+                price,
+                order: Some(receipt.order_id),
+                reason: self.reasons.get(&receipt.order_id).cloned(),
+            });
+        }
+    }
+
+    /// Records the trade of a filled (or partially filled) order
+    fn record_fill(&mut self, time: DateTime<Utc>, event: &Event) {
+        if let Event::OrderFilled {
+            id,
+            symbol,
+            side,
+            quantity,
+            price,
+        }
+        | Event::OrderPartiallyFilled {
+            id,
+            symbol,
+            side,
+            quantity,
+            price,
+            ..
+        } = event
+        {
+            let quantity = match side {
+                Side::Buy => *quantity,
+                Side::Sell => -*quantity,
+            };
+            self.trades.push(Trade {
+                time,
+                symbol: symbol.clone(),
+                quantity,
+                price: *price,
+                order: Some(*id),
+                reason: self.reasons.get(id).cloned(),
+            });
+        }
+    }
+}
+
+impl<M: Market + Send> Market for RecordingMarket<M> {
+    type Error = M::Error;
+
+    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        let event = self.market.next_event().await?;
+        if let Some((time, event)) = &event {
+            self.record_fill(*time, event);
+        }
+        self.record_equity().await?;
+
+        Ok(event)
+    }
+
+    async fn next_event_or_tick(
+        &mut self,
+        tick: TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), M::Error> {
+        let event = self.market.next_event_or_tick(tick).await?;
+        self.record_fill(event.0, &event.1);
+        self.record_equity().await?;
+
+        Ok(event)
+    }
+
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        let event = self.market.next_event_until(until).await?;
+        if let Some((time, event)) = &event {
+            self.record_fill(*time, event);
+        }
+        self.record_equity().await?;
+
+        Ok(event)
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.market.time()
+    }
+
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, M::Error> {
+        self.market.quote_at(symbol, time).await
+    }
+
+    async fn buy_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        let cash_before = self.market.cash();
+        let receipt = self.market.buy_at_market(symbol, quantity).await?;
+        self.record_trade(&receipt, receipt.quantity, cash_before);
+
+        Ok(receipt)
+    }
+
+    async fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        let cash_before = self.market.cash();
+        let receipt = self.market.sell_at_market(symbol, quantity).await?;
+        self.record_trade(&receipt, -receipt.quantity, cash_before);
+
+        Ok(receipt)
+    }
+
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, M::Error> {
+        self.market.submit_order(order).await
+    }
+
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), M::Error> {
+        self.market.submit_oco(first, second).await
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), M::Error> {
+        self.market.amend_order(id, amendment).await
+    }
+
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), M::Error> {
+        self.market.cancel_order(id).await
+    }
+
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.market.order_status(id)
+    }
+
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.market.open_orders()
+    }
+
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        self.market.set_tradeable(symbol, tradeable)
+    }
+
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        self.market.is_tradeable(symbol)
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.market.lot_size(symbol)
+    }
+
+    fn market_time(&self) -> MarketTime {
+        self.market.market_time()
+    }
+
+    fn cash(&self) -> Money {
+        self.market.cash()
+    }
+
+    fn settled_cash(&self) -> Money {
+        self.market.settled_cash()
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.market.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.market.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.market.position(symbol)
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.market.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.market.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[account::Lot] {
+        self.market.lots(symbol)
+    }
+
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.market.closed_lots()
+    }
+
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.market.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, M::Error> {
+        self.market.position_high_water_mark(symbol).await
+    }
+}
+
+/// Formats a time as ISO 8601 in a time zone, e.g.
+/// `2024-01-02T09:30:00-05:00` in New York
+pub fn format_time(time: DateTime<Utc>, time_zone: Tz) -> String {
+    time.with_timezone(&time_zone).to_rfc3339()
+}
+
+/// Returns the daily returns of an equity curve, using the last net worth of
+/// every day in `time_zone`. The first day's return is relative to the first
+/// net worth.
+pub fn daily_returns(
+    equity_curve: &[(DateTime<Utc>, Money)],
+    time_zone: Tz,
+) -> Vec<(NaiveDate, f64)> {
+    let Some((_, mut previous_close)) = equity_curve.first() else {
+        return Vec::new();
+    };
+
+    let mut closes: Vec<(NaiveDate, Money)> = Vec::new();
+    for (time, net_worth) in equity_curve {
+        let date = time.with_timezone(&time_zone).date_naive();
+        match closes.last_mut() {
+            Some((day, close)) if *day == date => *close = *net_worth,
+            _ => closes.push((date, *net_worth)),
+        }
+    }
+
+    closes
+        .into_iter()
+        .map(|(day, close)| {
+            let daily_return = close.to_f64() / previous_close.to_f64() - 1.0;
+            previous_close = close;
+            (day, daily_return)
+        })
+        .collect()
+}
+
+/// Writes the daily returns of an equity curve as a CSV that QuantStats
+/// (and pandas' `read_csv(..., index_col=0, parse_dates=True)`) reads as a
+/// returns series.
+pub fn write_quantstats_returns(
+    mut writer: impl io::Write,
+    equity_curve: &[(DateTime<Utc>, Money)],
+    time_zone: Tz,
+) -> io::Result<()> {
+    writeln!(writer, "date,returns")?;
+    for (day, daily_return) in daily_returns(equity_curve, time_zone) {
+        writeln!(writer, "{day},{daily_return}")?;
+    }
+
+    Ok(())
+}
+
+/// Writes trades as a CSV in the layout of pyfolio's `transactions`
+/// DataFrame (indexed by time, with `amount`, `price` and `symbol` columns)
+pub fn write_pyfolio_transactions(
+    mut writer: impl io::Write,
+    trades: &[Trade],
+    time_zone: Tz,
+) -> io::Result<()> {
+    writeln!(writer, "date,amount,price,symbol")?;
+    for trade in trades {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            format_time(trade.time, time_zone),
+            trade.quantity,
+            trade.price,
+            trade.symbol
+        )?;
+    }
+
+    Ok(())
+}
+
+/// What a broker charges per fill
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Fees {
+    pub per_fill: Money,
+    pub per_share: f64,
+    /// As a fraction of the traded value
+    pub rate: f64,
+}
+
+impl Fees {
+    pub fn of(&self, trade: &Trade) -> Money {
+        let shares = trade.quantity.abs();
+        self.per_fill
+            + Money::worth(shares, self.per_share)
+            + Money::worth(shares, self.rate * trade.price)
+    }
+}
+
+/// A trade with its accounting, as reported for taxes
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportedFill {
+    pub trade: Trade,
+    pub fees: Money,
+    /// What the sold shares cost, fees included, or zero for purchases
+    pub cost_basis: Money,
+    /// What the sale made after fees, or zero for purchases
+    pub proceeds: Money,
+    pub realized_pnl: Money,
+    /// When the earliest of the sold shares were bought
+    pub acquired: Option<DateTime<Utc>>,
+}
+
+/// Shares bought together, not sold yet
+struct Lot {
+    acquired: DateTime<Utc>,
+    shares: f64,
+    /// What the shares cost, including the purchase's fees
+    cost: Money,
+}
+
+/// Matches sales with the earliest purchased lots of the same symbol (FIFO)
+/// to realize their profit and loss. Shares sold beyond the held lots have
+/// no cost basis.
+pub fn fill_report(trades: &[Trade], fees: &Fees) -> Vec<ReportedFill> {
+    let mut lots: HashMap<&str, VecDeque<Lot>> = HashMap::new();
+
+    trades
+        .iter()
+        .map(|trade| {
+            let fees = fees.of(trade);
+            let shares = trade.quantity.abs();
+            let symbol_lots = lots.entry(trade.symbol.as_str()).or_default();
+
+            let mut fill = ReportedFill {
+                trade: trade.clone(),
+                fees,
+                cost_basis: Money::ZERO,
+                proceeds: Money::ZERO,
+                realized_pnl: Money::ZERO,
+                acquired: None,
+            };
+
+            if trade.quantity > 0.0 {
+                symbol_lots.push_back(Lot {
+                    acquired: trade.time,
+                    shares,
+                    cost: Money::worth(shares, trade.price) + fees,
+                });
+                return fill;
+            }
+
+            let mut unmatched = shares;
+            while unmatched > 0.0 {
+                let Some(lot) = symbol_lots.front_mut() else {
+                    break;
+                };
+                fill.acquired.get_or_insert(lot.acquired);
+
+                if unmatched >= lot.shares {
+                    fill.cost_basis += lot.cost;
+                    unmatched -= lot.shares;
+                    symbol_lots.pop_front();
+                    continue;
+                }
+                let cost = Money::worth(unmatched, lot.cost.to_f64() / lot.shares);
+                fill.cost_basis += cost;
+                lot.cost -= cost;
+                lot.shares -= unmatched;
+                unmatched = 0.0;
+            }
+
+            fill.proceeds = Money::worth(shares, trade.price) - fees;
+            fill.realized_pnl = fill.proceeds - fill.cost_basis;
+            fill
+        })
+        .collect()
+}
+
+/// A column of a fill report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillColumn {
+    Time,
+    Symbol,
+    Quantity,
+    Price,
+    Fees,
+    CostBasis,
+    Proceeds,
+    RealizedPnl,
+    Acquired,
+    Reason,
+}
+
+impl FillColumn {
+    pub const ALL: [FillColumn; 10] = [
+        FillColumn::Time,
+        FillColumn::Symbol,
+        FillColumn::Quantity,
+        FillColumn::Price,
+        FillColumn::Fees,
+        FillColumn::CostBasis,
+        FillColumn::Proceeds,
+        FillColumn::RealizedPnl,
+        FillColumn::Acquired,
+        FillColumn::Reason,
+    ];
+
+    fn header(self) -> &'static str {
+        match self {
+            FillColumn::Time => "time",
+            FillColumn::Symbol => "symbol",
+            FillColumn::Quantity => "quantity",
+            FillColumn::Price => "price",
+            FillColumn::Fees => "fees",
+            FillColumn::CostBasis => "cost_basis",
+            FillColumn::Proceeds => "proceeds",
+            FillColumn::RealizedPnl => "realized_pnl",
+            FillColumn::Acquired => "acquired",
+            FillColumn::Reason => "reason",
+        }
+    }
+
+    fn value(self, fill: &ReportedFill, time_zone: Tz) -> String {
+        match self {
+            FillColumn::Time => format_time(fill.trade.time, time_zone),
+            FillColumn::Symbol => fill.trade.symbol.clone(),
+            FillColumn::Quantity => fill.trade.quantity.to_string(),
+            FillColumn::Price => fill.trade.price.to_string(),
+            FillColumn::Fees => fill.fees.to_string(),
+            FillColumn::CostBasis => fill.cost_basis.to_string(),
+            FillColumn::Proceeds => fill.proceeds.to_string(),
+            FillColumn::RealizedPnl => fill.realized_pnl.to_string(),
+            FillColumn::Acquired => fill
+                .acquired
+                .map(|acquired| format_time(acquired, time_zone))
+                .unwrap_or_default(),
+            FillColumn::Reason => fill
+                .trade
+                .reason
+                .as_ref()
+                .map(TradeReason::to_string)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Writes a fill report as a CSV with the given columns, in order, and
+/// times in `time_zone`
+pub fn write_fill_report(
+    mut writer: impl io::Write,
+    fills: &[ReportedFill],
+    columns: &[FillColumn],
+    time_zone: Tz,
+) -> io::Result<()> {
+    let headers: Vec<_> = columns.iter().map(|column| column.header()).collect();
+    writeln!(writer, "{}", headers.join(","))?;
+
+    for fill in fills {
+        let values: Vec<_> = columns
+            .iter()
+            .map(|column| column.value(fill, time_zone))
+            .collect();
+        writeln!(writer, "{}", values.join(","))?;
+    }
+
+    Ok(())
+}
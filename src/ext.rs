@@ -0,0 +1,125 @@
+//! Shorthands for common trades, available on every market.
+
+use std::future::Future;
+
+use crate::{
+    market::{Market, MarketError as _},
+    money::Money,
+    order::{Side, TradeReceipt},
+    order_builder::MarketOrder,
+};
+
+pub trait MarketExt: Market {
+    /// Spends all of the settled cash on an equity, in whole lots (see
+    /// `Market::buy_notional`). Lots are given up one by one while the fill
+    /// would cost more than the cash (e.g. for price impact).
+    fn buy_max(&mut self, symbol: &str) -> impl Future<Output = Result<TradeReceipt, Self::Error>> {
+        async move {
+            let cash = self.settled_cash();
+            let lot_size = self.lot_size(symbol);
+            let quantity = self.notional_quantity(symbol, cash).await?;
+            let mut lots = (quantity / lot_size).round();
+            loop {
+                match self.buy_at_market(symbol, lots * lot_size).await {
+                    Err(error) if error.is_insufficient_funds() && lots > 1.0 => lots -= 1.0,
+                    result => return result,
+                }
+            }
+        }
+    }
+
+    /// Executes a market order from `OrderBuilder::market`
+    fn submit_market_order(
+        &mut self,
+        order: MarketOrder,
+    ) -> impl Future<Output = Result<TradeReceipt, Self::Error>> {
+        async move {
+            match order.side {
+                Side::Buy => self.buy_at_market(&order.symbol, order.quantity).await,
+                Side::Sell => self.sell_at_market(&order.symbol, order.quantity).await,
+            }
+        }
+    }
+
+    /// Sells every held share of an equity
+    fn sell_all(
+        &mut self,
+        symbol: &str,
+    ) -> impl Future<Output = Result<TradeReceipt, Self::Error>> {
+        let quantity = self.shares_of(symbol);
+        self.sell_at_market(symbol, quantity)
+    }
+
+    /// Buys or sells the difference between the held and the `target`
+    /// number of shares of an equity, returning `None` if they already match
+    fn order_target_quantity(
+        &mut self,
+        symbol: &str,
+        target: f64,
+    ) -> impl Future<Output = Result<Option<TradeReceipt>, Self::Error>> {
+        async move {
+            let delta = target - self.shares_of(symbol);
+            // Without trading the rounding error of earlier fractional fills
+            if delta.abs() < 1e-9 {
+                return Ok(None);
+            }
+
+            let receipt = if delta > 0.0 {
+                self.buy_at_market(symbol, delta).await?
+            } else {
+                self.sell_at_market(symbol, -delta).await?
+            };
+
+            Ok(Some(receipt))
+        }
+    }
+
+    /// Rebalances a position to be worth `target` at the current price, in
+    /// whole lots (see `Market::notional_quantity`)
+    fn order_target_value(
+        &mut self,
+        symbol: &str,
+        target: Money,
+    ) -> impl Future<Output = Result<Option<TradeReceipt>, Self::Error>> {
+        async move {
+            let quantity = self.notional_quantity(symbol, target).await?;
+            self.order_target_quantity(symbol, quantity).await
+        }
+    }
+
+    /// Rebalances a position to be worth `percent` of the current net worth,
+    /// e.g. 0.25 for a quarter of it
+    fn order_target_percent(
+        &mut self,
+        symbol: &str,
+        percent: f64,
+    ) -> impl Future<Output = Result<Option<TradeReceipt>, Self::Error>> {
+        async move {
+            let net_worth = self.net_worth().await?;
+            self.order_target_value(symbol, net_worth.times(percent))
+                .await
+        }
+    }
+
+    /// Sells every held position, in the order of their symbols
+    fn liquidate_all(&mut self) -> impl Future<Output = Result<Vec<TradeReceipt>, Self::Error>> {
+        async move {
+            let mut held: Vec<String> = self
+                .holdings()
+                .into_iter()
+                .filter(|(_, quantity)| **quantity > 0.0)
+                .map(|(symbol, _)| symbol.clone())
+                .collect();
+            held.sort();
+
+            let mut receipts = Vec::with_capacity(held.len());
+            for symbol in held {
+                receipts.push(self.sell_all(&symbol).await?);
+            }
+
+            Ok(receipts)
+        }
+    }
+}
+
+impl<M: Market + ?Sized> MarketExt for M {}
@@ -0,0 +1,169 @@
+//! Models of the price market orders fill at within the current bar, and of
+//! whether and where resting orders fill within bars when only OHLC bars are
+//! known, so results can be checked for sensitivity to fill assumptions.
+
+use std::sync::Mutex;
+
+use float_eq::float_eq;
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
+
+use crate::{domain::Candle, order::Side};
+
+/// Decides the price a market order fills at in the bar it is executed in
+pub trait FillModel: Send + Sync {
+    fn fill_price(&self, candle: &Candle, side: Side) -> f64;
+}
+
+/// Fills at the open of the bar
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AtOpen;
+
+impl FillModel for AtOpen {
+    fn fill_price(&self, candle: &Candle, _side: Side) -> f64 {
+        candle.open
+    }
+}
+
+/// Fills at the close of the bar, the latest known price
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AtClose;
+
+impl FillModel for AtClose {
+    fn fill_price(&self, candle: &Candle, _side: Side) -> f64 {
+        candle.close
+    }
+}
+
+/// Fills halfway between the high and the low of the bar
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AtMidpoint;
+
+impl FillModel for AtMidpoint {
+    fn fill_price(&self, candle: &Candle, _side: Side) -> f64 {
+        (candle.high + candle.low) / 2.0
+    }
+}
+
+/// Fills at a uniformly random price between the low and the high of the bar
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RandomInRange;
+
+impl FillModel for RandomInRange {
+    fn fill_price(&self, candle: &Candle, _side: Side) -> f64 {
+        if float_eq!(candle.low, candle.high, ulps <= 5) {
+            return candle.low;
+        }
+
+        rand::thread_rng().gen_range(candle.low..=candle.high)
+    }
+}
+
+/// Like `RandomInRange`, but drawn from a seeded generator, so a run can be
+/// reproduced (e.g. one run of an ensemble)
+pub struct SeededRandom {
+    rng: Mutex<StdRng>,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+impl FillModel for SeededRandom {
+    fn fill_price(&self, candle: &Candle, _side: Side) -> f64 {
+        if float_eq!(candle.low, candle.high, ulps <= 5) {
+            return candle.low;
+        }
+
+        self.rng.lock().unwrap().gen_range(candle.low..=candle.high)
+    }
+}
+
+/// Buys at the high and sells at the low of the bar, a pessimistic bound
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorstCase;
+
+impl FillModel for WorstCase {
+    fn fill_price(&self, candle: &Candle, side: Side) -> f64 {
+        match side {
+            Side::Buy => candle.high,
+            Side::Sell => candle.low,
+        }
+    }
+}
+
+/// The prices of a bar that resting orders are matched against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BarPrices {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl From<&Candle> for BarPrices {
+    fn from(candle: &Candle) -> Self {
+        BarPrices {
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+        }
+    }
+}
+
+/// Where a stop order fills within the bar that triggered it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StopFill {
+    /// At the stop price, or at the close if it is past it
+    #[default]
+    StopOrClose,
+    /// At the stop price, or at the open if the bar gapped past it
+    StopOrOpen,
+    /// At the worst price of the bar: the high for purchases and the low
+    /// for sales
+    WorstPrice,
+}
+
+impl StopFill {
+    pub fn price(&self, side: Side, stop_price: f64, bar: &BarPrices) -> f64 {
+        let past = match self {
+            StopFill::StopOrClose => bar.close,
+            StopFill::StopOrOpen => bar.open,
+            StopFill::WorstPrice => match side {
+                Side::Buy => bar.high,
+                Side::Sell => bar.low,
+            },
+        };
+
+        match side {
+            Side::Buy => stop_price.max(past),
+            Side::Sell => stop_price.min(past),
+        }
+    }
+}
+
+/// The assumptions resting orders are filled with within a bar (see
+/// `PendingOrder::on_bar`). The defaults fill limit orders whose price was
+/// touched and stops at their stop price or the close.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IntrabarFill {
+    /// How far past its limit price trades must go for a limit order to
+    /// fill, since the queue at the price itself may not have been reached
+    pub limit_penetration: f64,
+    pub stop_fill: StopFill,
+}
+
+impl IntrabarFill {
+    /// Whether trades within `bar` reached far enough past `limit_price` to
+    /// fill a limit order
+    pub fn is_limit_reached(&self, side: Side, limit_price: f64, bar: &BarPrices) -> bool {
+        match side {
+            Side::Buy => bar.low <= limit_price - self.limit_penetration,
+            Side::Sell => bar.high >= limit_price + self.limit_penetration,
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Skips the events a strategy has no use for (e.g. ticks outside of regular
+//! hours, or news of equities it does not follow), counting and optionally
+//! logging them, so it can be verified that the strategy saw what it was
+//! expected to see.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    account::{ClosedLot, Lot, LotId, Position, Transaction},
+    market::{Event, Market, MarketTime, PriceQuote},
+    money::Money,
+    order::{Amendment, OcoGroupId, Order, OrderId, OrderStatus, PendingOrder, TradeReceipt},
+};
+
+/// How many events were handed to the strategy, and how many were skipped
+/// for each reason
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SkipReport {
+    pub delivered: usize,
+    pub ticks_outside_regular_hours: usize,
+    /// Events of equities outside the subscriptions, by symbol
+    pub unsubscribed: HashMap<String, usize>,
+}
+
+impl SkipReport {
+    pub fn skipped(&self) -> usize {
+        self.ticks_outside_regular_hours + self.unsubscribed.values().sum::<usize>()
+    }
+}
+
+/// Wraps a market, skipping the events the strategy is not interested in.
+/// By default nothing is skipped. The strategy's own order events are never
+/// skipped.
+pub struct EventFilter<M: Market> {
+    market: M,
+    regular_hours_ticks_only: bool,
+    /// The equities whose events are delivered, or `None` for all of them
+    subscriptions: Option<HashSet<String>>,
+    /// The level skipped events are logged at, if they are
+    log_level: Option<log::Level>,
+    report: SkipReport,
+}
+
+impl<M: Market + Send> EventFilter<M> {
+    pub fn new(market: M) -> Self {
+        EventFilter {
+            market,
+            regular_hours_ticks_only: false,
+            subscriptions: None,
+            log_level: None,
+            report: SkipReport::default(),
+        }
+    }
+
+    /// Skips ticks outside of the regular session. Session events are still
+    /// delivered, but without further events `next_event_or_tick` only
+    /// returns once the next regular session started.
+    pub fn with_regular_hours_ticks_only(mut self) -> Self {
+        self.regular_hours_ticks_only = true;
+        self
+    }
+
+    /// Skips the events of equities other than `symbols` (e.g. earnings
+    /// reports, closed bars or scanner hits)
+    pub fn with_subscriptions(
+        mut self,
+        symbols: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.subscriptions = Some(symbols.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Logs every skipped event at `level`
+    pub fn with_logging(mut self, level: log::Level) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    pub fn report(&self) -> &SkipReport {
+        &self.report
+    }
+
+    pub fn into_inner(self) -> M {
+        self.market
+    }
+
+    /// Counts the event as delivered or skipped, returning whether it is
+    /// skipped
+    fn skip(&mut self, time: DateTime<Utc>, event: &Event) -> bool {
+        let reason = match event {
+            Event::Tick
+                if self.regular_hours_ticks_only
+                    && self.market.market_time() != MarketTime::Regular =>
+            {
+                self.report.ticks_outside_regular_hours += 1;
+                "outside of regular hours"
+            }
+            Event::Earnings { symbol }
+            | Event::BarClosed { symbol, .. }
+            | Event::ScannerHit { symbol, .. }
+                if self
+                    .subscriptions
+                    .as_ref()
+                    .is_some_and(|subscriptions| !subscriptions.contains(symbol)) =>
+            {
+                *self.report.unsubscribed.entry(symbol.clone()).or_default() += 1;
+                "not subscribed to"
+            }
+            _ => {
+                self.report.delivered += 1;
+                return false;
+            }
+        };
+
+        if let Some(level) = self.log_level {
+            log::log!(level, "skipped {event:?} at {time}: {reason}");
+        }
+        true
+    }
+}
+
+impl<M: Market + Send> Market for EventFilter<M> {
+    type Error = M::Error;
+
+    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        while let Some((time, event)) = self.market.next_event().await? {
+            if !self.skip(time, &event) {
+                return Ok(Some((time, event)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn next_event_or_tick(
+        &mut self,
+        tick: TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), M::Error> {
+        loop {
+            let (time, event) = self.market.next_event_or_tick(tick).await?;
+            if !self.skip(time, &event) {
+                return Ok((time, event));
+            }
+        }
+    }
+
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        while let Some((time, event)) = self.market.next_event_until(until).await? {
+            if !self.skip(time, &event) {
+                return Ok(Some((time, event)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.market.time()
+    }
+
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, M::Error> {
+        self.market.quote_at(symbol, time).await
+    }
+
+    async fn buy_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.buy_at_market(symbol, quantity).await
+    }
+
+    async fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.sell_at_market(symbol, quantity).await
+    }
+
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, M::Error> {
+        self.market.submit_order(order).await
+    }
+
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), M::Error> {
+        self.market.submit_oco(first, second).await
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), M::Error> {
+        self.market.amend_order(id, amendment).await
+    }
+
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), M::Error> {
+        self.market.cancel_order(id).await
+    }
+
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.market.order_status(id)
+    }
+
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.market.open_orders()
+    }
+
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        self.market.set_tradeable(symbol, tradeable)
+    }
+
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        self.market.is_tradeable(symbol)
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.market.lot_size(symbol)
+    }
+
+    fn market_time(&self) -> MarketTime {
+        self.market.market_time()
+    }
+
+    fn cash(&self) -> Money {
+        self.market.cash()
+    }
+
+    fn settled_cash(&self) -> Money {
+        self.market.settled_cash()
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.market.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.market.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.market.position(symbol)
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.market.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.market.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[Lot] {
+        self.market.lots(symbol)
+    }
+
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.market.closed_lots()
+    }
+
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.market.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, M::Error> {
+        self.market.position_high_water_mark(symbol).await
+    }
+}
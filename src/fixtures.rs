@@ -0,0 +1,137 @@
+//! A small dataset bundled with the crate, for end-to-end tests without a
+//! database: one-minute bars of a made-up equity over the first four hours
+//! of the regular session of 2024-03-04.
+//!
+//! The bars are made of the prices of the golden-file tests
+//! (`src/tests/fixtures/prices.csv`), one per minute, so the crate bundles
+//! a single dataset. The prices carry no volume, so neither do the bars. The
+//! same data can be loaded into QuestDB with the script from
+//! `questdb_import_script`.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, TimeZone as _, Utc};
+
+use crate::{
+    calendar::{us_equity_sessions, Session},
+    market::{Candle, Event},
+    questdb_market::system_event_symbol,
+};
+
+/// The symbols of the bundled equities
+pub const SYMBOLS: [&str; 1] = ["STOCK"];
+
+const PRICES: &str = include_str!("tests/fixtures/prices.csv");
+
+/// The duration of the bundled bars
+pub fn interval() -> TimeDelta {
+    TimeDelta::minutes(1)
+}
+
+/// The trading days of the bundled bars
+pub fn sessions() -> Vec<Session> {
+    let day = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+    us_equity_sessions(day, day)
+}
+
+/// The bundled bars, by symbol and in order. Each bar opens at the previous
+/// minute's price and closes at its own.
+pub fn bars() -> HashMap<String, Vec<Candle>> {
+    let prices: Vec<f64> = PRICES
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let [_, price] = line.split(',').collect::<Vec<_>>()[..] else {
+                panic!("malformed fixture price: {line}");
+            };
+            price.parse().unwrap()
+        })
+        .collect();
+    // At the open of the regular session, 9:30 in New York
+    let start = Utc.with_ymd_and_hms(2024, 3, 4, 14, 30, 0).unwrap();
+
+    let candles = prices
+        .iter()
+        .enumerate()
+        .map(|(minute, &close)| {
+            let open = prices[minute.saturating_sub(1)];
+            Candle {
+                start: start + interval() * minute as i32,
+                open,
+                high: open.max(close),
+                low: open.min(close),
+                close,
+                volume: 0.0,
+            }
+        })
+        .collect();
+
+    [(SYMBOLS[0].to_string(), candles)].into()
+}
+
+/// The bundled bars and sessions as the events a market would report, in
+/// order: each session event, and each bar once it closed
+pub fn events() -> Vec<(DateTime<Utc>, Event)> {
+    let mut events: Vec<_> = sessions().iter().flat_map(Session::events).collect();
+    for (symbol, candles) in bars() {
+        events.extend(candles.into_iter().map(|candle| {
+            (
+                candle.start + interval(),
+                Event::BarClosed {
+                    symbol: symbol.clone(),
+                    interval: interval(),
+                    candle,
+                },
+            )
+        }));
+    }
+    // Session events first when they coincide with bars, and bars by symbol
+    events.sort_by(|(a_time, a), (b_time, b)| {
+        let key = |event: &Event| match event {
+            Event::BarClosed { symbol, .. } => (1, symbol.clone()),
+            _ => (0, String::new()),
+        };
+        a_time.cmp(b_time).then_with(|| key(a).cmp(&key(b)))
+    });
+
+    events
+}
+
+/// SQL creating and filling the `prices` and `system_events` tables of a
+/// `QuestDbMarket` with the bundled data, e.g. to run through QuestDB's web
+/// console or `/exec` endpoint on a local installation
+pub fn questdb_import_script() -> String {
+    let mut script = String::from(
+        "CREATE TABLE IF NOT EXISTS prices (symbol SYMBOL, open DOUBLE, high DOUBLE, \
+         low DOUBLE, close DOUBLE, volume DOUBLE, timestamp TIMESTAMP) \
+         timestamp(timestamp) PARTITION BY DAY;\n\
+         CREATE TABLE IF NOT EXISTS system_events (symbol SYMBOL, timestamp TIMESTAMP) \
+         timestamp(timestamp);\n",
+    );
+
+    for (time, event) in sessions().iter().flat_map(Session::events) {
+        script += &format!(
+            "INSERT INTO system_events VALUES ('{}', '{}');\n",
+            system_event_symbol(&event).unwrap(),
+            time.format("%Y-%m-%dT%H:%M:%S%.6fZ")
+        );
+    }
+
+    let mut bars: Vec<_> = bars().into_iter().collect();
+    bars.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (symbol, candles) in bars {
+        for candle in candles {
+            script += &format!(
+                "INSERT INTO prices VALUES ('{symbol}', {}, {}, {}, {}, {}, '{}');\n",
+                candle.open,
+                candle.high,
+                candle.low,
+                candle.close,
+                candle.volume,
+                candle.start.format("%Y-%m-%dT%H:%M:%S%.6fZ")
+            );
+        }
+    }
+
+    script
+}
@@ -0,0 +1,166 @@
+//! A small HTTP health endpoint reporting the state of a running strategy,
+//! for orchestration and monitoring systems (e.g. a Kubernetes probe or a
+//! Prometheus blackbox check).
+//!
+//! The strategy updates a `HealthTracker` as it handles events and publishes
+//! its statuses through a `tokio::sync::watch` channel, which `serve` answers
+//! `GET /health` from. The feed is stale once no event was handled within a
+//! span of wall-clock time, so a strategy that hangs is reported as such even
+//! though it stops publishing statuses.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::TcpListener,
+    sync::watch,
+};
+
+use crate::{market::Market, money::Money};
+
+/// A snapshot of the state of a strategy
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HealthStatus {
+    /// The (virtual) time of the market
+    pub time: Option<DateTime<Utc>>,
+    /// When the market last reported an event
+    pub last_event_at: Option<DateTime<Utc>>,
+    /// The wall-clock time the strategy handled that event at
+    pub last_event_handled_at: Option<DateTime<Utc>>,
+    pub open_orders: usize,
+    /// How far the net worth fell from its peak, as a fraction of it
+    pub drawdown: f64,
+}
+
+impl HealthStatus {
+    /// How long no event was handled for as of the wall-clock time `now`, or
+    /// `None` before the first event
+    pub fn staleness(&self, now: DateTime<Utc>) -> Option<TimeDelta> {
+        Some(now - self.last_event_handled_at?)
+    }
+
+    /// Whether an event was handled within `max_staleness` of `now`
+    pub fn is_healthy(&self, max_staleness: TimeDelta, now: DateTime<Utc>) -> bool {
+        self.staleness(now)
+            .is_some_and(|staleness| staleness <= max_staleness)
+    }
+
+    /// The status as a JSON object as of the wall-clock time `now`, with
+    /// times in RFC 3339, the staleness in seconds and a drawdown that is not
+    /// a number (e.g. after a peak of zero) as `null`
+    pub fn to_json(&self, now: DateTime<Utc>) -> String {
+        let time = |time: Option<DateTime<Utc>>| {
+            time.map_or("null".to_string(), |time| {
+                format!("\"{}\"", time.to_rfc3339())
+            })
+        };
+        let staleness = self.staleness(now).map_or("null".to_string(), |staleness| {
+            format!("{}", staleness.num_milliseconds() as f64 / 1000.0)
+        });
+        let drawdown = if self.drawdown.is_finite() {
+            self.drawdown.to_string()
+        } else {
+            "null".to_string()
+        };
+
+        format!(
+            "{{\"time\":{},\"last_event_at\":{},\"staleness_seconds\":{staleness},\
+             \"open_orders\":{},\"drawdown\":{drawdown}}}",
+            time(self.time),
+            time(self.last_event_at),
+            self.open_orders,
+        )
+    }
+}
+
+/// Follows the events and net worth of a market to report its health
+#[derive(Clone, Debug, Default)]
+pub struct HealthTracker {
+    last_event_at: Option<DateTime<Utc>>,
+    last_event_handled_at: Option<DateTime<Utc>>,
+    peak_net_worth: Money,
+}
+
+impl HealthTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an event the market reported at `time`, handled now
+    pub fn on_event(&mut self, time: DateTime<Utc>) {
+        self.last_event_at = Some(time);
+        self.last_event_handled_at = Some(Utc::now());
+    }
+
+    /// The current status of a market, updating the peak of its net worth
+    pub async fn status<M: Market>(&mut self, market: &M) -> Result<HealthStatus, M::Error> {
+        let net_worth = market.net_worth().await?;
+        self.peak_net_worth = self.peak_net_worth.max(net_worth);
+        let drawdown = if self.peak_net_worth > Money::ZERO {
+            1.0 - net_worth.to_f64() / self.peak_net_worth.to_f64()
+        } else {
+            0.0
+        };
+
+        Ok(HealthStatus {
+            time: Some(market.time()),
+            last_event_at: self.last_event_at,
+            last_event_handled_at: self.last_event_handled_at,
+            open_orders: market.open_orders().into_iter().count(),
+            drawdown,
+        })
+    }
+}
+
+/// The longest request line read before answering
+const MAX_REQUEST_LINE: usize = 1024;
+
+/// Answers `GET /health` with the latest status as JSON, with a 200 while no
+/// more than `max_staleness` passed since the last event was handled and a
+/// 503 otherwise, and anything else with a 404, until the listener fails
+pub async fn serve(
+    listener: TcpListener,
+    status: watch::Receiver<HealthStatus>,
+    max_staleness: TimeDelta,
+) -> std::io::Result<()> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let status = status.clone();
+
+        tokio::spawn(async move {
+            // The request line may arrive over several reads
+            let mut request = Vec::new();
+            let mut buffer = [0; MAX_REQUEST_LINE];
+            while !request.windows(2).any(|pair| pair == b"\r\n")
+                && request.len() < MAX_REQUEST_LINE
+            {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request);
+
+            let response = if request.starts_with("GET /health ") {
+                let now = Utc::now();
+                let status = status.borrow().clone();
+                let body = status.to_json(now);
+                format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    if status.is_healthy(max_staleness, now) {
+                        "200 OK"
+                    } else {
+                        "503 Service Unavailable"
+                    },
+                    body.len()
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                log::warn!("cannot answer a health check: {e}");
+            }
+        });
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::money::Money;
+
+/// The trading specification of a single instrument
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instrument {
+    /// The smallest allowed price increment
+    pub tick_size: f64,
+    /// The number of shares in a single tradeable lot, a fraction of one
+    /// where fractional shares are traded (e.g. 0.0001)
+    pub lot_size: f64,
+    /// The smallest quantity of an order (e.g. a crypto exchange's
+    /// `minQty`), zero for none
+    pub min_quantity: f64,
+    /// The smallest value of an order at its price (e.g. a crypto
+    /// exchange's `minNotional`), zero for none
+    pub min_notional: Money,
+}
+
+impl Default for Instrument {
+    /// A US equity, quoted in cents and traded in single shares
+    fn default() -> Self {
+        Instrument {
+            tick_size: 0.01,
+            lot_size: 1.0,
+            min_quantity: 0.0,
+            min_notional: Money::ZERO,
+        }
+    }
+}
+
+impl Instrument {
+    /// The number of decimals needed to show any multiple of the tick size
+    pub fn price_decimals(&self) -> usize {
+        decimals_of(self.tick_size)
+    }
+
+    /// Formats a price with as many decimals as the tick size has, e.g.
+    /// `12.50` for a tick size of 0.01
+    pub fn format_price(&self, price: f64) -> String {
+        format!("{price:.*}", self.price_decimals())
+    }
+
+    /// Formats a quantity, with its number of lots when they are larger than
+    /// a single share
+    pub fn format_quantity(&self, quantity: f64) -> String {
+        if self.lot_size == 1.0 {
+            return quantity.to_string();
+        }
+
+        let lots = quantity / self.lot_size;
+        format!("{quantity} ({lots} lots)")
+    }
+}
+
+/// The currency amounts of money are in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Currency {
+    pub code: String,
+    /// The number of decimals of the smallest unit, e.g. 2 for cents
+    pub minor_units: usize,
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency {
+            code: "USD".to_string(),
+            minor_units: 2,
+        }
+    }
+}
+
+impl Currency {
+    /// Formats an amount rounded to the minor unit, e.g. `-1234.50 USD`
+    pub fn format(&self, amount: Money) -> String {
+        let amount = amount.round_to(self.minor_units as u32).to_f64();
+        format!("{amount:.*} {}", self.minor_units, self.code)
+    }
+}
+
+/// The number of decimals of a step size such as a tick size, ignoring
+/// floating point noise
+fn decimals_of(step: f64) -> usize {
+    (0..=9)
+        .find(|decimals| {
+            let scaled = step * 10f64.powi(*decimals as i32);
+            (scaled - scaled.round()).abs() <= TICK_TOLERANCE * scaled.abs().max(1.0)
+        })
+        .unwrap_or(9)
+}
+
+/// How order prices and quantities that do not match the instrument are
+/// handled
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RoundingMode {
+    /// Round prices to the nearest tick and quantities down to a whole lot
+    #[default]
+    Round,
+    /// Reject prices and quantities that are not already aligned
+    Strict,
+}
+
+#[derive(Error, Debug)]
+pub enum RoundingError {
+    #[error("Price {price} of {symbol} is not a multiple of the tick size {tick_size}")]
+    OffTickPrice {
+        symbol: String,
+        price: f64,
+        tick_size: f64,
+    },
+
+    #[error("Quantity {quantity} of {symbol} is not a multiple of the lot size {lot_size}")]
+    OddLotQuantity {
+        symbol: String,
+        quantity: f64,
+        lot_size: f64,
+    },
+
+    #[error("Quantity {quantity} of {symbol} is below the minimum quantity {min_quantity}")]
+    BelowMinQuantity {
+        symbol: String,
+        quantity: f64,
+        min_quantity: f64,
+    },
+
+    #[error("Value {notional} of the order of {symbol} is below the minimum value {min_notional}")]
+    BelowMinNotional {
+        symbol: String,
+        notional: Money,
+        min_notional: Money,
+    },
+}
+
+/// The relative distance from a whole tick that is still considered aligned,
+/// to absorb floating point noise (e.g. `0.1 + 0.2`)
+const TICK_TOLERANCE: f64 = 1e-6;
+
+/// Instrument specifications by symbol, used to align orders
+#[derive(Clone, Debug, Default)]
+pub struct InstrumentRegistry {
+    /// Specifications of known instruments, by symbol
+    instruments: HashMap<String, Instrument>,
+    /// The specification assumed for unregistered symbols
+    fallback: Instrument,
+    /// How misaligned orders are handled
+    mode: RoundingMode,
+}
+
+impl InstrumentRegistry {
+    pub fn new(mode: RoundingMode) -> Self {
+        InstrumentRegistry {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    /// Assumes `fallback` for the symbols that are not registered, instead
+    /// of a US equity
+    pub fn with_fallback(mut self, fallback: Instrument) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    pub fn insert(&mut self, symbol: &str, instrument: Instrument) {
+        self.instruments.insert(symbol.to_string(), instrument);
+    }
+
+    /// Returns the specification of an instrument, falling back to a US
+    /// equity when the symbol is not registered.
+    pub fn get(&self, symbol: &str) -> &Instrument {
+        self.instruments.get(symbol).unwrap_or(&self.fallback)
+    }
+
+    pub fn mode(&self) -> RoundingMode {
+        self.mode
+    }
+
+    pub fn format_price(&self, symbol: &str, price: f64) -> String {
+        self.get(symbol).format_price(price)
+    }
+
+    pub fn format_quantity(&self, symbol: &str, quantity: f64) -> String {
+        self.get(symbol).format_quantity(quantity)
+    }
+
+    /// Aligns a limit or stop price to the instrument's tick size.
+    ///
+    /// # Errors
+    ///
+    /// In strict mode, returns `RoundingError::OffTickPrice` if the price
+    /// is not a multiple of the tick size.
+    pub fn round_price(&self, symbol: &str, price: f64) -> Result<f64, RoundingError> {
+        let tick_size = self.get(symbol).tick_size;
+        let ticks = price / tick_size;
+        let rounded_ticks = ticks.round();
+
+        if self.mode == RoundingMode::Strict && (ticks - rounded_ticks).abs() > TICK_TOLERANCE {
+            return Err(RoundingError::OffTickPrice {
+                symbol: symbol.to_string(),
+                price,
+                tick_size,
+            });
+        }
+
+        Ok(rounded_ticks * tick_size)
+    }
+
+    /// Aligns a quantity down to a whole number of lots.
+    ///
+    /// # Errors
+    ///
+    /// In strict mode, returns `RoundingError::OddLotQuantity` if the
+    /// quantity is not a multiple of the lot size.
+    pub fn round_quantity(&self, symbol: &str, quantity: f64) -> Result<f64, RoundingError> {
+        let lot_size = self.get(symbol).lot_size;
+        let lots = quantity / lot_size;
+        let whole_lots = (lots + TICK_TOLERANCE).floor();
+
+        if self.mode == RoundingMode::Strict && (lots - whole_lots).abs() > TICK_TOLERANCE {
+            return Err(RoundingError::OddLotQuantity {
+                symbol: symbol.to_string(),
+                quantity,
+                lot_size,
+            });
+        }
+
+        Ok(whole_lots * lot_size)
+    }
+
+    /// Checks an order against the instrument's minimum quantity and value,
+    /// valued at `price`. Unlike misaligned orders, these cannot be rounded
+    /// into a valid order, so they are rejected in either mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RoundingError::BelowMinQuantity` or
+    /// `RoundingError::BelowMinNotional` if the order is too small.
+    pub fn check_minimums(
+        &self,
+        symbol: &str,
+        quantity: f64,
+        price: f64,
+    ) -> Result<(), RoundingError> {
+        let instrument = self.get(symbol);
+
+        if quantity < instrument.min_quantity * (1.0 - TICK_TOLERANCE) {
+            return Err(RoundingError::BelowMinQuantity {
+                symbol: symbol.to_string(),
+                quantity,
+                min_quantity: instrument.min_quantity,
+            });
+        }
+
+        let notional = Money::worth(quantity, price);
+        if notional < instrument.min_notional.times(1.0 - TICK_TOLERANCE) {
+            return Err(RoundingError::BelowMinNotional {
+                symbol: symbol.to_string(),
+                notional,
+                min_notional: instrument.min_notional,
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{
+    account::{ClosedLot, Lot, LotId, Position, Transaction},
+    market::{Event, Market, MarketTime, PriceQuote},
+    money::Money,
+    order::{Amendment, OcoGroupId, Order, OrderId, OrderStatus, PendingOrder, TradeReceipt},
+};
+
+/// What to do when the algorithm takes longer than its budget to handle an
+/// event
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverrunPolicy {
+    /// Only log a warning
+    #[default]
+    Warn,
+    /// Also skip the next tick, so a slow algorithm catches up with a live
+    /// feed instead of falling further behind. Other events are never
+    /// skipped.
+    SkipTick,
+}
+
+/// Percentiles of how long the algorithm took to handle events
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencyReport {
+    pub events: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    /// How many events took longer than the budget
+    pub overruns: usize,
+    pub skipped_ticks: usize,
+}
+
+/// Wraps a market, measuring the wall-clock time an algorithm spends between
+/// receiving an event and asking for the next one
+pub struct LatencyMonitor<M: Market> {
+    market: M,
+    budget: Option<Duration>,
+    policy: OverrunPolicy,
+
+    /// When the last event was handed to the algorithm
+    handling_since: Option<Instant>,
+    latencies: Vec<Duration>,
+    overruns: usize,
+    skip_tick: bool,
+    skipped_ticks: usize,
+}
+
+impl<M: Market + Send> LatencyMonitor<M> {
+    pub fn new(market: M) -> Self {
+        LatencyMonitor {
+            market,
+            budget: None,
+            policy: OverrunPolicy::default(),
+
+            handling_since: None,
+            latencies: Vec::new(),
+            overruns: 0,
+            skip_tick: false,
+            skipped_ticks: 0,
+        }
+    }
+
+    /// The time each event should be handled in
+    pub fn with_budget(mut self, budget: Duration, policy: OverrunPolicy) -> Self {
+        self.budget = Some(budget);
+        self.policy = policy;
+        self
+    }
+
+    pub fn latencies(&self) -> &[Duration] {
+        &self.latencies
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+
+        // Nearest rank
+        let percentile = |percent: usize| {
+            let rank = (percent * sorted.len()).div_ceil(100).max(1);
+            sorted.get(rank - 1).copied().unwrap_or_default()
+        };
+
+        LatencyReport {
+            events: sorted.len(),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: sorted.last().copied().unwrap_or_default(),
+            overruns: self.overruns,
+            skipped_ticks: self.skipped_ticks,
+        }
+    }
+
+    pub fn into_inner(self) -> M {
+        self.market
+    }
+
+    /// Records how long the previous event took to handle
+    fn finish_handling(&mut self) {
+        let Some(since) = self.handling_since.take() else {
+            return;
+        };
+        let latency = since.elapsed();
+        self.latencies.push(latency);
+
+        if let Some(budget) = self.budget.filter(|budget| latency > *budget) {
+            self.overruns += 1;
+            log::warn!(
+                "handling the event at {} took {latency:?}, over the budget of {budget:?}",
+                self.market.time()
+            );
+
+            if self.policy == OverrunPolicy::SkipTick {
+                self.skip_tick = true;
+            }
+        }
+    }
+}
+
+impl<M: Market + Send> Market for LatencyMonitor<M> {
+    type Error = M::Error;
+
+    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        self.finish_handling();
+        let event = self.market.next_event().await?;
+        self.handling_since = Some(Instant::now());
+
+        Ok(event)
+    }
+
+    async fn next_event_or_tick(
+        &mut self,
+        tick: TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), M::Error> {
+        self.finish_handling();
+        let mut event = self.market.next_event_or_tick(tick).await?;
+        if std::mem::take(&mut self.skip_tick) && event.1 == Event::Tick {
+            log::warn!("skipping the tick at {} to catch up", event.0);
+            self.skipped_ticks += 1;
+            event = self.market.next_event_or_tick(tick).await?;
+        }
+        self.handling_since = Some(Instant::now());
+
+        Ok(event)
+    }
+
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        self.finish_handling();
+        let event = self.market.next_event_until(until).await?;
+        self.handling_since = Some(Instant::now());
+
+        Ok(event)
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.market.time()
+    }
+
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, M::Error> {
+        self.market.quote_at(symbol, time).await
+    }
+
+    async fn buy_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.buy_at_market(symbol, quantity).await
+    }
+
+    async fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.sell_at_market(symbol, quantity).await
+    }
+
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, M::Error> {
+        self.market.submit_order(order).await
+    }
+
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), M::Error> {
+        self.market.submit_oco(first, second).await
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), M::Error> {
+        self.market.amend_order(id, amendment).await
+    }
+
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), M::Error> {
+        self.market.cancel_order(id).await
+    }
+
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.market.order_status(id)
+    }
+
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.market.open_orders()
+    }
+
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        self.market.set_tradeable(symbol, tradeable)
+    }
+
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        self.market.is_tradeable(symbol)
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.market.lot_size(symbol)
+    }
+
+    fn market_time(&self) -> MarketTime {
+        self.market.market_time()
+    }
+
+    fn cash(&self) -> Money {
+        self.market.cash()
+    }
+
+    fn settled_cash(&self) -> Money {
+        self.market.settled_cash()
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.market.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.market.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.market.position(symbol)
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.market.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.market.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[Lot] {
+        self.market.lots(symbol)
+    }
+
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.market.closed_lots()
+    }
+
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.market.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, M::Error> {
+        self.market.position_high_water_mark(symbol).await
+    }
+}
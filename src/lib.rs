@@ -1,8 +1,49 @@
-#![feature(async_iterator)]
-
+pub mod account;
 mod algorithm;
+pub mod align;
+pub mod bars;
+pub mod breakpoint;
+pub mod calendar;
+pub mod chaos;
+#[cfg(feature = "analytics")]
+pub mod divergence;
+pub mod domain;
+#[cfg(feature = "questdb")]
+pub mod downsample;
+#[cfg(feature = "questdb")]
+pub mod engine;
+#[cfg(feature = "analytics")]
+pub mod ensemble;
+pub mod error;
+pub mod execution;
+#[cfg(feature = "analytics")]
+pub mod export;
+pub mod ext;
+pub mod fill;
+pub mod filter;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+pub mod health;
+pub mod instrument;
+pub mod latency;
 pub mod market;
+pub mod money;
+pub mod order;
+pub mod order_builder;
+pub mod pricing;
+#[cfg(feature = "questdb")]
 pub mod questdb_market;
+pub mod quoting;
+pub mod ranking;
+#[cfg(feature = "analytics")]
+pub mod reconcile;
+pub mod replay;
+pub mod risk;
+pub mod routing;
+pub mod scanner;
+pub mod sizing;
+pub mod sync_market;
+pub mod tick;
 
 #[cfg(test)]
 mod tests;
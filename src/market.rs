@@ -2,84 +2,30 @@ use std::future::Future;
 
 use chrono::{DateTime, TimeDelta, Utc};
 use futures::future::try_join_all;
-use thiserror::Error;
-
-// TODO Add `SellCompleted` and `PurchaseCompleted` events
-#[derive(Clone, Debug, PartialEq)]
-pub enum Event {
-    Tick,
-    PreMarketStart,
-    RegularMarketStart,
-    RegularMarketEnd,
-    PostMarketEnd,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum MarketTime {
-    NotTrading,
-    PreMarket,
-    Regular,
-    PostMarket,
-    Unknown,
-}
 
-#[derive(Error, Debug)]
-pub enum ImpossibleEvent {
-    #[error("{event:?} reported during {market_time:?} market time")]
-    MarketTimeSkip {
-        event: Event,
-        market_time: MarketTime,
+pub use crate::domain::{
+    Candle, Event, EventMask, Importance, ImpossibleEvent, MarketTime, PriceQuote, PriceSource,
+};
+use crate::{
+    account::{ClosedLot, Lot, LotId, Position, Transaction},
+    money::Money,
+    order::{
+        Amendment, OcoGroupId, Order, OrderId, OrderKind, OrderStatus, PendingOrder, Side,
+        TradeReceipt, Trail,
     },
-}
-
-macro_rules! update_market_time {
-    ($self:ident, $event:ident, $current_state:expr, $next_state:expr) => {
-        if $self == &$current_state || $self == &MarketTime::Unknown {
-            *$self = $next_state;
-            Ok(())
-        } else {
-            Err(ImpossibleEvent::MarketTimeSkip {
-                event: $event.clone(),
-                market_time: $self.clone(),
-            })
-        }
-    };
-}
-
-impl MarketTime {
-    pub fn update(&mut self, event: &Event) -> Result<(), ImpossibleEvent> {
-        match event {
-            Event::PreMarketStart => {
-                update_market_time!(self, event, MarketTime::NotTrading, MarketTime::PreMarket)
-            }
-            Event::RegularMarketStart => {
-                update_market_time!(self, event, MarketTime::PreMarket, MarketTime::Regular)
-            }
-            Event::RegularMarketEnd => {
-                update_market_time!(self, event, MarketTime::Regular, MarketTime::PostMarket)
-            }
-            Event::PostMarketEnd => {
-                update_market_time!(self, event, MarketTime::PostMarket, MarketTime::NotTrading)
-            }
-            _ => Ok(()),
-        }
-    }
+    risk::{RiskEstimate, RiskMethod},
+    tick::AdaptiveTick,
+};
 
-    /// Determines if the market is currently open.
-    ///
-    /// # Returns
-    ///
-    /// * `true` if the market is open (Pre-Market, Regular, or Post-Market)
-    /// * `false` if the market is closed (any other state)
-    pub fn is_open(&self) -> bool {
-        self == &MarketTime::PreMarket
-            || self == &MarketTime::Regular
-            || self == &MarketTime::PostMarket
-    }
+/// What algorithms may tell apart about the errors of a market
+pub trait MarketError {
+    /// Whether an order was rejected because the cash could not pay for it,
+    /// so a smaller one may not be
+    fn is_insufficient_funds(&self) -> bool;
 }
 
 pub trait Market: Sync {
-    type Error: Send;
+    type Error: MarketError + Send;
 
     fn next_event(
         &mut self,
@@ -90,45 +36,456 @@ pub trait Market: Sync {
         tick: TimeDelta,
     ) -> impl Future<Output = Result<(DateTime<Utc>, Event), Self::Error>> + Send;
 
+    /// The next event at or before `until`, or `None` once the virtual time
+    /// advanced to `until` without one
+    fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Option<(DateTime<Utc>, Event)>, Self::Error>> + Send;
+
+    /// Fast-forwards the virtual time to `time` (e.g. to do nothing until
+    /// 15:30), returning the events on the way. Orders filled upon arrival
+    /// are reported by the next event.
+    fn advance_to(
+        &mut self,
+        time: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Vec<(DateTime<Utc>, Event)>, Self::Error>> {
+        async move {
+            let mut events = Vec::new();
+            while let Some(event) = self.next_event_until(time).await? {
+                events.push(event);
+            }
+
+            Ok(events)
+        }
+    }
+
+    /// Like `next_event`, skipping the events outside of `mask`. The skipped
+    /// events still take effect (e.g. sessions still start and end), and
+    /// markets may avoid looking for them in the first place.
+    fn next_event_matching(
+        &mut self,
+        mask: EventMask,
+    ) -> impl Future<Output = Result<Option<(DateTime<Utc>, Event)>, Self::Error>> {
+        async move {
+            while let Some((time, event)) = self.next_event().await? {
+                if mask.matches(&event) {
+                    return Ok(Some((time, event)));
+                }
+            }
+
+            Ok(None)
+        }
+    }
+
+    /// Like `next_event_or_tick`, with the tick chosen by `tick` from the
+    /// current holdings and open orders
+    fn next_event_or_adaptive_tick(
+        &mut self,
+        tick: AdaptiveTick,
+    ) -> impl Future<Output = Result<(DateTime<Utc>, Event), Self::Error>> + Send {
+        let tick = tick.tick(self);
+        self.next_event_or_tick(tick)
+    }
+
+    /// The current virtual time.
+    ///
+    /// Time only advances through the `&mut self` methods, so queries issued
+    /// together between two events (e.g. with `try_join!`) all observe the
+    /// same snapshot of the market.
     fn time(&self) -> DateTime<Utc>;
 
+    fn quote_at(
+        &self,
+        symbol: &str,
+        time: DateTime<Utc>,
+    ) -> impl Future<Output = Result<PriceQuote, Self::Error>> + Send;
+
+    fn current_quote(
+        &self,
+        symbol: &str,
+    ) -> impl Future<Output = Result<PriceQuote, Self::Error>> + Send {
+        self.quote_at(symbol, self.time())
+    }
+
     fn price_at(
         &self,
         symbol: &str,
         time: DateTime<Utc>,
-    ) -> impl Future<Output = Result<f64, Self::Error>> + Send;
+    ) -> impl Future<Output = Result<f64, Self::Error>> + Send {
+        async move { Ok(self.quote_at(symbol, time).await?.price) }
+    }
 
     fn current_price(&self, symbol: &str) -> impl Future<Output = Result<f64, Self::Error>> + Send {
         self.price_at(symbol, self.time())
     }
 
+    /// Places a market order, which usually fills before this returns, as
+    /// its receipt tells. Fills that happen later are reported as events.
     fn buy_at_market(
         &mut self,
         symbol: &str,
-        quantity: u32,
-    ) -> impl Future<Output = Result<(), Self::Error>>;
+        quantity: f64,
+    ) -> impl Future<Output = Result<TradeReceipt, Self::Error>>;
     fn sell_at_market(
         &mut self,
         symbol: &str,
-        quantity: u32,
+        quantity: f64,
+    ) -> impl Future<Output = Result<TradeReceipt, Self::Error>>;
+
+    /// Buys as many shares as `amount` buys at the current price, rounded
+    /// down to whole lots (which may be fractions of a share, see
+    /// `lot_size`)
+    fn buy_notional(
+        &mut self,
+        symbol: &str,
+        amount: Money,
+    ) -> impl Future<Output = Result<TradeReceipt, Self::Error>> {
+        async move {
+            let quantity = self.notional_quantity(symbol, amount).await?;
+            self.buy_at_market(symbol, quantity).await
+        }
+    }
+
+    /// Sells as many shares as are worth `amount` at the current price,
+    /// rounded down to whole lots
+    fn sell_notional(
+        &mut self,
+        symbol: &str,
+        amount: Money,
+    ) -> impl Future<Output = Result<TradeReceipt, Self::Error>> {
+        async move {
+            let quantity = self.notional_quantity(symbol, amount).await?;
+            self.sell_at_market(symbol, quantity).await
+        }
+    }
+
+    /// The number of shares worth `amount` at the current price, rounded
+    /// down to whole lots
+    fn notional_quantity(
+        &self,
+        symbol: &str,
+        amount: Money,
+    ) -> impl Future<Output = Result<f64, Self::Error>> + Send {
+        async move {
+            let price = self.current_price(symbol).await?;
+            let lot_size = self.lot_size(symbol);
+            // Without losing a lot to the rounding error of the division
+            let lots = (amount.to_f64() / price / lot_size + 1e-9).floor().max(0.0);
+
+            Ok(lots * lot_size)
+        }
+    }
+
+    /// The smallest quantity of an equity that can be traded, by default a
+    /// single share, or a fraction of one where fractional shares are
+    /// traded
+    fn lot_size(&self, symbol: &str) -> f64;
+
+    /// Places an order that rests until its price is reached (see
+    /// `PendingOrder::on_trades`), or fills right away at the current price
+    /// if it is already marketable. Fills are reported as
+    /// `Event::OrderFilled`, and orders canceled for their time in force as
+    /// `Event::OrderCanceled`.
+    fn submit_order(&mut self, order: Order) -> impl Future<Output = Result<OrderId, Self::Error>>;
+
+    /// Places two orders in the same one-cancels-other group: once either is
+    /// filled, the other is canceled with an `Event::OrderCanceled`. If the
+    /// first fills right away, the second is canceled without being placed.
+    fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> impl Future<Output = Result<(OcoGroupId, [OrderId; 2]), Self::Error>>;
+
+    /// Changes the quantity or prices of an open order in place, keeping its
+    /// ID. The amended order is validated like a new one, reported as an
+    /// `Event::OrderAmended`, and fills right away if it became marketable.
+    fn amend_order(
+        &mut self,
+        id: OrderId,
+        amendment: Amendment,
     ) -> impl Future<Output = Result<(), Self::Error>>;
 
+    /// Cancels an open order, which is reported as an `Event::OrderCanceled`.
+    /// Orders that were filled or canceled already cannot be canceled.
+    fn cancel_order(&mut self, id: OrderId) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// The status of an order submitted to this market, or `None` if there is
+    /// no such order
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus>;
+
+    /// The orders waiting to be filled
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder>;
+
+    fn buy_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        limit_price: f64,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Buy,
+            quantity,
+            OrderKind::Limit { limit_price },
+        ))
+    }
+
+    fn sell_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        limit_price: f64,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Sell,
+            quantity,
+            OrderKind::Limit { limit_price },
+        ))
+    }
+
+    fn buy_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Buy,
+            quantity,
+            OrderKind::Stop { stop_price },
+        ))
+    }
+
+    /// Sells once the price falls to `stop_price`, e.g. to cap the loss of a
+    /// position
+    fn sell_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Sell,
+            quantity,
+            OrderKind::Stop { stop_price },
+        ))
+    }
+
+    /// Buys at `limit_price` or better once the price rises to `stop_price`
+    fn buy_stop_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+        limit_price: f64,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Buy,
+            quantity,
+            OrderKind::StopLimit {
+                stop_price,
+                limit_price,
+            },
+        ))
+    }
+
+    /// Sells at `limit_price` or better once the price falls to `stop_price`
+    fn sell_stop_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+        limit_price: f64,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Sell,
+            quantity,
+            OrderKind::StopLimit {
+                stop_price,
+                limit_price,
+            },
+        ))
+    }
+
+    /// Buys once the price rebounds by `trail` from its lowest since the order
+    /// was placed
+    fn buy_trailing_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        trail: Trail,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Buy,
+            quantity,
+            OrderKind::TrailingStop { trail },
+        ))
+    }
+
+    /// Sells once the price retraces by `trail` from its highest since the
+    /// order was placed, e.g. to lock in the gains of a position
+    fn sell_trailing_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        trail: Trail,
+    ) -> impl Future<Output = Result<OrderId, Self::Error>> {
+        self.submit_order(Order::new(
+            symbol,
+            Side::Sell,
+            quantity,
+            OrderKind::TrailingStop { trail },
+        ))
+    }
+
+    /// Enables or disables trading in a symbol. Orders in a disabled symbol
+    /// are rejected until it is enabled again.
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool);
+
+    fn is_tradeable(&self, symbol: &str) -> bool;
+
     fn market_time(&self) -> MarketTime;
 
-    fn cash(&self) -> f64;
+    fn cash(&self) -> Money;
+
+    /// The cash purchases may use, without the proceeds of sales that did
+    /// not settle yet (see `with_settlement_delay` of the simulated markets)
+    fn settled_cash(&self) -> Money;
 
-    fn shares_of(&self, symbol: &str) -> u32;
+    fn shares_of(&self, symbol: &str) -> f64;
 
-    fn holdings(&self) -> impl IntoIterator<Item = (&String, &u32)>;
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)>;
+
+    /// The current position in an equity, with its cost basis, or `None` if
+    /// no shares of it are held
+    fn position(&self, symbol: &str) -> Option<Position>;
+
+    /// The profit of the shares sold so far over their average cost, net of
+    /// nothing else (e.g. fees or interest)
+    fn realized_pnl(&self) -> Money;
+
+    /// Every trade executed in this market so far, in order
+    fn transactions(&self) -> &[Transaction];
+
+    /// The purchase lots of an equity that are still held, from the oldest
+    fn lots(&self, symbol: &str) -> &[Lot];
+
+    /// The lots closed by sales so far, with their realized gains
+    fn closed_lots(&self) -> &[ClosedLot];
+
+    /// Makes the next sales of an equity close specific lots first (e.g. the
+    /// costliest, to realize a loss), before the market's lot selection
+    /// applies
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]);
+
+    /// The profit the current position in an equity would make if it was
+    /// sold at the current price, zero if no shares of it are held
+    fn unrealized_pnl(
+        &self,
+        symbol: &str,
+    ) -> impl Future<Output = Result<Money, Self::Error>> + Send {
+        async move {
+            let Some(position) = self.position(symbol) else {
+                return Ok(Money::ZERO);
+            };
+            let current_price = self.current_price(symbol).await?;
+
+            Ok(position.unrealized_pnl(current_price))
+        }
+    }
+
+    /// The unrealized profit of all the current positions
+    fn total_unrealized_pnl(&self) -> impl Future<Output = Result<Money, Self::Error>> + Send {
+        async {
+            let pnls = try_join_all(
+                self.holdings()
+                    .into_iter()
+                    .map(|(symbol, _)| self.unrealized_pnl(symbol)),
+            )
+            .await?;
+
+            Ok(pnls.iter().sum())
+        }
+    }
+
+    /// The highest price of an equity since the current position in it was
+    /// opened, or `None` if no shares of it are held.
+    fn position_high_water_mark(
+        &self,
+        symbol: &str,
+    ) -> impl Future<Output = Result<Option<f64>, Self::Error>> + Send;
+
+    /// How far the price of an equity has retraced from its high-water mark
+    /// since the current position was opened, as a fraction of it (e.g. `0.1`
+    /// after falling from 100 to 90), or `None` if no shares of it are held.
+    fn position_drawdown(
+        &self,
+        symbol: &str,
+    ) -> impl Future<Output = Result<Option<f64>, Self::Error>> + Send {
+        async move {
+            let Some(high_water_mark) = self.position_high_water_mark(symbol).await? else {
+                return Ok(None);
+            };
+            let current_price = self.current_price(symbol).await?;
+
+            Ok(Some(1.0 - current_price / high_water_mark))
+        }
+    }
+
+    /// Estimates how much of its net worth the current book could lose over
+    /// one `interval`, from the returns the same holdings would have had over
+    /// the last `periods` intervals. `None` without at least two periods.
+    fn portfolio_risk(
+        &self,
+        interval: TimeDelta,
+        periods: usize,
+        confidence: f64,
+        method: RiskMethod,
+    ) -> impl Future<Output = Result<Option<RiskEstimate>, Self::Error>> + Send {
+        async move {
+            let now = self.time();
+            let book: Vec<_> = self
+                .holdings()
+                .into_iter()
+                .map(|(symbol, quantity)| (symbol.clone(), *quantity))
+                .collect();
+            let book = &book;
+
+            let values = try_join_all((0..=periods).rev().map(|period| async move {
+                let time = now - interval * period as i32;
+                let worths = try_join_all(book.iter().map(|(symbol, quantity)| async move {
+                    Ok(Money::worth(*quantity, self.price_at(symbol, time).await?))
+                }))
+                .await?;
+
+                Ok::<_, Self::Error>(worths.iter().sum::<Money>() + self.cash())
+            }))
+            .await?;
+            let returns: Vec<f64> = values
+                .windows(2)
+                .map(|pair| pair[1].to_f64() / pair[0].to_f64() - 1.0)
+                .collect();
+
+            Ok(method.estimate(&returns, confidence))
+        }
+    }
 
-    fn net_worth(&self) -> impl std::future::Future<Output = Result<f64, Self::Error>> + Send {
+    fn net_worth(&self) -> impl std::future::Future<Output = Result<Money, Self::Error>> + Send {
         async {
             let individual_holding_worth =
                 try_join_all(self.holdings().into_iter().map(|(symbol, quantity)| async {
-                    Ok(self.current_price(symbol).await? * (*quantity as f64))
+                    Ok(Money::worth(*quantity, self.current_price(symbol).await?))
                 }))
                 .await?;
-            let gross_holdings_worth: f64 = individual_holding_worth.iter().sum();
+            let gross_holdings_worth: Money = individual_holding_worth.iter().sum();
 
             Ok(gross_holdings_worth + self.cash())
         }
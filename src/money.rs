@@ -0,0 +1,129 @@
+//! An exact amount of money, for the cash, valuations and profits that
+//! trades are added to and taken from over and over, so they do not drift
+//! the way a sum of `f64`s does over a long backtest.
+//!
+//! Every amount of money is a `Money`: the balances of `SimulatedAccount`,
+//! the cash, net worth and profit accessors of `Market`, positions' cost
+//! bases, fees and the notional amounts orders are sized by. Prices per
+//! share and quantities stay `f64`, as QuestDB stores them: they are
+//! multiplied into an amount (see `Money::worth`), which is rounded once,
+//! and never summed themselves.
+
+use std::{
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+};
+
+/// The number of units of a `Money` in a unit of currency
+const SCALE: f64 = 1e9;
+
+/// An amount of money in billionths of a unit of currency (e.g. of a dollar).
+///
+/// Amounts are rounded to a billionth once, when converted from an `f64`
+/// (such as a fill's price times its quantity), and then added and
+/// subtracted exactly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i128);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    /// Rounds an amount to a billionth, the one lossy step of adding it to
+    /// a balance
+    pub fn from_f64(amount: f64) -> Self {
+        Money((amount * SCALE).round() as i128)
+    }
+
+    /// The worth of `quantity` shares at `price` per share
+    pub fn worth(quantity: f64, price: f64) -> Self {
+        Money::from_f64(quantity * price)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE
+    }
+
+    /// The amount multiplied by `factor` (e.g. a fraction of the net worth),
+    /// rounded to a billionth
+    pub fn times(self, factor: f64) -> Self {
+        Money((self.0 as f64 * factor).round() as i128)
+    }
+
+    pub fn abs(self) -> Self {
+        Money(self.0.abs())
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// The amount rounded to a number of decimals (e.g. 2 for cents), half
+    /// away from zero
+    pub fn round_to(self, decimals: u32) -> Self {
+        let step = 10i128.pow(9u32.saturating_sub(decimals));
+        let half = step / 2 * self.0.signum();
+
+        Money((self.0 + half) / step * step)
+    }
+}
+
+impl From<Money> for f64 {
+    fn from(money: Money) -> Self {
+        money.to_f64()
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        Money(iter.map(|money| money.0).sum())
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_f64().fmt(f)
+    }
+}
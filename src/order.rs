@@ -0,0 +1,1364 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    account::{AccountError, SimulatedAccount, QUANTITY_TOLERANCE},
+    domain::MarketTime,
+    execution::OrderBookSimulator,
+    fill::{BarPrices, IntrabarFill},
+    instrument::{Currency, InstrumentRegistry},
+    market::Event,
+    money::Money,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderKind {
+    /// Fills at `limit_price` or better: the highest price to buy at, or the
+    /// lowest price to sell at
+    Limit { limit_price: f64 },
+    /// Becomes a market order once the price reaches `stop_price`: rises to
+    /// it for purchases, or falls to it for sales
+    Stop { stop_price: f64 },
+    /// Becomes a limit order once the price reaches `stop_price`
+    StopLimit { stop_price: f64, limit_price: f64 },
+    /// A stop that follows the price at a distance of `trail`: a sale stops
+    /// once the price retraces from its highest since the order was placed,
+    /// a purchase once it rebounds from its lowest
+    TrailingStop { trail: Trail },
+}
+
+/// How far a trailing stop follows the price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trail {
+    Amount(f64),
+    /// E.g. 5.0 for 5%
+    Percent(f64),
+}
+
+impl Trail {
+    fn below(self, price: f64) -> f64 {
+        match self {
+            Trail::Amount(amount) => price - amount,
+            Trail::Percent(percent) => price * (1.0 - percent / 100.0),
+        }
+    }
+
+    fn above(self, price: f64) -> f64 {
+        match self {
+            Trail::Amount(amount) => price + amount,
+            Trail::Percent(percent) => price * (1.0 + percent / 100.0),
+        }
+    }
+}
+
+/// How long an order stays in the market before it is canceled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeInForce {
+    /// Expires at the end of the regular session it was placed in
+    Day,
+    /// Rests until it is filled
+    #[default]
+    GoodTillCanceled,
+    /// Fills right away, or is canceled. Without partial fills, this is the
+    /// same as `FillOrKill`.
+    ImmediateOrCancel,
+    /// Fills completely right away, or is canceled
+    FillOrKill,
+}
+
+/// When market orders are filled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MarketFill {
+    /// Right away, at the current price
+    #[default]
+    Immediate,
+    /// At the open of the next bar, reported as an `Event::OrderFilled`, so
+    /// an algorithm that decides on a bar's prices cannot also trade at them
+    NextBarOpen,
+}
+
+/// Which sessions orders may trade in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SessionPolicy {
+    /// Only the regular session
+    RegularOnly,
+    /// The pre- and post-market sessions too, for orders that allow extended
+    /// hours. Market orders are limited to the regular session.
+    #[default]
+    Extended,
+    /// Any open session, for every order
+    All,
+}
+
+impl SessionPolicy {
+    /// Whether an order may trade during `market_time`
+    pub fn allows(&self, market_time: MarketTime, allow_extended_hours: bool) -> bool {
+        match market_time {
+            MarketTime::Regular => true,
+            MarketTime::PreMarket | MarketTime::PostMarket => match self {
+                SessionPolicy::RegularOnly => false,
+                SessionPolicy::Extended => allow_extended_hours,
+                SessionPolicy::All => true,
+            },
+            MarketTime::NotTrading | MarketTime::Unknown => false,
+        }
+    }
+}
+
+/// Caps fills at a fraction of the volume traded in the bars they fill in,
+/// so a large order is not filled at once
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VolumeLimit {
+    /// E.g. 0.1 to fill at most a tenth of a bar's volume
+    pub fraction: f64,
+    pub remainder: Remainder,
+}
+
+impl VolumeLimit {
+    /// How much of `quantity` may fill in bars of `volume`, in whole lots of
+    /// `lot_size` shares. Without volume data, everything may.
+    pub fn cap(&self, quantity: f64, volume: Option<f64>, lot_size: f64) -> f64 {
+        match volume {
+            Some(volume) => {
+                // Without losing a lot to the rounding error of the division
+                let lots = (volume * self.fraction / lot_size + 1e-9).floor().max(0.0);
+                quantity.min(lots * lot_size)
+            }
+            None => quantity,
+        }
+    }
+}
+
+/// What becomes of the part of an order beyond a bar's volume limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Remainder {
+    /// It is canceled, reported as an `Event::OrderCanceled`
+    Cancel,
+    /// It is left to fill in the following bars
+    #[default]
+    RollOver,
+}
+
+/// How the price impact of a fill grows with its share of a bar's volume
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImpactCurve {
+    Linear,
+    /// The usual empirical shape, which flattens for large participation
+    #[default]
+    SquareRoot,
+}
+
+/// Moves the price of fills that are large compared to the volume of the
+/// bar they fill in against the order, so strategies trading large capital
+/// do not fill at prices that only small orders could get
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceImpact {
+    /// The share of a bar's volume up to which fills are not impacted
+    pub threshold: f64,
+    /// The price change, as a fraction of the price, of a fill as large as
+    /// the bar's volume
+    pub coefficient: f64,
+    pub curve: ImpactCurve,
+}
+
+impl PriceImpact {
+    /// The price a fill of `quantity` shares at `price` executes at, in bars
+    /// of `volume`. Without volume data, fills are not impacted.
+    pub fn apply(&self, price: f64, side: Side, quantity: f64, volume: Option<f64>) -> f64 {
+        let Some(volume) = volume.filter(|volume| *volume > 0.0) else {
+            return price;
+        };
+        let participation = quantity / volume;
+        if participation <= self.threshold {
+            return price;
+        }
+
+        let impact = self.coefficient
+            * match self.curve {
+                ImpactCurve::Linear => participation,
+                ImpactCurve::SquareRoot => participation.sqrt(),
+            };
+        match side {
+            Side::Buy => price * (1.0 + impact),
+            Side::Sell => price * (1.0 - impact).max(0.0),
+        }
+    }
+}
+
+/// The delay between submitting a market order and its execution, so it
+/// fills at the price of a later time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Latency {
+    Fixed(TimeDelta),
+    /// Uniformly distributed between two delays
+    Random {
+        min: TimeDelta,
+        max: TimeDelta,
+    },
+}
+
+impl Latency {
+    pub fn sample(&self, rng: &mut impl Rng) -> TimeDelta {
+        match *self {
+            Latency::Fixed(delay) => delay,
+            Latency::Random { min, max } if min >= max => min,
+            Latency::Random { min, max } => TimeDelta::microseconds(rng.gen_range(
+                min.num_microseconds().unwrap_or(0)..=max.num_microseconds().unwrap_or(i64::MAX),
+            )),
+        }
+    }
+}
+
+/// A market order waiting for the next bar to open, or for its latency to
+/// pass
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueuedMarketOrder {
+    pub id: OrderId,
+    pub symbol: String,
+    pub side: Side,
+    pub quantity: f64,
+    pub submitted_at: DateTime<Utc>,
+    /// When an order delayed by the execution latency fills, at the price
+    /// of that time rather than at the next bar's open
+    pub executes_at: Option<DateTime<Utc>>,
+}
+
+/// An order that rests in a market until its price is reached
+#[derive(Clone, Debug, PartialEq)]
+pub struct Order {
+    pub symbol: String,
+    pub side: Side,
+    pub quantity: f64,
+    pub kind: OrderKind,
+    pub time_in_force: TimeInForce,
+    /// Whether the order may trade in the pre- and post-market sessions,
+    /// where the market's `SessionPolicy` permits it
+    pub allow_extended_hours: bool,
+}
+
+impl Order {
+    /// A good-till-canceled order
+    pub fn new(symbol: &str, side: Side, quantity: f64, kind: OrderKind) -> Self {
+        Order {
+            symbol: symbol.to_string(),
+            side,
+            quantity,
+            kind,
+            time_in_force: TimeInForce::default(),
+            allow_extended_hours: false,
+        }
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn with_extended_hours(mut self, allow_extended_hours: bool) -> Self {
+        self.allow_extended_hours = allow_extended_hours;
+        self
+    }
+
+    /// The price the order is expected to fill around, used to validate it
+    /// before it fills, or `None` if it follows the current price
+    pub fn reference_price(&self) -> Option<f64> {
+        match self.kind {
+            OrderKind::Limit { limit_price } => Some(limit_price),
+            OrderKind::Stop { stop_price } => Some(stop_price),
+            OrderKind::StopLimit { limit_price, .. } => Some(limit_price),
+            OrderKind::TrailingStop { .. } => None,
+        }
+    }
+
+    /// The order with all of its prices passed through `round` (e.g. to the
+    /// tick size). Trails are kept as they are.
+    pub fn round_prices<E>(self, round: impl Fn(f64) -> Result<f64, E>) -> Result<Self, E> {
+        let kind = match self.kind {
+            OrderKind::Limit { limit_price } => OrderKind::Limit {
+                limit_price: round(limit_price)?,
+            },
+            OrderKind::Stop { stop_price } => OrderKind::Stop {
+                stop_price: round(stop_price)?,
+            },
+            OrderKind::StopLimit {
+                stop_price,
+                limit_price,
+            } => OrderKind::StopLimit {
+                stop_price: round(stop_price)?,
+                limit_price: round(limit_price)?,
+            },
+            OrderKind::TrailingStop { trail } => OrderKind::TrailingStop { trail },
+        };
+
+        Ok(Order { kind, ..self })
+    }
+
+    /// The order with an amendment applied, or `None` if it amends a price
+    /// the order does not have
+    pub fn amended(&self, amendment: &Amendment) -> Option<Self> {
+        let kind = match (self.kind, amendment.limit_price, amendment.stop_price) {
+            (kind, None, None) => kind,
+            (OrderKind::Limit { limit_price }, new_limit_price, None) => OrderKind::Limit {
+                limit_price: new_limit_price.unwrap_or(limit_price),
+            },
+            (OrderKind::Stop { stop_price }, None, new_stop_price) => OrderKind::Stop {
+                stop_price: new_stop_price.unwrap_or(stop_price),
+            },
+            (
+                OrderKind::StopLimit {
+                    stop_price,
+                    limit_price,
+                },
+                new_limit_price,
+                new_stop_price,
+            ) => OrderKind::StopLimit {
+                stop_price: new_stop_price.unwrap_or(stop_price),
+                limit_price: new_limit_price.unwrap_or(limit_price),
+            },
+            _ => return None,
+        };
+
+        Some(Order {
+            quantity: amendment.quantity.unwrap_or(self.quantity),
+            kind,
+            ..self.clone()
+        })
+    }
+
+    /// The worst price the order may fill at, if it has one
+    pub fn limit_price(&self) -> Option<f64> {
+        match self.kind {
+            OrderKind::Limit { limit_price } | OrderKind::StopLimit { limit_price, .. } => {
+                Some(limit_price)
+            }
+            OrderKind::Stop { .. } | OrderKind::TrailingStop { .. } => None,
+        }
+    }
+
+    fn stop_price(&self) -> Option<f64> {
+        match self.kind {
+            OrderKind::Stop { stop_price } | OrderKind::StopLimit { stop_price, .. } => {
+                Some(stop_price)
+            }
+            OrderKind::Limit { .. } | OrderKind::TrailingStop { .. } => None,
+        }
+    }
+
+    /// Whether trades between `low` and `high` reached the stop price
+    fn is_stop_reached(&self, stop_price: f64, low: f64, high: f64) -> bool {
+        match self.side {
+            Side::Buy => high >= stop_price,
+            Side::Sell => low <= stop_price,
+        }
+    }
+
+    /// Whether trades between `low` and `high` reached the limit price
+    fn is_limit_reached(&self, limit_price: f64, low: f64, high: f64) -> bool {
+        match self.side {
+            Side::Buy => low <= limit_price,
+            Side::Sell => high >= limit_price,
+        }
+    }
+}
+
+/// Identifies an order within its market
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OrderId(pub u64);
+
+/// Identifies a one-cancels-other group of orders, in which the fill of one
+/// order cancels the others
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OcoGroupId(pub u64);
+
+/// Why a market canceled an order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CancelReason {
+    /// Another order of its one-cancels-other group was filled
+    OneCancelsOther(OcoGroupId),
+    /// A day order outlived its session
+    Expired,
+    /// An immediate-or-cancel or fill-or-kill order could not be filled
+    /// right away
+    NotFilledImmediately,
+    /// Nothing was left to trade after rounding the quantity to the lot size
+    ZeroQuantity,
+    /// The algorithm canceled it
+    Requested,
+    /// The rest of it is beyond the volume limit of the bar it filled in
+    VolumeLimit,
+    /// The account could not afford (or cover) a queued market order when
+    /// it came to fill
+    Rejected,
+    /// It did not fill by the end of the trading day
+    EndOfDay,
+}
+
+/// Changes to a resting order. Prices only apply to orders that have them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Amendment {
+    pub quantity: Option<f64>,
+    pub limit_price: Option<f64>,
+    pub stop_price: Option<f64>,
+}
+
+/// Where a resting order is in its life cycle. Filled orders leave the
+/// market's book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderState {
+    /// A stop order whose stop price was not reached yet
+    Untriggered,
+    /// Waiting to be filled: a limit order, or a triggered stop-limit order
+    Resting,
+}
+
+/// What a market order did when it was placed
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradeReceipt {
+    pub order_id: OrderId,
+    pub symbol: String,
+    /// The shares ordered, after rounding to the lot size
+    pub quantity: f64,
+    /// The price per share it filled at, or `None` if it did not fill
+    /// completely right away, in which case fills are reported as events
+    pub fill_price: Option<f64>,
+    /// The fees charged for the fill. Simulated markets charge none yet.
+    pub fees: Money,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// What became of an order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderStatus {
+    Open(OrderState),
+    Filled { price: f64 },
+    Canceled(CancelReason),
+}
+
+/// An order in a market's book of resting orders
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingOrder {
+    pub id: OrderId,
+    pub order: Order,
+    pub state: OrderState,
+    /// For trailing stops, the highest price since submission for sales, or
+    /// the lowest for purchases
+    pub extreme_price: Option<f64>,
+    pub oco_group: Option<OcoGroupId>,
+}
+
+impl PendingOrder {
+    pub fn new(id: OrderId, order: Order) -> Self {
+        let state = match order.kind {
+            OrderKind::Limit { .. } => OrderState::Resting,
+            OrderKind::Stop { .. }
+            | OrderKind::StopLimit { .. }
+            | OrderKind::TrailingStop { .. } => OrderState::Untriggered,
+        };
+
+        PendingOrder {
+            id,
+            order,
+            state,
+            extreme_price: None,
+            oco_group: None,
+        }
+    }
+
+    /// The price that triggers the order, if it has one. Trailing stops only
+    /// have one once submitted.
+    pub fn stop_price(&self) -> Option<f64> {
+        let OrderKind::TrailingStop { trail } = self.order.kind else {
+            return self.order.stop_price();
+        };
+
+        let extreme_price = self.extreme_price?;
+        Some(match self.order.side {
+            Side::Buy => trail.above(extreme_price),
+            Side::Sell => trail.below(extreme_price),
+        })
+    }
+
+    /// Updates the order with the current price when it is submitted (or
+    /// amended), returning the price it fills at right away, if it does
+    pub fn on_submit(&mut self, price: f64) -> Option<f64> {
+        if let OrderKind::TrailingStop { .. } = self.order.kind {
+            self.extreme_price.get_or_insert(price);
+            self.follow(price, price);
+        }
+
+        self.on_trades(price, price, price).map(|_| price)
+    }
+
+    /// Updates the order with trades between `low` and `high` that ended at
+    /// `close`, returning the price it fills at, if it does. This is
+    /// `on_bar` with the default assumptions, which do not depend on the
+    /// open.
+    pub fn on_trades(&mut self, low: f64, high: f64, close: f64) -> Option<f64> {
+        let bar = BarPrices {
+            open: close,
+            high,
+            low,
+            close,
+        };
+        self.on_bar(&bar, &IntrabarFill::default())
+    }
+
+    /// Updates the order with the trades of a bar, returning the price it
+    /// fills at under `fill`'s assumptions, if it does. The order stays in
+    /// its (possibly triggered) state until the fill is executed.
+    ///
+    /// Limit orders fill at their limit price. Triggered stops fill as
+    /// `fill.stop_fill` decides. A stop-limit order triggered by the trades
+    /// only fills with them if the close is within its limit, since the
+    /// order of earlier trades is unknown. For the same reason, trailing
+    /// stops only follow the trades once they did not trigger them.
+    pub fn on_bar(&mut self, bar: &BarPrices, fill: &IntrabarFill) -> Option<f64> {
+        if self.state == OrderState::Untriggered {
+            let stop_price = self.stop_price()?;
+            if !self.order.is_stop_reached(stop_price, bar.low, bar.high) {
+                self.follow(bar.low, bar.high);
+                return None;
+            }
+
+            let Some(limit_price) = self.order.limit_price() else {
+                return Some(fill.stop_fill.price(self.order.side, stop_price, bar));
+            };
+
+            self.state = OrderState::Resting;
+            return self
+                .order
+                .is_limit_reached(limit_price, bar.close, bar.close)
+                .then_some(limit_price);
+        }
+
+        let limit_price = self.order.limit_price()?;
+        fill.is_limit_reached(self.order.side, limit_price, bar)
+            .then_some(limit_price)
+    }
+
+    fn follow(&mut self, low: f64, high: f64) {
+        if let Some(extreme_price) = &mut self.extreme_price {
+            *extreme_price = match self.order.side {
+                Side::Buy => extreme_price.min(low),
+                Side::Sell => extreme_price.max(high),
+            };
+        }
+    }
+}
+
+/// The trades a pending order is matched against: those of the bars entered
+/// since it was last matched, merged into one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trades {
+    pub prices: BarPrices,
+    /// The volume of the bars, if it is known
+    pub volume: Option<f64>,
+}
+
+/// The price a queued market order executes at, in the bar it executes in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MarketOrderFill {
+    pub price: f64,
+    /// The volume of the bar, if it is known
+    pub volume: Option<f64>,
+    /// When the bar completes, after which a remainder beyond its volume
+    /// limit rolls over
+    pub bar_time: DateTime<Utc>,
+}
+
+/// An order validated by its market, with the price and volume it is placed
+/// at
+pub type PreparedOrder = (PendingOrder, f64, Option<f64>);
+
+/// What an `OrderEngine` fills orders into: the account they trade in and
+/// the (virtual) time they trade at
+pub struct FillContext<'a> {
+    pub account: &'a mut SimulatedAccount,
+    pub instruments: &'a InstrumentRegistry,
+    pub currency: &'a Currency,
+    pub time: DateTime<Utc>,
+    pub market_time: MarketTime,
+}
+
+/// The life cycle of the orders of a simulated market: submitting, queuing,
+/// matching, filling, expiring and canceling them, shared by the simulated
+/// markets, which only feed it their bars and prices.
+///
+/// The engine collects the events of the orders (fills and cancellations)
+/// for its market to report, see `take_events`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderEngine {
+    /// Orders waiting for their stop or limit price to be reached
+    pending_orders: Vec<PendingOrder>,
+    /// Market orders waiting for the next bar, with `MarketFill::NextBarOpen`
+    /// or with remainders beyond the volume limit
+    queued_market_orders: Vec<QueuedMarketOrder>,
+    /// The final status of filled and canceled orders
+    closed_orders: HashMap<OrderId, OrderStatus>,
+    /// The ID of the next order
+    next_order_id: u64,
+    /// The ID of the next one-cancels-other group
+    next_oco_group: u64,
+
+    /// When market orders are filled
+    market_fill: MarketFill,
+    /// How much of the volume of bars orders may fill
+    volume_limit: Option<VolumeLimit>,
+    /// How large fills move their price
+    price_impact: Option<PriceImpact>,
+    /// Which sessions orders may trade in
+    session_policy: SessionPolicy,
+    /// How long market orders take to execute, with the generator random
+    /// delays are drawn from
+    latency: Option<(Latency, StdRng)>,
+    /// Whether orders that are still waiting when the post-market session
+    /// ends are canceled
+    cancel_at_end_of_day: bool,
+    /// How resting orders fill within bars
+    intrabar_fill: IntrabarFill,
+    /// The queues of resting limit orders, if they are simulated
+    order_book: Option<OrderBookSimulator>,
+
+    /// The events of the orders since they were last taken
+    events: Vec<Event>,
+}
+
+impl OrderEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_market_fill(mut self, market_fill: MarketFill) -> Self {
+        self.market_fill = market_fill;
+        self
+    }
+
+    pub fn with_volume_limit(mut self, volume_limit: VolumeLimit) -> Self {
+        self.volume_limit = Some(volume_limit);
+        self
+    }
+
+    pub fn with_price_impact(mut self, price_impact: PriceImpact) -> Self {
+        self.price_impact = Some(price_impact);
+        self
+    }
+
+    pub fn with_session_policy(mut self, session_policy: SessionPolicy) -> Self {
+        self.session_policy = session_policy;
+        self
+    }
+
+    /// Delays market orders by `latency`, drawing random delays from a
+    /// generator seeded with `seed`, so a run can be reproduced
+    pub fn with_latency(mut self, latency: Latency, seed: u64) -> Self {
+        self.latency = Some((latency, StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    pub fn with_end_of_day_cancellation(mut self, enabled: bool) -> Self {
+        self.cancel_at_end_of_day = enabled;
+        self
+    }
+
+    pub fn with_intrabar_fill(mut self, intrabar_fill: IntrabarFill) -> Self {
+        self.intrabar_fill = intrabar_fill;
+        self
+    }
+
+    pub fn with_order_book(mut self, order_book: OrderBookSimulator) -> Self {
+        self.order_book = Some(order_book);
+        self
+    }
+
+    pub fn session_policy(&self) -> SessionPolicy {
+        self.session_policy
+    }
+
+    /// Whether fills depend on the volume of the bars they fill in
+    pub fn needs_volume(&self) -> bool {
+        self.volume_limit.is_some() || self.price_impact.is_some()
+    }
+
+    pub fn new_order_id(&mut self) -> OrderId {
+        self.next_order_id += 1;
+        OrderId(self.next_order_id - 1)
+    }
+
+    pub fn new_oco_group(&mut self) -> OcoGroupId {
+        self.next_oco_group += 1;
+        OcoGroupId(self.next_oco_group - 1)
+    }
+
+    /// The events of the orders since this was last called, for the market
+    /// to report at its current time
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn pending_orders(&self) -> &[PendingOrder] {
+        &self.pending_orders
+    }
+
+    pub fn pending_order(&self, id: OrderId) -> Option<&PendingOrder> {
+        self.pending_orders.iter().find(|pending| pending.id == id)
+    }
+
+    pub fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.pending_order(id)
+            .map(|pending| OrderStatus::Open(pending.state))
+            .or_else(|| {
+                self.queued_market_orders
+                    .iter()
+                    .any(|queued| queued.id == id)
+                    .then_some(OrderStatus::Open(OrderState::Resting))
+            })
+            .or_else(|| self.closed_orders.get(&id).copied())
+    }
+
+    /// Closes an order with nothing left to trade after rounding
+    pub fn cancel_empty(&mut self, id: OrderId) {
+        self.closed_orders
+            .insert(id, OrderStatus::Canceled(CancelReason::ZeroQuantity));
+    }
+
+    /// The receipt of a market order with nothing left to trade after
+    /// rounding
+    pub fn empty_order(&mut self, symbol: &str, time: DateTime<Utc>) -> TradeReceipt {
+        let id = self.new_order_id();
+        self.cancel_empty(id);
+        self.receipt(id, symbol, 0.0, time)
+    }
+
+    /// Fills a validated market order at `price`, the current price of the
+    /// market's fill model, or queues it for the next bar
+    pub fn submit_market_order(
+        &mut self,
+        context: &mut FillContext,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        volume: Option<f64>,
+    ) -> Result<TradeReceipt, AccountError> {
+        let id = self.new_order_id();
+        if self.market_fill == MarketFill::Immediate
+            && self.volume_limit.is_none()
+            && self.latency.is_none()
+        {
+            let price = self.impact(side, quantity, price, volume);
+            self.fill(context, symbol, side, quantity, price)?;
+            self.closed_orders.insert(id, OrderStatus::Filled { price });
+            return Ok(self.receipt(id, symbol, quantity, context.time));
+        }
+
+        // Validated at the current price, the best estimate of the fill
+        context
+            .account
+            .check(symbol, side, quantity, price, context.time)?;
+
+        let order = QueuedMarketOrder {
+            id,
+            symbol: symbol.to_string(),
+            side,
+            quantity,
+            submitted_at: context.time,
+            executes_at: None,
+        };
+        let delay = self
+            .latency
+            .as_mut()
+            .map(|(latency, rng)| latency.sample(rng));
+        match (self.market_fill, delay) {
+            (MarketFill::Immediate, Some(delay)) => {
+                self.queued_market_orders.push(QueuedMarketOrder {
+                    executes_at: Some(context.time + delay),
+                    ..order
+                });
+            }
+            (MarketFill::NextBarOpen, Some(delay)) => {
+                // The order reaches the market after its latency
+                self.queued_market_orders.push(QueuedMarketOrder {
+                    submitted_at: context.time + delay,
+                    ..order
+                });
+            }
+            (MarketFill::Immediate, None) => {
+                let time = context.time;
+                if let Some(price) = self.fill_within_volume(context, order, price, volume, time)? {
+                    self.closed_orders.insert(id, OrderStatus::Filled { price });
+                }
+            }
+            (MarketFill::NextBarOpen, None) => self.queued_market_orders.push(order),
+        }
+
+        Ok(self.receipt(id, symbol, quantity, context.time))
+    }
+
+    /// Fills a prepared order right away if it is marketable, or rests it,
+    /// returning whether it was filled
+    pub fn submit(
+        &mut self,
+        context: &mut FillContext,
+        (mut pending, current_price, volume): PreparedOrder,
+    ) -> Result<bool, AccountError> {
+        let order = &pending.order;
+        let quantity = self.capped_quantity(context, &order.symbol, order.quantity, volume);
+        // Fill-or-kill orders are not filled at all unless completely
+        let fillable = quantity == pending.order.quantity
+            || (quantity > 0.0 && pending.order.time_in_force != TimeInForce::FillOrKill);
+        let Some(price) = pending.on_submit(current_price).filter(|_| fillable) else {
+            match pending.order.time_in_force {
+                TimeInForce::Day | TimeInForce::GoodTillCanceled => {
+                    if let Some(book) = &mut self.order_book {
+                        book.join(&pending, current_price);
+                    }
+                    self.pending_orders.push(pending)
+                }
+                TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => {
+                    self.report_cancel(pending, CancelReason::NotFilledImmediately)
+                }
+            }
+            return Ok(false);
+        };
+
+        let order = &pending.order;
+        let price = self.impacted_price(order, quantity, price, volume);
+        self.fill(context, &order.symbol, order.side, quantity, price)?;
+        self.pending_orders.push(pending.clone());
+        self.settle_fill(self.pending_orders.len() - 1, pending, quantity, price);
+
+        Ok(true)
+    }
+
+    /// Places prepared orders as a one-cancels-other group: once one of them
+    /// fills, right away or later, the others are canceled
+    pub fn submit_oco(
+        &mut self,
+        context: &mut FillContext,
+        orders: impl IntoIterator<Item = PreparedOrder>,
+    ) -> Result<OcoGroupId, AccountError> {
+        let group = self.new_oco_group();
+
+        let mut filled = false;
+        for (mut pending, current_price, volume) in orders {
+            pending.oco_group = Some(group);
+            if filled {
+                self.report_cancel(pending, CancelReason::OneCancelsOther(group));
+            } else {
+                filled = self.submit(context, (pending, current_price, volume))?;
+            }
+        }
+
+        Ok(group)
+    }
+
+    /// Replaces a pending order with its amended version, which keeps its
+    /// place in its life cycle but loses its place in the queue, or cancels
+    /// it if nothing is left of the amended order after rounding. Returns
+    /// whether the order was pending.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AccountError` if the amended order fills right away but
+    /// the account cannot afford it, leaving the order as it was.
+    pub fn amend(
+        &mut self,
+        context: &mut FillContext,
+        id: OrderId,
+        amended: Option<PreparedOrder>,
+    ) -> Result<bool, AccountError> {
+        let Some(index) = self.pending_orders.iter().position(|p| p.id == id) else {
+            return Ok(false);
+        };
+
+        let previous = self.pending_orders.remove(index);
+        let place = self.order_book.as_mut().and_then(|book| {
+            let ahead = book.queue_ahead(id);
+            book.leave(id);
+            ahead
+        });
+        let Some((amended, current_price, volume)) = amended else {
+            self.report_cancel(previous, CancelReason::ZeroQuantity);
+            return Ok(true);
+        };
+
+        let pending = PendingOrder {
+            order: amended.order,
+            ..previous.clone()
+        };
+        let (quantity, kind) = (pending.order.quantity, pending.order.kind);
+        let events = self.events.len();
+        if let Err(error) = self.submit(context, (pending, current_price, volume)) {
+            // Rejected like a new order, so the order stays as it was
+            self.pending_orders.insert(index, previous);
+            if let (Some(book), Some(ahead)) = (&mut self.order_book, place) {
+                book.rejoin(id, ahead);
+            }
+            return Err(error);
+        }
+        // Reported before the fills of the amended order
+        self.events
+            .insert(events, Event::OrderAmended { id, quantity, kind });
+
+        Ok(true)
+    }
+
+    /// Cancels a pending order at the algorithm's request, returning whether
+    /// it was pending
+    pub fn cancel(&mut self, id: OrderId) -> bool {
+        let Some(index) = self.pending_orders.iter().position(|p| p.id == id) else {
+            return false;
+        };
+
+        let pending = self.pending_orders.remove(index);
+        self.report_cancel(pending, CancelReason::Requested);
+
+        true
+    }
+
+    /// The symbols of the pending orders that may trade during
+    /// `market_time`, whose trades `match_orders` needs
+    pub fn matching_symbols(&self, market_time: MarketTime) -> Vec<String> {
+        if !market_time.is_open() {
+            return Vec::new();
+        }
+
+        let mut symbols: Vec<_> = self
+            .pending_orders
+            .iter()
+            .filter(|pending| {
+                self.session_policy
+                    .allows(market_time, pending.order.allow_extended_hours)
+            })
+            .map(|pending| pending.order.symbol.clone())
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+
+        symbols
+    }
+
+    /// Triggers and fills the pending orders whose prices were reached by
+    /// `trades`, by symbol. Orders without trades, and orders that cannot be
+    /// afforded (or covered) anymore, keep resting.
+    pub fn match_orders(&mut self, context: &mut FillContext, trades: &HashMap<String, Trades>) {
+        if self.pending_orders.is_empty() || !context.market_time.is_open() {
+            return;
+        }
+
+        let mut index = 0;
+        while index < self.pending_orders.len() {
+            let mut pending = self.pending_orders[index].clone();
+            // Orders rest untouched through the sessions they may not trade in
+            if !self
+                .session_policy
+                .allows(context.market_time, pending.order.allow_extended_hours)
+            {
+                index += 1;
+                continue;
+            }
+
+            let trades = trades.get(&pending.order.symbol);
+            let volume = trades.and_then(|trades| trades.volume);
+            let fill_price =
+                trades.and_then(|trades| pending.on_bar(&trades.prices, &self.intrabar_fill));
+            let queued_quantity = match (&mut self.order_book, trades, fill_price) {
+                (Some(book), Some(trades), Some(_)) => book.on_trades(
+                    &pending,
+                    trades.prices.low,
+                    trades.prices.high,
+                    trades.prices.close,
+                    volume.unwrap_or_default(),
+                    context.instruments.get(&pending.order.symbol).lot_size,
+                ),
+                _ => pending.order.quantity,
+            };
+
+            let order = &pending.order;
+            let quantity = queued_quantity.min(self.capped_quantity(
+                context,
+                &order.symbol,
+                order.quantity,
+                volume,
+            ));
+            let filled = fill_price.filter(|_| quantity > 0.0).and_then(|price| {
+                let price = self.impacted_price(order, quantity, price, volume);
+                self.fill(context, &order.symbol, order.side, quantity, price)
+                    .ok()
+                    .map(|_| price)
+            });
+            match filled {
+                Some(price) => {
+                    index = self.settle_fill(index, pending, quantity, price);
+                }
+                None => {
+                    self.pending_orders[index] = pending;
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    /// The queued market orders that may execute during `market_time`, whose
+    /// fills `fill_market_orders` needs
+    pub fn queued_market_orders(&self, market_time: MarketTime) -> &[QueuedMarketOrder] {
+        if !self.session_policy.allows(market_time, false) {
+            return &[];
+        }
+
+        &self.queued_market_orders
+    }
+
+    /// Fills the queued market orders that `fills` has a fill for, by order,
+    /// within the volume limit. The others keep waiting.
+    pub fn fill_market_orders(
+        &mut self,
+        context: &mut FillContext,
+        fills: &HashMap<OrderId, MarketOrderFill>,
+    ) {
+        if !self.session_policy.allows(context.market_time, false) {
+            return;
+        }
+
+        for queued in std::mem::take(&mut self.queued_market_orders) {
+            match fills.get(&queued.id) {
+                Some(fill) => self.fill_queued_market_order(context, queued, fill),
+                None => self.queued_market_orders.push(queued),
+            }
+        }
+    }
+
+    /// Cancels the day orders once the regular session ends, and every
+    /// order still waiting once the post-market session ends, if orders are
+    /// canceled at the end of the day
+    pub fn expire_orders(&mut self, event: &Event) {
+        let end_of_day = *event == Event::PostMarketEnd && self.cancel_at_end_of_day;
+        if *event != Event::RegularMarketEnd && !end_of_day {
+            return;
+        }
+
+        let (expired, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_orders)
+            .into_iter()
+            .partition(|pending| end_of_day || pending.order.time_in_force == TimeInForce::Day);
+        self.pending_orders = kept;
+
+        let reason = if end_of_day {
+            CancelReason::EndOfDay
+        } else {
+            CancelReason::Expired
+        };
+        for pending in expired {
+            self.report_cancel(pending, reason);
+        }
+
+        if end_of_day {
+            for queued in std::mem::take(&mut self.queued_market_orders) {
+                self.closed_orders
+                    .insert(queued.id, OrderStatus::Canceled(CancelReason::EndOfDay));
+                self.events.push(Event::OrderCanceled {
+                    id: queued.id,
+                    symbol: queued.symbol,
+                    side: queued.side,
+                    quantity: queued.quantity,
+                    reason: CancelReason::EndOfDay,
+                });
+            }
+        }
+    }
+
+    /// The receipt of a market order that was just placed
+    fn receipt(
+        &self,
+        order_id: OrderId,
+        symbol: &str,
+        quantity: f64,
+        time: DateTime<Utc>,
+    ) -> TradeReceipt {
+        let fill_price = match self.closed_orders.get(&order_id) {
+            Some(OrderStatus::Filled { price }) => Some(*price),
+            _ => None,
+        };
+
+        TradeReceipt {
+            order_id,
+            symbol: symbol.to_string(),
+            quantity,
+            fill_price,
+            fees: Money::ZERO,
+            timestamp: time,
+        }
+    }
+
+    /// Settles the pending order at `index` once `quantity` of its shares
+    /// were executed: removes it if it was filled completely, or handles its
+    /// remainder otherwise. Returns the index of the next pending order.
+    fn settle_fill(
+        &mut self,
+        index: usize,
+        mut pending: PendingOrder,
+        quantity: f64,
+        price: f64,
+    ) -> usize {
+        let remaining = pending.order.quantity - quantity;
+        // Fills in fractional lots may not add up to the order exactly
+        if remaining < QUANTITY_TOLERANCE {
+            self.pending_orders.remove(index);
+            let group = pending.oco_group;
+            self.report_fill(pending, price);
+            return index - self.cancel_oco_siblings(group, index);
+        }
+
+        let order = &pending.order;
+        self.events.push(Event::OrderPartiallyFilled {
+            id: pending.id,
+            symbol: order.symbol.clone(),
+            side: order.side,
+            quantity,
+            remaining,
+            price,
+        });
+        pending.order.quantity = remaining;
+
+        // A partially filled order leaves its group, canceling the others
+        let group = pending.oco_group.take();
+        let next = match (pending.order.time_in_force, self.remainder()) {
+            (TimeInForce::ImmediateOrCancel, _) => {
+                self.pending_orders.remove(index);
+                self.report_cancel(pending, CancelReason::NotFilledImmediately);
+                index
+            }
+            (_, Remainder::Cancel) => {
+                self.pending_orders.remove(index);
+                self.report_cancel(pending, CancelReason::VolumeLimit);
+                index
+            }
+            (_, Remainder::RollOver) => {
+                self.pending_orders[index] = pending;
+                index + 1
+            }
+        };
+        next - self.cancel_oco_siblings(group, next)
+    }
+
+    /// How much of `quantity` may fill in bars of `volume`, in whole lots of
+    /// the instrument
+    fn capped_quantity(
+        &self,
+        context: &FillContext,
+        symbol: &str,
+        quantity: f64,
+        volume: Option<f64>,
+    ) -> f64 {
+        let lot_size = context.instruments.get(symbol).lot_size;
+        self.volume_limit
+            .map_or(quantity, |limit| limit.cap(quantity, volume, lot_size))
+    }
+
+    /// The price a fill of `quantity` shares at `price` executes at, in bars
+    /// of `volume`
+    fn impact(&self, side: Side, quantity: f64, price: f64, volume: Option<f64>) -> f64 {
+        self.price_impact
+            .map_or(price, |impact| impact.apply(price, side, quantity, volume))
+    }
+
+    /// Like `impact`, but never worse than the limit price of an order
+    fn impacted_price(&self, order: &Order, quantity: f64, price: f64, volume: Option<f64>) -> f64 {
+        let price = self.impact(order.side, quantity, price, volume);
+        match (order.side, order.limit_price()) {
+            (Side::Buy, Some(limit_price)) => price.min(limit_price),
+            (Side::Sell, Some(limit_price)) => price.max(limit_price),
+            (_, None) => price,
+        }
+    }
+
+    fn remainder(&self) -> Remainder {
+        self.volume_limit
+            .map(|limit| limit.remainder)
+            .unwrap_or_default()
+    }
+
+    /// Fills as much of a market order at `price` as the volume limit allows,
+    /// rolling the rest over to the bar after `bar_time` or canceling it.
+    /// Returns the price of the last fill if the order was filled completely.
+    fn fill_within_volume(
+        &mut self,
+        context: &mut FillContext,
+        order: QueuedMarketOrder,
+        price: f64,
+        volume: Option<f64>,
+        bar_time: DateTime<Utc>,
+    ) -> Result<Option<f64>, AccountError> {
+        let quantity = self.capped_quantity(context, &order.symbol, order.quantity, volume);
+        let price = self.impact(order.side, quantity, price, volume);
+        if quantity > 0.0 {
+            self.fill(context, &order.symbol, order.side, quantity, price)?;
+        }
+
+        let remaining = order.quantity - quantity;
+        if remaining < QUANTITY_TOLERANCE {
+            return Ok(Some(price));
+        }
+
+        if quantity > 0.0 {
+            self.events.push(Event::OrderPartiallyFilled {
+                id: order.id,
+                symbol: order.symbol.clone(),
+                side: order.side,
+                quantity,
+                remaining,
+                price,
+            });
+        }
+        match self.remainder() {
+            Remainder::Cancel => {
+                self.closed_orders
+                    .insert(order.id, OrderStatus::Canceled(CancelReason::VolumeLimit));
+                self.events.push(Event::OrderCanceled {
+                    id: order.id,
+                    symbol: order.symbol,
+                    side: order.side,
+                    quantity: remaining,
+                    reason: CancelReason::VolumeLimit,
+                });
+            }
+            Remainder::RollOver => self.queued_market_orders.push(QueuedMarketOrder {
+                quantity: remaining,
+                submitted_at: bar_time,
+                executes_at: None,
+                ..order
+            }),
+        }
+
+        Ok(None)
+    }
+
+    fn fill_queued_market_order(
+        &mut self,
+        context: &mut FillContext,
+        queued: QueuedMarketOrder,
+        fill: &MarketOrderFill,
+    ) {
+        let (id, symbol, side, quantity) = (
+            queued.id,
+            queued.symbol.clone(),
+            queued.side,
+            queued.quantity,
+        );
+        match self.fill_within_volume(context, queued, fill.price, fill.volume, fill.bar_time) {
+            Ok(Some(price)) => {
+                self.closed_orders.insert(id, OrderStatus::Filled { price });
+                self.events.push(Event::OrderFilled {
+                    id,
+                    symbol,
+                    side,
+                    quantity,
+                    price,
+                });
+            }
+            Ok(None) => {}
+            Err(error) => {
+                log::warn!("{}: rejecting a queued market order: {error}", context.time);
+                self.closed_orders
+                    .insert(id, OrderStatus::Canceled(CancelReason::Rejected));
+                self.events.push(Event::OrderCanceled {
+                    id,
+                    symbol,
+                    side,
+                    quantity,
+                    reason: CancelReason::Rejected,
+                });
+            }
+        }
+    }
+
+    /// Cancels the pending orders of a one-cancels-other group, returning
+    /// how many of them were before `index` in the pending orders
+    fn cancel_oco_siblings(&mut self, group: Option<OcoGroupId>, index: usize) -> usize {
+        let Some(group) = group else {
+            return 0;
+        };
+
+        let (canceled, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_orders)
+            .into_iter()
+            .enumerate()
+            .partition(|(_, pending)| pending.oco_group == Some(group));
+        self.pending_orders = kept.into_iter().map(|(_, pending)| pending).collect();
+
+        let canceled_before_index = canceled.iter().filter(|(i, _)| *i < index).count();
+        for (_, pending) in canceled {
+            self.report_cancel(pending, CancelReason::OneCancelsOther(group));
+        }
+
+        canceled_before_index
+    }
+
+    fn report_fill(&mut self, pending: PendingOrder, price: f64) {
+        self.closed_orders
+            .insert(pending.id, OrderStatus::Filled { price });
+        if let Some(book) = &mut self.order_book {
+            book.leave(pending.id);
+        }
+
+        let order = pending.order;
+        self.events.push(Event::OrderFilled {
+            id: pending.id,
+            symbol: order.symbol,
+            side: order.side,
+            quantity: order.quantity,
+            price,
+        });
+    }
+
+    fn report_cancel(&mut self, pending: PendingOrder, reason: CancelReason) {
+        self.closed_orders
+            .insert(pending.id, OrderStatus::Canceled(reason));
+        if let Some(book) = &mut self.order_book {
+            book.leave(pending.id);
+        }
+
+        let order = pending.order;
+        self.events.push(Event::OrderCanceled {
+            id: pending.id,
+            symbol: order.symbol,
+            side: order.side,
+            quantity: order.quantity,
+            reason,
+        });
+    }
+
+    /// Executes a trade at a price, updating the cash and the holdings
+    fn fill(
+        &mut self,
+        context: &mut FillContext,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+        price: f64,
+    ) -> Result<(), AccountError> {
+        context
+            .account
+            .fill(symbol, side, quantity, price, context.time)?;
+
+        log::debug!(
+            "{}: {} {} {symbol} at {}, cash {}",
+            context.time,
+            match side {
+                Side::Buy => "bought",
+                Side::Sell => "sold",
+            },
+            context.instruments.format_quantity(symbol, quantity),
+            context.instruments.format_price(symbol, price),
+            context.currency.format(context.account.cash())
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! A builder of orders that checks at compile time that every order has a
+//! side, a symbol and a quantity, and only takes the options its kind
+//! supports, e.g.
+//! `OrderBuilder::limit(10.0).buy("STOCK", 5.0).tif(TimeInForce::Day).build()`.
+//!
+//! Market orders fill right away or wait for the next bar (see
+//! `MarketFill`), so they take no time in force and cannot trade in the
+//! extended sessions.
+
+use crate::order::{Order, OrderKind, Side, TimeInForce, Trail};
+
+/// The kind of a market order
+pub struct AtMarket;
+
+/// The kind of an order that rests until its price is reached
+pub struct Resting(OrderKind);
+
+/// An order without a side yet
+pub struct Unsided;
+
+/// An order with its side, symbol and quantity
+pub struct Sided {
+    symbol: String,
+    side: Side,
+    quantity: f64,
+}
+
+/// A market order, executed with `Market::buy_at_market` or
+/// `Market::sell_at_market` (see `MarketExt::submit_market_order`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarketOrder {
+    pub symbol: String,
+    pub side: Side,
+    pub quantity: f64,
+}
+
+pub struct OrderBuilder<K, S> {
+    kind: K,
+    sided: S,
+    time_in_force: TimeInForce,
+    allow_extended_hours: bool,
+}
+
+impl OrderBuilder<AtMarket, Unsided> {
+    pub fn market() -> Self {
+        OrderBuilder::of(AtMarket)
+    }
+}
+
+impl OrderBuilder<Resting, Unsided> {
+    pub fn limit(limit_price: f64) -> Self {
+        OrderBuilder::of(Resting(OrderKind::Limit { limit_price }))
+    }
+
+    pub fn stop(stop_price: f64) -> Self {
+        OrderBuilder::of(Resting(OrderKind::Stop { stop_price }))
+    }
+
+    pub fn stop_limit(stop_price: f64, limit_price: f64) -> Self {
+        OrderBuilder::of(Resting(OrderKind::StopLimit {
+            stop_price,
+            limit_price,
+        }))
+    }
+
+    pub fn trailing_stop(trail: Trail) -> Self {
+        OrderBuilder::of(Resting(OrderKind::TrailingStop { trail }))
+    }
+}
+
+impl<K> OrderBuilder<K, Unsided> {
+    fn of(kind: K) -> Self {
+        OrderBuilder {
+            kind,
+            sided: Unsided,
+            time_in_force: TimeInForce::default(),
+            allow_extended_hours: false,
+        }
+    }
+
+    pub fn buy(self, symbol: &str, quantity: f64) -> OrderBuilder<K, Sided> {
+        self.side(symbol, Side::Buy, quantity)
+    }
+
+    pub fn sell(self, symbol: &str, quantity: f64) -> OrderBuilder<K, Sided> {
+        self.side(symbol, Side::Sell, quantity)
+    }
+
+    fn side(self, symbol: &str, side: Side, quantity: f64) -> OrderBuilder<K, Sided> {
+        OrderBuilder {
+            kind: self.kind,
+            sided: Sided {
+                symbol: symbol.to_string(),
+                side,
+                quantity,
+            },
+            time_in_force: self.time_in_force,
+            allow_extended_hours: self.allow_extended_hours,
+        }
+    }
+}
+
+impl<S> OrderBuilder<Resting, S> {
+    /// By default, good till canceled
+    pub fn tif(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    /// Lets the order trade in the pre- and post-market sessions, where the
+    /// market's `SessionPolicy` permits it
+    pub fn extended_hours(mut self) -> Self {
+        self.allow_extended_hours = true;
+        self
+    }
+}
+
+impl OrderBuilder<Resting, Sided> {
+    pub fn build(self) -> Order {
+        let Sided {
+            symbol,
+            side,
+            quantity,
+        } = self.sided;
+
+        Order::new(&symbol, side, quantity, self.kind.0)
+            .with_time_in_force(self.time_in_force)
+            .with_extended_hours(self.allow_extended_hours)
+    }
+}
+
+impl OrderBuilder<AtMarket, Sided> {
+    pub fn build(self) -> MarketOrder {
+        let Sided {
+            symbol,
+            side,
+            quantity,
+        } = self.sided;
+
+        MarketOrder {
+            symbol,
+            side,
+            quantity,
+        }
+    }
+}
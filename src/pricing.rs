@@ -0,0 +1,171 @@
+//! Option pricing models, for marking options between sparse trades and
+//! modelling exercise at expiry.
+
+use chrono::TimeDelta;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionKind {
+    Call,
+    Put,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExerciseStyle {
+    /// Exercisable only at expiry
+    European,
+    /// Exercisable at any time until expiry
+    American,
+}
+
+/// The inputs of an option pricing model. Rates and volatilities are
+/// annualized, and the underlying is assumed to pay no dividends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptionParameters {
+    pub kind: OptionKind,
+    pub spot: f64,
+    pub strike: f64,
+    /// In years, see `years`
+    pub time_to_expiry: f64,
+    /// The continuously compounded risk-free rate
+    pub rate: f64,
+    pub volatility: f64,
+}
+
+impl OptionParameters {
+    /// The value of exercising the option right away
+    pub fn intrinsic_value(&self) -> f64 {
+        intrinsic_value(self.kind, self.spot, self.strike)
+    }
+
+    /// The Black-Scholes price of a European option
+    pub fn black_scholes(&self) -> f64 {
+        if self.time_to_expiry <= 0.0 || self.volatility <= 0.0 {
+            let discounted_strike = self.strike * (-self.rate * self.time_to_expiry.max(0.0)).exp();
+            return intrinsic_value(self.kind, self.spot, discounted_strike);
+        }
+
+        let deviation = self.volatility * self.time_to_expiry.sqrt();
+        let d1 = ((self.spot / self.strike).ln()
+            + (self.rate + self.volatility * self.volatility / 2.0) * self.time_to_expiry)
+            / deviation;
+        let d2 = d1 - deviation;
+        let discounted_strike = self.strike * (-self.rate * self.time_to_expiry).exp();
+
+        match self.kind {
+            OptionKind::Call => self.spot * normal_cdf(d1) - discounted_strike * normal_cdf(d2),
+            OptionKind::Put => discounted_strike * normal_cdf(-d2) - self.spot * normal_cdf(-d1),
+        }
+    }
+
+    /// The price of an option on a Cox-Ross-Rubinstein binomial tree with
+    /// `steps` steps until expiry, which (unlike Black-Scholes) also prices
+    /// early exercise.
+    pub fn binomial(&self, style: ExerciseStyle, steps: usize) -> f64 {
+        if self.time_to_expiry <= 0.0 || self.volatility <= 0.0 || steps == 0 {
+            return self.black_scholes();
+        }
+
+        let step = self.time_to_expiry / steps as f64;
+        let up = (self.volatility * step.sqrt()).exp();
+        let down = 1.0 / up;
+        let discount = (-self.rate * step).exp();
+        let up_probability = ((self.rate * step).exp() - down) / (up - down);
+
+        let spot_at = |step_index: usize, ups: usize| {
+            self.spot * up.powi(ups as i32) * down.powi((step_index - ups) as i32)
+        };
+
+        let mut values: Vec<f64> = (0..=steps)
+            .map(|ups| intrinsic_value(self.kind, spot_at(steps, ups), self.strike))
+            .collect();
+
+        for step_index in (0..steps).rev() {
+            for ups in 0..=step_index {
+                let continuation = discount
+                    * (up_probability * values[ups + 1] + (1.0 - up_probability) * values[ups]);
+
+                values[ups] = match style {
+                    ExerciseStyle::European => continuation,
+                    ExerciseStyle::American => continuation.max(intrinsic_value(
+                        self.kind,
+                        spot_at(step_index, ups),
+                        self.strike,
+                    )),
+                };
+            }
+        }
+
+        values[0]
+    }
+
+    /// The volatility at which the Black-Scholes price matches `price`
+    /// (ignoring `self.volatility`), or `None` if no volatility does, e.g.
+    /// because the price is below the option's lower bound.
+    pub fn implied_volatility(&self, price: f64) -> Option<f64> {
+        let price_with = |volatility: f64| {
+            OptionParameters {
+                volatility,
+                ..*self
+            }
+            .black_scholes()
+        };
+
+        let (mut low, mut high) = (1e-6, 10.0);
+        if price < price_with(low) || price > price_with(high) {
+            return None;
+        }
+
+        // The price increases monotonically with the volatility
+        for _ in 0..100 {
+            let middle = (low + high) / 2.0;
+            if price_with(middle) < price {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        Some((low + high) / 2.0)
+    }
+}
+
+/// The value of exercising an option at a spot price
+pub fn intrinsic_value(kind: OptionKind, spot: f64, strike: f64) -> f64 {
+    match kind {
+        OptionKind::Call => (spot - strike).max(0.0),
+        OptionKind::Put => (strike - spot).max(0.0),
+    }
+}
+
+/// Converts a duration (e.g. until expiry) to years of 365 days
+pub fn years(duration: TimeDelta) -> f64 {
+    duration.num_milliseconds() as f64 / TimeDelta::days(365).num_milliseconds() as f64
+}
+
+/// The cumulative distribution function of the standard normal distribution
+pub(crate) fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+/// The complementary error function, with a fractional error below 1.2e-7
+/// (Numerical Recipes' `erfcc`)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let result = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
@@ -1,11 +1,36 @@
-use std::collections::{HashMap, LinkedList};
+use std::{
+    collections::{HashMap, HashSet, LinkedList},
+    ops::Range,
+};
 
-use chrono::{DateTime, DurationRound as _, NaiveDateTime, Utc};
-use thiserror::Error;
+use chrono::{DateTime, DurationRound as _, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use futures::future::try_join_all;
 use tokio::try_join;
-use tokio_postgres::Statement;
+use tokio_postgres::{types::ToSql, Row, Statement};
 
-use crate::market::{Event, ImpossibleEvent, Market, MarketTime};
+use crate::{
+    account::{
+        ClosedLot, DayTradeLimit, Lot, LotId, LotSelection, Margin, Position, SimulatedAccount,
+        Transaction,
+    },
+    bars::{BarEvents, BarType},
+    calendar::next_us_equity_trading_time,
+    downsample::{sample_by_interval, Resolution},
+    error::check_quantity,
+    execution::{OrderBookSimulator, SyntheticDepth},
+    fill::{AtClose, BarPrices, FillModel, IntrabarFill},
+    instrument::{Currency, InstrumentRegistry},
+    market::{Candle, Event, EventMask, Importance, Market, MarketTime, PriceQuote, PriceSource},
+    money::Money,
+    order::{
+        Amendment, FillContext, Latency, MarketFill, MarketOrderFill, OcoGroupId, Order,
+        OrderEngine, OrderId, OrderStatus, PendingOrder, PreparedOrder, PriceImpact, SessionPolicy,
+        Side, TradeReceipt, Trades, VolumeLimit,
+    },
+    scanner::{Scanner, ScannerHistory},
+};
+
+pub use crate::error::Error;
 
 pub struct QuestDbMarket<'a> {
     /// A database client
@@ -18,70 +43,119 @@ pub struct QuestDbMarket<'a> {
     /// All the following events. This does not include system events and ticks.
     events: LinkedList<(DateTime<Utc>, Event)>,
 
-    // TODO seperate `cash` to `available_cash` and `locked_cash` (or some other name). =
-    // available_cash will be subtracted from when submitting an order, and added to
-    // locked_cash. Upon trade complete, this will be updated.
-    /// The amount of cash on hand
-    cash: f64,
-    /// How many shares of each equity are owned, by symbol
-    holdings: HashMap<String, u32>,
+    /// The simulated cash and positions
+    account: SimulatedAccount,
+    /// Symbols in which trading is currently disabled
+    untradeable: HashSet<String>,
+    /// The pending, queued and closed orders
+    orders: OrderEngine,
+    /// The price market orders fill at in the current bar
+    fill_model: Box<dyn FillModel>,
+    /// What quotes are derived from
+    price_source: PriceSource,
+    /// Tick and lot sizes that orders are aligned to
+    instruments: InstrumentRegistry,
+    /// The currency cash is held in
+    currency: Currency,
+    /// How old a quote may be outside of trading hours before it is
+    /// considered stale
+    max_quote_age: Option<TimeDelta>,
+
+    /// Resolutions that pre-aggregated bar tables exist for
+    downsampled: Vec<Resolution>,
+    /// The New York time of day ticks are at, if the market advances once
+    /// per trading day on daily bars
+    daily: Option<NaiveTime>,
+    /// The table quotes and fills are read from
+    price_table: &'static str,
+    /// How long after their timestamp bars of the price table are complete,
+    /// and so visible
+    bar_delay: TimeDelta,
+
+    /// How much virtual time passes between recorded snapshots, if they
+    /// are recorded
+    snapshot_interval: Option<TimeDelta>,
+    /// Snapshots of the market's state, from oldest to newest
+    snapshots: Vec<MarketSnapshot>,
 
     /// A prepared statement for querying the N most recent trade prices
     /// of an equity
     price_query_statement: Statement,
     /// A prepared statement for qureying the next system event
     system_event_query_statement: Statement,
+    /// Upcoming earnings reports, if an earnings calendar was loaded
+    earnings_calendar: Option<EarningsCalendar>,
+    /// Upcoming macroeconomic announcements, if a macro calendar was loaded
+    macro_calendar: Option<MacroCalendar>,
+    /// Upcoming funding of perpetual swaps, if funding rates were loaded
+    funding_rates: Option<FundingRates>,
+    /// The time and symbol of the last funding that was settled
+    funded_until: Option<(DateTime<Utc>, String)>,
+    /// The time and symbol of the last earnings report that was reported
+    reported_until: Option<(DateTime<Utc>, String)>,
+    /// The time and name of the last macroeconomic announcement that was
+    /// reported (ahead of it)
+    announced_until: Option<(DateTime<Utc>, String)>,
+    /// Scans the universe for setups on every bar, if a scanner was added
+    scanner: Option<Scanner>,
+    /// The end of the last bar the scanner was given
+    scanned_until: Option<DateTime<Utc>>,
+    /// The bars reported as `Event::BarClosed` events, if any
+    bar_events: Option<BarEvents>,
+    /// The end of the last bar reported as closed
+    bars_closed_until: Option<DateTime<Utc>>,
 }
 
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error("PostgreSQL error")]
-    DatabaseError(#[from] tokio_postgres::Error),
-
-    #[error("Attempted to trade {0} at {1}, outside of trading hours")]
-    UntimelyTrade(String, DateTime<Utc>),
-
-    #[error("Attempted to trade {0} yet the price is unknown")]
-    UnknownPrice(String),
-
-    #[error("Cannot buy {quantity} shares of {symbol} for {total_price} with {cash} in cash")]
-    InsufficientCash {
-        quantity: u32,
-        symbol: String,
-        total_price: f64,
-        cash: f64,
-    },
+/// The full simulated state of a `QuestDbMarket` at some virtual time, from
+/// which a backtest can be resumed
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarketSnapshot {
+    pub time: DateTime<Utc>,
+    pub market_time: MarketTime,
+    pub events: LinkedList<(DateTime<Utc>, Event)>,
 
-    #[error("Cannot sell {quantity} shares of {symbol} because only {owned} shares are owned")]
-    InsufficientShares {
-        quantity: u32,
-        symbol: String,
-        owned: u32,
-    },
+    pub account: SimulatedAccount,
+    pub untradeable: HashSet<String>,
+    pub orders: OrderEngine,
+    pub funded_until: Option<(DateTime<Utc>, String)>,
+    pub reported_until: Option<(DateTime<Utc>, String)>,
+    pub announced_until: Option<(DateTime<Utc>, String)>,
+    /// The bars kept by the scanner, if one was added
+    pub scanner_history: Option<ScannerHistory>,
+    pub scanned_until: Option<DateTime<Utc>>,
+    pub bars_closed_until: Option<DateTime<Utc>>,
+}
 
-    #[error(
-        "Symbol '{symbol}' found in database, which is not of the expected kind, {expected_kind}"
-    )]
-    UnexpectedDatabaseSymbol {
-        symbol: String,
-        expected_kind: String,
-    },
+struct EarningsCalendar {
+    /// A prepared statement for querying the next earnings report of any
+    /// equity after a time and symbol
+    next_report_statement: Statement,
+    /// A prepared statement for querying the next earnings report of a
+    /// specific equity
+    next_symbol_report_statement: Statement,
+    /// How long before an earnings report purchases are blocked
+    blackout: Option<TimeDelta>,
+}
 
-    #[error("Impossible event, internal logic fault")]
-    ImpossibleEvent(#[from] ImpossibleEvent),
+struct FundingRates {
+    /// A prepared statement for querying the next funding of any perpetual
+    /// after a time and symbol
+    next_funding_statement: Statement,
+}
 
-    #[error("Tried to query data from {future_time} at {current_time}")]
-    FutureQuery {
-        future_time: DateTime<Utc>,
-        current_time: DateTime<Utc>,
-    },
+struct MacroCalendar {
+    /// A prepared statement for querying the next macroeconomic announcement
+    /// after a time and name
+    next_announcement_statement: Statement,
+    /// How long before an announcement its event is reported
+    lead: TimeDelta,
 }
 
 impl<'a> QuestDbMarket<'a> {
     pub async fn new(
         database: &'a tokio_postgres::Client,
         start: DateTime<Utc>,
-        cash: f64,
+        cash: Money,
     ) -> Result<Self, Error> {
         let (price_query_statement, system_event_query_statement) = try_join!(
             database.prepare(
@@ -99,14 +173,620 @@ impl<'a> QuestDbMarket<'a> {
             market_time: MarketTime::Unknown,
             events: LinkedList::new(),
 
-            cash,
-            holdings: HashMap::new(),
+            account: SimulatedAccount::new(cash),
+            untradeable: HashSet::new(),
+            orders: OrderEngine::new(),
+            fill_model: Box::new(AtClose),
+            price_source: PriceSource::default(),
+            instruments: InstrumentRegistry::default(),
+            currency: Currency::default(),
+            max_quote_age: None,
+            downsampled: Vec::new(),
+            daily: None,
+            price_table: "prices",
+            bar_delay: TimeDelta::zero(),
+
+            snapshot_interval: None,
+            snapshots: Vec::new(),
 
             price_query_statement,
             system_event_query_statement,
+            earnings_calendar: None,
+            macro_calendar: None,
+            funding_rates: None,
+            funded_until: None,
+            reported_until: None,
+            announced_until: None,
+            scanner: None,
+            scanned_until: None,
+            bar_events: None,
+            bars_closed_until: None,
         })
     }
 
+    /// Records a snapshot of the market every `interval` of virtual time, so
+    /// a suspicious part of a long backtest can be investigated by
+    /// restoring the latest snapshot before it (e.g. with debug logging
+    /// enabled) instead of replaying it from the start.
+    pub fn with_snapshot_interval(mut self, interval: TimeDelta) -> Self {
+        self.snapshot_interval = Some(interval);
+        self
+    }
+
+    /// Captures the market's current state
+    pub fn snapshot(&self) -> MarketSnapshot {
+        MarketSnapshot {
+            time: self.time,
+            market_time: self.market_time,
+            events: self.events.clone(),
+
+            account: self.account.clone(),
+            untradeable: self.untradeable.clone(),
+            orders: self.orders.clone(),
+            funded_until: self.funded_until.clone(),
+            reported_until: self.reported_until.clone(),
+            announced_until: self.announced_until.clone(),
+            scanner_history: self.scanner.as_ref().map(Scanner::history),
+            scanned_until: self.scanned_until,
+            bars_closed_until: self.bars_closed_until,
+        }
+    }
+
+    /// Returns the market to a previously captured state. Recorded snapshots
+    /// after it are discarded, as they are about to be replayed.
+    ///
+    /// Only the market is restored; the algorithm must be brought to the
+    /// matching state (or restarted) separately.
+    pub fn restore(&mut self, snapshot: MarketSnapshot) {
+        self.snapshots
+            .retain(|recorded| recorded.time <= snapshot.time);
+
+        self.time = snapshot.time;
+        self.market_time = snapshot.market_time;
+        self.events = snapshot.events;
+
+        self.account = snapshot.account;
+        self.untradeable = snapshot.untradeable;
+        self.orders = snapshot.orders;
+        self.funded_until = snapshot.funded_until;
+        self.reported_until = snapshot.reported_until;
+        self.announced_until = snapshot.announced_until;
+        if let (Some(scanner), Some(history)) = (&mut self.scanner, snapshot.scanner_history) {
+            scanner.restore_history(history);
+        }
+        self.scanned_until = snapshot.scanned_until;
+        self.bars_closed_until = snapshot.bars_closed_until;
+    }
+
+    /// The recorded snapshots, from oldest to newest
+    pub fn snapshots(&self) -> &[MarketSnapshot] {
+        &self.snapshots
+    }
+
+    /// The latest recorded snapshot taken at or before `time`
+    pub fn snapshot_before(&self, time: DateTime<Utc>) -> Option<&MarketSnapshot> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.time <= time)
+    }
+
+    fn record_snapshot(&mut self) {
+        let Some(interval) = self.snapshot_interval else {
+            return;
+        };
+
+        let due = match self.snapshots.last() {
+            Some(last) => self.time - last.time >= interval,
+            None => true,
+        };
+        if due {
+            self.snapshots.push(self.snapshot());
+        }
+    }
+
+    /// Declares which pre-aggregated bar tables exist (see
+    /// `downsample::downsample`), so `candles` can read from the coarsest
+    /// suitable one instead of the `prices` table.
+    pub fn with_downsampled(mut self, resolutions: impl IntoIterator<Item = Resolution>) -> Self {
+        self.downsampled = resolutions.into_iter().collect();
+        self
+    }
+
+    /// Advances the market once per trading day, at `at` New York time,
+    /// whatever tick is asked for, and reads quotes and fills from the
+    /// daily bars of `Resolution::OneDay` instead of the `prices` table, so
+    /// years of daily decisions are replayed without simulating every
+    /// minute in between.
+    ///
+    /// Daily bars only become visible once their day is over, so the price
+    /// at a tick is the previous trading day's close. Session candles (see
+    /// `daily_candle`) are still aggregated from the `prices` table.
+    pub async fn with_daily_resolution(mut self, at: NaiveTime) -> Result<Self, Error> {
+        let resolution = Resolution::OneDay;
+        self.price_query_statement = self
+            .db_client
+            .prepare(&format!(
+                "SELECT * FROM {} WHERE timestamp <= $1::TIMESTAMP AND symbol = $2::TEXT ORDER BY timestamp DESC LIMIT $3::INT;",
+                resolution.table()
+            ))
+            .await?;
+        self.daily = Some(at);
+        self.price_table = resolution.table();
+        self.bar_delay = resolution.duration();
+        if !self.downsampled.contains(&resolution) {
+            self.downsampled.push(resolution);
+        }
+
+        Ok(self)
+    }
+
+    /// The bound on the timestamps of the bars of the price table that are
+    /// complete at a time, as a query parameter
+    fn bar_cutoff(&self, time: DateTime<Utc>) -> f64 {
+        (time - self.bar_delay).timestamp_micros() as f64
+    }
+
+    /// Loads the `earnings` table, so earnings reports are reported as
+    /// `Event::Earnings` events and through `next_earnings`.
+    ///
+    /// If a `blackout` is given, purchases of an equity are rejected when
+    /// its next earnings report is due within it.
+    pub async fn with_earnings_calendar(
+        mut self,
+        blackout: Option<TimeDelta>,
+    ) -> Result<Self, Error> {
+        let (next_report_statement, next_symbol_report_statement) = try_join!(
+            self.db_client.prepare(
+                "SELECT * FROM earnings WHERE timestamp > $1::TIMESTAMP OR (timestamp = $1::TIMESTAMP AND symbol > $2::TEXT) ORDER BY timestamp ASC, symbol ASC LIMIT 1;"
+            ),
+            self.db_client.prepare(
+                "SELECT * FROM earnings WHERE timestamp > $1::TIMESTAMP AND symbol = $2::TEXT ORDER BY timestamp ASC LIMIT 1;"
+            ),
+        )?;
+
+        self.earnings_calendar = Some(EarningsCalendar {
+            next_report_statement,
+            next_symbol_report_statement,
+            blackout,
+        });
+
+        Ok(self)
+    }
+
+    /// Loads the `macro_events` table, so scheduled macroeconomic
+    /// announcements are reported as `Event::Macro` events, `lead` before
+    /// they are due.
+    pub async fn with_macro_calendar(mut self, lead: TimeDelta) -> Result<Self, Error> {
+        let next_announcement_statement = self
+            .db_client
+            .prepare(
+                "SELECT * FROM macro_events WHERE timestamp > $1::TIMESTAMP OR (timestamp = $1::TIMESTAMP AND name > $2::TEXT) ORDER BY timestamp ASC, name ASC LIMIT 1;",
+            )
+            .await?;
+
+        self.macro_calendar = Some(MacroCalendar {
+            next_announcement_statement,
+            lead,
+        });
+
+        Ok(self)
+    }
+
+    /// Loads the `funding_rates` table (with `symbol`, `rate` and `timestamp`
+    /// columns) of perpetual swaps, whose funding is settled at each of its
+    /// timestamps and reported as `Event::FundingPayment` events. Held
+    /// positions pay their value times a positive rate, and receive it for a
+    /// negative one.
+    pub async fn with_funding_rates(mut self) -> Result<Self, Error> {
+        let next_funding_statement = self
+            .db_client
+            .prepare(
+                "SELECT * FROM funding_rates WHERE timestamp > $1::TIMESTAMP OR (timestamp = $1::TIMESTAMP AND symbol > $2::TEXT) ORDER BY timestamp ASC, symbol ASC LIMIT 1;",
+            )
+            .await?;
+
+        self.funding_rates = Some(FundingRates {
+            next_funding_statement,
+        });
+
+        Ok(self)
+    }
+
+    /// Evaluates the rules of a scanner against the universe whenever a bar
+    /// of its interval closes, reporting matches as `Event::ScannerHit`
+    /// events right after the event the bar closed at
+    pub fn with_scanner(mut self, scanner: Scanner) -> Self {
+        self.scanner = Some(scanner);
+        self
+    }
+
+    /// Reports the bars of a universe as `Event::BarClosed` events whenever
+    /// they close, right after the event they closed at
+    pub fn with_bar_events(mut self, bar_events: BarEvents) -> Self {
+        self.bar_events = Some(bar_events);
+        self
+    }
+
+    /// Returns the latest row of a custom table (e.g. signals or alternative
+    /// data) recorded for `symbol` at or before `time`.
+    ///
+    /// The table is expected to have a `symbol` column and `timestamp` as its
+    /// designated timestamp, which `LATEST ON` finds the row by without
+    /// sorting the rows before it. Like prices, rows from after the current
+    /// virtual time cannot be queried.
+    pub async fn query_asof(
+        &self,
+        table: &str,
+        symbol: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<Row>, Error> {
+        if time > self.time {
+            return Err(Error::FutureQuery {
+                future_time: time,
+                current_time: self.time,
+            });
+        }
+
+        Ok(self
+            .db_client
+            .query_opt(&asof_query(table)?, &[&time.naive_utc(), &symbol])
+            .await?)
+    }
+
+    /// Runs an arbitrary query on the rows of its tables whose `timestamp`
+    /// column is not after the current virtual time.
+    ///
+    /// Every table the query reads is replaced with a subquery of its rows
+    /// up to the virtual time (see `bound_tables`), so aggregates, window
+    /// functions and `LATEST ON` inside the query cannot see future rows
+    /// either. Every table must therefore have a `timestamp` column. The
+    /// virtual time is bound as an extra parameter after `params`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnboundedQuery` if the query reads from something
+    /// that is not a table, such as a table function.
+    pub async fn query_historical(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, Error> {
+        let time = self.time.timestamp_micros() as f64;
+        let guarded_sql = bound_tables(sql, params.len() + 1)?;
+
+        let mut guarded_params = params.to_vec();
+        guarded_params.push(&time);
+
+        Ok(self.db_client.query(&guarded_sql, &guarded_params).await?)
+    }
+
+    /// Returns the candles of an equity between `start` and `end`, one per
+    /// `interval`. Candles are aligned to the calendar and only made of the
+    /// bars of the source table that are complete by the current virtual
+    /// time, so the last one may be partial.
+    pub async fn candles(
+        &self,
+        symbol: &str,
+        interval: TimeDelta,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>, Error> {
+        // Every candle must be made of whole bars of the source table
+        let resolution = self
+            .downsampled
+            .iter()
+            .filter(|resolution| {
+                interval.num_milliseconds() % resolution.duration().num_milliseconds() == 0
+            })
+            .max();
+        let table = resolution.map_or("prices", |resolution| resolution.table());
+        // Only bars complete by now, so the latest candle is made of the
+        // bars that already closed
+        let complete_until = resolution.map_or(self.time, |resolution| {
+            resolution.last_complete_start(self.time)
+        });
+
+        let rows = self
+            .db_client
+            .query(
+                &format!(
+                    "SELECT symbol, first(open) open, max(high) high, min(low) low, last(close) close, sum(volume) volume, timestamp FROM {table} WHERE symbol = $1::TEXT AND timestamp >= $2::TIMESTAMP AND timestamp < $3::TIMESTAMP AND timestamp <= $4::TIMESTAMP SAMPLE BY {} ALIGN TO CALENDAR;",
+                    sample_by_interval(interval)
+                ),
+                &[
+                    &symbol,
+                    &(start.timestamp_micros() as f64),
+                    &(end.timestamp_micros() as f64),
+                    &(complete_until.timestamp_micros() as f64),
+                ],
+            )
+            .await?;
+
+        Ok(rows.iter().map(candle_from_row).collect())
+    }
+
+    /// Returns the timestamps of the first and the last bars of an equity,
+    /// or `None` if there are none. Unlike other queries, this looks past
+    /// the current virtual time, so universes and backtest windows can be
+    /// validated up front.
+    pub async fn data_range(
+        &self,
+        symbol: &str,
+    ) -> Result<Option<(DateTime<Utc>, DateTime<Utc>)>, Error> {
+        let row = self
+            .db_client
+            .query_one(
+                &format!(
+                    "SELECT min(timestamp) first, max(timestamp) last FROM {} WHERE symbol = $1::TEXT;",
+                    self.price_table
+                ),
+                &[&symbol],
+            )
+            .await?;
+
+        let first: Option<NaiveDateTime> = row.get("first");
+        let last: Option<NaiveDateTime> = row.get("last");
+        Ok(first
+            .zip(last)
+            .map(|(first, last)| (first.and_utc(), last.and_utc())))
+    }
+
+    /// Returns the equities whose data does not cover `start` to `end`,
+    /// logging a warning for each of them
+    pub async fn uncovered_symbols<'s>(
+        &self,
+        symbols: impl IntoIterator<Item = &'s str>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<String>, Error> {
+        let mut uncovered = Vec::new();
+        for symbol in symbols {
+            match self.data_range(symbol).await? {
+                Some((first, last)) if first <= start && end <= last => continue,
+                Some((first, last)) => log::warn!(
+                    "{symbol} has data from {first} to {last}, which does not cover {start} to {end}"
+                ),
+                None => log::warn!("{symbol} has no data"),
+            }
+            uncovered.push(symbol.to_string());
+        }
+
+        Ok(uncovered)
+    }
+
+    /// Returns the candle of an equity's regular session on a date, as
+    /// bounded by the `system_events` table (rather than the UTC day), or
+    /// `None` if there was no session or no trades.
+    ///
+    /// A session that is still in progress results in a partial candle.
+    pub async fn daily_candle(
+        &self,
+        symbol: &str,
+        date: NaiveDate,
+    ) -> Result<Option<Candle>, Error> {
+        let day_start = date.and_time(NaiveTime::MIN).and_utc();
+        let session_events = self
+            .db_client
+            .query(
+                "SELECT * FROM system_events WHERE timestamp >= $1::TIMESTAMP AND timestamp < $2::TIMESTAMP ORDER BY timestamp ASC;",
+                &[
+                    &(day_start.timestamp_micros() as f64),
+                    &((day_start + TimeDelta::days(1)).timestamp_micros() as f64),
+                ],
+            )
+            .await?
+            .iter()
+            .map(system_event_from_row)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let session_time = |event: Event| {
+            session_events
+                .iter()
+                .find(|(_, session_event)| session_event == &event)
+                .map(|(time, _)| *time)
+        };
+        let Some(session_start) = session_time(Event::RegularMarketStart) else {
+            return Ok(None);
+        };
+        let session_end =
+            session_time(Event::RegularMarketEnd).unwrap_or(day_start + TimeDelta::days(1));
+
+        let row = self
+            .db_client
+            .query_one(
+                "SELECT first(open) open, max(high) high, min(low) low, last(close) close, sum(volume) volume, count() trades FROM prices WHERE symbol = $1::TEXT AND timestamp >= $2::TIMESTAMP AND timestamp < $3::TIMESTAMP AND timestamp <= $4::TIMESTAMP;",
+                &[
+                    &symbol,
+                    &(session_start.timestamp_micros() as f64),
+                    &(session_end.timestamp_micros() as f64),
+                    &(self.time.timestamp_micros() as f64),
+                ],
+            )
+            .await?;
+
+        if row.get::<_, i64>("trades") == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Candle {
+            start: session_start,
+            open: row.get("open"),
+            high: row.get("high"),
+            low: row.get("low"),
+            close: row.get("close"),
+            volume: row.get("volume"),
+        }))
+    }
+
+    /// Returns the bars of an equity between `start` and `end`, constructed
+    /// from its candles of the given interval.
+    pub async fn bars(
+        &self,
+        symbol: &str,
+        interval: TimeDelta,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bar_type: BarType,
+    ) -> Result<Vec<Candle>, Error> {
+        let candles = self.candles(symbol, interval, start, end).await?;
+
+        Ok(bar_type.build(&candles))
+    }
+
+    /// Returns the time of the next earnings report of an equity, or `None`
+    /// if none is known or no earnings calendar was loaded.
+    pub async fn next_earnings(&self, symbol: &str) -> Result<Option<DateTime<Utc>>, Error> {
+        let Some(calendar) = &self.earnings_calendar else {
+            return Ok(None);
+        };
+
+        let next_row = self
+            .db_client
+            .query_opt(
+                &calendar.next_symbol_report_statement,
+                &[&(self.time.timestamp_micros() as f64), &symbol],
+            )
+            .await?;
+
+        Ok(next_row.map(|row| row.get::<_, NaiveDateTime>("timestamp").and_utc()))
+    }
+
+    /// Sets the instrument specifications that order prices and quantities
+    /// are aligned to.
+    pub fn with_instruments(mut self, instruments: InstrumentRegistry) -> Self {
+        self.instruments = instruments;
+        self
+    }
+
+    /// Sets when market orders are filled
+    pub fn with_market_fill(mut self, market_fill: MarketFill) -> Self {
+        self.orders = self.orders.with_market_fill(market_fill);
+        self
+    }
+
+    /// Caps fills at a fraction of the traded volume, instead of filling
+    /// orders of any size at once
+    pub fn with_volume_limit(mut self, volume_limit: VolumeLimit) -> Self {
+        self.orders = self.orders.with_volume_limit(volume_limit);
+        self
+    }
+
+    /// Moves the price of fills that are large compared to the traded volume
+    /// against them
+    pub fn with_price_impact(mut self, price_impact: PriceImpact) -> Self {
+        self.orders = self.orders.with_price_impact(price_impact);
+        self
+    }
+
+    /// Sets which sessions orders may trade in, by default the regular
+    /// session, and the extended hours for orders that allow them
+    pub fn with_session_policy(mut self, session_policy: SessionPolicy) -> Self {
+        self.orders = self.orders.with_session_policy(session_policy);
+        self
+    }
+
+    /// Sets the price market orders fill at in the current bar, by default
+    /// its close
+    pub fn with_fill_model(mut self, fill_model: impl FillModel + 'static) -> Self {
+        self.fill_model = Box::new(fill_model);
+        self
+    }
+
+    /// Sets what quotes are derived from, by default the last trade. Bids and
+    /// asks are read from a `quotes` table (with `symbol`, `bid`, `ask` and
+    /// `timestamp` columns).
+    pub fn with_price_source(mut self, price_source: PriceSource) -> Self {
+        self.price_source = price_source;
+        self
+    }
+
+    /// Delays the execution of market orders, which then fill at the price
+    /// of the time they execute at, reported as an `Event::OrderFilled`.
+    /// Random delays are drawn from a generator seeded with `seed`.
+    pub fn with_latency(mut self, latency: Latency, seed: u64) -> Self {
+        self.orders = self.orders.with_latency(latency, seed);
+        self
+    }
+
+    /// Cancels the orders that did not fill by the end of the post-market
+    /// session, as brokers do when nobody traded against them, instead of
+    /// letting good-till-canceled orders rest overnight. Market orders
+    /// waiting for a bar are canceled too. Day orders still expire when the
+    /// regular session ends.
+    pub fn with_end_of_day_cancellation(mut self, enabled: bool) -> Self {
+        self.orders = self.orders.with_end_of_day_cancellation(enabled);
+        self
+    }
+
+    /// Sets the assumptions resting orders fill with within bars, by
+    /// default when their limit price is touched, and stops at their stop
+    /// price or the close
+    pub fn with_intrabar_fill(mut self, intrabar_fill: IntrabarFill) -> Self {
+        self.orders = self.orders.with_intrabar_fill(intrabar_fill);
+        self
+    }
+
+    /// Simulates the order book behind resting limit orders (see
+    /// `execution::OrderBookSimulator`), so they fill once the queue ahead
+    /// of them traded rather than whenever their price is touched. Meant
+    /// for backtests on tick-level data.
+    pub fn with_order_book(mut self, depth: SyntheticDepth) -> Self {
+        self.orders = self.orders.with_order_book(OrderBookSimulator::new(depth));
+        self
+    }
+
+    /// Lets purchases borrow on margin, charging interest on the debit
+    /// balance at the end of each trading day
+    pub fn with_margin(mut self, margin: Margin) -> Self {
+        self.account = self.account.with_margin(margin);
+        self
+    }
+
+    /// Makes the proceeds of sales unavailable to purchases until they
+    /// settle, `trading_days` after the sale (e.g. 1 for T+1), as in a cash
+    /// account
+    pub fn with_settlement_delay(mut self, trading_days: usize) -> Self {
+        self.account = self.account.with_settlement_delay(trading_days);
+        self
+    }
+
+    /// Rejects the sales that would break the pattern day trader rule, e.g.
+    /// to backtest a small margin account
+    pub fn with_day_trade_limit(mut self, limit: DayTradeLimit) -> Self {
+        self.account = self.account.with_day_trade_limit(limit);
+        self
+    }
+
+    /// Chooses which lots sales close first, e.g. to study the taxes of a
+    /// strategy
+    pub fn with_lot_selection(mut self, lot_selection: LotSelection) -> Self {
+        self.account = self.account.with_lot_selection(lot_selection);
+        self
+    }
+
+    /// Sets the currency cash is formatted in
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
+    pub fn instruments(&self) -> &InstrumentRegistry {
+        &self.instruments
+    }
+
+    pub fn currency(&self) -> &Currency {
+        &self.currency
+    }
+
+    /// Rejects quotes older than `max_quote_age` while the market is not
+    /// open. During trading hours quotes are always returned, since a
+    /// missing trade usually just means low liquidity.
+    pub fn with_max_quote_age(mut self, max_quote_age: TimeDelta) -> Self {
+        self.max_quote_age = Some(max_quote_age);
+        self
+    }
+
     async fn next_system_event(&self) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
         if let Some(next_row) = self
             .db_client
@@ -116,91 +796,977 @@ impl<'a> QuestDbMarket<'a> {
             )
             .await?
         {
-            let event_type = match next_row.get(0) {
-                "system_hours_start" => Ok(Event::PreMarketStart),
-                "regular_hours_start" => Ok(Event::RegularMarketStart),
-                "regular_hours_end" => Ok(Event::RegularMarketEnd),
-                "system_hours_end" => Ok(Event::PostMarketEnd),
-                symbol => Err(Error::UnexpectedDatabaseSymbol {
+            Ok(Some(system_event_from_row(&next_row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn next_earnings_event(&self) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        let Some(calendar) = &self.earnings_calendar else {
+            return Ok(None);
+        };
+
+        // Several equities often report at once, so the rows of the same
+        // time are told apart by symbol
+        let (after, after_symbol) = resume_after(&self.reported_until, self.time);
+        let next_row = self
+            .db_client
+            .query_opt(
+                &calendar.next_report_statement,
+                &[&(after.timestamp_micros() as f64), &after_symbol],
+            )
+            .await?;
+
+        Ok(next_row.map(|row| {
+            let timestamp: NaiveDateTime = row.get("timestamp");
+            let symbol: &str = row.get("symbol");
+
+            (
+                timestamp.and_utc(),
+                Event::Earnings {
                     symbol: symbol.to_string(),
-                    expected_kind: "system event".to_string(),
-                }),
-            }?;
+                },
+            )
+        }))
+    }
 
-            let timestamp: NaiveDateTime = next_row.get(1);
-            // let timestamp = DateTime::from_sql(Timestamp, next_row.get(1));
+    /// The next funding of a perpetual, at the current time or later, with
+    /// its amount settled once it is reached (see `settle_funding`)
+    async fn next_funding_event(&self) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        let Some(funding_rates) = &self.funding_rates else {
+            return Ok(None);
+        };
 
-            Ok(Some((timestamp.and_utc(), event_type)))
+        // Several perpetuals are usually funded at once, so the rows of the
+        // same time are told apart by symbol
+        let (after, after_symbol) = resume_after(&self.funded_until, self.time);
+        let next_row = self
+            .db_client
+            .query_opt(
+                &funding_rates.next_funding_statement,
+                &[&(after.timestamp_micros() as f64), &after_symbol],
+            )
+            .await?;
+
+        Ok(next_row.map(|row| {
+            let timestamp: NaiveDateTime = row.get("timestamp");
+            let symbol: &str = row.get("symbol");
+
+            (
+                timestamp.and_utc(),
+                Event::FundingPayment {
+                    symbol: symbol.to_string(),
+                    rate: row.get("rate"),
+                    amount: Money::ZERO,
+                },
+            )
+        }))
+    }
+
+    /// Pays or receives the funding of a held perpetual, returning the event
+    /// with the amount settled
+    async fn settle_funding(&mut self, event: Event) -> Result<Event, Error> {
+        let Event::FundingPayment { symbol, rate, .. } = event else {
+            return Ok(event);
+        };
+        self.funded_until = Some((self.time, symbol.clone()));
+
+        let quantity = self.account.shares_of(&symbol);
+        let amount = if quantity == 0.0 {
+            Money::ZERO
         } else {
-            Ok(None)
+            -Money::worth(quantity, self.current_price(&symbol).await? * rate)
+        };
+        self.account.credit(amount);
+
+        Ok(Event::FundingPayment {
+            symbol,
+            rate,
+            amount,
+        })
+    }
+
+    async fn next_macro_event(&self) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        let Some(calendar) = &self.macro_calendar else {
+            return Ok(None);
+        };
+
+        // Announcements are reported ahead of time, so look `lead` further.
+        // Several are often scheduled at once, so the rows of the same time
+        // are told apart by name.
+        let (after, after_name) = resume_after(&self.announced_until, self.time + calendar.lead);
+        let Some(next_row) = self
+            .db_client
+            .query_opt(
+                &calendar.next_announcement_statement,
+                &[&(after.timestamp_micros() as f64), &after_name],
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let importance = match next_row.get("importance") {
+            "low" => Ok(Importance::Low),
+            "medium" => Ok(Importance::Medium),
+            "high" => Ok(Importance::High),
+            symbol => Err(Error::UnexpectedDatabaseSymbol {
+                symbol: symbol.to_string(),
+                expected_kind: "macro event importance".to_string(),
+            }),
+        }?;
+        let name: &str = next_row.get("name");
+        let time = next_row.get::<_, NaiveDateTime>("timestamp").and_utc();
+
+        Ok(Some((
+            time - calendar.lead,
+            Event::Macro {
+                name: name.to_string(),
+                importance,
+                time,
+            },
+        )))
+    }
+
+    /// The next event, without querying the calendars of events outside of
+    /// `mask`. System and internal events and fundings are always looked
+    /// up, since they take effect either way.
+    async fn peek_next_event(
+        &self,
+        mask: EventMask,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        let (next_system_event, next_earnings_event, next_macro_event, next_funding_event) = try_join!(
+            self.next_system_event(),
+            async {
+                match mask.contains(EventMask::EARNINGS) {
+                    true => self.next_earnings_event().await,
+                    false => Ok(None),
+                }
+            },
+            async {
+                match mask.contains(EventMask::MACRO) {
+                    true => self.next_macro_event().await,
+                    false => Ok(None),
+                }
+            },
+            self.next_funding_event()
+        )?;
+        let next_internal_event = self.events.front().cloned();
+
+        // Internal events come first when they coincide with external ones
+        Ok([
+            next_internal_event,
+            next_system_event,
+            next_earnings_event,
+            next_macro_event,
+            next_funding_event,
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(time, _)| *time))
+    }
+
+    /// Gives the scanner the bars of its universe that closed since the last
+    /// scan, all at once, and reports its hits at the current time
+    async fn scan(&mut self) -> Result<(), Error> {
+        let Some(scanner) = &self.scanner else {
+            return Ok(());
+        };
+        let interval = scanner.interval();
+        let end = self.time.duration_trunc(interval).unwrap();
+        let start = self.scanned_until.unwrap_or(end - interval);
+        if start >= end {
+            return Ok(());
+        }
+
+        let market = &*self;
+        let candles = try_join_all(scanner.universe().iter().map(|symbol| async move {
+            let candles = market.candles(symbol, interval, start, end).await?;
+            Ok::<_, Error>((symbol.clone(), candles))
+        }))
+        .await?;
+        self.scanned_until = Some(end);
+
+        let scanner = self.scanner.as_mut().unwrap();
+        let mut hits = Vec::new();
+        for (symbol, candles) in candles {
+            for candle in candles {
+                hits.extend(
+                    scanner
+                        .on_bar(&symbol, candle)
+                        .into_iter()
+                        .map(|hit| (candle.start, hit)),
+                );
+            }
+        }
+        // Bar by bar across the universe
+        hits.sort_by_key(|(start, _)| *start);
+        for (_, hit) in hits {
+            self.report(hit);
+        }
+
+        Ok(())
+    }
+
+    /// Reports the bars of the `with_bar_events` universe that closed since
+    /// the last ones, bar by bar across the universe
+    async fn close_bars(&mut self) -> Result<(), Error> {
+        let Some(bar_events) = &self.bar_events else {
+            return Ok(());
+        };
+        let interval = bar_events.interval;
+        let end = self.time.duration_trunc(interval).unwrap();
+        let start = self.bars_closed_until.unwrap_or(end - interval);
+        if start >= end {
+            return Ok(());
+        }
+
+        let market = &*self;
+        let candles = try_join_all(bar_events.universe.iter().map(|symbol| async move {
+            let candles = market.candles(symbol, interval, start, end).await?;
+            Ok::<_, Error>((symbol.clone(), candles))
+        }))
+        .await?;
+        self.bars_closed_until = Some(end);
+
+        let mut bars: Vec<_> = candles
+            .into_iter()
+            .flat_map(|(symbol, candles)| {
+                candles
+                    .into_iter()
+                    .map(move |candle| (symbol.clone(), candle))
+            })
+            .collect();
+        bars.sort_by_key(|(_, candle)| candle.start);
+        for (symbol, candle) in bars {
+            self.report(Event::BarClosed {
+                symbol,
+                interval,
+                candle,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Advances the virtual time to an event and lets it take effect, filling
+    /// and expiring orders as of then. Returns the event, with the amount of
+    /// a funding settled.
+    async fn step(&mut self, time: DateTime<Utc>, event: Event) -> Result<Event, Error> {
+        let since = self.time;
+        self.advance_to_event(time, &event)?;
+        match &event {
+            Event::Earnings { symbol } => self.reported_until = Some((time, symbol.clone())),
+            Event::Macro {
+                name, time: due, ..
+            } => self.announced_until = Some((*due, name.clone())),
+            _ => {}
+        }
+        let event = self.settle_funding(event).await?;
+        self.mark_holdings().await;
+        self.fill_queued_market_orders().await?;
+        self.match_orders(since).await?;
+        self.orders.expire_orders(&event);
+        self.report_order_events();
+        self.charge_interest(&event);
+        self.close_bars().await?;
+        self.scan().await?;
+
+        log::debug!("{time}: {event:?}");
+        self.record_snapshot();
+
+        Ok(event)
+    }
+
+    /// Charges the margin interest at the end of each trading day
+    fn charge_interest(&mut self, event: &Event) {
+        if *event != Event::PostMarketEnd {
+            return;
+        }
+
+        let interest = self.account.charge_interest(self.time);
+        if interest > Money::ZERO {
+            log::debug!(
+                "charged {} in margin interest",
+                self.currency.format(interest)
+            );
+        }
+    }
+
+    /// Advances the virtual time to an event, removing it from the internal
+    /// events if it is one of them
+    fn advance_to_event(&mut self, time: DateTime<Utc>, event: &Event) -> Result<(), Error> {
+        self.market_time.update(event)?;
+        if self.events.front() == Some(&(time, event.clone())) {
+            self.events.pop_front();
+        }
+        self.time = time;
+
+        Ok(())
+    }
+
+    /// Triggers and fills the pending orders whose prices were reached by
+    /// trades since `since`, reporting the fills as events at the current time
+    async fn match_orders(&mut self, since: DateTime<Utc>) -> Result<(), Error> {
+        let mut trades = HashMap::new();
+        for symbol in self.orders.matching_symbols(self.market_time) {
+            let row = self
+                .db_client
+                .query_one(
+                    &format!(
+                        "SELECT first(open) open, min(low) low, max(high) high, last(close) close, sum(volume) volume FROM {} WHERE symbol = $1::TEXT AND timestamp > $2::TIMESTAMP AND timestamp <= $3::TIMESTAMP;",
+                        self.price_table
+                    ),
+                    &[&symbol, &self.bar_cutoff(since), &self.bar_cutoff(self.time)],
+                )
+                .await?;
+            if let (Some(open), Some(low), Some(high), Some(close)) = (
+                row.get("open"),
+                row.get("low"),
+                row.get("high"),
+                row.get("close"),
+            ) {
+                let prices = BarPrices {
+                    open,
+                    high,
+                    low,
+                    close,
+                };
+                let volume = row.get("volume");
+                trades.insert(symbol, Trades { prices, volume });
+            }
+        }
+
+        let (orders, mut context) = self.fill_context();
+        orders.match_orders(&mut context, &trades);
+        self.report_order_events();
+
+        Ok(())
+    }
+
+    /// Fills a validated market order at the current price, or queues it for
+    /// the next bar
+    async fn execute_market_order(
+        &mut self,
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+    ) -> Result<TradeReceipt, Error> {
+        self.mark_holdings().await;
+        // TODO include fees, bid and ask too
+        let candle = self.candle_at(symbol, self.time).await?;
+        let price = self.fill_model.fill_price(&candle, side);
+        let volume = self.current_volume(symbol).await?;
+
+        let (orders, mut context) = self.fill_context();
+        let receipt =
+            orders.submit_market_order(&mut context, symbol, side, quantity, price, volume);
+        self.report_order_events();
+
+        Ok(receipt?)
+    }
+
+    /// The order engine, with what it fills orders into as of now
+    fn fill_context(&mut self) -> (&mut OrderEngine, FillContext<'_>) {
+        (
+            &mut self.orders,
+            FillContext {
+                account: &mut self.account,
+                instruments: &self.instruments,
+                currency: &self.currency,
+                time: self.time,
+                market_time: self.market_time,
+            },
+        )
+    }
+
+    /// Reports the events of the orders as of now
+    fn report_order_events(&mut self) {
+        for event in self.orders.take_events() {
+            self.report(event);
+        }
+    }
+
+    /// Marks the held equities at their current prices, valuing the account
+    /// for its day trade limit. Equities without a price keep their marks.
+    async fn mark_holdings(&mut self) {
+        if self.account.day_trade_limit().is_none() {
+            return;
+        }
+
+        let symbols: Vec<String> = self
+            .account
+            .holdings()
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        for symbol in symbols {
+            if let Ok(price) = self.current_price(&symbol).await {
+                self.account.mark(&symbol, price);
+            }
         }
     }
 
-    async fn peek_next_event(&self) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
-        let next_system_event = self.next_system_event().await?;
-        let next_internal_event = self.events.front();
+    /// The middle of the latest bid and ask of an equity at a time, from the
+    /// `quotes` table
+    async fn midpoint_at(
+        &self,
+        symbol: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<PriceQuote>, Error> {
+        let row = self
+            .db_client
+            .query_opt(
+                "SELECT bid, ask, timestamp FROM quotes WHERE symbol = $1::TEXT AND timestamp <= $2::TIMESTAMP ORDER BY timestamp DESC LIMIT 1;",
+                &[&symbol, &(time.timestamp_micros() as f64)],
+            )
+            .await?;
+
+        Ok(row.map(|row| PriceQuote {
+            price: (row.get::<_, f64>("bid") + row.get::<_, f64>("ask")) / 2.0,
+            as_of: row.get::<_, NaiveDateTime>("timestamp").and_utc(),
+        }))
+    }
+
+    /// The latest bar of an equity at a time
+    async fn candle_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<Candle, Error> {
+        let row = self
+            .db_client
+            .query_opt(
+                &self.price_query_statement,
+                &[&self.bar_cutoff(time), &symbol, &1f64],
+            )
+            .await?
+            .ok_or(Error::UnknownPrice(symbol.to_string()))?;
 
-        match (next_system_event, next_internal_event) {
-            (Some(next_sys), Some(next_int)) => {
-                if next_sys.0 >= next_int.0 {
-                    Ok(Some(next_int.clone()))
-                } else {
-                    Ok(Some(next_sys))
+        Ok(candle_from_row(&row))
+    }
+
+    /// The volume of the latest bar of an equity, if a volume limit or a
+    /// price impact applies
+    async fn current_volume(&self, symbol: &str) -> Result<Option<f64>, Error> {
+        if !self.orders.needs_volume() {
+            return Ok(None);
+        }
+
+        let row = self
+            .db_client
+            .query_opt(
+                &format!(
+                    "SELECT volume FROM {} WHERE symbol = $1::TEXT AND timestamp <= $2::TIMESTAMP ORDER BY timestamp DESC LIMIT 1;",
+                    self.price_table
+                ),
+                &[&symbol, &self.bar_cutoff(self.time)],
+            )
+            .await?;
+
+        Ok(row.map(|row| row.get("volume")))
+    }
+
+    /// Fills the queued market orders at the open of the first bar after
+    /// their submission, once it was reached, within its volume limit.
+    /// Orders delayed by latency fill at the price of the time they execute
+    /// at instead. Orders that cannot be afforded (or covered) anymore are
+    /// dropped.
+    async fn fill_queued_market_orders(&mut self) -> Result<(), Error> {
+        let mut fills = HashMap::new();
+        for queued in self.orders.queued_market_orders(self.market_time) {
+            if let Some(executes_at) = queued.executes_at {
+                if executes_at > self.time {
+                    continue;
                 }
+
+                let candle = self.candle_at(&queued.symbol, executes_at).await?;
+                let fill = MarketOrderFill {
+                    price: self.fill_model.fill_price(&candle, queued.side),
+                    volume: Some(candle.volume),
+                    bar_time: candle.start + self.bar_delay,
+                };
+                fills.insert(queued.id, fill);
+                continue;
+            }
+
+            let row = self
+                .db_client
+                .query_opt(
+                    &format!(
+                        "SELECT open, volume, timestamp FROM {} WHERE symbol = $1::TEXT AND timestamp > $2::TIMESTAMP AND timestamp <= $3::TIMESTAMP ORDER BY timestamp ASC LIMIT 1;",
+                        self.price_table
+                    ),
+                    &[
+                        &queued.symbol,
+                        &self.bar_cutoff(queued.submitted_at),
+                        &self.bar_cutoff(self.time),
+                    ],
+                )
+                .await?;
+            if let Some(row) = row {
+                let fill = MarketOrderFill {
+                    price: row.get("open"),
+                    volume: row.get("volume"),
+                    bar_time: row.get::<_, NaiveDateTime>("timestamp").and_utc() + self.bar_delay,
+                };
+                fills.insert(queued.id, fill);
+            }
+        }
+
+        let (orders, mut context) = self.fill_context();
+        orders.fill_market_orders(&mut context, &fills);
+        self.report_order_events();
+
+        Ok(())
+    }
+
+    /// Ensures an order may trade in the current session
+    fn ensure_session(&self, symbol: &str, allow_extended_hours: bool) -> Result<(), Error> {
+        if !self.market_time.is_open() {
+            return Err(Error::UntimelyTrade(symbol.to_string(), self.time));
+        }
+
+        if !self
+            .orders
+            .session_policy()
+            .allows(self.market_time, allow_extended_hours)
+        {
+            return Err(Error::OutsideSession(symbol.to_string(), self.market_time));
+        }
+
+        Ok(())
+    }
+
+    /// Checks a market order against the instrument's minimums, querying
+    /// its price only if the instrument has a minimum value
+    async fn check_market_order_minimums(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<(), Error> {
+        let price = if self.instruments.get(symbol).min_notional > Money::ZERO {
+            self.current_price(symbol).await?
+        } else {
+            0.0
+        };
+
+        Ok(self.instruments.check_minimums(symbol, quantity, price)?)
+    }
+
+    /// Validates and rounds an order, returning it with the current price
+    /// and volume, or `None` if nothing is left to trade after rounding
+    async fn prepare_order(
+        &mut self,
+        id: OrderId,
+        order: Order,
+    ) -> Result<Option<PreparedOrder>, Error> {
+        let symbol = order.symbol.as_str();
+
+        self.ensure_session(symbol, order.allow_extended_hours)?;
+        self.mark_holdings().await;
+
+        if !self.is_tradeable(symbol) {
+            return Err(Error::UntradeableSymbol(symbol.to_string()));
+        }
+
+        check_quantity(symbol, order.quantity)?;
+        let quantity = self.instruments.round_quantity(symbol, order.quantity)?;
+        let instruments = &self.instruments;
+        let symbol = order.symbol.clone();
+        let order = Order {
+            quantity,
+            ..order.round_prices(|price| instruments.round_price(&symbol, price))?
+        };
+        if order.quantity == 0.0 {
+            self.orders.cancel_empty(id);
+            return Ok(None);
+        }
+
+        let current_price = self.current_price(&order.symbol).await?;
+        self.instruments.check_minimums(
+            &order.symbol,
+            order.quantity,
+            order.reference_price().unwrap_or(current_price),
+        )?;
+
+        // Reject orders that could not be filled even if their price was
+        // reached right away
+        if order.side == Side::Buy {
+            self.check_earnings_blackout(&order.symbol).await?;
+        }
+        self.account.check(
+            &order.symbol,
+            order.side,
+            order.quantity,
+            order.reference_price().unwrap_or(current_price),
+            self.time,
+        )?;
+
+        let volume = self.current_volume(&order.symbol).await?;
+        Ok(Some((PendingOrder::new(id, order), current_price, volume)))
+    }
+
+    /// Reports an event at the current time
+    fn report(&mut self, event: Event) {
+        let event = (self.time, event);
+
+        // After the events already due, so events are reported in order
+        let due = self
+            .events
+            .iter()
+            .take_while(|(time, _)| time <= &self.time)
+            .count();
+        let mut later = self.events.split_off(due);
+        self.events.push_back(event);
+        self.events.append(&mut later);
+    }
+
+    /// Ensures an equity is not bought shortly before its earnings report
+    async fn check_earnings_blackout(&self, symbol: &str) -> Result<(), Error> {
+        let Some(blackout) = self.earnings_calendar.as_ref().and_then(|c| c.blackout) else {
+            return Ok(());
+        };
+
+        match self.next_earnings(symbol).await? {
+            Some(report_time) if report_time - self.time <= blackout => {
+                Err(Error::EarningsBlackout {
+                    symbol: symbol.to_string(),
+                    report_time,
+                })
             }
-            (Some(next_sys), None) => Ok(Some(next_sys)),
-            (None, Some(next_int)) => Ok(Some(next_int.clone())),
-            (None, None) => Ok(None),
+            _ => Ok(()),
         }
     }
 }
 
+/// Where to resume reading a calendar ordered by time and a key (e.g. a
+/// symbol): after its last reported row, unless that is before `time`, in
+/// which case at `time` (before any key)
+fn resume_after(
+    reported_until: &Option<(DateTime<Utc>, String)>,
+    time: DateTime<Utc>,
+) -> (DateTime<Utc>, &str) {
+    match reported_until {
+        Some((until, key)) if *until >= time => (*until, key.as_str()),
+        _ => (time, ""),
+    }
+}
+
+/// A token of a SQL query, for finding the tables it reads
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SqlToken<'a> {
+    /// A keyword or an identifier
+    Word(&'a str),
+    /// A double quoted identifier, with its quotes
+    Quoted(&'a str),
+    Punctuation(char),
+    /// A string or a number
+    Literal,
+}
+
+/// The keywords that may directly follow a table, so they are not taken for
+/// its alias
+const CLAUSE_KEYWORDS: [&str; 24] = [
+    "align",
+    "asof",
+    "cross",
+    "except",
+    "fill",
+    "full",
+    "group",
+    "having",
+    "inner",
+    "intersect",
+    "join",
+    "latest",
+    "left",
+    "limit",
+    "lt",
+    "on",
+    "order",
+    "outer",
+    "right",
+    "sample",
+    "splice",
+    "timestamp",
+    "union",
+    "where",
+];
+
+/// Splits a query into tokens with their byte ranges, skipping whitespace
+/// and comments
+fn tokenize_sql(sql: &str) -> Vec<(Range<usize>, SqlToken<'_>)> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        // The end of the token, after the characters that match
+        let mut end_of = |matches: fn(char) -> bool| {
+            let mut end = start + c.len_utf8();
+            while let Some((index, next)) = chars.next_if(|&(_, next)| matches(next)) {
+                end = index + next.len_utf8();
+            }
+            end
+        };
+
+        let (end, token) = match c {
+            _ if c.is_whitespace() => continue,
+            '-' if sql[start..].starts_with("--") => {
+                end_of(|next| next != '\n');
+                continue;
+            }
+            '\'' | '"' => {
+                // Until the closing quote, where doubled quotes are escapes
+                let mut end = sql.len();
+                while let Some((index, next)) = chars.next() {
+                    if next == c && chars.next_if(|&(_, after)| after == c).is_none() {
+                        end = index + 1;
+                        break;
+                    }
+                }
+                let token = match c {
+                    '"' => SqlToken::Quoted(&sql[start..end]),
+                    _ => SqlToken::Literal,
+                };
+                (end, token)
+            }
+            _ if c.is_ascii_digit() => (
+                end_of(|next| next.is_ascii_alphanumeric() || next == '.'),
+                SqlToken::Literal,
+            ),
+            _ if c.is_alphabetic() || c == '_' => {
+                let end = end_of(|next| next.is_alphanumeric() || next == '_');
+                (end, SqlToken::Word(&sql[start..end]))
+            }
+            _ => (start + c.len_utf8(), SqlToken::Punctuation(c)),
+        };
+        tokens.push((start..end, token));
+    }
+
+    tokens
+}
+
+/// The query of the latest row of `table` for the symbol `$2` at or before
+/// the time `$1`
+///
+/// # Errors
+///
+/// Returns `Error::InvalidTableName` unless the table is a plain identifier,
+/// as table names cannot be bound as parameters.
+pub(crate) fn asof_query(table: &str) -> Result<String, Error> {
+    if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Error::InvalidTableName(table.to_string()));
+    }
+
+    Ok(format!(
+        "SELECT * FROM {table} WHERE timestamp <= $1::TIMESTAMP AND symbol = $2::TEXT \
+         LATEST ON timestamp PARTITION BY symbol;"
+    ))
+}
+
+/// Rewrites a query so that every table it reads (after a `FROM` or a
+/// `JOIN`) is replaced with its rows whose `timestamp` is at most the query
+/// parameter `$time_param`, keeping the table's name or alias.
+///
+/// Subqueries are bounded through the tables they read, and the names of
+/// common table expressions are left as they are.
+///
+/// # Errors
+///
+/// Returns `Error::UnboundedQuery` if the query reads from anything else
+/// after a `FROM` or a `JOIN`, such as a table function.
+pub(crate) fn bound_tables(sql: &str, time_param: usize) -> Result<String, Error> {
+    let sql = sql.trim().trim_end_matches(';');
+    let tokens = tokenize_sql(sql);
+    let is_keyword = |token: &SqlToken, keyword: &str| matches!(token, SqlToken::Word(word) if word.eq_ignore_ascii_case(keyword));
+    let name_of = |token: &SqlToken<'_>| match *token {
+        SqlToken::Word(name) | SqlToken::Quoted(name) => Some(name.to_lowercase()),
+        _ => None,
+    };
+    let unbounded = || Error::UnboundedQuery(sql.to_string());
+
+    // Common table expressions are defined as `name AS (`
+    let common_tables: HashSet<String> = tokens
+        .windows(3)
+        .filter(|window| {
+            is_keyword(&window[1].1, "as") && window[2].1 == SqlToken::Punctuation('(')
+        })
+        .filter_map(|window| name_of(&window[0].1))
+        .collect();
+
+    let mut bounded = String::new();
+    let mut copied = 0;
+    let mut index = 0;
+    while index < tokens.len() {
+        let reads = is_keyword(&tokens[index].1, "from") || is_keyword(&tokens[index].1, "join");
+        // Not the `FROM` of a function's arguments, as in `extract(year FROM timestamp)`
+        let in_function = index >= 2
+            && tokens[index - 2].1 == SqlToken::Punctuation('(')
+            && !is_keyword(&tokens[index - 1].1, "select");
+        index += 1;
+        if !reads || in_function {
+            continue;
+        }
+
+        // A list of tables is read from after commas
+        loop {
+            let Some((range, token)) = tokens.get(index) else {
+                return Err(unbounded());
+            };
+            if *token == SqlToken::Punctuation('(') {
+                break;
+            }
+            let Some(name) = name_of(token) else {
+                return Err(unbounded());
+            };
+            let next = tokens.get(index + 1).map(|(_, token)| *token);
+            if next == Some(SqlToken::Punctuation('(')) {
+                return Err(unbounded());
+            }
+            index += 1;
+
+            if !common_tables.contains(&name) {
+                let table = &sql[range.clone()];
+                bounded.push_str(&sql[copied..range.start]);
+                bounded.push_str(&format!(
+                    "(SELECT * FROM {table} WHERE timestamp <= ${time_param}::TIMESTAMP)"
+                ));
+                // Keeps the table's name, unless it is given an alias
+                let aliased = next.is_some_and(|next| match next {
+                    SqlToken::Word(word) => !CLAUSE_KEYWORDS
+                        .iter()
+                        .any(|keyword| word.eq_ignore_ascii_case(keyword)),
+                    SqlToken::Quoted(_) => true,
+                    _ => false,
+                });
+                if !aliased {
+                    bounded.push(' ');
+                    bounded.push_str(table);
+                }
+                copied = range.end;
+            }
+
+            // Past an alias
+            if tokens
+                .get(index)
+                .is_some_and(|(_, token)| is_keyword(token, "as"))
+            {
+                index += 1;
+            }
+            if tokens
+                .get(index)
+                .and_then(|(_, token)| name_of(token))
+                .is_some_and(|name| !CLAUSE_KEYWORDS.contains(&name.as_str()))
+            {
+                index += 1;
+            }
+            if tokens.get(index).map(|(_, token)| *token) != Some(SqlToken::Punctuation(',')) {
+                break;
+            }
+            index += 1;
+        }
+    }
+    bounded.push_str(&sql[copied..]);
+
+    Ok(bounded)
+}
+
+fn system_event_from_row(row: &Row) -> Result<(DateTime<Utc>, Event), Error> {
+    let event_type = match row.get(0) {
+        "system_hours_start" => Ok(Event::PreMarketStart),
+        "regular_hours_start" => Ok(Event::RegularMarketStart),
+        "regular_hours_end" => Ok(Event::RegularMarketEnd),
+        "system_hours_end" => Ok(Event::PostMarketEnd),
+        symbol => Err(Error::UnexpectedDatabaseSymbol {
+            symbol: symbol.to_string(),
+            expected_kind: "system event".to_string(),
+        }),
+    }?;
+
+    let timestamp: NaiveDateTime = row.get(1);
+    // let timestamp = DateTime::from_sql(Timestamp, row.get(1));
+
+    Ok((timestamp.and_utc(), event_type))
+}
+
+/// The `system_events` symbol of a session event, the inverse of
+/// `system_event_from_row`
+pub(crate) fn system_event_symbol(event: &Event) -> Option<&'static str> {
+    match event {
+        Event::PreMarketStart => Some("system_hours_start"),
+        Event::RegularMarketStart => Some("regular_hours_start"),
+        Event::RegularMarketEnd => Some("regular_hours_end"),
+        Event::PostMarketEnd => Some("system_hours_end"),
+        _ => None,
+    }
+}
+
+fn candle_from_row(row: &Row) -> Candle {
+    Candle {
+        start: row.get::<_, NaiveDateTime>("timestamp").and_utc(),
+        open: row.get("open"),
+        high: row.get("high"),
+        low: row.get("low"),
+        close: row.get("close"),
+        volume: row.get("volume"),
+    }
+}
+
 impl<'a> Market for QuestDbMarket<'a> {
     type Error = Error;
 
     async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
-        match self.peek_next_event().await? {
-            Some((time, event)) => {
-                self.time = time;
-                self.market_time.update(&event)?;
-
-                // TODO if the event is internal, pop it from the linked list
+        self.next_event_matching(EventMask::ALL).await
+    }
 
-                Ok(Some((time, event)))
+    async fn next_event_matching(
+        &mut self,
+        mask: EventMask,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        while let Some((time, event)) = self.peek_next_event(mask).await? {
+            let event = self.step(time, event).await?;
+            if mask.matches(&event) {
+                return Ok(Some((time, event)));
             }
-            None => Ok(None),
         }
+
+        Ok(None)
     }
 
     async fn next_event_or_tick(
         &mut self,
         tick: chrono::TimeDelta,
     ) -> Result<(DateTime<Utc>, Event), Error> {
-        let next_tick = self.time.duration_trunc(tick).unwrap() + tick;
-
-        let event = if let Some((time, event)) = self.peek_next_event().await? {
-            if time <= next_tick {
-                self.market_time.update(&event)?;
+        let next_tick = match self.daily {
+            Some(at) => next_us_equity_trading_time(self.time, at),
+            None => self.time.duration_trunc(tick).unwrap() + tick,
+        };
 
-                // TODO if the event is internal, pop it from the linked list
-                (time, event)
-            } else {
-                (next_tick, Event::Tick)
-            }
-        } else {
-            (next_tick, Event::Tick)
+        let (time, event) = match self.peek_next_event(EventMask::ALL).await? {
+            Some((time, event)) if time <= next_tick => (time, event),
+            _ => (next_tick, Event::Tick),
         };
 
-        self.time = event.0;
+        Ok((time, self.step(time, event).await?))
+    }
 
-        Ok(event)
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        match self.peek_next_event(EventMask::ALL).await? {
+            Some((time, event)) if time <= until => Ok(Some((time, self.step(time, event).await?))),
+            _ => {
+                if until > self.time {
+                    self.step(until, Event::Tick).await?;
+                }
+                Ok(None)
+            }
+        }
     }
 
     fn time(&self) -> DateTime<Utc> {
         self.time
     }
 
-    async fn price_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<f64, Error> {
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, Error> {
         // TODO Remember the random value for a stock and deviate from it using
         // geometric Brownian motion (or some estimation of it). Assume the
         // price is in the middle of the bid/ask spread
@@ -216,126 +1782,228 @@ impl<'a> Market for QuestDbMarket<'a> {
             });
         }
 
-        let row = self
+        // The last close price
+        let trade = self
             .db_client
             .query_opt(
                 &self.price_query_statement,
-                &[&(time.timestamp_micros() as f64), &symbol, &1f64],
+                &[&self.bar_cutoff(time), &symbol, &1f64],
             )
             .await?
+            .map(|row| PriceQuote {
+                price: row.get(4),
+                as_of: row.get::<_, NaiveDateTime>("timestamp").and_utc(),
+            });
+        let midpoint = match self.price_source {
+            PriceSource::Trade => None,
+            PriceSource::Midpoint | PriceSource::Blend { .. } => {
+                self.midpoint_at(symbol, time).await?
+            }
+        };
+        let quote = self
+            .price_source
+            .quote(trade, midpoint)
             .ok_or(Error::UnknownPrice(symbol.to_string()))?;
+        let as_of = quote.as_of;
 
-        // Return the last close price
-        Ok(row.get(4))
+        if let Some(max_quote_age) = self.max_quote_age {
+            if !self.market_time.is_open() && time - as_of > max_quote_age {
+                return Err(Error::StalePrice {
+                    symbol: symbol.to_string(),
+                    as_of,
+                    time,
+                });
+            }
+        }
+
+        Ok(quote)
     }
 
-    async fn buy_at_market(&mut self, symbol: &str, quantity: u32) -> Result<(), Error> {
-        // Ensure the market is open
-        if !self.market_time.is_open() {
-            return Err(Error::UntimelyTrade(symbol.to_string(), self.time));
+    async fn buy_at_market(&mut self, symbol: &str, quantity: f64) -> Result<TradeReceipt, Error> {
+        self.ensure_session(symbol, false)?;
+
+        if !self.is_tradeable(symbol) {
+            return Err(Error::UntradeableSymbol(symbol.to_string()));
         }
 
-        if quantity == 0 {
-            return Ok(());
+        check_quantity(symbol, quantity)?;
+        let quantity = self.instruments.round_quantity(symbol, quantity)?;
+        if quantity == 0.0 {
+            return Ok(self.orders.empty_order(symbol, self.time));
         }
+        self.check_market_order_minimums(symbol, quantity).await?;
 
-        // Calculate the transaction's cost
-        // TODO include fees, bid and ask too
-        let price_per_share = self.current_price(symbol).await?;
-        let total_price = price_per_share * quantity as f64;
+        self.check_earnings_blackout(symbol).await?;
 
-        // Ensure the cash is sufficient for it
-        if total_price > self.cash {
-            return Err(Error::InsufficientCash {
-                quantity,
-                symbol: symbol.to_string(),
-                total_price,
-                cash: self.cash,
-            });
-        }
+        self.execute_market_order(symbol, Side::Buy, quantity).await
+    }
 
-        // Update the cash and the holdings
-        self.cash -= total_price;
+    async fn sell_at_market(&mut self, symbol: &str, quantity: f64) -> Result<TradeReceipt, Error> {
+        self.ensure_session(symbol, false)?;
 
-        if let Some(v) = self.holdings.get_mut(symbol) {
-            *v += quantity;
-        } else {
-            self.holdings.insert(symbol.to_string(), quantity);
+        if !self.is_tradeable(symbol) {
+            return Err(Error::UntradeableSymbol(symbol.to_string()));
         }
 
-        // TODO Add an event of PurchaseComplete
-        // TODO The transaction might be canceled if it's at the end of the
-        // day and there are no buyers/sellers
+        check_quantity(symbol, quantity)?;
+        let quantity = self.instruments.round_quantity(symbol, quantity)?;
+        if quantity == 0.0 {
+            return Ok(self.orders.empty_order(symbol, self.time));
+        }
+        self.check_market_order_minimums(symbol, quantity).await?;
 
-        Ok(())
+        self.execute_market_order(symbol, Side::Sell, quantity)
+            .await
     }
 
-    async fn sell_at_market(&mut self, symbol: &str, quantity: u32) -> Result<(), Error> {
-        // Ensure the market is open
-        if !self.market_time.is_open() {
-            return Err(Error::UntimelyTrade(symbol.to_string(), self.time));
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, Error> {
+        let id = self.orders.new_order_id();
+        if let Some(prepared) = self.prepare_order(id, order).await? {
+            let (orders, mut context) = self.fill_context();
+            let submitted = orders.submit(&mut context, prepared);
+            self.report_order_events();
+            submitted?;
         }
 
-        if quantity == 0 {
-            return Ok(());
-        }
+        Ok(id)
+    }
 
-        // Calculate the transaction's cost
-        // TODO include fees, bid and ask too
-        let price_per_share = self.current_price(symbol).await?;
-        let total_price = price_per_share * quantity as f64;
-
-        // Ensure there are enough shares of this stock
-        let owned_shares_opt = self.holdings.get_mut(symbol);
-        if owned_shares_opt.is_none() {
-            return Err(Error::InsufficientShares {
-                quantity,
-                symbol: symbol.to_string(),
-                owned: 0,
-            });
-        }
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), Error> {
+        let ids = [self.orders.new_order_id(), self.orders.new_order_id()];
+        let first = self.prepare_order(ids[0], first).await?;
+        let second = self.prepare_order(ids[1], second).await?;
 
-        if &quantity > owned_shares_opt.as_ref().unwrap() {
-            return Err(Error::InsufficientShares {
-                quantity,
-                symbol: symbol.to_string(),
-                owned: *owned_shares_opt.unwrap(),
-            });
+        let (orders, mut context) = self.fill_context();
+        let group = orders.submit_oco(&mut context, first.into_iter().chain(second));
+        self.report_order_events();
+
+        Ok((group?, ids))
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), Error> {
+        let amended = self
+            .orders
+            .pending_order(id)
+            .ok_or(Error::OrderNotOpen(id))?
+            .order
+            .amended(&amendment)
+            .ok_or(Error::InvalidAmendment(id))?;
+
+        // Validated like a new order, but keeping its place in its life cycle
+        let prepared = self.prepare_order(id, amended).await?;
+        let (orders, mut context) = self.fill_context();
+        let amended = orders.amend(&mut context, id, prepared);
+        self.report_order_events();
+        amended?;
+
+        Ok(())
+    }
+
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), Error> {
+        if !self.orders.cancel(id) {
+            return Err(Error::OrderNotOpen(id));
         }
+        self.report_order_events();
 
-        // Update the cash and the holdings
-        self.cash += total_price;
+        Ok(())
+    }
 
-        if let Some(v) = self.holdings.get_mut(symbol) {
-            *v -= quantity
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.orders.order_status(id)
+    }
+
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.orders.pending_orders()
+    }
+
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        if tradeable {
+            self.untradeable.remove(symbol);
         } else {
-            unreachable!()
+            self.untradeable.insert(symbol.to_string());
         }
+    }
 
-        // TODO Add an event of SellComplete
-        // TODO The transaction might be canceled if it's at the end of the
-        // day and there are no buyers/sellers
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        !self.untradeable.contains(symbol)
+    }
 
-        Ok(())
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.instruments.get(symbol).lot_size
     }
 
     fn market_time(&self) -> crate::market::MarketTime {
         self.market_time
     }
 
-    fn cash(&self) -> f64 {
-        self.cash
+    fn cash(&self) -> Money {
+        self.account.cash()
     }
 
-    fn shares_of(&self, symbol: &str) -> u32 {
-        if let Some(q) = self.holdings.get(symbol) {
-            *q
-        } else {
-            0
-        }
+    fn settled_cash(&self) -> Money {
+        self.account.settled_cash(self.time)
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.account.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.account.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.account.position(symbol).copied()
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.account.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.account.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[Lot] {
+        self.account.lots(symbol)
+    }
+
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.account.closed_lots()
     }
 
-    fn holdings(&self) -> impl IntoIterator<Item = (&String, &u32)> {
-        &self.holdings
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.account.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, Error> {
+        let Some(&Position { opened_at, .. }) = self.account.position(symbol) else {
+            return Ok(None);
+        };
+
+        let row = self
+            .db_client
+            .query_one(
+                &format!(
+                    "SELECT max(high) high FROM {} WHERE symbol = $1::TEXT AND timestamp >= $2::TIMESTAMP AND timestamp <= $3::TIMESTAMP;",
+                    self.price_table
+                ),
+                &[
+                    &symbol,
+                    &self.bar_cutoff(opened_at),
+                    &self.bar_cutoff(self.time),
+                ],
+            )
+            .await?;
+        let highest_price: Option<f64> = row.get("high");
+        let current_price = self.current_price(symbol).await?;
+
+        Ok(Some(
+            highest_price.map_or(current_price, |high| high.max(current_price)),
+        ))
     }
 }
@@ -0,0 +1,248 @@
+//! Quoting primitives for market-making strategies: a bid and an ask resting
+//! around the current price, skewed by the inventory they accumulated and
+//! refreshed (e.g. on every tick) as the price moves.
+
+use std::collections::HashMap;
+
+use crate::{
+    market::{Event, Market},
+    money::Money,
+    order::{Amendment, Order, OrderId, OrderKind, OrderStatus, Side, TimeInForce},
+};
+
+/// What the quotes of a `Quoter` traded
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Inventory {
+    /// The shares bought less the shares sold
+    pub position: f64,
+    pub bought: f64,
+    pub sold: f64,
+    /// The cash received for sales less the cash paid for purchases
+    pub cash_flow: Money,
+}
+
+impl Inventory {
+    /// The profit of the quotes, with the position valued at `price`
+    pub fn pnl(&self, price: f64) -> Money {
+        self.cash_flow + Money::worth(self.position, price)
+    }
+}
+
+/// Keeps a bid and an ask of an equity resting around its current price.
+///
+/// Since the simulated accounts cannot sell short, the ask only offers the
+/// inventory the bid bought.
+pub struct Quoter {
+    symbol: String,
+    quantity: f64,
+    half_spread: f64,
+    /// How far the quotes move down per share of inventory
+    skew: f64,
+    max_inventory: Option<f64>,
+    bid: Option<OrderId>,
+    ask: Option<OrderId>,
+    /// The side and unfilled quantity of every quote whose fills may still
+    /// be reported, including replaced ones
+    orders: HashMap<OrderId, (Side, f64)>,
+    inventory: Inventory,
+}
+
+impl Quoter {
+    /// Quotes `quantity` shares `half_spread` below and above the current
+    /// price
+    pub fn new(symbol: &str, quantity: f64, half_spread: f64) -> Self {
+        Quoter {
+            symbol: symbol.to_string(),
+            quantity,
+            half_spread,
+            skew: 0.0,
+            max_inventory: None,
+            bid: None,
+            ask: None,
+            orders: HashMap::new(),
+            inventory: Inventory::default(),
+        }
+    }
+
+    /// Moves both quotes down by `skew` per share held, so a long inventory
+    /// is more likely to be sold than added to
+    pub fn with_inventory_skew(mut self, skew: f64) -> Self {
+        self.skew = skew;
+        self
+    }
+
+    /// Stops bidding once `max_inventory` shares are held
+    pub fn with_max_inventory(mut self, max_inventory: f64) -> Self {
+        self.max_inventory = Some(max_inventory);
+        self
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    /// The resting bid and ask, as of the last refresh
+    pub fn quotes(&self) -> (Option<OrderId>, Option<OrderId>) {
+        (self.bid, self.ask)
+    }
+
+    /// The prices of the bid and the ask around `price`
+    pub fn prices(&self, price: f64) -> (f64, f64) {
+        let center = price - self.skew * self.inventory.position;
+
+        (center - self.half_spread, center + self.half_spread)
+    }
+
+    /// Tracks the fills of the quotes, including those reported after a
+    /// refresh replaced them. Every event of the market should be passed
+    /// here.
+    pub fn on_event(&mut self, event: &Event) {
+        match event {
+            Event::OrderFilled {
+                id,
+                quantity,
+                price,
+                ..
+            } => self.record_fill(*id, *quantity, *price, true),
+            Event::OrderPartiallyFilled {
+                id,
+                quantity,
+                price,
+                ..
+            } => self.record_fill(*id, *quantity, *price, false),
+            Event::OrderCanceled { id, .. } => self.forget(*id),
+            _ => {}
+        }
+    }
+
+    /// Moves the quotes to the current price, amending the resting ones in
+    /// place and placing the missing ones
+    pub async fn refresh<M: Market>(&mut self, market: &mut M) -> Result<(), M::Error> {
+        self.settle(market);
+        let price = market.current_price(&self.symbol).await?;
+        let (bid_price, ask_price) = self.prices(price);
+
+        let room = self
+            .max_inventory
+            .map_or(self.quantity, |max| max - self.inventory.position);
+        let bid_quantity = room.clamp(0.0, self.quantity);
+        let ask_quantity = self.inventory.position.clamp(0.0, self.quantity);
+
+        self.bid = self
+            .requote(market, self.bid, Side::Buy, bid_quantity, bid_price)
+            .await?;
+        self.ask = self
+            .requote(market, self.ask, Side::Sell, ask_quantity, ask_price)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Cancels the resting quotes, e.g. ahead of the close or news
+    pub async fn withdraw<M: Market>(&mut self, market: &mut M) -> Result<(), M::Error> {
+        for id in [self.bid.take(), self.ask.take()].into_iter().flatten() {
+            if matches!(market.order_status(id), Some(OrderStatus::Open(_))) {
+                market.cancel_order(id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_fill(&mut self, id: OrderId, quantity: f64, price: f64, done: bool) {
+        let Some((side, remaining)) = self.orders.get_mut(&id) else {
+            return;
+        };
+        *remaining -= quantity;
+
+        match side {
+            Side::Buy => {
+                self.inventory.position += quantity;
+                self.inventory.bought += quantity;
+                self.inventory.cash_flow -= Money::worth(quantity, price);
+            }
+            Side::Sell => {
+                self.inventory.position -= quantity;
+                self.inventory.sold += quantity;
+                self.inventory.cash_flow += Money::worth(quantity, price);
+            }
+        }
+        if done {
+            self.forget(id);
+        }
+    }
+
+    /// Records the fills the market already made but did not report yet, so
+    /// the quotes are not sized by an outdated inventory
+    fn settle<M: Market>(&mut self, market: &M) {
+        let filled: Vec<_> = self
+            .orders
+            .iter()
+            .filter_map(|(id, (_, remaining))| match market.order_status(*id) {
+                Some(OrderStatus::Filled { price }) => Some((*id, *remaining, price)),
+                _ => None,
+            })
+            .collect();
+
+        for (id, remaining, price) in filled {
+            self.record_fill(id, remaining, price, true);
+        }
+    }
+
+    fn forget(&mut self, id: OrderId) {
+        self.orders.remove(&id);
+        if self.bid == Some(id) {
+            self.bid = None;
+        }
+        if self.ask == Some(id) {
+            self.ask = None;
+        }
+    }
+
+    async fn requote<M: Market>(
+        &mut self,
+        market: &mut M,
+        id: Option<OrderId>,
+        side: Side,
+        quantity: f64,
+        limit_price: f64,
+    ) -> Result<Option<OrderId>, M::Error> {
+        let open = id.filter(|id| matches!(market.order_status(*id), Some(OrderStatus::Open(_))));
+        // Without quoting the rounding error of fractional fills
+        let quoted = quantity > 1e-9;
+
+        match open {
+            Some(id) if quoted => {
+                let amendment = Amendment {
+                    quantity: Some(quantity),
+                    limit_price: Some(limit_price),
+                    ..Default::default()
+                };
+                market.amend_order(id, amendment).await?;
+                self.orders.insert(id, (side, quantity));
+                Ok(Some(id))
+            }
+            Some(id) => {
+                market.cancel_order(id).await?;
+                Ok(None)
+            }
+            None if quoted => {
+                let order = Order::new(
+                    &self.symbol,
+                    side,
+                    quantity,
+                    OrderKind::Limit { limit_price },
+                )
+                .with_time_in_force(TimeInForce::Day);
+                let id = market.submit_order(order).await?;
+                self.orders.insert(id, (side, quantity));
+                Ok(Some(id))
+            }
+            None => Ok(None),
+        }
+    }
+}
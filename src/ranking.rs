@@ -0,0 +1,162 @@
+//! Cross-sectional scores of a universe at a rebalance point (e.g. momentum
+//! or value), normalized to ranks or z-scores so factors can be compared and
+//! combined, and split into the legs of a long-short portfolio.
+
+use std::collections::HashMap;
+
+use chrono::TimeDelta;
+use futures::future::try_join_all;
+use tokio::try_join;
+
+use crate::market::Market;
+
+/// The return of every equity of `universe` from `lookback` ago until `skip`
+/// ago (e.g. 12 months and 1 month, leaving out the short-term reversal),
+/// with the prices of all of them queried at once
+pub async fn momentum<M: Market>(
+    market: &M,
+    universe: &[String],
+    lookback: TimeDelta,
+    skip: TimeDelta,
+) -> Result<HashMap<String, f64>, M::Error> {
+    let now = market.time();
+    let returns = try_join_all(universe.iter().map(|symbol| async move {
+        let (then, recently) = try_join!(
+            market.price_at(symbol, now - lookback),
+            market.price_at(symbol, now - skip)
+        )?;
+        Ok((symbol.clone(), recently / then - 1.0))
+    }))
+    .await?;
+
+    Ok(returns.into_iter().collect())
+}
+
+/// The ratio of a fundamental value per share (e.g. book value or
+/// earnings) of every equity to its current price, so cheaper equities
+/// score higher
+pub async fn value<M: Market>(
+    market: &M,
+    fundamentals: &HashMap<String, f64>,
+) -> Result<HashMap<String, f64>, M::Error> {
+    let ratios = try_join_all(fundamentals.iter().map(|(symbol, fundamental)| async move {
+        let price = market.current_price(symbol).await?;
+        Ok((symbol.clone(), fundamental / price))
+    }))
+    .await?;
+
+    Ok(ratios.into_iter().collect())
+}
+
+/// Ranks scores from 0 for the lowest to 1 for the highest, giving ties
+/// the mean of their ranks. A single score ranks 0.5, and scores that are
+/// not finite are left out.
+pub fn percentile_ranks(scores: &HashMap<String, f64>) -> HashMap<String, f64> {
+    let mut sorted: Vec<(&String, f64)> = scores
+        .iter()
+        .filter(|(_, score)| score.is_finite())
+        .map(|(symbol, score)| (symbol, *score))
+        .collect();
+    sorted.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    if sorted.len() == 1 {
+        return [(sorted[0].0.clone(), 0.5)].into();
+    }
+
+    let last = (sorted.len() - 1) as f64;
+    let mut ranks = HashMap::with_capacity(sorted.len());
+    let mut first = 0;
+    while first < sorted.len() {
+        let ties = sorted[first..]
+            .iter()
+            .take_while(|(_, score)| *score == sorted[first].1)
+            .count();
+        let rank = (first as f64 + (ties - 1) as f64 / 2.0) / last;
+        for (symbol, _) in &sorted[first..first + ties] {
+            ranks.insert((*symbol).clone(), rank);
+        }
+        first += ties;
+    }
+
+    ranks
+}
+
+/// How many standard deviations every score is from the mean of the
+/// universe. Scores that are not finite are left out, and all are 0 if
+/// they do not differ.
+pub fn z_scores(scores: &HashMap<String, f64>) -> HashMap<String, f64> {
+    let finite: Vec<(&String, f64)> = scores
+        .iter()
+        .filter(|(_, score)| score.is_finite())
+        .map(|(symbol, score)| (symbol, *score))
+        .collect();
+    if finite.is_empty() {
+        return HashMap::new();
+    }
+
+    let count = finite.len() as f64;
+    let mean = finite.iter().map(|(_, score)| score).sum::<f64>() / count;
+    let deviation = (finite
+        .iter()
+        .map(|(_, score)| (score - mean).powi(2))
+        .sum::<f64>()
+        / count)
+        .sqrt();
+
+    finite
+        .into_iter()
+        .map(|(symbol, score)| {
+            let z_score = if deviation > 0.0 {
+                (score - mean) / deviation
+            } else {
+                0.0
+            };
+            (symbol.clone(), z_score)
+        })
+        .collect()
+}
+
+/// The weighted sum of normalized factors, for the equities that have a
+/// score in every one of them
+pub fn combine(factors: &[(&HashMap<String, f64>, f64)]) -> HashMap<String, f64> {
+    let Some(((first, _), rest)) = factors.split_first() else {
+        return HashMap::new();
+    };
+
+    first
+        .keys()
+        .filter(|symbol| rest.iter().all(|(factor, _)| factor.contains_key(*symbol)))
+        .map(|symbol| {
+            let score = factors
+                .iter()
+                .map(|(factor, weight)| factor[symbol] * weight)
+                .sum();
+            (symbol.clone(), score)
+        })
+        .collect()
+}
+
+/// The `count` highest scoring equities to buy and the `count` lowest
+/// scoring ones to sell short, best first in both. Fewer are returned if
+/// the legs would overlap.
+pub fn long_short(scores: &HashMap<String, f64>, count: usize) -> (Vec<String>, Vec<String>) {
+    let mut sorted: Vec<(&String, f64)> = scores
+        .iter()
+        .filter(|(_, score)| score.is_finite())
+        .map(|(symbol, score)| (symbol, *score))
+        .collect();
+    // Ties in symbol order, so legs do not depend on the map's order
+    sorted.sort_by(|(a_symbol, a), (b_symbol, b)| b.total_cmp(a).then(a_symbol.cmp(b_symbol)));
+
+    let count = count.min(sorted.len() / 2);
+    let long = sorted[..count]
+        .iter()
+        .map(|(symbol, _)| (*symbol).clone())
+        .collect();
+    let short = sorted[sorted.len() - count..]
+        .iter()
+        .rev()
+        .map(|(symbol, _)| (*symbol).clone())
+        .collect();
+
+    (long, short)
+}
@@ -0,0 +1,83 @@
+//! Compares snapshots of an account, e.g. the market's internal accounting
+//! against what a broker reports, or the same account across days.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::{market::Market, money::Money};
+
+/// The cash and positions of an account at some time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountSnapshot {
+    pub time: DateTime<Utc>,
+    pub cash: Money,
+    /// Held shares by symbol, without empty positions
+    pub holdings: BTreeMap<String, f64>,
+}
+
+impl AccountSnapshot {
+    /// The account of a market as it is accounted internally
+    pub fn of<M: Market>(market: &M) -> Self {
+        AccountSnapshot {
+            time: market.time(),
+            cash: market.cash(),
+            holdings: market
+                .holdings()
+                .into_iter()
+                .filter(|(_, quantity)| **quantity > 0.0)
+                .map(|(symbol, quantity)| (symbol.clone(), *quantity))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Discrepancy {
+    Cash {
+        expected: Money,
+        actual: Money,
+    },
+    Position {
+        symbol: String,
+        expected: f64,
+        actual: f64,
+    },
+}
+
+/// Lists how `actual` differs from `expected`, with positions sorted by
+/// symbol. Cash differences up to `cash_tolerance` (e.g. rounding by the
+/// broker) are ignored.
+pub fn reconcile(
+    expected: &AccountSnapshot,
+    actual: &AccountSnapshot,
+    cash_tolerance: Money,
+) -> Vec<Discrepancy> {
+    let mut discrepancies = Vec::new();
+
+    if (expected.cash - actual.cash).abs() > cash_tolerance {
+        discrepancies.push(Discrepancy::Cash {
+            expected: expected.cash,
+            actual: actual.cash,
+        });
+    }
+
+    let mut symbols: Vec<&String> = expected.holdings.keys().collect();
+    symbols.extend(actual.holdings.keys());
+    symbols.sort();
+    symbols.dedup();
+
+    for symbol in symbols {
+        let expected = expected.holdings.get(symbol).copied().unwrap_or(0.0);
+        let actual = actual.holdings.get(symbol).copied().unwrap_or(0.0);
+        if expected != actual {
+            discrepancies.push(Discrepancy::Position {
+                symbol: symbol.clone(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    discrepancies
+}
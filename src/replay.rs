@@ -0,0 +1,286 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::sync::watch;
+
+use crate::{
+    account::{ClosedLot, Lot, LotId, Position, Transaction},
+    market::{Event, Market, MarketTime, PriceQuote},
+    money::Money,
+    order::{Amendment, OcoGroupId, Order, OrderId, OrderStatus, PendingOrder, TradeReceipt},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct ControlState {
+    paused: bool,
+    /// Simulated time per real time, or `None` to replay as fast as possible
+    speed: Option<f64>,
+    /// How many steps were requested in total
+    steps: u64,
+}
+
+/// Controls the pace of a `Replay` from another task, e.g. a debugger's
+/// input loop or a demo's UI
+#[derive(Clone, Debug)]
+pub struct ReplayControl {
+    sender: Arc<watch::Sender<ControlState>>,
+}
+
+impl ReplayControl {
+    /// Holds back events until resumed or stepped
+    pub fn pause(&self) {
+        self.sender.send_modify(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.sender.send_modify(|state| state.paused = false);
+    }
+
+    /// Lets a single event through while paused
+    pub fn step(&self) {
+        self.sender.send_modify(|state| state.steps += 1);
+    }
+
+    /// Replays `speed` times faster than real time (e.g. 60.0 replays a
+    /// minute per second)
+    pub fn set_speed(&self, speed: f64) {
+        self.sender.send_modify(|state| state.speed = Some(speed));
+    }
+
+    /// Replays as fast as possible, the default
+    pub fn unlimited_speed(&self) {
+        self.sender.send_modify(|state| state.speed = None);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.sender.borrow().paused
+    }
+}
+
+/// Wraps a market, pacing its events with a `ReplayControl`, so a backtest
+/// can be stepped event by event while debugging or replayed at a watchable
+/// speed.
+///
+/// While paused, the next event is not requested from the wrapped market at
+/// all, so an interrupted wait loses nothing. Once the control is dropped,
+/// the replay resumes for good.
+pub struct Replay<M: Market> {
+    market: M,
+    receiver: watch::Receiver<ControlState>,
+    /// How many of the requested steps were taken
+    steps_taken: u64,
+    /// When the previous event happened, to pace the next one from
+    last_event_time: Option<DateTime<Utc>>,
+}
+
+impl<M: Market + Send> Replay<M> {
+    pub fn new(market: M) -> (Self, ReplayControl) {
+        let (sender, receiver) = watch::channel(ControlState::default());
+
+        (
+            Replay {
+                market,
+                receiver,
+                steps_taken: 0,
+                last_event_time: None,
+            },
+            ReplayControl {
+                sender: Arc::new(sender),
+            },
+        )
+    }
+
+    pub fn market(&self) -> &M {
+        &self.market
+    }
+
+    pub fn into_inner(self) -> M {
+        self.market
+    }
+
+    /// Waits until the next event may be requested
+    async fn wait_until_released(&mut self) {
+        loop {
+            let state = *self.receiver.borrow_and_update();
+            if !state.paused {
+                // Steps requested while running are not saved for later
+                self.steps_taken = state.steps;
+                return;
+            }
+            if state.steps > self.steps_taken {
+                self.steps_taken += 1;
+                return;
+            }
+
+            if self.receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Waits until it is time to report an event at `time`
+    async fn pace(&mut self, time: DateTime<Utc>) {
+        let speed = self.receiver.borrow().speed;
+        let last_event_time = self.last_event_time.replace(time);
+
+        let (Some(speed), Some(last_event_time)) = (speed, last_event_time) else {
+            return;
+        };
+        if speed <= 0.0 {
+            return;
+        }
+
+        let elapsed = (time - last_event_time).max(TimeDelta::zero());
+        if let Ok(elapsed) = elapsed.to_std() {
+            tokio::time::sleep(elapsed.div_f64(speed)).await;
+        }
+    }
+}
+
+impl<M: Market + Send> Market for Replay<M> {
+    type Error = M::Error;
+
+    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        self.wait_until_released().await;
+        let event = self.market.next_event().await?;
+        if let Some((time, _)) = &event {
+            self.pace(*time).await;
+        }
+
+        Ok(event)
+    }
+
+    async fn next_event_or_tick(
+        &mut self,
+        tick: TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), M::Error> {
+        self.wait_until_released().await;
+        let event = self.market.next_event_or_tick(tick).await?;
+        self.pace(event.0).await;
+
+        Ok(event)
+    }
+
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        self.wait_until_released().await;
+        let event = self.market.next_event_until(until).await?;
+        self.pace(self.market.time()).await;
+
+        Ok(event)
+    }
+
+    fn time(&self) -> DateTime<Utc> {
+        self.market.time()
+    }
+
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, M::Error> {
+        self.market.quote_at(symbol, time).await
+    }
+
+    async fn buy_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.buy_at_market(symbol, quantity).await
+    }
+
+    async fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.market.sell_at_market(symbol, quantity).await
+    }
+
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, M::Error> {
+        self.market.submit_order(order).await
+    }
+
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), M::Error> {
+        self.market.submit_oco(first, second).await
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), M::Error> {
+        self.market.amend_order(id, amendment).await
+    }
+
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), M::Error> {
+        self.market.cancel_order(id).await
+    }
+
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.market.order_status(id)
+    }
+
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.market.open_orders()
+    }
+
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        self.market.set_tradeable(symbol, tradeable)
+    }
+
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        self.market.is_tradeable(symbol)
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.market.lot_size(symbol)
+    }
+
+    fn market_time(&self) -> MarketTime {
+        self.market.market_time()
+    }
+
+    fn cash(&self) -> Money {
+        self.market.cash()
+    }
+
+    fn settled_cash(&self) -> Money {
+        self.market.settled_cash()
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.market.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.market.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.market.position(symbol)
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.market.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.market.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[Lot] {
+        self.market.lots(symbol)
+    }
+
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.market.closed_lots()
+    }
+
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.market.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, M::Error> {
+        self.market.position_high_water_mark(symbol).await
+    }
+}
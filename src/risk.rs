@@ -0,0 +1,121 @@
+//! Value at risk and expected shortfall estimates from return histories, and
+//! limits on them.
+
+use thiserror::Error;
+
+use crate::{pricing::normal_cdf, sizing::volatility};
+
+/// How far losses over one period may go, as fractions of the portfolio's
+/// value. Losses are positive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RiskEstimate {
+    /// The loss that is not exceeded with the estimate's confidence
+    pub value_at_risk: f64,
+    /// The mean loss when the value at risk is exceeded
+    pub expected_shortfall: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RiskMethod {
+    /// Assumes normally distributed returns, with the sample's mean and
+    /// standard deviation
+    Parametric,
+    /// Uses the worst returns of the sample as they are
+    #[default]
+    Historical,
+}
+
+impl RiskMethod {
+    /// Estimates the risk of `returns` at `confidence` (e.g. 0.95), or `None`
+    /// with fewer than two returns
+    pub fn estimate(&self, returns: &[f64], confidence: f64) -> Option<RiskEstimate> {
+        match self {
+            RiskMethod::Parametric => parametric(returns, confidence),
+            RiskMethod::Historical => historical(returns, confidence),
+        }
+    }
+}
+
+pub fn parametric(returns: &[f64], confidence: f64) -> Option<RiskEstimate> {
+    let deviation = volatility(returns)?;
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+
+    let tail = 1.0 - confidence;
+    let z = normal_quantile(tail);
+    let density = (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt();
+
+    Some(RiskEstimate {
+        value_at_risk: -(mean + z * deviation),
+        expected_shortfall: -mean + deviation * density / tail,
+    })
+}
+
+pub fn historical(returns: &[f64], confidence: f64) -> Option<RiskEstimate> {
+    if returns.len() < 2 {
+        return None;
+    }
+
+    let mut sorted = returns.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    // The returns in the tail, at least the worst one
+    let tail = (((1.0 - confidence) * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    let worst = &sorted[..tail];
+
+    Some(RiskEstimate {
+        value_at_risk: -worst[tail - 1],
+        expected_shortfall: -worst.iter().sum::<f64>() / tail as f64,
+    })
+}
+
+/// The inverse of the standard normal CDF, by bisection
+fn normal_quantile(probability: f64) -> f64 {
+    let (mut low, mut high) = (-10.0, 10.0);
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if normal_cdf(middle) < probability {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum RiskLimitExceeded {
+    #[error("The value at risk of {value_at_risk} exceeds the limit of {limit}")]
+    ValueAtRisk { value_at_risk: f64, limit: f64 },
+
+    #[error("The expected shortfall of {expected_shortfall} exceeds the limit of {limit}")]
+    ExpectedShortfall { expected_shortfall: f64, limit: f64 },
+}
+
+/// Caps on the risk of a portfolio, e.g. to stop adding to positions while
+/// they are exceeded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RiskLimit {
+    pub max_value_at_risk: f64,
+    pub max_expected_shortfall: Option<f64>,
+}
+
+impl RiskLimit {
+    pub fn check(&self, estimate: &RiskEstimate) -> Result<(), RiskLimitExceeded> {
+        if estimate.value_at_risk > self.max_value_at_risk {
+            return Err(RiskLimitExceeded::ValueAtRisk {
+                value_at_risk: estimate.value_at_risk,
+                limit: self.max_value_at_risk,
+            });
+        }
+
+        match self.max_expected_shortfall {
+            Some(limit) if estimate.expected_shortfall > limit => {
+                Err(RiskLimitExceeded::ExpectedShortfall {
+                    expected_shortfall: estimate.expected_shortfall,
+                    limit,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
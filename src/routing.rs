@@ -0,0 +1,259 @@
+//! Routing orders among several markets (venues), so a single strategy can
+//! e.g. trade equities at one broker and crypto at another.
+//!
+//! Every venue keeps its own clock, account and order IDs. A `Router` picks
+//! the venue of each order by its symbol, or by an explicit venue name, and
+//! advances all the venues together.
+
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
+use thiserror::Error;
+
+use crate::{
+    market::{Event, Market},
+    money::Money,
+    order::{Order, OrderId, OrderStatus, TradeReceipt},
+};
+
+#[derive(Error, Debug)]
+pub enum RoutingError<E> {
+    #[error("No venue is named {0}")]
+    UnknownVenue(String),
+
+    #[error(transparent)]
+    Market(E),
+}
+
+/// An order placed through a `Router`, identified by its venue since the
+/// venues' order IDs may collide
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RoutedOrder {
+    pub venue: String,
+    pub id: OrderId,
+}
+
+/// How a route matches the symbol of an order
+enum Rule {
+    Symbol(String),
+    /// E.g. an asset class, such as symbols ending with `USDT`
+    Matching(Box<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl Rule {
+    fn matches(&self, symbol: &str) -> bool {
+        match self {
+            Rule::Symbol(routed) => routed == symbol,
+            Rule::Matching(predicate) => predicate(symbol),
+        }
+    }
+}
+
+/// Chooses among several venues per order: by the first route matching its
+/// symbol, or by the default venue (the first one) if none does.
+///
+/// The venues are markets of the same type, e.g. several `QuestDbMarket`s
+/// with their own instruments and fees.
+pub struct Router<M: Market> {
+    venues: Vec<(String, M)>,
+    /// The venue of the symbols each rule matches, in order of precedence
+    routes: Vec<(Rule, usize)>,
+}
+
+impl<M: Market> Router<M> {
+    /// Routes every order to `market` until other venues and routes are added
+    pub fn new(name: &str, market: M) -> Self {
+        Router {
+            venues: vec![(name.to_string(), market)],
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn with_venue(mut self, name: &str, market: M) -> Self {
+        self.venues.push((name.to_string(), market));
+        self
+    }
+
+    /// Routes the orders of `symbol` to a venue
+    ///
+    /// # Panics
+    ///
+    /// Panics if no venue is named `venue`.
+    pub fn with_symbol_route(mut self, symbol: &str, venue: &str) -> Self {
+        let index = self.expect_venue(venue);
+        self.routes.push((Rule::Symbol(symbol.to_string()), index));
+        self
+    }
+
+    /// Routes the orders of the symbols `predicate` matches to a venue
+    ///
+    /// # Panics
+    ///
+    /// Panics if no venue is named `venue`.
+    pub fn with_route(
+        mut self,
+        predicate: impl Fn(&str) -> bool + Send + Sync + 'static,
+        venue: &str,
+    ) -> Self {
+        let index = self.expect_venue(venue);
+        self.routes
+            .push((Rule::Matching(Box::new(predicate)), index));
+        self
+    }
+
+    /// The name of the venue the orders of `symbol` are routed to
+    pub fn venue_for(&self, symbol: &str) -> &str {
+        &self.venues[self.route(symbol)].0
+    }
+
+    pub fn venue(&self, name: &str) -> Option<&M> {
+        self.venues
+            .iter()
+            .find(|(venue, _)| venue == name)
+            .map(|(_, market)| market)
+    }
+
+    pub fn venue_mut(&mut self, name: &str) -> Option<&mut M> {
+        self.venues
+            .iter_mut()
+            .find(|(venue, _)| venue == name)
+            .map(|(_, market)| market)
+    }
+
+    /// The venues, by name, in the order they were added
+    pub fn venues(&self) -> impl Iterator<Item = (&str, &M)> {
+        self.venues
+            .iter()
+            .map(|(name, market)| (name.as_str(), market))
+    }
+
+    /// Places an order at the venue its symbol is routed to
+    pub async fn submit_order(&mut self, order: Order) -> Result<RoutedOrder, M::Error> {
+        let index = self.route(&order.symbol);
+        let (venue, market) = &mut self.venues[index];
+        let id = market.submit_order(order).await?;
+
+        Ok(RoutedOrder {
+            venue: venue.clone(),
+            id,
+        })
+    }
+
+    /// Places an order at an explicitly chosen venue, regardless of the
+    /// routes
+    pub async fn submit_order_to(
+        &mut self,
+        venue: &str,
+        order: Order,
+    ) -> Result<RoutedOrder, RoutingError<M::Error>> {
+        let market = self
+            .venue_mut(venue)
+            .ok_or_else(|| RoutingError::UnknownVenue(venue.to_string()))?;
+        let id = market
+            .submit_order(order)
+            .await
+            .map_err(RoutingError::Market)?;
+
+        Ok(RoutedOrder {
+            venue: venue.to_string(),
+            id,
+        })
+    }
+
+    pub async fn buy_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<(String, TradeReceipt), M::Error> {
+        let index = self.route(symbol);
+        let (venue, market) = &mut self.venues[index];
+        let receipt = market.buy_at_market(symbol, quantity).await?;
+
+        Ok((venue.clone(), receipt))
+    }
+
+    pub async fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<(String, TradeReceipt), M::Error> {
+        let index = self.route(symbol);
+        let (venue, market) = &mut self.venues[index];
+        let receipt = market.sell_at_market(symbol, quantity).await?;
+
+        Ok((venue.clone(), receipt))
+    }
+
+    pub async fn cancel_order(
+        &mut self,
+        order: &RoutedOrder,
+    ) -> Result<(), RoutingError<M::Error>> {
+        let market = self
+            .venue_mut(&order.venue)
+            .ok_or_else(|| RoutingError::UnknownVenue(order.venue.clone()))?;
+
+        market
+            .cancel_order(order.id)
+            .await
+            .map_err(RoutingError::Market)
+    }
+
+    pub fn order_status(&self, order: &RoutedOrder) -> Option<OrderStatus> {
+        self.venue(&order.venue)?.order_status(order.id)
+    }
+
+    /// Fast-forwards every venue to `time`, returning the events on the way
+    /// by venue and in order of time (and venue, for simultaneous events)
+    pub async fn advance_to(
+        &mut self,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<(String, DateTime<Utc>, Event)>, M::Error> {
+        let mut events = Vec::new();
+        for (venue, market) in &mut self.venues {
+            events.extend(
+                market
+                    .advance_to(time)
+                    .await?
+                    .into_iter()
+                    .map(|(time, event)| (venue.clone(), time, event)),
+            );
+        }
+        // A stable sort keeps the venues in order
+        events.sort_by_key(|(_, time, _)| *time);
+
+        Ok(events)
+    }
+
+    /// The cash on hand at all the venues
+    pub fn cash(&self) -> Money {
+        self.venues.iter().map(|(_, market)| market.cash()).sum()
+    }
+
+    /// The shares of an equity held at all the venues
+    pub fn shares_of(&self, symbol: &str) -> f64 {
+        self.venues
+            .iter()
+            .map(|(_, market)| market.shares_of(symbol))
+            .sum()
+    }
+
+    /// The net worth of the accounts at all the venues
+    pub async fn net_worth(&self) -> Result<Money, M::Error> {
+        let worths = try_join_all(self.venues.iter().map(|(_, market)| market.net_worth())).await?;
+
+        Ok(worths.iter().sum())
+    }
+
+    fn route(&self, symbol: &str) -> usize {
+        self.routes
+            .iter()
+            .find(|(rule, _)| rule.matches(symbol))
+            .map_or(0, |(_, index)| *index)
+    }
+
+    fn expect_venue(&self, name: &str) -> usize {
+        self.venues
+            .iter()
+            .position(|(venue, _)| venue == name)
+            .unwrap_or_else(|| panic!("no venue is named {name}"))
+    }
+}
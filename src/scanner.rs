@@ -0,0 +1,191 @@
+//! Scans a universe of equities for setups (e.g. gaps, oversold prices or
+//! volume surges) on every bar, reporting matches as `Event::ScannerHit`
+//! events for strategies that discover what to trade as they go.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::TimeDelta;
+
+use crate::market::{Candle, Event};
+
+/// Decides from the recent bars of an equity, oldest first, whether it
+/// matches a rule
+type Predicate = Box<dyn Fn(&[Candle]) -> bool + Send + Sync>;
+
+struct Rule {
+    name: String,
+    predicate: Predicate,
+}
+
+/// The bars a `Scanner` kept for its rules, by symbol, from which it can
+/// resume scanning (e.g. along with a market snapshot)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScannerHistory(HashMap<String, VecDeque<Candle>>);
+
+/// Evaluates rules against the recent bars of every equity of a universe
+pub struct Scanner {
+    universe: Vec<String>,
+    interval: TimeDelta,
+    /// How many bars are kept for the rules, by default 100
+    lookback: usize,
+    rules: Vec<Rule>,
+    /// The kept bars, by symbol
+    histories: HashMap<String, VecDeque<Candle>>,
+}
+
+impl Scanner {
+    /// A scanner of `universe` on bars of `interval`, without rules
+    pub fn new(universe: impl IntoIterator<Item = impl Into<String>>, interval: TimeDelta) -> Self {
+        Scanner {
+            universe: universe.into_iter().map(Into::into).collect(),
+            interval,
+            lookback: 100,
+            rules: Vec::new(),
+            histories: HashMap::new(),
+        }
+    }
+
+    /// Adds a rule, whose matches are reported with `name`
+    pub fn with_rule(
+        mut self,
+        name: &str,
+        predicate: impl Fn(&[Candle]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.rules.push(Rule {
+            name: name.to_string(),
+            predicate: Box::new(predicate),
+        });
+        self
+    }
+
+    /// Sets how many bars of each equity the rules are given
+    pub fn with_lookback(mut self, bars: usize) -> Self {
+        self.lookback = bars.max(1);
+        self
+    }
+
+    pub fn universe(&self) -> &[String] {
+        &self.universe
+    }
+
+    pub fn interval(&self) -> TimeDelta {
+        self.interval
+    }
+
+    /// The bars the rules are given, to resume scanning from later
+    pub fn history(&self) -> ScannerHistory {
+        ScannerHistory(self.histories.clone())
+    }
+
+    /// Replaces the kept bars with a previously captured history
+    pub fn restore_history(&mut self, history: ScannerHistory) {
+        self.histories = history.0;
+    }
+
+    /// Adds a closed bar of an equity, returning an `Event::ScannerHit` for
+    /// every rule it now matches. Equities outside the universe are ignored.
+    pub fn on_bar(&mut self, symbol: &str, candle: Candle) -> Vec<Event> {
+        if !self.universe.iter().any(|member| member == symbol) {
+            return Vec::new();
+        }
+
+        let history = self.histories.entry(symbol.to_string()).or_default();
+        history.push_back(candle);
+        while history.len() > self.lookback {
+            history.pop_front();
+        }
+        let bars = history.make_contiguous();
+
+        self.rules
+            .iter()
+            .filter(|rule| (rule.predicate)(bars))
+            .map(|rule| Event::ScannerHit {
+                symbol: symbol.to_string(),
+                rule: rule.name.clone(),
+            })
+            .collect()
+    }
+
+    /// Like `on_bar`, for the `Event::BarClosed` events of the scanner's
+    /// interval (e.g. from a `BarAggregator` of a live feed)
+    pub fn on_event(&mut self, event: &Event) -> Vec<Event> {
+        match event {
+            Event::BarClosed {
+                symbol,
+                interval,
+                candle,
+            } if *interval == self.interval => self.on_bar(symbol, *candle),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Matches bars that opened more than `fraction` (e.g. 0.03) above the
+/// previous close
+pub fn gap_up(fraction: f64) -> impl Fn(&[Candle]) -> bool + Send + Sync {
+    move |bars| match bars {
+        [.., previous, last] => last.open > previous.close * (1.0 + fraction),
+        _ => false,
+    }
+}
+
+/// Matches bars that opened more than `fraction` below the previous close
+pub fn gap_down(fraction: f64) -> impl Fn(&[Candle]) -> bool + Send + Sync {
+    move |bars| match bars {
+        [.., previous, last] => last.open < previous.close * (1.0 - fraction),
+        _ => false,
+    }
+}
+
+/// Matches when the RSI over `periods` bars is below `threshold` (e.g. 30)
+pub fn rsi_below(periods: usize, threshold: f64) -> impl Fn(&[Candle]) -> bool + Send + Sync {
+    move |bars| rsi(bars, periods).is_some_and(|rsi| rsi < threshold)
+}
+
+/// Matches when the RSI over `periods` bars is above `threshold` (e.g. 70)
+pub fn rsi_above(periods: usize, threshold: f64) -> impl Fn(&[Candle]) -> bool + Send + Sync {
+    move |bars| rsi(bars, periods).is_some_and(|rsi| rsi > threshold)
+}
+
+/// Matches bars that traded more than `multiple` times the mean volume of
+/// the `periods` bars before them
+pub fn volume_surge(multiple: f64, periods: usize) -> impl Fn(&[Candle]) -> bool + Send + Sync {
+    move |bars| {
+        let Some((last, earlier)) = bars.split_last() else {
+            return false;
+        };
+        if periods == 0 || earlier.len() < periods {
+            return false;
+        }
+
+        let earlier = &earlier[earlier.len() - periods..];
+        let mean = earlier.iter().map(|bar| bar.volume).sum::<f64>() / periods as f64;
+        last.volume > mean * multiple
+    }
+}
+
+/// The relative strength index of the closes of bars with Wilder's
+/// smoothing over `periods`, or `None` with no more than `periods` bars
+pub fn rsi(bars: &[Candle], periods: usize) -> Option<f64> {
+    if periods == 0 || bars.len() <= periods {
+        return None;
+    }
+
+    let changes: Vec<f64> = bars
+        .windows(2)
+        .map(|pair| pair[1].close - pair[0].close)
+        .collect();
+    let (first, rest) = changes.split_at(periods);
+    let mut gain = first.iter().map(|change| change.max(0.0)).sum::<f64>() / periods as f64;
+    let mut loss = first.iter().map(|change| (-change).max(0.0)).sum::<f64>() / periods as f64;
+    for change in rest {
+        gain = (gain * (periods - 1) as f64 + change.max(0.0)) / periods as f64;
+        loss = (loss * (periods - 1) as f64 + (-change).max(0.0)) / periods as f64;
+    }
+
+    if loss == 0.0 {
+        return Some(if gain == 0.0 { 50.0 } else { 100.0 });
+    }
+
+    Some(100.0 - 100.0 / (1.0 + gain / loss))
+}
@@ -0,0 +1,112 @@
+//! Position sizing helpers, e.g. to give every pick the same share of the
+//! portfolio's risk.
+
+use std::collections::HashMap;
+
+use crate::money::Money;
+
+/// What an equity's risk is measured by when sizing positions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RiskMeasure {
+    /// The standard deviation of its returns
+    #[default]
+    Volatility,
+    /// Its sensitivity to a benchmark, so positions contribute equally to
+    /// the portfolio's market exposure
+    Beta,
+}
+
+/// The inputs to sizing a position in an equity
+#[derive(Clone, Debug, PartialEq)]
+pub struct RiskProfile {
+    pub symbol: String,
+    pub price: f64,
+    pub volatility: f64,
+    /// Relative to a benchmark, if known
+    pub beta: Option<f64>,
+    /// The shares it trades in, as the market's `lot_size` reports it
+    pub lot_size: f64,
+}
+
+impl RiskProfile {
+    /// The risk of the equity by `measure`, if it is known and positive
+    fn risk(&self, measure: RiskMeasure) -> Option<f64> {
+        match measure {
+            RiskMeasure::Volatility => Some(self.volatility),
+            RiskMeasure::Beta => self.beta.map(f64::abs),
+        }
+        .filter(|risk| *risk > 0.0 && risk.is_finite())
+    }
+}
+
+/// The sample standard deviation of returns, or `None` with fewer than two
+pub fn volatility(returns: &[f64]) -> Option<f64> {
+    if returns.len() < 2 {
+        return None;
+    }
+
+    let count = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / count;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (count - 1.0);
+
+    Some(variance.sqrt())
+}
+
+/// The beta of returns against the returns of a benchmark over the same
+/// periods, or `None` if there are fewer than two or the benchmark did not
+/// move
+pub fn beta(returns: &[f64], benchmark: &[f64]) -> Option<f64> {
+    let count = returns.len().min(benchmark.len());
+    if count < 2 {
+        return None;
+    }
+
+    let (returns, benchmark) = (&returns[..count], &benchmark[..count]);
+    let mean = returns.iter().sum::<f64>() / count as f64;
+    let benchmark_mean = benchmark.iter().sum::<f64>() / count as f64;
+    let covariance: f64 = returns
+        .iter()
+        .zip(benchmark)
+        .map(|(r, b)| (r - mean) * (b - benchmark_mean))
+        .sum();
+    let variance: f64 = benchmark.iter().map(|b| (b - benchmark_mean).powi(2)).sum();
+
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// Weights that sum to 1 and give every equity the same risk contribution,
+/// ignoring correlations: each is inversely proportional to its risk.
+/// Equities with an unknown or zero risk are left out.
+pub fn equal_risk_weights(profiles: &[RiskProfile], measure: RiskMeasure) -> HashMap<String, f64> {
+    let inverse_risks: Vec<_> = profiles
+        .iter()
+        .filter_map(|profile| Some((&profile.symbol, 1.0 / profile.risk(measure)?)))
+        .collect();
+    let total: f64 = inverse_risks.iter().map(|(_, inverse)| inverse).sum();
+
+    inverse_risks
+        .into_iter()
+        .map(|(symbol, inverse)| (symbol.clone(), inverse / total))
+        .collect()
+}
+
+/// How many shares of each equity to hold so `capital` is spread across
+/// them with equal risk contributions, rounded down to whole lots
+pub fn risk_parity_quantities(
+    capital: Money,
+    profiles: &[RiskProfile],
+    measure: RiskMeasure,
+) -> HashMap<String, f64> {
+    let weights = equal_risk_weights(profiles, measure);
+
+    profiles
+        .iter()
+        .filter(|profile| profile.price > 0.0 && profile.lot_size > 0.0)
+        .filter_map(|profile| {
+            let weight = weights.get(&profile.symbol)?;
+            let lots = capital.times(*weight).to_f64() / profile.price / profile.lot_size;
+            let quantity = (lots + 1e-9).floor().max(0.0) * profile.lot_size;
+            Some((profile.symbol.clone(), quantity))
+        })
+        .collect()
+}
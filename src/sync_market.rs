@@ -0,0 +1,364 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    account::{ClosedLot, Lot, LotId, Position, Transaction},
+    market::{Event, EventMask, Market, MarketTime, PriceQuote},
+    money::Money,
+    order::{
+        Amendment, OcoGroupId, Order, OrderId, OrderStatus, PendingOrder, TradeReceipt, Trail,
+    },
+    risk::{RiskEstimate, RiskMethod},
+    tick::AdaptiveTick,
+};
+
+/// A blocking facade over a market, driving its async methods on an internal
+/// runtime. Meant for interactive use (e.g. from evcxr or Jupyter), where
+/// writing async code is inconvenient.
+///
+/// Markets that depend on background tasks (like the database connection of
+/// a `QuestDbMarket`) must have those tasks spawned on the same runtime, e.g.
+///
+/// ```ignore
+/// let runtime = tokio::runtime::Runtime::new()?;
+/// let (client, connection) = runtime.block_on(tokio_postgres::connect(config, NoTls))?;
+/// runtime.spawn(connection);
+/// let market = runtime.block_on(QuestDbMarket::new(&client, start, 10_000.0))?;
+/// let mut market = SyncMarket::with_runtime(runtime, market);
+/// ```
+pub struct SyncMarket<M: Market> {
+    runtime: Runtime,
+    market: M,
+}
+
+impl<M: Market> SyncMarket<M> {
+    /// Wraps a market that needs no background tasks, on a new
+    /// single-threaded runtime
+    pub fn new(market: M) -> std::io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+
+        Ok(SyncMarket { runtime, market })
+    }
+
+    pub fn with_runtime(runtime: Runtime, market: M) -> Self {
+        SyncMarket { runtime, market }
+    }
+
+    /// Blocks on any future, e.g. a market-specific async method
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn market(&self) -> &M {
+        &self.market
+    }
+
+    pub fn market_mut(&mut self) -> &mut M {
+        &mut self.market
+    }
+
+    pub fn into_inner(self) -> M {
+        self.market
+    }
+
+    pub fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        self.runtime.block_on(self.market.next_event())
+    }
+
+    pub fn next_event_matching(
+        &mut self,
+        mask: EventMask,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        self.runtime.block_on(self.market.next_event_matching(mask))
+    }
+
+    pub fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, M::Error> {
+        self.runtime.block_on(self.market.next_event_until(until))
+    }
+
+    pub fn advance_to(
+        &mut self,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, Event)>, M::Error> {
+        self.runtime.block_on(self.market.advance_to(time))
+    }
+
+    pub fn next_event_or_tick(
+        &mut self,
+        tick: TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), M::Error> {
+        self.runtime.block_on(self.market.next_event_or_tick(tick))
+    }
+
+    pub fn next_event_or_adaptive_tick(
+        &mut self,
+        tick: AdaptiveTick,
+    ) -> Result<(DateTime<Utc>, Event), M::Error> {
+        self.runtime
+            .block_on(self.market.next_event_or_adaptive_tick(tick))
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        self.market.time()
+    }
+
+    pub fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, M::Error> {
+        self.runtime.block_on(self.market.quote_at(symbol, time))
+    }
+
+    pub fn current_quote(&self, symbol: &str) -> Result<PriceQuote, M::Error> {
+        self.runtime.block_on(self.market.current_quote(symbol))
+    }
+
+    pub fn price_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<f64, M::Error> {
+        self.runtime.block_on(self.market.price_at(symbol, time))
+    }
+
+    pub fn current_price(&self, symbol: &str) -> Result<f64, M::Error> {
+        self.runtime.block_on(self.market.current_price(symbol))
+    }
+
+    pub fn buy_at_market(&mut self, symbol: &str, quantity: f64) -> Result<TradeReceipt, M::Error> {
+        self.runtime
+            .block_on(self.market.buy_at_market(symbol, quantity))
+    }
+
+    pub fn sell_at_market(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+    ) -> Result<TradeReceipt, M::Error> {
+        self.runtime
+            .block_on(self.market.sell_at_market(symbol, quantity))
+    }
+
+    pub fn buy_notional(&mut self, symbol: &str, amount: Money) -> Result<TradeReceipt, M::Error> {
+        self.runtime
+            .block_on(self.market.buy_notional(symbol, amount))
+    }
+
+    pub fn sell_notional(&mut self, symbol: &str, amount: Money) -> Result<TradeReceipt, M::Error> {
+        self.runtime
+            .block_on(self.market.sell_notional(symbol, amount))
+    }
+
+    pub fn submit_order(&mut self, order: Order) -> Result<OrderId, M::Error> {
+        self.runtime.block_on(self.market.submit_order(order))
+    }
+
+    pub fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), M::Error> {
+        self.runtime.block_on(self.market.submit_oco(first, second))
+    }
+
+    pub fn buy_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        limit_price: f64,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime
+            .block_on(self.market.buy_limit(symbol, quantity, limit_price))
+    }
+
+    pub fn sell_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        limit_price: f64,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime
+            .block_on(self.market.sell_limit(symbol, quantity, limit_price))
+    }
+
+    pub fn buy_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime
+            .block_on(self.market.buy_stop(symbol, quantity, stop_price))
+    }
+
+    pub fn sell_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime
+            .block_on(self.market.sell_stop(symbol, quantity, stop_price))
+    }
+
+    pub fn buy_stop_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+        limit_price: f64,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime.block_on(
+            self.market
+                .buy_stop_limit(symbol, quantity, stop_price, limit_price),
+        )
+    }
+
+    pub fn sell_stop_limit(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        stop_price: f64,
+        limit_price: f64,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime.block_on(self.market.sell_stop_limit(
+            symbol,
+            quantity,
+            stop_price,
+            limit_price,
+        ))
+    }
+
+    pub fn buy_trailing_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        trail: Trail,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime
+            .block_on(self.market.buy_trailing_stop(symbol, quantity, trail))
+    }
+
+    pub fn sell_trailing_stop(
+        &mut self,
+        symbol: &str,
+        quantity: f64,
+        trail: Trail,
+    ) -> Result<OrderId, M::Error> {
+        self.runtime
+            .block_on(self.market.sell_trailing_stop(symbol, quantity, trail))
+    }
+
+    pub fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), M::Error> {
+        self.runtime
+            .block_on(self.market.amend_order(id, amendment))
+    }
+
+    pub fn cancel_order(&mut self, id: OrderId) -> Result<(), M::Error> {
+        self.runtime.block_on(self.market.cancel_order(id))
+    }
+
+    pub fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.market.order_status(id)
+    }
+
+    /// The orders waiting to be filled
+    pub fn open_orders(&self) -> Vec<PendingOrder> {
+        self.market.open_orders().into_iter().cloned().collect()
+    }
+
+    pub fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        self.market.set_tradeable(symbol, tradeable)
+    }
+
+    pub fn is_tradeable(&self, symbol: &str) -> bool {
+        self.market.is_tradeable(symbol)
+    }
+
+    pub fn lot_size(&self, symbol: &str) -> f64 {
+        self.market.lot_size(symbol)
+    }
+
+    pub fn market_time(&self) -> MarketTime {
+        self.market.market_time()
+    }
+
+    pub fn cash(&self) -> Money {
+        self.market.cash()
+    }
+
+    pub fn settled_cash(&self) -> Money {
+        self.market.settled_cash()
+    }
+
+    pub fn shares_of(&self, symbol: &str) -> f64 {
+        self.market.shares_of(symbol)
+    }
+
+    pub fn position(&self, symbol: &str) -> Option<Position> {
+        self.market.position(symbol)
+    }
+
+    pub fn realized_pnl(&self) -> Money {
+        self.market.realized_pnl()
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        self.market.transactions()
+    }
+
+    pub fn lots(&self, symbol: &str) -> &[Lot] {
+        self.market.lots(symbol)
+    }
+
+    pub fn closed_lots(&self) -> &[ClosedLot] {
+        self.market.closed_lots()
+    }
+
+    pub fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.market.designate_lots(symbol, lots);
+    }
+
+    pub fn unrealized_pnl(&self, symbol: &str) -> Result<Money, M::Error> {
+        self.runtime.block_on(self.market.unrealized_pnl(symbol))
+    }
+
+    pub fn total_unrealized_pnl(&self) -> Result<Money, M::Error> {
+        self.runtime.block_on(self.market.total_unrealized_pnl())
+    }
+
+    /// Held shares by symbol, sorted by symbol
+    pub fn holdings(&self) -> Vec<(String, f64)> {
+        let mut holdings: Vec<(String, f64)> = self
+            .market
+            .holdings()
+            .into_iter()
+            .map(|(symbol, quantity)| (symbol.clone(), *quantity))
+            .collect();
+        holdings.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        holdings
+    }
+
+    pub fn net_worth(&self) -> Result<Money, M::Error> {
+        self.runtime.block_on(self.market.net_worth())
+    }
+
+    pub fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, M::Error> {
+        self.runtime
+            .block_on(self.market.position_high_water_mark(symbol))
+    }
+
+    pub fn position_drawdown(&self, symbol: &str) -> Result<Option<f64>, M::Error> {
+        self.runtime.block_on(self.market.position_drawdown(symbol))
+    }
+
+    pub fn portfolio_risk(
+        &self,
+        interval: TimeDelta,
+        periods: usize,
+        confidence: f64,
+        method: RiskMethod,
+    ) -> Result<Option<RiskEstimate>, M::Error> {
+        self.runtime.block_on(
+            self.market
+                .portfolio_risk(interval, periods, confidence, method),
+        )
+    }
+}
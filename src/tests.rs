@@ -1 +1,46 @@
-pub(self) mod test_market;
+mod test_account;
+mod test_align;
+mod test_bars;
+mod test_breakpoint;
+mod test_calendar;
+mod test_chaos;
+#[cfg(feature = "analytics")]
+mod test_divergence;
+mod test_domain;
+#[cfg(feature = "questdb")]
+mod test_downsample;
+#[cfg(feature = "questdb")]
+mod test_engine;
+#[cfg(feature = "analytics")]
+mod test_ensemble;
+mod test_execution;
+#[cfg(feature = "analytics")]
+mod test_export;
+mod test_ext;
+mod test_fill;
+mod test_filter;
+#[cfg(feature = "fixtures")]
+mod test_fixtures;
+mod test_fuzz;
+#[cfg(feature = "analytics")]
+mod test_golden;
+mod test_health;
+mod test_instrument;
+mod test_latency;
+mod test_market;
+mod test_money;
+mod test_order_builder;
+mod test_pricing;
+#[cfg(feature = "questdb")]
+mod test_questdb_market;
+mod test_quoting;
+mod test_ranking;
+#[cfg(feature = "analytics")]
+mod test_reconcile;
+mod test_replay;
+mod test_risk;
+mod test_routing;
+mod test_scanner;
+mod test_sizing;
+mod test_sync_market;
+mod test_tick;
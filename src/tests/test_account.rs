@@ -0,0 +1,324 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use crate::{
+    account::{AccountError, DayTradeLimit, LotId, LotSelection, Margin, SimulatedAccount},
+    money::Money,
+    order::Side,
+};
+
+#[test]
+fn test_fills() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut account = SimulatedAccount::new(Money::from_f64(100.0));
+
+    account.fill("STOCK", Side::Buy, 5.0, 10.0, start).unwrap();
+    account
+        .fill("STOCK", Side::Buy, 2.0, 15.0, start + TimeDelta::hours(1))
+        .unwrap();
+    assert_float_eq!(20.0, account.cash().to_f64(), ulps <= 5);
+    assert_eq!(7.0, account.shares_of("STOCK"));
+    // 80 paid for 7 shares
+    assert_float_eq!(
+        80.0 / 7.0,
+        account.position("STOCK").unwrap().avg_cost(),
+        ulps <= 5
+    );
+    // The position was opened by the first purchase
+    assert_eq!(
+        Some(start),
+        account.position("STOCK").map(|position| position.opened_at)
+    );
+
+    account.fill("STOCK", Side::Sell, 7.0, 20.0, start).unwrap();
+    assert_float_eq!(160.0, account.cash().to_f64(), ulps <= 5);
+    assert_eq!(0.0, account.shares_of("STOCK"));
+    assert_eq!(None, account.position("STOCK"));
+}
+
+#[test]
+fn test_fractional_shares() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut account = SimulatedAccount::new(Money::from_f64(100.0));
+
+    // All of the cash is invested, rather than what buys whole shares
+    account.fill("STOCK", Side::Buy, 0.3, 300.0, start).unwrap();
+    assert_float_eq!(10.0, account.cash().to_f64(), abs <= 1e-9);
+
+    account
+        .fill("STOCK", Side::Sell, 0.1, 300.0, start)
+        .unwrap();
+    account
+        .fill("STOCK", Side::Sell, 0.2, 300.0, start)
+        .unwrap();
+    assert_eq!(0.0, account.shares_of("STOCK"));
+    assert_eq!(None, account.position("STOCK"));
+}
+
+#[test]
+fn test_insufficient_funds() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut account = SimulatedAccount::new(Money::from_f64(100.0));
+
+    assert!(matches!(
+        account.fill("STOCK", Side::Buy, 11.0, 10.0, start),
+        Err(AccountError::InsufficientCash { quantity: 11.0, .. })
+    ));
+    assert!(matches!(
+        account.check("STOCK", Side::Sell, 1.0, 10.0, start),
+        Err(AccountError::InsufficientShares { owned: 0.0, .. })
+    ));
+    // Even of nothing, without a position to sell from
+    assert!(matches!(
+        account.fill("STOCK", Side::Sell, 0.0, 10.0, start),
+        Err(AccountError::InsufficientShares { owned: 0.0, .. })
+    ));
+    assert!(matches!(
+        account.fill("STOCK", Side::Sell, -1.0, 10.0, start),
+        Err(AccountError::InsufficientShares { owned: 0.0, .. })
+    ));
+
+    // Failed trades change nothing
+    assert_eq!(SimulatedAccount::new(Money::from_f64(100.0)), account);
+}
+
+#[test]
+fn test_margin_interest() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut account = SimulatedAccount::new(Money::from_f64(100.0)).with_margin(Margin {
+        limit: Money::from_f64(100.0),
+        annual_rate: 0.36,
+    });
+
+    account.fill("STOCK", Side::Buy, 15.0, 10.0, start).unwrap();
+    assert_float_eq!(-50.0, account.cash().to_f64(), abs <= 1e-9);
+    assert_float_eq!(50.0, account.debit_balance().to_f64(), abs <= 1e-9);
+    assert!(matches!(
+        account.check("STOCK", Side::Buy, 6.0, 10.0, start),
+        Err(AccountError::InsufficientCash { .. })
+    ));
+
+    // 0.1% a day
+    assert_float_eq!(0.05, account.charge_interest(start).to_f64(), abs <= 1e-9);
+    // Over a weekend, on the interest too
+    assert_float_eq!(
+        0.15015,
+        account.charge_interest(start + TimeDelta::days(3)).to_f64(),
+        abs <= 1e-9
+    );
+    assert_float_eq!(0.20015, account.interest_paid().to_f64(), abs <= 1e-9);
+
+    // Nothing is charged without a debit balance
+    account
+        .fill("STOCK", Side::Sell, 15.0, 10.0, start)
+        .unwrap();
+    assert_eq!(Money::from_f64(0.0), account.debit_balance());
+    assert_eq!(
+        Money::from_f64(0.0),
+        account.charge_interest(start + TimeDelta::days(4))
+    );
+    assert_eq!(
+        Money::from_f64(0.0),
+        SimulatedAccount::new(Money::from_f64(-10.0)).charge_interest(start)
+    );
+}
+
+#[test]
+fn test_tax_lots() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let buy_lots = |account: &mut SimulatedAccount| {
+        for (day, price) in [10.0, 12.0, 14.0].into_iter().enumerate() {
+            let time = start + TimeDelta::days(day as i64);
+            account.fill("STOCK", Side::Buy, 2.0, price, time).unwrap();
+        }
+    };
+    let sold_at = start + TimeDelta::days(10);
+    let sell = |account: &mut SimulatedAccount, quantity: f64| {
+        account
+            .fill("STOCK", Side::Sell, quantity, 15.0, sold_at)
+            .unwrap();
+    };
+    let closed = |account: &SimulatedAccount| -> Vec<(LotId, f64, f64)> {
+        account
+            .closed_lots()
+            .iter()
+            .map(|lot| (lot.lot, lot.quantity, lot.realized_gain().to_f64()))
+            .collect()
+    };
+
+    let mut fifo = SimulatedAccount::new(Money::from_f64(100.0));
+    buy_lots(&mut fifo);
+    sell(&mut fifo, 3.0);
+    assert_eq!(
+        vec![(LotId(0), 2.0, 10.0), (LotId(1), 1.0, 3.0)],
+        closed(&fifo)
+    );
+    assert_eq!(TimeDelta::days(10), fifo.closed_lots()[0].holding_period());
+    assert_eq!(
+        vec![(LotId(1), 1.0), (LotId(2), 2.0)],
+        fifo.lots("STOCK")
+            .iter()
+            .map(|lot| (lot.id, lot.quantity))
+            .collect::<Vec<_>>()
+    );
+
+    let mut lifo =
+        SimulatedAccount::new(Money::from_f64(100.0)).with_lot_selection(LotSelection::Lifo);
+    buy_lots(&mut lifo);
+    sell(&mut lifo, 3.0);
+    assert_eq!(
+        vec![(LotId(2), 2.0, 2.0), (LotId(1), 1.0, 3.0)],
+        closed(&lifo)
+    );
+
+    // Designated lots are closed first, and only by the next sales
+    let mut specific = SimulatedAccount::new(Money::from_f64(100.0));
+    buy_lots(&mut specific);
+    specific.designate_lots("STOCK", &[LotId(1), LotId(7)]);
+    sell(&mut specific, 3.0);
+    sell(&mut specific, 1.0);
+    assert_eq!(
+        vec![
+            (LotId(1), 2.0, 6.0),
+            (LotId(0), 1.0, 5.0),
+            (LotId(0), 1.0, 5.0)
+        ],
+        closed(&specific)
+    );
+
+    sell(&mut specific, 2.0);
+    assert!(specific.lots("STOCK").is_empty());
+    let gains: Money = specific
+        .closed_lots()
+        .iter()
+        .map(|lot| lot.realized_gain())
+        .sum();
+    assert_eq!(Money::from_f64(6.0 * 15.0 - 72.0), gains);
+}
+
+#[test]
+fn test_day_trade_limit() {
+    // A Monday, at 10:00 in New York
+    let monday = Utc.with_ymd_and_hms(1970, 1, 5, 15, 0, 0).unwrap();
+    let limit = DayTradeLimit {
+        max_day_trades: 1,
+        period_days: 5,
+        min_equity: Money::from_f64(25_000.0),
+    };
+    let mut account = SimulatedAccount::new(Money::from_f64(1000.0)).with_day_trade_limit(limit);
+
+    account.fill("STOCK", Side::Buy, 2.0, 10.0, monday).unwrap();
+    account
+        .fill("STOCK", Side::Sell, 1.0, 10.0, monday + TimeDelta::hours(1))
+        .unwrap();
+    assert_eq!(1, account.day_trades_within(5, monday));
+
+    // Another day trade is rejected, selling Monday's shares on Tuesday is
+    // not one
+    let tuesday = monday + TimeDelta::days(1);
+    account
+        .fill("OTHER", Side::Buy, 1.0, 10.0, tuesday)
+        .unwrap();
+    assert!(matches!(
+        account.fill("OTHER", Side::Sell, 1.0, 10.0, tuesday),
+        Err(AccountError::DayTradeLimit { day_trades: 2, .. })
+    ));
+    account
+        .fill("STOCK", Side::Sell, 1.0, 10.0, tuesday)
+        .unwrap();
+
+    // The day trade leaves the period after five trading days, skipping the
+    // weekend
+    let friday = monday + TimeDelta::days(4);
+    assert_eq!(1, account.day_trades_within(5, friday));
+    let next_monday = monday + TimeDelta::days(7);
+    assert_eq!(0, account.day_trades_within(5, next_monday));
+    account
+        .fill("OTHER", Side::Buy, 1.0, 10.0, next_monday)
+        .unwrap();
+    account
+        .fill("OTHER", Side::Sell, 2.0, 10.0, next_monday)
+        .unwrap();
+
+    // Accounts with enough equity are not limited
+    let mut large = SimulatedAccount::new(Money::from_f64(30_000.0)).with_day_trade_limit(limit);
+    for hour in 0..3 {
+        let time = monday + TimeDelta::hours(hour);
+        large.fill("STOCK", Side::Buy, 1.0, 10.0, time).unwrap();
+        large.fill("STOCK", Side::Sell, 1.0, 10.0, time).unwrap();
+    }
+    assert_eq!(3, large.day_trades_within(5, monday));
+}
+
+#[test]
+fn test_day_trade_limit_at_current_prices() {
+    // A Monday, at 10:00 in New York
+    let monday = Utc.with_ymd_and_hms(1970, 1, 5, 15, 0, 0).unwrap();
+    let limit = DayTradeLimit {
+        max_day_trades: 1,
+        period_days: 5,
+        min_equity: Money::from_f64(25_000.0),
+    };
+    let mut account = SimulatedAccount::new(Money::from_f64(1000.0)).with_day_trade_limit(limit);
+
+    account
+        .fill("OTHER", Side::Buy, 50.0, 10.0, monday - TimeDelta::days(3))
+        .unwrap();
+    account
+        .fill("STOCK", Side::Buy, 10.0, 10.0, monday)
+        .unwrap();
+    account
+        .fill("STOCK", Side::Sell, 5.0, 10.0, monday)
+        .unwrap();
+    // At cost, the account is far from the minimum equity
+    assert_float_eq!(1000.0, account.equity().to_f64(), abs <= 1e-9);
+
+    // Worth enough at its current prices to day trade again
+    account.mark("OTHER", 500.0);
+    assert_float_eq!(25_500.0, account.equity().to_f64(), abs <= 1e-9);
+    account
+        .fill("STOCK", Side::Sell, 2.0, 10.0, monday)
+        .unwrap();
+
+    account.mark("OTHER", 10.0);
+    assert!(matches!(
+        account.fill("STOCK", Side::Sell, 1.0, 10.0, monday),
+        Err(AccountError::DayTradeLimit { day_trades: 3, .. })
+    ));
+
+    // Unheld equities are not marked
+    account.mark("NONE", 10.0);
+    assert_float_eq!(1000.0, account.equity().to_f64(), abs <= 1e-9);
+}
+
+#[test]
+fn test_settlement_delay() {
+    // A Friday, at 10:00 in New York
+    let friday = Utc.with_ymd_and_hms(1970, 1, 2, 15, 0, 0).unwrap();
+    let mut account = SimulatedAccount::new(Money::from_f64(100.0)).with_settlement_delay(1);
+
+    account
+        .fill("STOCK", Side::Buy, 10.0, 10.0, friday)
+        .unwrap();
+    account
+        .fill("STOCK", Side::Sell, 4.0, 10.0, friday)
+        .unwrap();
+    assert_eq!(Money::from_f64(40.0), account.cash());
+    assert_eq!(Money::from_f64(0.0), account.settled_cash(friday));
+    assert!(matches!(
+        account.check("STOCK", Side::Buy, 1.0, 10.0, friday),
+        Err(AccountError::UnsettledFunds { .. })
+    ));
+    assert!(matches!(
+        account.check("STOCK", Side::Buy, 5.0, 10.0, friday),
+        Err(AccountError::InsufficientCash { .. })
+    ));
+
+    // Settled on the next trading day, after the weekend
+    let saturday = friday + TimeDelta::days(1);
+    assert_eq!(Money::from_f64(0.0), account.settled_cash(saturday));
+    let monday = friday + TimeDelta::days(3);
+    assert_eq!(Money::from_f64(40.0), account.settled_cash(monday));
+    account.fill("STOCK", Side::Buy, 4.0, 10.0, monday).unwrap();
+    assert_eq!(Money::from_f64(0.0), account.cash());
+}
@@ -0,0 +1,86 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use crate::align::{as_of, asof_join, forward_fill, tick_grid};
+
+#[test]
+fn test_as_of() {
+    let series = [
+        (Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap(), 1),
+        (Utc.with_ymd_and_hms(1970, 1, 1, 0, 3, 0).unwrap(), 3),
+    ];
+
+    assert_eq!(
+        None,
+        as_of(&series, Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap())
+    );
+    assert_eq!(
+        Some(&1),
+        as_of(&series, Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap())
+    );
+    assert_eq!(
+        Some(&1),
+        as_of(&series, Utc.with_ymd_and_hms(1970, 1, 1, 0, 2, 59).unwrap())
+    );
+    assert_eq!(
+        Some(&3),
+        as_of(&series, Utc.with_ymd_and_hms(1970, 1, 2, 0, 0, 0).unwrap())
+    );
+}
+
+#[test]
+fn test_tick_grid() {
+    let grid: Vec<_> = tick_grid(
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 30).unwrap(),
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 3, 0).unwrap(),
+        TimeDelta::minutes(1),
+    )
+    .collect();
+
+    assert_eq!(
+        vec![
+            Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap(),
+            Utc.with_ymd_and_hms(1970, 1, 1, 0, 2, 0).unwrap(),
+            Utc.with_ymd_and_hms(1970, 1, 1, 0, 3, 0).unwrap(),
+        ],
+        grid
+    );
+}
+
+#[test]
+fn test_forward_fill_daily_onto_minutes() {
+    let daily = [
+        (Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(), "day 1"),
+        (Utc.with_ymd_and_hms(1970, 1, 2, 0, 0, 0).unwrap(), "day 2"),
+    ];
+
+    let aligned = forward_fill(
+        &daily,
+        tick_grid(
+            Utc.with_ymd_and_hms(1970, 1, 1, 23, 59, 0).unwrap(),
+            Utc.with_ymd_and_hms(1970, 1, 2, 0, 1, 0).unwrap(),
+            TimeDelta::minutes(1),
+        ),
+    );
+
+    assert_eq!(
+        vec![Some(&"day 1"), Some(&"day 2"), Some(&"day 2")],
+        aligned.into_iter().map(|(_, v)| v).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_asof_join() {
+    let prices = [
+        (Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(), 10.0),
+        (Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap(), 11.0),
+        (Utc.with_ymd_and_hms(1970, 1, 1, 0, 2, 0).unwrap(), 12.0),
+    ];
+    let signals = [(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 30).unwrap(), true)];
+
+    let joined = asof_join(&prices, &signals);
+
+    assert_eq!(
+        vec![None, Some(&true), Some(&true)],
+        joined.into_iter().map(|(_, _, s)| s).collect::<Vec<_>>()
+    );
+}
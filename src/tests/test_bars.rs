@@ -0,0 +1,228 @@
+use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    bars::{BarAggregator, BarEvents, BarType},
+    market::{Candle, Event, Market},
+};
+
+fn candle(minute: u32, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Candle {
+    Candle {
+        start: Utc.with_ymd_and_hms(1970, 1, 1, 0, minute, 0).unwrap(),
+        open,
+        high,
+        low,
+        close,
+        volume,
+    }
+}
+
+fn minute(minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(1970, 1, 1, 0, minute, 0).unwrap()
+}
+
+#[test]
+fn test_bar_aggregation() {
+    let mut aggregator = BarAggregator::new(TimeDelta::minutes(1));
+
+    for (second, price) in [(0, 10.0), (20, 12.0), (40, 9.0), (59, 11.0)] {
+        assert_eq!(
+            None,
+            aggregator.push_trade(
+                "STOCK",
+                Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, second).unwrap(),
+                price,
+                100.0
+            )
+        );
+    }
+
+    assert_eq!(
+        Some(Event::BarClosed {
+            symbol: "STOCK".to_string(),
+            interval: TimeDelta::minutes(1),
+            candle: Candle {
+                start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 11.0,
+                volume: 400.0,
+            },
+        }),
+        aggregator.push_trade(
+            "STOCK",
+            Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap(),
+            11.5,
+            100.0
+        )
+    );
+}
+
+#[test]
+fn test_closing_bars_without_trades() {
+    let mut aggregator = BarAggregator::new(TimeDelta::minutes(1));
+
+    aggregator.push_trade(
+        "A",
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 30).unwrap(),
+        1.0,
+        1.0,
+    );
+    aggregator.push_trade(
+        "B",
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 30).unwrap(),
+        2.0,
+        1.0,
+    );
+
+    let closed = aggregator.close_until(Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap());
+    assert_eq!(1, closed.len());
+    assert!(matches!(&closed[0], Event::BarClosed { symbol, .. } if symbol == "A"));
+
+    let closed = aggregator.close_until(Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap());
+    assert!(closed.is_empty());
+}
+
+#[test]
+fn test_late_trades() {
+    let mut aggregator = BarAggregator::new(TimeDelta::minutes(1));
+    let second = |minute, second| Utc.with_ymd_and_hms(1970, 1, 1, 0, minute, second).unwrap();
+
+    aggregator.push_trade("STOCK", second(1, 0), 10.0, 1.0);
+    // From the bar before the current one, which is not reopened
+    assert_eq!(
+        None,
+        aggregator.push_trade("STOCK", second(0, 59), 100.0, 1.0)
+    );
+
+    let closed = aggregator.close_until(second(2, 0));
+    assert_eq!(
+        vec![Event::BarClosed {
+            symbol: "STOCK".to_string(),
+            interval: TimeDelta::minutes(1),
+            candle: Candle {
+                start: second(1, 0),
+                open: 10.0,
+                high: 10.0,
+                low: 10.0,
+                close: 10.0,
+                volume: 1.0,
+            },
+        }],
+        closed
+    );
+
+    // Nor is a bar that was closed without a trade after it
+    assert_eq!(
+        None,
+        aggregator.push_trade("STOCK", second(1, 30), 100.0, 1.0)
+    );
+    assert!(aggregator.close_until(second(3, 0)).is_empty());
+}
+
+#[tokio::test]
+async fn test_market_bar_events() {
+    let start = minute(0);
+    let mut market = TestMarket::new(
+        start,
+        [
+            ("A".to_string(), vec![10.0..11.0, 11.0..12.0]),
+            ("B".to_string(), vec![20.0..20.0; 2]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_bar_events(BarEvents {
+        universe: vec!["A".to_string()],
+        interval: TimeDelta::minutes(1),
+    });
+
+    let mut bars = Vec::new();
+    for _ in 0..6 {
+        let (time, event) = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        if let Event::BarClosed { symbol, candle, .. } = event {
+            bars.push((time, symbol, candle));
+        }
+    }
+
+    // Each bar of the universe once it closed, not while it is open
+    assert_eq!(
+        vec![
+            (
+                minute(1),
+                "A".to_string(),
+                candle(0, 10.0, 11.0, 10.0, 11.0, 0.0)
+            ),
+            (
+                minute(2),
+                "A".to_string(),
+                candle(1, 11.0, 12.0, 11.0, 12.0, 0.0)
+            ),
+        ],
+        bars
+    );
+}
+
+#[test]
+fn test_heikin_ashi_bars() {
+    let bars = BarType::HeikinAshi.build(&[
+        candle(0, 10.0, 12.0, 8.0, 10.0, 1.0),
+        candle(1, 10.0, 14.0, 10.0, 14.0, 1.0),
+    ]);
+
+    assert_float_eq!(10.0, bars[0].open, ulps <= 5);
+    assert_float_eq!(10.0, bars[0].close, ulps <= 5);
+
+    assert_float_eq!(10.0, bars[1].open, ulps <= 5);
+    assert_float_eq!(12.0, bars[1].close, ulps <= 5);
+    assert_float_eq!(14.0, bars[1].high, ulps <= 5);
+    assert_float_eq!(10.0, bars[1].low, ulps <= 5);
+}
+
+#[test]
+fn test_renko_bricks() {
+    let bricks = BarType::Renko { brick_size: 1.0 }.build(&[
+        candle(0, 10.0, 10.0, 10.0, 10.0, 1.0),
+        candle(1, 10.0, 10.5, 10.0, 10.5, 1.0),
+        candle(2, 10.5, 12.2, 10.5, 12.2, 1.0),
+        candle(3, 12.2, 12.2, 10.9, 10.9, 1.0),
+    ]);
+
+    assert_eq!(
+        vec![
+            (minute(2), 10.0, 11.0),
+            (minute(2), 11.0, 12.0),
+            (minute(3), 12.0, 11.0),
+        ],
+        bricks
+            .iter()
+            .map(|brick| (brick.start, brick.open, brick.close))
+            .collect::<Vec<_>>()
+    );
+    assert_float_eq!(3.0, bricks[0].volume, ulps <= 5);
+}
+
+#[test]
+fn test_volume_and_dollar_bars() {
+    let candles = [
+        candle(0, 10.0, 11.0, 9.0, 10.0, 50.0),
+        candle(1, 10.0, 12.0, 10.0, 11.0, 60.0),
+        candle(2, 11.0, 11.0, 8.0, 9.0, 100.0),
+        candle(3, 9.0, 9.0, 9.0, 9.0, 10.0),
+    ];
+
+    let volume_bars = BarType::Volume { threshold: 100.0 }.build(&candles);
+    assert_eq!(2, volume_bars.len());
+    assert_eq!(candle(0, 10.0, 12.0, 9.0, 11.0, 110.0), volume_bars[0]);
+    assert_eq!(candle(2, 11.0, 11.0, 8.0, 9.0, 100.0), volume_bars[1]);
+
+    let dollar_bars = BarType::Dollar { threshold: 1000.0 }.build(&candles);
+    assert_eq!(1, dollar_bars.len());
+    assert_float_eq!(110.0, dollar_bars[0].volume, ulps <= 5);
+}
@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{breakpoint::Breakpoints, market::Market};
+
+#[tokio::test]
+async fn test_breakpoints() {
+    let market = TestMarket::new(
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        [(
+            "STOCK".to_string(),
+            vec![
+                10.0..10.0,
+                10.0..10.0,
+                9.0..9.0,
+                8.0..8.0,
+                10.0..10.0,
+                8.0..8.0,
+            ],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    let breaks = Arc::new(Mutex::new(Vec::new()));
+    let on_break = {
+        let breaks = breaks.clone();
+        move |name: &str, _: &_| breaks.lock().unwrap().push(name.to_string())
+    };
+    let mut market = Breakpoints::new(market).with_on_break(on_break);
+    market.add("net worth dropped 5% today", |context| {
+        context.net_worth < context.day_start_net_worth.times(0.95)
+    });
+    market.add("large position", |context| context.shares_of("STOCK") > 5.0);
+
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    market.buy_at_market("STOCK", 10.0).await.unwrap();
+
+    // Breakpoints are only evaluated on events
+    assert!(market.hits().is_empty());
+
+    for _ in 0..4 {
+        market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+
+    // Each breakpoint fires when its condition starts holding, and again
+    // after it stopped holding in between
+    let hits: Vec<_> = market
+        .hits()
+        .iter()
+        .map(|(name, context)| (name.as_str(), context.time.format("%M").to_string()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("net worth dropped 5% today", "02".to_string()),
+            ("large position", "02".to_string()),
+            ("net worth dropped 5% today", "05".to_string()),
+        ],
+        hits
+    );
+    // The callback is called for every hit
+    assert_eq!(
+        hits.iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>(),
+        *breaks.lock().unwrap()
+    );
+}
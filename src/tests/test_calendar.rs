@@ -0,0 +1,97 @@
+use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+
+use crate::{
+    calendar::{next_us_equity_trading_time, us_equity_sessions},
+    market::Event,
+};
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn test_us_equity_trading_days() {
+    let sessions = us_equity_sessions(date(2024, 1, 1), date(2024, 12, 31));
+    assert_eq!(252, sessions.len());
+
+    let is_trading_day = |day: NaiveDate| sessions.iter().any(|session| session.date == day);
+    // Good Friday, Juneteenth, Independence Day and Thanksgiving
+    assert!(!is_trading_day(date(2024, 3, 29)));
+    assert!(!is_trading_day(date(2024, 6, 19)));
+    assert!(!is_trading_day(date(2024, 7, 4)));
+    assert!(!is_trading_day(date(2024, 11, 28)));
+    assert!(is_trading_day(date(2024, 11, 29)));
+
+    // Christmas on a Saturday is observed on the Friday before, while New
+    // Year's Day on a Saturday is not observed at all
+    let sessions = us_equity_sessions(date(2021, 12, 20), date(2021, 12, 31));
+    assert!(!sessions
+        .iter()
+        .any(|session| session.date == date(2021, 12, 24)));
+    assert!(sessions
+        .iter()
+        .any(|session| session.date == date(2021, 12, 31)));
+}
+
+#[test]
+fn test_us_equity_session_times() {
+    let sessions = us_equity_sessions(date(2024, 3, 8), date(2024, 3, 11));
+    assert_eq!(2, sessions.len());
+
+    // Daylight saving time started on March 10th
+    assert_eq!(
+        [
+            (
+                Utc.with_ymd_and_hms(2024, 3, 8, 9, 0, 0).unwrap(),
+                Event::PreMarketStart
+            ),
+            (
+                Utc.with_ymd_and_hms(2024, 3, 8, 14, 30, 0).unwrap(),
+                Event::RegularMarketStart
+            ),
+            (
+                Utc.with_ymd_and_hms(2024, 3, 8, 21, 0, 0).unwrap(),
+                Event::RegularMarketEnd
+            ),
+            (
+                Utc.with_ymd_and_hms(2024, 3, 9, 1, 0, 0).unwrap(),
+                Event::PostMarketEnd
+            ),
+        ],
+        sessions[0].events()
+    );
+    assert_eq!(
+        Utc.with_ymd_and_hms(2024, 3, 11, 13, 30, 0).unwrap(),
+        sessions[1].regular_start
+    );
+}
+
+#[test]
+fn test_us_equity_early_closes() {
+    let early_closes: Vec<NaiveDate> = us_equity_sessions(date(2024, 1, 1), date(2024, 12, 31))
+        .into_iter()
+        .filter(|session| session.is_early_close())
+        .map(|session| session.date)
+        .collect();
+
+    assert_eq!(
+        vec![date(2024, 7, 3), date(2024, 11, 29), date(2024, 12, 24)],
+        early_closes
+    );
+}
+
+#[test]
+fn test_next_us_equity_trading_time() {
+    let at = NaiveTime::from_hms_opt(10, 0, 0).unwrap();
+
+    // Later on the same day, 10:00 in New York being 14:00 UTC in summer
+    assert_eq!(
+        Utc.with_ymd_and_hms(2024, 3, 28, 14, 0, 0).unwrap(),
+        next_us_equity_trading_time(Utc.with_ymd_and_hms(2024, 3, 28, 12, 0, 0).unwrap(), at)
+    );
+    // Over Good Friday and the weekend
+    assert_eq!(
+        Utc.with_ymd_and_hms(2024, 4, 1, 14, 0, 0).unwrap(),
+        next_us_equity_trading_time(Utc.with_ymd_and_hms(2024, 3, 28, 14, 0, 0).unwrap(), at)
+    );
+}
@@ -0,0 +1,97 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{
+    chaos::{ChaosError, ChaosMarket, Fault},
+    market::{Event, Market},
+    order::Side,
+};
+
+fn market() -> TestMarket {
+    TestMarket::new(
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        [("STOCK".to_string(), vec![10.0..10.0; 10])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+}
+
+#[tokio::test]
+async fn test_injected_failures() {
+    let mut market = ChaosMarket::new(market(), 0)
+        .with_query_failures(1.0)
+        .with_order_rejections(1.0);
+
+    assert!(matches!(
+        market.current_price("STOCK").await,
+        Err(ChaosError::Injected(Fault::QueryTimeout))
+    ));
+    assert!(matches!(
+        market.buy_at_market("STOCK", 1.0).await,
+        Err(ChaosError::Injected(Fault::OrderRejected))
+    ));
+    assert!(matches!(
+        market.buy_limit("STOCK", 1.0, 10.0).await,
+        Err(ChaosError::Injected(Fault::OrderRejected))
+    ));
+    assert_eq!(0.0, market.shares_of("STOCK"));
+
+    // Without injected failures, the market's own errors come through
+    let mut market = ChaosMarket::new(market.into_inner(), 0);
+    assert!(matches!(
+        market.buy_at_market("OTHER", 1.0).await,
+        Err(ChaosError::Market(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_dropped_events() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = ChaosMarket::new(market(), 0).with_dropped_events(1.0);
+
+    // At most one event is dropped per call
+    let (time, _) = market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(start + TimeDelta::minutes(1), time);
+}
+
+#[tokio::test]
+async fn test_delayed_fills() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = ChaosMarket::new(market(), 0).with_delayed_fills(1.0, TimeDelta::minutes(2));
+
+    let id = market.buy_limit("STOCK", 1.0, 10.0).await.unwrap();
+    // The fill happens right away, only its report is late
+    assert_eq!(1.0, market.shares_of("STOCK"));
+
+    let mut events = Vec::new();
+    for _ in 0..4 {
+        events.push(
+            market
+                .next_event_or_tick(TimeDelta::minutes(1))
+                .await
+                .unwrap(),
+        );
+    }
+
+    assert_eq!(
+        vec![
+            (start + TimeDelta::minutes(0), Event::Tick),
+            (start + TimeDelta::minutes(1), Event::Tick),
+            (start + TimeDelta::minutes(2), Event::Tick),
+            (
+                start + TimeDelta::minutes(2),
+                Event::OrderFilled {
+                    id,
+                    symbol: "STOCK".to_string(),
+                    side: Side::Buy,
+                    quantity: 1.0,
+                    price: 10.0,
+                }
+            ),
+        ],
+        events
+    );
+}
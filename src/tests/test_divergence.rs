@@ -0,0 +1,73 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use crate::{
+    divergence::{compare_fills, Divergence, Tolerances},
+    export::Trade,
+};
+
+fn trade(minute: u32, second: u32, symbol: &str, quantity: i64, price: f64) -> Trade {
+    Trade {
+        time: Utc.with_ymd_and_hms(1970, 1, 1, 0, minute, second).unwrap(),
+        symbol: symbol.to_string(),
+        quantity: quantity as f64,
+        price,
+        order: None,
+        reason: None,
+    }
+}
+
+#[test]
+fn test_compare_fills() {
+    let live = [
+        trade(0, 1, "A", 10, 100.0),
+        trade(1, 30, "A", -10, 102.0),
+        trade(2, 0, "B", 5, 50.0),
+        trade(3, 0, "C", 1, 10.0),
+    ];
+    let simulated = [
+        trade(0, 0, "A", 10, 100.0),
+        trade(1, 0, "A", -10, 101.0),
+        trade(2, 0, "B", 4, 50.0),
+        trade(9, 0, "D", 1, 10.0),
+    ];
+
+    let report = compare_fills(&live, &simulated, Tolerances::default());
+
+    assert_eq!(3, report.matched);
+    assert_eq!(
+        vec![
+            Divergence::Price {
+                live: live[1].clone(),
+                simulated: simulated[1].clone()
+            },
+            Divergence::Timing {
+                live: live[1].clone(),
+                simulated: simulated[1].clone()
+            },
+            Divergence::Quantity {
+                live: live[2].clone(),
+                simulated: simulated[2].clone()
+            },
+            Divergence::Unsimulated(live[3].clone()),
+            Divergence::Unfilled(simulated[3].clone()),
+        ],
+        report.divergences
+    );
+    assert_float_eq!(
+        (102.0 / 101.0 - 1.0) / 3.0,
+        report.mean_relative_price_difference,
+        abs <= 1e-12
+    );
+
+    // Fills further apart than the match window are not paired
+    let report = compare_fills(
+        &live[..1],
+        &simulated[..1],
+        Tolerances {
+            match_window: TimeDelta::zero(),
+            ..Default::default()
+        },
+    );
+    assert_eq!(0, report.matched);
+}
@@ -0,0 +1,56 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use crate::domain::{Event, EventMask, PriceQuote, PriceSource};
+
+#[test]
+fn test_price_sources() {
+    let time = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let trade = PriceQuote {
+        price: 10.0,
+        as_of: time,
+    };
+    let midpoint = PriceQuote {
+        price: 11.0,
+        as_of: time + TimeDelta::seconds(1),
+    };
+
+    assert_eq!(
+        Some(trade),
+        PriceSource::Trade.quote(Some(trade), Some(midpoint))
+    );
+    assert_eq!(
+        Some(midpoint),
+        PriceSource::Midpoint.quote(Some(trade), Some(midpoint))
+    );
+
+    let blend = PriceSource::Blend { trade_weight: 0.25 }
+        .quote(Some(trade), Some(midpoint))
+        .unwrap();
+    assert_float_eq!(10.75, blend.price, ulps <= 5);
+    assert_eq!(time, blend.as_of);
+
+    // Missing sources fall back on the others
+    assert_eq!(Some(trade), PriceSource::Midpoint.quote(Some(trade), None));
+    assert_eq!(
+        Some(midpoint),
+        PriceSource::Blend { trade_weight: 0.5 }.quote(None, Some(midpoint))
+    );
+    assert_eq!(None, PriceSource::Trade.quote(None, None));
+}
+
+#[test]
+fn test_event_masks() {
+    let earnings = Event::Earnings {
+        symbol: "STOCK".to_string(),
+    };
+    let mask = EventMask::SESSIONS | EventMask::FILLS;
+
+    assert!(mask.matches(&Event::RegularMarketStart));
+    assert!(!mask.matches(&earnings));
+    assert!(!mask.matches(&Event::Tick));
+    assert!(mask.contains(EventMask::FILLS));
+    assert!(!mask.contains(EventMask::FILLS | EventMask::BARS));
+    assert!(EventMask::default().matches(&earnings));
+    assert!(!EventMask::NONE.matches(&Event::Tick));
+}
@@ -0,0 +1,43 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use crate::downsample::{sample_by_interval, Resolution};
+
+#[test]
+fn test_sample_by_interval() {
+    assert_eq!("30s", sample_by_interval(TimeDelta::seconds(30)));
+    assert_eq!("90s", sample_by_interval(TimeDelta::seconds(90)));
+    assert_eq!("5m", sample_by_interval(TimeDelta::minutes(5)));
+    assert_eq!("2h", sample_by_interval(TimeDelta::hours(2)));
+    assert_eq!("1d", sample_by_interval(TimeDelta::days(1)));
+}
+
+#[test]
+fn test_resolutions_are_ordered_by_duration() {
+    let mut resolutions = Resolution::ALL;
+    resolutions.sort_by_key(|resolution| resolution.duration());
+
+    assert_eq!(Resolution::ALL, resolutions);
+    assert_eq!(Some(&Resolution::OneDay), Resolution::ALL.iter().max());
+}
+
+#[test]
+fn test_bars_complete_in_the_middle_of_a_bar() {
+    // Half way through the bar stamped 10:00
+    let time = Utc.with_ymd_and_hms(2024, 1, 2, 10, 2, 30).unwrap();
+    let last_complete_start = Resolution::FiveMinutes.last_complete_start(time);
+
+    let complete = |start| start <= last_complete_start;
+    assert!(complete(
+        Utc.with_ymd_and_hms(2024, 1, 2, 9, 55, 0).unwrap()
+    ));
+    assert!(!complete(
+        Utc.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap()
+    ));
+
+    // A bar is complete at its very end
+    let end = Utc.with_ymd_and_hms(2024, 1, 2, 10, 5, 0).unwrap();
+    assert_eq!(
+        Utc.with_ymd_and_hms(2024, 1, 2, 10, 0, 0).unwrap(),
+        Resolution::FiveMinutes.last_complete_start(end)
+    );
+}
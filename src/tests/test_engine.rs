@@ -0,0 +1,32 @@
+use chrono::NaiveTime;
+
+use crate::{
+    engine::{Engine, EngineConfig, EngineError, Mode},
+    market::Market,
+    Algorithm,
+};
+
+struct Idle;
+
+impl Algorithm for Idle {
+    fn wake_ups() -> impl Iterator<Item = NaiveTime> {
+        vec![].into_iter()
+    }
+
+    async fn run<M: Market>(&mut self, _market: &mut M) -> Result<(), M::Error> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_live_mode_unavailable() {
+    let config = EngineConfig {
+        database: "host=localhost".to_string(),
+        mode: Mode::Live,
+    };
+
+    assert!(matches!(
+        Engine::run(&config, &mut Idle).await,
+        Err(EngineError::LiveTradingUnavailable)
+    ));
+}
@@ -0,0 +1,68 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    ensemble::{run_ensemble, Distribution, EnsembleReport, RunMetrics},
+    error::Error,
+    export::RecordingMarket,
+    fill::SeededRandom,
+    market::Market,
+};
+
+async fn backtest(seed: u64) -> Result<Option<RunMetrics>, Error> {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![9.0..11.0, 10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_fill_model(SeededRandom::new(seed));
+    let mut market = RecordingMarket::new(market);
+
+    market.buy_at_market("STOCK", 5.0).await?;
+    while market.time() < start + TimeDelta::minutes(1) {
+        market.next_event_or_tick(TimeDelta::minutes(1)).await?;
+    }
+
+    Ok(RunMetrics::of(seed, &market))
+}
+
+#[tokio::test]
+async fn test_ensemble() {
+    let report = run_ensemble(0..20, backtest).await.unwrap();
+    assert_eq!(20, report.runs.len());
+
+    // Fills are reproducible from their seeds
+    let final_net_worths = |report: &EnsembleReport| -> Vec<f64> {
+        report
+            .runs
+            .iter()
+            .map(|run| run.final_net_worth.to_f64())
+            .collect()
+    };
+    let rerun = run_ensemble(0..20, backtest).await.unwrap();
+    assert_eq!(final_net_worths(&report), final_net_worths(&rerun));
+
+    // Buying between 9 and 11 and marking at 10 spreads the outcomes
+    let final_net_worth = report.final_net_worth().unwrap();
+    assert!(final_net_worth.min >= 95.0 && final_net_worth.max <= 105.0);
+    assert!(final_net_worth.standard_deviation > 0.0);
+    assert_float_eq!(1.0, report.trades().unwrap().mean, ulps <= 5);
+
+    assert_eq!(None, EnsembleReport::default().total_return());
+}
+
+#[test]
+fn test_distribution() {
+    let distribution = Distribution::of(&[4.0, 1.0, 3.0, 2.0, 5.0]).unwrap();
+
+    assert_float_eq!(3.0, distribution.mean, ulps <= 5);
+    assert_float_eq!(2.0f64.sqrt(), distribution.standard_deviation, ulps <= 5);
+    assert_float_eq!(1.0, distribution.min, ulps <= 5);
+    assert_float_eq!(1.2, distribution.p5, ulps <= 5);
+    assert_float_eq!(3.0, distribution.median, ulps <= 5);
+    assert_float_eq!(4.8, distribution.p95, ulps <= 5);
+    assert_float_eq!(5.0, distribution.max, ulps <= 5);
+}
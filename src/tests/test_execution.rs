@@ -0,0 +1,95 @@
+use float_eq::assert_float_eq;
+
+use crate::{
+    execution::{OrderBookSimulator, SyntheticDepth},
+    order::{Order, OrderId, OrderKind, PendingOrder, Side},
+};
+
+const DEPTH: SyntheticDepth = SyntheticDepth {
+    tick_size: 0.5,
+    top: 100.0,
+    growth: 0.5,
+};
+
+#[test]
+fn test_synthetic_depth() {
+    let book = DEPTH.book(10.0, 3);
+
+    let bids: Vec<_> = book.bids.iter().map(|l| (l.price, l.quantity)).collect();
+    assert_eq!(vec![(9.5, 100.0), (9.0, 150.0), (8.5, 200.0)], bids);
+    assert_float_eq!(10.5, book.asks[0].price, abs <= 1e-9);
+    assert_float_eq!(200.0, book.asks[2].quantity, abs <= 1e-9);
+
+    // Nothing rests at better prices than the last one
+    assert_float_eq!(0.0, DEPTH.quantity_at(Side::Buy, 10.5, 10.0), abs <= 1e-9);
+}
+
+#[test]
+fn test_queue_position() {
+    let mut simulator = OrderBookSimulator::new(DEPTH);
+    let pending = PendingOrder::new(
+        OrderId(0),
+        Order::new(
+            "STOCK",
+            Side::Sell,
+            50.0,
+            OrderKind::Limit { limit_price: 11.0 },
+        ),
+    );
+
+    simulator.join(&pending, 10.0);
+    assert_eq!(Some(150.0), simulator.queue_ahead(pending.id));
+
+    // A bar from 10 to 11 trades a third of its volume at 11
+    assert_eq!(
+        0.0,
+        simulator.on_trades(&pending, 10.0, 11.0, 11.0, 300.0, 1.0)
+    );
+    assert_eq!(Some(50.0), simulator.queue_ahead(pending.id));
+    assert_eq!(
+        10.0,
+        simulator.on_trades(&pending, 10.5, 11.0, 11.0, 120.0, 1.0)
+    );
+    assert_eq!(
+        50.0,
+        simulator.on_trades(&pending, 10.5, 11.5, 11.5, 1.0, 1.0)
+    );
+
+    simulator.leave(pending.id);
+    assert_eq!(None, simulator.queue_ahead(pending.id));
+}
+
+#[test]
+fn test_queue_fills_in_lots() {
+    let mut simulator = OrderBookSimulator::new(DEPTH);
+    let pending = |quantity| {
+        PendingOrder::new(
+            OrderId(0),
+            Order::new(
+                "STOCK",
+                Side::Sell,
+                quantity,
+                OrderKind::Limit { limit_price: 11.0 },
+            ),
+        )
+    };
+    simulator.join(&pending(50.0), 10.0);
+
+    // 1.25 of the shares traded at the limit price are left after the 150
+    // ahead
+    assert_float_eq!(
+        1.2,
+        simulator.on_trades(&pending(50.0), 10.5, 11.0, 11.0, 302.5, 0.1),
+        abs <= 1e-9
+    );
+    // Fractions of a share, in whole lots
+    assert_float_eq!(
+        0.3,
+        simulator.on_trades(&pending(0.35), 10.5, 11.0, 11.0, 0.7, 0.1),
+        abs <= 1e-9
+    );
+    assert_eq!(
+        0.0,
+        simulator.on_trades(&pending(1.0), 10.5, 11.0, 11.0, 0.7, 1.0)
+    );
+}
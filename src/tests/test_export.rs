@@ -0,0 +1,203 @@
+use chrono::{NaiveDate, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    export::{
+        daily_returns, fill_report, format_time, write_fill_report, write_pyfolio_transactions,
+        write_quantstats_returns, Fees, FillColumn, RecordingMarket, Trade, TradeReason,
+    },
+    market::Market,
+    money::Money,
+};
+
+#[test]
+fn test_daily_returns() {
+    let equity_curve = [
+        (
+            Utc.with_ymd_and_hms(1970, 1, 1, 14, 0, 0).unwrap(),
+            Money::from_f64(100.0),
+        ),
+        (
+            Utc.with_ymd_and_hms(1970, 1, 1, 20, 0, 0).unwrap(),
+            Money::from_f64(110.0),
+        ),
+        (
+            Utc.with_ymd_and_hms(1970, 1, 2, 14, 0, 0).unwrap(),
+            Money::from_f64(90.0),
+        ),
+        (
+            Utc.with_ymd_and_hms(1970, 1, 2, 20, 0, 0).unwrap(),
+            Money::from_f64(99.0),
+        ),
+    ];
+
+    let returns = daily_returns(&equity_curve, Tz::UTC);
+
+    assert_eq!(2, returns.len());
+    assert_eq!(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(), returns[0].0);
+    assert_float_eq!(0.1, returns[0].1, abs <= 1e-9);
+    assert_eq!(NaiveDate::from_ymd_opt(1970, 1, 2).unwrap(), returns[1].0);
+    assert_float_eq!(-0.1, returns[1].1, abs <= 1e-9);
+
+    let mut csv = Vec::new();
+    write_quantstats_returns(&mut csv, &equity_curve[..2], Tz::UTC).unwrap();
+    assert_eq!(
+        "date,returns\n1970-01-01,0.10000000000000009\n",
+        String::from_utf8(csv).unwrap()
+    );
+}
+
+#[test]
+fn test_pyfolio_transactions() {
+    let trades = [
+        Trade {
+            time: Utc.with_ymd_and_hms(1970, 1, 1, 14, 0, 0).unwrap(),
+            symbol: "STOCK".to_string(),
+            quantity: 10.0,
+            price: 1.5,
+            order: None,
+            reason: None,
+        },
+        Trade {
+            time: Utc.with_ymd_and_hms(1970, 1, 1, 15, 0, 0).unwrap(),
+            symbol: "STOCK".to_string(),
+            quantity: -10.0,
+            price: 2.0,
+            order: None,
+            reason: None,
+        },
+    ];
+
+    let mut csv = Vec::new();
+    write_pyfolio_transactions(&mut csv, &trades, Tz::UTC).unwrap();
+
+    assert_eq!(
+        "date,amount,price,symbol\n\
+         1970-01-01T14:00:00+00:00,10,1.5,STOCK\n\
+         1970-01-01T15:00:00+00:00,-10,2,STOCK\n",
+        String::from_utf8(csv).unwrap()
+    );
+}
+
+#[test]
+fn test_fill_report() {
+    let trade = |hour, quantity, price| Trade {
+        time: Utc.with_ymd_and_hms(1970, 1, 1, hour, 0, 0).unwrap(),
+        symbol: "STOCK".to_string(),
+        quantity,
+        price,
+        order: None,
+        reason: None,
+    };
+    let trades = [
+        trade(14, 10.0, 10.0),
+        trade(15, 10.0, 12.0),
+        trade(16, -15.0, 15.0),
+    ];
+    let fees = Fees {
+        per_fill: Money::from_f64(1.0),
+        ..Default::default()
+    };
+
+    let fills = fill_report(&trades, &fees);
+
+    assert_float_eq!(0.0, fills[0].realized_pnl.to_f64(), abs <= 1e-9);
+    // 10 shares of the first lot and 5 of the second, at their cost with
+    // fees
+    assert_float_eq!(
+        10.0 * 10.1 + 5.0 * 12.1,
+        fills[2].cost_basis.to_f64(),
+        abs <= 1e-9
+    );
+    assert_float_eq!(224.0, fills[2].proceeds.to_f64(), abs <= 1e-9);
+    assert_float_eq!(62.5, fills[2].realized_pnl.to_f64(), abs <= 1e-9);
+    assert_eq!(Some(trades[0].time), fills[2].acquired);
+
+    let mut csv = Vec::new();
+    write_fill_report(
+        &mut csv,
+        &fills[2..],
+        &[
+            FillColumn::Symbol,
+            FillColumn::Quantity,
+            FillColumn::RealizedPnl,
+        ],
+        Tz::UTC,
+    )
+    .unwrap();
+    assert_eq!(
+        "symbol,quantity,realized_pnl\nSTOCK,-15,62.5\n",
+        String::from_utf8(csv).unwrap()
+    );
+}
+
+#[test]
+fn test_local_timestamps() {
+    let time = Utc.with_ymd_and_hms(1970, 1, 2, 3, 0, 0).unwrap();
+    assert_eq!(
+        "1970-01-01T22:00:00-05:00",
+        format_time(time, Tz::America__New_York)
+    );
+
+    // An evening in New York is already the next day in UTC
+    let equity_curve = [
+        (
+            Utc.with_ymd_and_hms(1970, 1, 1, 20, 0, 0).unwrap(),
+            Money::from_f64(100.0),
+        ),
+        (time, Money::from_f64(110.0)),
+    ];
+    assert_eq!(2, daily_returns(&equity_curve, Tz::UTC).len());
+    assert_eq!(1, daily_returns(&equity_curve, Tz::America__New_York).len());
+}
+
+#[tokio::test]
+async fn test_trade_reasons() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = RecordingMarket::new(TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 8.0..8.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    ));
+
+    // Reasons attach to fills that happened already, and to later ones
+    let bought = market.buy_at_market("STOCK", 5.0).await.unwrap().order_id;
+    market.explain(
+        bought,
+        TradeReason::new("breakout")
+            .with_signal("close", 10.0)
+            .with_signal("high", 9.5),
+    );
+    let dip = market.buy_limit("STOCK", 1.0, 9.0).await.unwrap();
+    market.explain(dip, TradeReason::new("dip"));
+    market.sell_at_market("STOCK", 1.0).await.unwrap();
+    while market.trades().len() < 3 {
+        market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+
+    let trades = market.trades();
+    assert_eq!(3, trades.len());
+    assert_eq!(Some(bought), trades[0].order);
+    assert_eq!(market.reason(bought), trades[0].reason.as_ref());
+    assert_eq!(None, trades[1].reason);
+    assert_eq!(Some(&TradeReason::new("dip")), trades[2].reason.as_ref());
+
+    let mut csv = Vec::new();
+    write_fill_report(
+        &mut csv,
+        &fill_report(trades, &Fees::default()),
+        &[FillColumn::Quantity, FillColumn::Reason],
+        Tz::UTC,
+    )
+    .unwrap();
+    assert_eq!(
+        "quantity,reason\n5,breakout (close=10; high=9.5)\n-1,\n1,dip\n",
+        String::from_utf8(csv).unwrap()
+    );
+}
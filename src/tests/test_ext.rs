@@ -0,0 +1,116 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    error::Error,
+    ext::MarketExt,
+    market::Market,
+    money::Money,
+    order::{ImpactCurve, PriceImpact},
+};
+
+#[tokio::test]
+async fn test_market_ext() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [
+            ("A".to_string(), vec![10.0..10.0]),
+            ("B".to_string(), vec![20.0..20.0]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        105.0,
+    );
+
+    market.buy_at_market("B", 2.0).await.unwrap();
+    let bought = market.buy_max("A").await.unwrap();
+    assert_eq!(6.0, bought.quantity);
+    assert_float_eq!(5.0, market.cash().to_f64(), abs <= 1e-9);
+
+    let sold = market.sell_all("A").await.unwrap();
+    assert_eq!(6.0, sold.quantity);
+    assert_eq!(0.0, market.shares_of("A"));
+
+    // Only the positions still held
+    market.buy_at_market("A", 1.0).await.unwrap();
+    let receipts = market.liquidate_all().await.unwrap();
+    assert_eq!(
+        vec![("A", 1.0), ("B", 2.0)],
+        receipts
+            .iter()
+            .map(|receipt| (receipt.symbol.as_str(), receipt.quantity))
+            .collect::<Vec<_>>()
+    );
+    assert_float_eq!(105.0, market.cash().to_f64(), abs <= 1e-9);
+}
+
+#[tokio::test]
+async fn test_buy_max_within_the_cash() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("A".to_string(), vec![10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_price_impact(
+        PriceImpact {
+            threshold: 0.0,
+            coefficient: 1.0,
+            curve: ImpactCurve::Linear,
+        },
+        [("A".to_string(), vec![100.0])].into(),
+    )
+    .with_settlement_delay(1);
+
+    // Ten shares would fill at 11 each, but nine at 10.9
+    let bought = market.buy_max("A").await.unwrap();
+    assert_eq!(9.0, bought.quantity);
+    assert_eq!(Money::from_f64(100.0 - 9.0 * 10.9), market.cash());
+
+    // Nothing is left once the unsettled proceeds of a sale are not spent
+    market.sell_at_market("A", 4.0).await.unwrap();
+    assert!(market.cash() > Money::from_f64(10.0));
+    assert!(matches!(
+        market.buy_max("A").await,
+        Err(Error::InvalidQuantity { quantity, .. }) if quantity == 0.0
+    ));
+}
+
+#[tokio::test]
+async fn test_target_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [
+            ("A".to_string(), vec![10.0..10.0]),
+            ("B".to_string(), vec![20.0..20.0]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        200.0,
+    );
+
+    let bought = market.order_target_quantity("A", 5.0).await.unwrap();
+    assert_eq!(Some(5.0), bought.map(|receipt| receipt.quantity));
+    assert_eq!(None, market.order_target_quantity("A", 5.0).await.unwrap());
+
+    // A quarter of the net worth of 200, in whole shares
+    market.order_target_percent("B", 0.25).await.unwrap();
+    assert_eq!(2.0, market.shares_of("B"));
+
+    // Selling down to the target
+    let sold = market
+        .order_target_value("A", Money::from_f64(25.0))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(3.0, sold.quantity);
+    assert_eq!(2.0, market.shares_of("A"));
+
+    market.order_target_percent("A", 0.0).await.unwrap();
+    assert_eq!(0.0, market.shares_of("A"));
+    assert_float_eq!(160.0, market.cash().to_f64(), abs <= 1e-9);
+}
@@ -0,0 +1,141 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    fill::{
+        AtClose, AtMidpoint, AtOpen, BarPrices, FillModel, IntrabarFill, RandomInRange, StopFill,
+        WorstCase,
+    },
+    market::{Candle, Market},
+    order::{Order, OrderId, OrderKind, PendingOrder, Side},
+};
+
+fn candle() -> Candle {
+    Candle {
+        start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        open: 10.0,
+        high: 12.0,
+        low: 8.0,
+        close: 11.0,
+        volume: 100.0,
+    }
+}
+
+#[test]
+fn test_fill_models() {
+    let candle = candle();
+
+    assert_float_eq!(10.0, AtOpen.fill_price(&candle, Side::Buy), ulps <= 5);
+    assert_float_eq!(11.0, AtClose.fill_price(&candle, Side::Buy), ulps <= 5);
+    assert_float_eq!(10.0, AtMidpoint.fill_price(&candle, Side::Sell), ulps <= 5);
+    assert_float_eq!(12.0, WorstCase.fill_price(&candle, Side::Buy), ulps <= 5);
+    assert_float_eq!(8.0, WorstCase.fill_price(&candle, Side::Sell), ulps <= 5);
+
+    for _ in 0..100 {
+        let price = RandomInRange.fill_price(&candle, Side::Buy);
+        assert!((8.0..=12.0).contains(&price));
+    }
+}
+
+#[tokio::test]
+async fn test_market_fill_model() {
+    let start = candle().start;
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..11.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_fill_model(WorstCase);
+
+    market.buy_at_market("STOCK", 2.0).await.unwrap();
+    assert_float_eq!(78.0, market.cash().to_f64(), ulps <= 5);
+    market.sell_at_market("STOCK", 2.0).await.unwrap();
+    assert_float_eq!(98.0, market.cash().to_f64(), ulps <= 5);
+}
+
+#[test]
+fn test_intrabar_fills() {
+    let bar = BarPrices::from(&candle());
+    let stop = |stop_fill: StopFill| {
+        let fill = IntrabarFill {
+            stop_fill,
+            ..Default::default()
+        };
+        let mut pending = PendingOrder::new(
+            OrderId(0),
+            Order::new(
+                "STOCK",
+                Side::Sell,
+                1.0,
+                OrderKind::Stop { stop_price: 9.0 },
+            ),
+        );
+        pending.on_bar(&bar, &fill)
+    };
+    assert_eq!(Some(9.0), stop(StopFill::StopOrClose));
+    assert_eq!(Some(8.0), stop(StopFill::WorstPrice));
+
+    // A bar that gapped below the stop fills it at the open
+    let mut gap = PendingOrder::new(
+        OrderId(0),
+        Order::new(
+            "STOCK",
+            Side::Sell,
+            1.0,
+            OrderKind::Stop { stop_price: 10.5 },
+        ),
+    );
+    let fill = IntrabarFill {
+        stop_fill: StopFill::StopOrOpen,
+        ..Default::default()
+    };
+    assert_eq!(Some(10.0), gap.on_bar(&bar, &fill));
+
+    // Touching a limit price is not enough with a penetration
+    let limit = Order::new(
+        "STOCK",
+        Side::Buy,
+        1.0,
+        OrderKind::Limit { limit_price: 8.0 },
+    );
+    let penetrating = IntrabarFill {
+        limit_penetration: 0.1,
+        ..Default::default()
+    };
+    let mut pending = PendingOrder::new(OrderId(0), limit);
+    assert_eq!(Some(8.0), pending.on_bar(&bar, &IntrabarFill::default()));
+    assert_eq!(None, pending.on_bar(&bar, &penetrating));
+}
+
+#[tokio::test]
+async fn test_market_intrabar_fill() {
+    let start = candle().start;
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 10.0..9.0, 9.0..8.9])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_intrabar_fill(IntrabarFill {
+        limit_penetration: 0.05,
+        ..Default::default()
+    });
+
+    market.buy_limit("STOCK", 1.0, 9.0).await.unwrap();
+    for _ in 0..2 {
+        market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+    assert_eq!(0.0, market.shares_of("STOCK"));
+
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(1.0, market.shares_of("STOCK"));
+    assert_float_eq!(91.0, market.cash().to_f64(), ulps <= 5);
+}
@@ -0,0 +1,65 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{
+    filter::{EventFilter, SkipReport},
+    market::{Event, Market},
+};
+
+#[tokio::test]
+async fn test_skipped_events() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let earnings = |symbol: &str| Event::Earnings {
+        symbol: symbol.to_string(),
+    };
+    let market = TestMarket::new(
+        start,
+        [("A".to_string(), vec![10.0..10.0; 5])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_events(
+        [
+            (start + TimeDelta::seconds(30), earnings("A")),
+            (start + TimeDelta::seconds(40), earnings("B")),
+            (start + TimeDelta::seconds(90), Event::RegularMarketEnd),
+            (start + TimeDelta::seconds(150), earnings("A")),
+        ]
+        .into(),
+    );
+    let mut market = EventFilter::new(market)
+        .with_regular_hours_ticks_only()
+        .with_subscriptions(["A"])
+        .with_logging(log::Level::Debug);
+
+    let mut delivered = Vec::new();
+    while delivered.len() < 5 {
+        delivered.push(
+            market
+                .next_event_or_tick(TimeDelta::minutes(1))
+                .await
+                .unwrap(),
+        );
+    }
+
+    assert_eq!(
+        vec![
+            (start, Event::Tick),
+            (start + TimeDelta::seconds(30), earnings("A")),
+            (start + TimeDelta::minutes(1), Event::Tick),
+            (start + TimeDelta::seconds(90), Event::RegularMarketEnd),
+            (start + TimeDelta::seconds(150), earnings("A")),
+        ],
+        delivered
+    );
+    // The tick at 2 minutes was after the regular session
+    assert_eq!(
+        &SkipReport {
+            delivered: 5,
+            ticks_outside_regular_hours: 1,
+            unsubscribed: [("B".to_string(), 1)].into(),
+        },
+        market.report()
+    );
+    assert_eq!(2, market.report().skipped());
+}
@@ -0,0 +1,88 @@
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    export::RecordingMarket,
+    ext::MarketExt,
+    fixtures::{bars, events, interval, questdb_import_script, sessions, SYMBOLS},
+    market::{Event, Market},
+};
+
+#[test]
+fn test_bundled_data() {
+    let bars = bars();
+    assert_eq!(SYMBOLS.len(), bars.len());
+    for candles in bars.values() {
+        // A bar a minute over the first four hours of the session
+        assert_eq!(240, candles.len());
+        assert!(candles
+            .windows(2)
+            .all(|pair| pair[0].start < pair[1].start && pair[0].close == pair[1].open));
+        assert!(candles.iter().all(|candle| candle.low <= candle.high));
+    }
+
+    let events = events();
+    assert_eq!(4 * sessions().len() + 240, events.len());
+    assert!(events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+
+    let script = questdb_import_script();
+    assert_eq!(2 + events.len(), script.lines().count());
+    assert!(script.contains(
+        "INSERT INTO system_events VALUES ('regular_hours_start', '2024-03-04T14:30:00.000000Z');"
+    ));
+}
+
+/// Trades the bundled data with a moving average crossover
+#[tokio::test]
+async fn test_end_to_end() {
+    let session = &sessions()[0];
+    let candles = &bars()["STOCK"];
+    let last = candles[candles.len() - 1];
+    let bar_events = events()
+        .into_iter()
+        .filter(|(_, event)| matches!(event, Event::BarClosed { .. }));
+    let market = TestMarket::new(
+        session.regular_start,
+        [(
+            "STOCK".to_string(),
+            // Priced at the opens, and at the last close once the bars ended
+            candles
+                .iter()
+                .map(|candle| candle.open..candle.open)
+                .chain([last.close..last.close])
+                .collect(),
+        )]
+        .into(),
+        interval(),
+        10_000.0,
+    )
+    .with_events(bar_events.collect());
+    let mut market = RecordingMarket::new(market);
+
+    let mut closes = Vec::new();
+    while let Some((_, event)) = market.next_event().await.unwrap() {
+        let Event::BarClosed { candle, .. } = event else {
+            continue;
+        };
+        closes.push(candle.close);
+        if closes.len() < 12 {
+            continue;
+        }
+        let average = closes[closes.len() - 12..].iter().sum::<f64>() / 12.0;
+        let target = if candle.close > average { 0.5 } else { 0.0 };
+        market.order_target_percent("STOCK", target).await.unwrap();
+    }
+    market.liquidate_all().await.unwrap();
+
+    assert_eq!(candles.len(), closes.len());
+    assert!(!market.trades().is_empty());
+    assert_eq!(0.0, market.shares_of("STOCK"));
+    // The cash only changed by the recorded trades
+    let traded: f64 = market
+        .trades()
+        .iter()
+        .map(|trade| trade.quantity * trade.price)
+        .sum();
+    assert_float_eq!(10_000.0 - traded, market.cash().to_f64(), abs <= 1e-6);
+    assert_eq!(last.start + interval(), market.time());
+}
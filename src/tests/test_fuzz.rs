@@ -0,0 +1,112 @@
+//! Randomized tests of the market state machine, driving the test market
+//! with arbitrary event sequences, ticks and orders while checking its
+//! invariants. See also the `fuzz` crate for coverage-guided fuzzing.
+
+use std::collections::VecDeque;
+
+use chrono::{TimeDelta, TimeZone, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::test_market::TestMarket;
+use crate::{
+    account::AccountError,
+    error::Error,
+    market::{Event, Market, MarketTime},
+    money::Money,
+};
+
+const SESSION_EVENTS: [Event; 4] = [
+    Event::PreMarketStart,
+    Event::RegularMarketStart,
+    Event::RegularMarketEnd,
+    Event::PostMarketEnd,
+];
+
+/// Arbitrary session events, mostly but not always in a valid order
+fn random_events(rng: &mut StdRng, length: usize) -> VecDeque<(chrono::DateTime<Utc>, Event)> {
+    let mut time = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut session = rng.gen_range(0..SESSION_EVENTS.len());
+
+    (0..length)
+        .map(|_| {
+            time += TimeDelta::minutes(rng.gen_range(0..30));
+            session = if rng.gen_bool(0.9) {
+                (session + 1) % SESSION_EVENTS.len()
+            } else {
+                rng.gen_range(0..SESSION_EVENTS.len())
+            };
+
+            (time, SESSION_EVENTS[session].clone())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_random_sessions_and_orders() {
+    for seed in 0..200 {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let prices = (0..1_000)
+            .map(|_| {
+                let low = rng.gen_range(1.0..100.0);
+                low..low + rng.gen_range(0.0..5.0)
+            })
+            .collect();
+        let mut market = TestMarket::new(
+            Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+            [("STOCK".to_string(), prices)].into(),
+            TimeDelta::minutes(1),
+            1_000.0,
+        )
+        .with_events(random_events(&mut rng, 20));
+
+        let mut last_time = market.time();
+
+        for _ in 0..100 {
+            let market_time = market.market_time();
+            let result = if rng.gen_bool(0.2) {
+                market.next_event().await.map(|event| event.map(|(t, _)| t))
+            } else {
+                let tick = TimeDelta::minutes(rng.gen_range(1..10));
+                market.next_event_or_tick(tick).await.map(|(t, _)| Some(t))
+            };
+
+            match result {
+                // The only possible failure is an out of order session event,
+                // which must not change the market time
+                Err(Error::ImpossibleEvent(_)) => {
+                    assert_eq!(market_time, market.market_time(), "seed {seed}");
+                    break;
+                }
+                Err(e) => panic!("seed {seed}: unexpected error {e:?}"),
+                Ok(None) => break,
+                Ok(Some(time)) => {
+                    assert!(time >= last_time, "seed {seed}: time went backwards");
+                    assert_eq!(time, market.time(), "seed {seed}");
+                    last_time = time;
+                }
+            }
+
+            let quantity = rng.gen_range(1..20) as f64;
+            let order = if rng.gen_bool(0.5) {
+                market.buy_at_market("STOCK", quantity).await
+            } else {
+                market.sell_at_market("STOCK", quantity).await
+            };
+
+            match order {
+                // Market orders only trade in the regular session by default
+                Ok(_) => assert_eq!(MarketTime::Regular, market.market_time(), "seed {seed}"),
+                Err(Error::UntimelyTrade(..))
+                | Err(Error::OutsideSession(..))
+                | Err(Error::Account(AccountError::InsufficientCash { .. }))
+                | Err(Error::Account(AccountError::InsufficientShares { .. }))
+                | Err(Error::UnknownPrice(_)) => {}
+                Err(e) => panic!("seed {seed}: unexpected error {e:?}"),
+            }
+
+            assert!(market.cash() >= Money::ZERO, "seed {seed}: negative cash");
+            assert_ne!(MarketTime::Unknown, market.market_time(), "seed {seed}");
+        }
+    }
+}
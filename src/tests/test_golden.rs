@@ -0,0 +1,160 @@
+//! Golden-file regression tests: a strategy is run against a bundled
+//! fixture dataset, and its trade log and equity curve are compared with
+//! checked-in results. Run with `UPDATE_GOLDEN=1` to regenerate the golden
+//! files after an intended change in results.
+
+use std::{collections::VecDeque, fmt::Write as _, path::PathBuf};
+
+use chrono::{NaiveTime, TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{export::RecordingMarket, market::Market, Algorithm};
+
+/// The absolute tolerance when comparing numbers with the golden files
+const TOLERANCE: f64 = 1e-6;
+
+/// Goes all in when the short moving average crosses above the long one, and
+/// sells everything when it crosses below
+struct CrossMovingAverage {
+    symbol: String,
+    ticks: usize,
+    short_duration: usize,
+    long_duration: usize,
+
+    samples: VecDeque<f64>,
+}
+
+impl Algorithm for CrossMovingAverage {
+    fn wake_ups() -> impl Iterator<Item = NaiveTime> {
+        vec![].into_iter()
+    }
+
+    async fn run<M: Market>(&mut self, market: &mut M) -> Result<(), M::Error> {
+        for _ in 0..self.ticks {
+            market.next_event_or_tick(TimeDelta::minutes(1)).await?;
+
+            let price = market.current_price(&self.symbol).await?;
+            self.samples.push_front(price);
+            self.samples.truncate(self.long_duration);
+            if self.samples.len() < self.long_duration {
+                continue;
+            }
+
+            let average = |n: usize| self.samples.iter().take(n).sum::<f64>() / n as f64;
+            let held = market.shares_of(&self.symbol);
+
+            if average(self.short_duration) > average(self.long_duration) {
+                let quantity = (market.cash().to_f64() / price) as u32;
+                if held == 0.0 && quantity > 0 {
+                    market.buy_at_market(&self.symbol, quantity.into()).await?;
+                }
+            } else if held > 0.0 {
+                market.sell_at_market(&self.symbol, held).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn fixture_prices() -> Vec<f64> {
+    include_str!("fixtures/prices.csv")
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').nth(1).unwrap().parse().unwrap())
+        .collect()
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/tests/golden")
+        .join(name)
+}
+
+/// Compares CSV output with a golden file, treating numeric fields as equal
+/// within `TOLERANCE`
+fn assert_golden(name: &str, actual: &str) {
+    let path = golden_path(name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("cannot read {}: {e}", path.display()));
+    let (expected_lines, actual_lines): (Vec<_>, Vec<_>) =
+        (expected.lines().collect(), actual.lines().collect());
+    assert_eq!(
+        expected_lines.len(),
+        actual_lines.len(),
+        "{name}: different number of lines"
+    );
+
+    for (line_number, (expected_line, actual_line)) in
+        expected_lines.iter().zip(actual_lines.iter()).enumerate()
+    {
+        let fields_match = expected_line.split(',').count() == actual_line.split(',').count()
+            && expected_line.split(',').zip(actual_line.split(',')).all(
+                |(expected_field, actual_field)| match (
+                    expected_field.parse::<f64>(),
+                    actual_field.parse::<f64>(),
+                ) {
+                    (Ok(e), Ok(a)) => (e - a).abs() <= TOLERANCE,
+                    _ => expected_field == actual_field,
+                },
+            );
+
+        assert!(
+            fields_match,
+            "{name}:{}: expected `{expected_line}`, got `{actual_line}`",
+            line_number + 1
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_cross_moving_average_golden() {
+    let prices = fixture_prices();
+    let market = TestMarket::new(
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        [(
+            "STOCK".to_string(),
+            prices.iter().map(|price| *price..*price).collect(),
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        10_000.0,
+    );
+    let mut market = RecordingMarket::new(market);
+
+    let mut algorithm = CrossMovingAverage {
+        symbol: "STOCK".to_string(),
+        ticks: prices.len(),
+        short_duration: 5,
+        long_duration: 20,
+
+        samples: VecDeque::new(),
+    };
+    algorithm.run(&mut market).await.unwrap();
+
+    let mut trades = String::from("time,symbol,quantity,price\n");
+    for trade in market.trades() {
+        writeln!(
+            trades,
+            "{},{},{},{:.6}",
+            trade.time.to_rfc3339(),
+            trade.symbol,
+            trade.quantity,
+            trade.price
+        )
+        .unwrap();
+    }
+    let mut equity = String::from("time,net_worth\n");
+    for (time, net_worth) in market.equity_curve() {
+        writeln!(equity, "{},{net_worth:.6}", time.to_rfc3339()).unwrap();
+    }
+
+    assert_golden("cross_moving_average_trades.csv", &trades);
+    assert_golden("cross_moving_average_equity.csv", &equity);
+}
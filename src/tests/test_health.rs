@@ -0,0 +1,102 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+
+use super::test_market::TestMarket;
+use crate::{
+    health::{serve, HealthStatus, HealthTracker},
+    market::Market,
+};
+
+#[tokio::test]
+async fn test_health_status() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 8.0..8.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    let mut tracker = HealthTracker::new();
+    let status = tracker.status(&market).await.unwrap();
+    assert_eq!(None, status.staleness(Utc::now()));
+    assert!(!status.is_healthy(TimeDelta::days(365), Utc::now()));
+
+    market.buy_at_market("STOCK", 5.0).await.unwrap();
+    market.buy_limit("STOCK", 1.0, 5.0).await.unwrap();
+    tracker.on_event(start);
+    assert_eq!(0.0, tracker.status(&market).await.unwrap().drawdown);
+
+    // Without any event on the way
+    assert!(market
+        .advance_to(start + TimeDelta::minutes(1))
+        .await
+        .unwrap()
+        .is_empty());
+    let status = tracker.status(&market).await.unwrap();
+    assert_eq!(Some(start), status.last_event_at);
+    assert_eq!(1, status.open_orders);
+    assert_float_eq!(0.1, status.drawdown, abs <= 1e-9);
+
+    // Stale by the wall clock, however far the market's time moved
+    let handled_at = status.last_event_handled_at.unwrap();
+    let now = handled_at + TimeDelta::minutes(1);
+    assert_eq!(Some(TimeDelta::minutes(1)), status.staleness(now));
+    assert!(status.is_healthy(TimeDelta::minutes(1), now));
+    assert!(!status.is_healthy(TimeDelta::seconds(59), now));
+    assert!(status.to_json(now).starts_with(
+        "{\"time\":\"1970-01-01T00:01:00+00:00\",\"last_event_at\":\"1970-01-01T00:00:00+00:00\",\
+         \"staleness_seconds\":60,\"open_orders\":1,\"drawdown\":0.0999"
+    ));
+
+    // Not a number is not valid JSON
+    let status = HealthStatus {
+        drawdown: f64::NAN,
+        ..status
+    };
+    assert!(status.to_json(now).ends_with("\"drawdown\":null}"));
+}
+
+#[tokio::test]
+async fn test_health_endpoint() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let status = HealthStatus {
+        open_orders: 3,
+        last_event_handled_at: Some(Utc::now()),
+        ..Default::default()
+    };
+    let (sender, receiver) = watch::channel(status);
+    tokio::spawn(serve(listener, receiver, TimeDelta::minutes(1)));
+
+    // Sent in parts, to be read until the end of the request line
+    let get = |path: &'static str| async move {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\n\r\n");
+        let (first, rest) = request.split_at(6);
+        stream.write_all(first.as_bytes()).await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        stream.write_all(rest.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let response = get("/health").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.ends_with("\"open_orders\":3,\"drawdown\":0}"));
+    assert!(get("/metrics").await.starts_with("HTTP/1.1 404"));
+
+    // No event handled for longer than allowed
+    sender.send_modify(|status| {
+        status.last_event_handled_at = Some(Utc::now() - TimeDelta::minutes(2))
+    });
+    assert!(get("/health")
+        .await
+        .starts_with("HTTP/1.1 503 Service Unavailable"));
+}
@@ -0,0 +1,162 @@
+use float_eq::assert_float_eq;
+
+use crate::{
+    instrument::{Currency, Instrument, InstrumentRegistry, RoundingError, RoundingMode},
+    money::Money,
+};
+
+fn futures_registry(mode: RoundingMode) -> InstrumentRegistry {
+    let mut registry = InstrumentRegistry::new(mode);
+    registry.insert(
+        "FUTURE",
+        Instrument {
+            tick_size: 0.25,
+            lot_size: 10.0,
+            ..Default::default()
+        },
+    );
+
+    registry
+}
+
+#[test]
+fn test_round_price() {
+    let registry = futures_registry(RoundingMode::Round);
+
+    assert_float_eq!(
+        100.25,
+        registry.round_price("FUTURE", 100.3).unwrap(),
+        ulps <= 5
+    );
+    assert_float_eq!(
+        100.01,
+        registry.round_price("STOCK", 100.0123).unwrap(),
+        ulps <= 5
+    );
+}
+
+#[test]
+fn test_round_quantity() {
+    let registry = futures_registry(RoundingMode::Round);
+
+    assert_eq!(20.0, registry.round_quantity("FUTURE", 29.0).unwrap());
+    assert_eq!(0.0, registry.round_quantity("FUTURE", 9.0).unwrap());
+    assert_eq!(29.0, registry.round_quantity("STOCK", 29.0).unwrap());
+}
+
+#[test]
+fn test_fractional_lots() {
+    let mut registry = InstrumentRegistry::new(RoundingMode::Strict);
+    registry.insert(
+        "STOCK",
+        Instrument {
+            lot_size: 0.001,
+            ..Default::default()
+        },
+    );
+
+    assert_float_eq!(
+        2.345,
+        registry.round_quantity("STOCK", 2.345).unwrap(),
+        abs <= 1e-9
+    );
+    assert!(matches!(
+        registry.round_quantity("STOCK", 2.3456),
+        Err(RoundingError::OddLotQuantity { .. })
+    ));
+}
+
+#[test]
+fn test_exchange_minimums() {
+    let mut registry = InstrumentRegistry::new(RoundingMode::Round);
+    registry.insert(
+        "BTCUSDT",
+        Instrument {
+            lot_size: 0.00001,
+            min_quantity: 0.0001,
+            min_notional: Money::from_f64(5.0),
+            ..Default::default()
+        },
+    );
+
+    // Rounded down to the step size, then checked
+    let quantity = registry.round_quantity("BTCUSDT", 0.000123456).unwrap();
+    assert_float_eq!(0.00012, quantity, abs <= 1e-12);
+    assert!(registry
+        .check_minimums("BTCUSDT", quantity, 50_000.0)
+        .is_ok());
+
+    assert!(matches!(
+        registry.check_minimums("BTCUSDT", 0.00005, 50_000.0),
+        Err(RoundingError::BelowMinQuantity { .. })
+    ));
+    assert!(matches!(
+        registry.check_minimums("BTCUSDT", 0.0001, 40_000.0),
+        Err(RoundingError::BelowMinNotional { notional, .. }) if notional == Money::from_f64(4.0)
+    ));
+
+    // Unregistered symbols have no minimums
+    assert!(registry.check_minimums("STOCK", 1.0, 0.01).is_ok());
+}
+
+#[test]
+fn test_strict_rounding() {
+    let registry = futures_registry(RoundingMode::Strict);
+
+    assert!(matches!(
+        registry.round_price("FUTURE", 100.3),
+        Err(RoundingError::OffTickPrice { .. })
+    ));
+    assert!(matches!(
+        registry.round_quantity("FUTURE", 29.0),
+        Err(RoundingError::OddLotQuantity { .. })
+    ));
+
+    // Aligned values pass through, despite floating point noise
+    assert_float_eq!(
+        0.3,
+        registry.round_price("STOCK", 0.1 + 0.2).unwrap(),
+        ulps <= 5
+    );
+    assert_eq!(30.0, registry.round_quantity("FUTURE", 30.0).unwrap());
+}
+
+#[test]
+fn test_formatting() {
+    let equity = Instrument::default();
+    assert_eq!("12.50", equity.format_price(12.5));
+    assert_eq!("7", equity.format_quantity(7.0));
+
+    let future = Instrument {
+        tick_size: 0.25,
+        lot_size: 100.0,
+        ..Default::default()
+    };
+    assert_eq!(2, future.price_decimals());
+    assert_eq!("300 (3 lots)", future.format_quantity(300.0));
+
+    let whole = Instrument {
+        tick_size: 5.0,
+        lot_size: 1.0,
+        ..Default::default()
+    };
+    assert_eq!("1235", whole.format_price(1234.6));
+
+    // Floating point noise in the tick size is ignored
+    let micro = Instrument {
+        tick_size: 0.1 + 0.2 - 0.2,
+        lot_size: 1.0,
+        ..Default::default()
+    };
+    assert_eq!(1, micro.price_decimals());
+
+    assert_eq!(
+        "-1234.50 USD",
+        Currency::default().format(Money::from_f64(-1234.5))
+    );
+    let yen = Currency {
+        code: "JPY".to_string(),
+        minor_units: 0,
+    };
+    assert_eq!("1235 JPY", yen.format(Money::from_f64(1234.6)));
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{
+    latency::{LatencyMonitor, OverrunPolicy},
+    market::Market,
+};
+
+#[tokio::test]
+async fn test_latency_monitor() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0; 10])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    let mut market =
+        LatencyMonitor::new(market).with_budget(Duration::from_millis(20), OverrunPolicy::SkipTick);
+
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+
+    // A slow handler skips the following tick
+    std::thread::sleep(Duration::from_millis(40));
+    let (time, _) = market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(start + TimeDelta::minutes(3), time);
+
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+
+    let report = market.report();
+    assert_eq!(3, report.events);
+    assert_eq!(1, report.overruns);
+    assert_eq!(1, report.skipped_ticks);
+    assert!(report.max >= Duration::from_millis(40));
+    assert_eq!(report.max, report.p99);
+    assert!(report.p50 < Duration::from_millis(20));
+}
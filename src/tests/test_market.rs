@@ -1,16 +1,34 @@
-use core::panic;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     ops::Range,
 };
 
-use chrono::{DateTime, DurationRound, RoundingError, TimeDelta, TimeZone, Utc};
+use chrono::{DateTime, DurationRound, TimeDelta, TimeZone, Utc};
 use float_eq::{assert_float_eq, float_eq};
-use rand::Rng;
-use tokio;
-
-use crate::market::{Event, Market, MarketTime};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    account::{
+        AccountError, ClosedLot, DayTradeLimit, Lot, LotId, LotSelection, Margin, Position,
+        SimulatedAccount, Transaction,
+    },
+    bars::BarEvents,
+    error::{check_quantity, Error},
+    execution::{OrderBookSimulator, SyntheticDepth},
+    fill::{BarPrices, FillModel, IntrabarFill, RandomInRange},
+    instrument::{Currency, Instrument, InstrumentRegistry},
+    market::{Candle, Event, EventMask, Market, MarketTime, PriceQuote},
+    money::Money,
+    order::{
+        Amendment, CancelReason, FillContext, ImpactCurve, Latency, MarketFill, MarketOrderFill,
+        OcoGroupId, Order, OrderEngine, OrderId, OrderKind, OrderState, OrderStatus, PendingOrder,
+        PreparedOrder, PriceImpact, Remainder, SessionPolicy, Side, TimeInForce, TradeReceipt,
+        Trades, Trail, VolumeLimit,
+    },
+    scanner::Scanner,
+};
 
+#[derive(Default)]
 pub struct TestMarket {
     events: VecDeque<(DateTime<Utc>, Event)>,
     time: DateTime<Utc>,
@@ -21,36 +39,512 @@ pub struct TestMarket {
     price_history_start: DateTime<Utc>,
     price_history_interval: TimeDelta,
 
-    cash: f64,
-    holdings: HashMap<String, u32>,
+    account: SimulatedAccount,
+    untradeable: HashSet<String>,
+    orders: OrderEngine,
+    /// How market orders fill, by default at a random price in the current
+    /// candle
+    fill_model: Option<Box<dyn FillModel>>,
+    /// The traded volume per interval, by symbol
+    volumes: HashMap<String, Vec<f64>>,
+    /// The lot sizes of the symbols (see `with_lot_size`), and what fills
+    /// are logged with
+    instruments: InstrumentRegistry,
+    currency: Currency,
+    scanner: Option<Scanner>,
+    scanned_until: Option<DateTime<Utc>>,
+    bar_events: Option<BarEvents>,
+    bars_closed_until: Option<DateTime<Utc>>,
 }
 
-impl Market for TestMarket {
-    type Error = ();
+impl TestMarket {
+    /// A market that is open from `start` on, with the given price ranges
+    /// per interval
+    pub(super) fn new(
+        start: DateTime<Utc>,
+        price_histories: HashMap<String, Vec<Range<f64>>>,
+        price_history_interval: TimeDelta,
+        cash: f64,
+    ) -> Self {
+        TestMarket {
+            time: start,
+            next_time: start,
+            market_time: MarketTime::Regular,
+
+            price_histories,
+            price_history_start: start,
+            price_history_interval,
+
+            account: SimulatedAccount::new(Money::from_f64(cash)),
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the session events the market will report
+    pub(super) fn with_events(mut self, events: VecDeque<(DateTime<Utc>, Event)>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub(super) fn with_margin(mut self, margin: Margin) -> Self {
+        self.account = self.account.with_margin(margin);
+        self
+    }
+
+    pub(super) fn with_settlement_delay(mut self, trading_days: usize) -> Self {
+        self.account = self.account.with_settlement_delay(trading_days);
+        self
+    }
+
+    pub(super) fn with_day_trade_limit(mut self, limit: DayTradeLimit) -> Self {
+        self.account = self.account.with_day_trade_limit(limit);
+        self
+    }
+
+    pub(super) fn with_lot_selection(mut self, lot_selection: LotSelection) -> Self {
+        self.account = self.account.with_lot_selection(lot_selection);
+        self
+    }
+
+    pub(super) fn with_market_fill(mut self, market_fill: MarketFill) -> Self {
+        self.orders = self.orders.with_market_fill(market_fill);
+        self
+    }
+
+    pub(super) fn with_volume_limit(
+        mut self,
+        volume_limit: VolumeLimit,
+        volumes: HashMap<String, Vec<f64>>,
+    ) -> Self {
+        self.orders = self.orders.with_volume_limit(volume_limit);
+        self.volumes = volumes;
+        self
+    }
+
+    pub(super) fn with_session_policy(mut self, session_policy: SessionPolicy) -> Self {
+        self.orders = self.orders.with_session_policy(session_policy);
+        self
+    }
+
+    pub(super) fn with_fill_model(mut self, fill_model: impl FillModel + 'static) -> Self {
+        self.fill_model = Some(Box::new(fill_model));
+        self
+    }
+
+    pub(super) fn with_latency(mut self, latency: Latency, seed: u64) -> Self {
+        self.orders = self.orders.with_latency(latency, seed);
+        self
+    }
+
+    pub(super) fn with_end_of_day_cancellation(mut self, enabled: bool) -> Self {
+        self.orders = self.orders.with_end_of_day_cancellation(enabled);
+        self
+    }
+
+    pub(super) fn with_intrabar_fill(mut self, intrabar_fill: IntrabarFill) -> Self {
+        self.orders = self.orders.with_intrabar_fill(intrabar_fill);
+        self
+    }
+
+    /// Simulates the queues of resting limit orders, which then need the
+    /// intervals' volumes
+    pub(super) fn with_order_book(
+        mut self,
+        depth: SyntheticDepth,
+        volumes: HashMap<String, Vec<f64>>,
+    ) -> Self {
+        self.orders = self.orders.with_order_book(OrderBookSimulator::new(depth));
+        self.volumes = volumes;
+        self
+    }
+
+    pub(super) fn with_price_impact(
+        mut self,
+        price_impact: PriceImpact,
+        volumes: HashMap<String, Vec<f64>>,
+    ) -> Self {
+        self.orders = self.orders.with_price_impact(price_impact);
+        self.volumes = volumes;
+        self
+    }
+
+    pub(super) fn with_lot_size(mut self, lot_size: f64) -> Self {
+        self.instruments = self.instruments.with_fallback(Instrument {
+            lot_size,
+            ..Default::default()
+        });
+        self
+    }
+
+    pub(super) fn with_scanner(mut self, scanner: Scanner) -> Self {
+        self.scanner = Some(scanner);
+        self
+    }
+
+    pub(super) fn with_bar_events(mut self, bar_events: BarEvents) -> Self {
+        self.bar_events = Some(bar_events);
+        self
+    }
+
+    fn current_volume(&self, symbol: &str) -> Option<f64> {
+        self.volumes
+            .get(symbol)?
+            .get(self.candle_index(self.time).max(0) as usize)
+            .copied()
+    }
+
+    fn candle_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<Candle, Error> {
+        let candle_index = self.candle_index(time).max(0);
+        let range = self
+            .price_history(symbol)?
+            .get(candle_index as usize)
+            .ok_or(Error::UnknownPrice(symbol.to_string()))?;
+
+        Ok(Candle {
+            start: self.price_history_start + self.price_history_interval * candle_index as i32,
+            open: range.start,
+            high: range.start.max(range.end),
+            low: range.start.min(range.end),
+            close: range.end,
+            volume: self
+                .volumes
+                .get(symbol)
+                .and_then(|volumes| volumes.get(candle_index as usize))
+                .copied()
+                .unwrap_or(0.0),
+        })
+    }
+
+    fn candle_index(&self, time: DateTime<Utc>) -> i64 {
+        (time - self.price_history_start).num_nanoseconds().unwrap()
+            / self.price_history_interval.num_nanoseconds().unwrap()
+    }
+
+    fn price_history(&self, symbol: &str) -> Result<&Vec<Range<f64>>, Error> {
+        self.price_histories
+            .get(symbol)
+            .ok_or(Error::UnknownPrice(symbol.to_string()))
+    }
 
-    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, ()> {
-        let event = self.events.pop_front();
+    fn ensure_tradeable(&self, symbol: &str, allow_extended_hours: bool) -> Result<(), Error> {
+        if !self.market_time.is_open() {
+            return Err(Error::UntimelyTrade(symbol.to_string(), self.time));
+        }
+
+        if !self
+            .orders
+            .session_policy()
+            .allows(self.market_time, allow_extended_hours)
+        {
+            return Err(Error::OutsideSession(symbol.to_string(), self.market_time));
+        }
 
-        if let Some((time, ref event_type)) = event {
-            self.market_time.update(event_type).unwrap();
-            self.next_time = time;
-            self.time = time;
+        if !self.is_tradeable(symbol) {
+            return Err(Error::UntradeableSymbol(symbol.to_string()));
         }
 
-        Ok(event)
+        Ok(())
     }
 
-    async fn next_event_or_tick(
+    /// Triggers and fills the pending orders whose prices were reached in the
+    /// candles entered since `since`
+    fn match_orders(&mut self, since: DateTime<Utc>) {
+        let candles = (self.candle_index(since) + 1).max(0) as usize
+            ..(self.candle_index(self.time) + 1).max(0) as usize;
+        let mut trades = HashMap::new();
+        for symbol in self.orders.matching_symbols(self.market_time) {
+            let volume = self
+                .volumes
+                .get(&symbol)
+                .and_then(|volumes| volumes.get(candles.clone()))
+                .map(|entered| entered.iter().sum());
+            let prices = self
+                .price_histories
+                .get(&symbol)
+                .and_then(|history| history.get(candles.clone()))
+                .and_then(|entered| {
+                    let low = entered
+                        .iter()
+                        .map(|c| c.start.min(c.end))
+                        .reduce(f64::min)?;
+                    let high = entered
+                        .iter()
+                        .map(|c| c.start.max(c.end))
+                        .reduce(f64::max)?;
+                    Some(BarPrices {
+                        open: entered.first()?.start,
+                        high,
+                        low,
+                        close: entered.last()?.end,
+                    })
+                });
+            if let Some(prices) = prices {
+                trades.insert(symbol, Trades { prices, volume });
+            }
+        }
+
+        let (orders, mut context) = self.fill_context();
+        orders.match_orders(&mut context, &trades);
+        self.report_order_events();
+    }
+
+    async fn execute_market_order(
         &mut self,
-        tick: chrono::TimeDelta,
-    ) -> Result<(DateTime<Utc>, Event), ()> {
+        symbol: &str,
+        side: Side,
+        quantity: f64,
+    ) -> Result<TradeReceipt, Error> {
+        self.mark_holdings().await;
+        let candle = self.candle_at(symbol, self.time)?;
+        let price = self
+            .fill_model
+            .as_deref()
+            .unwrap_or(&RandomInRange)
+            .fill_price(&candle, side);
+        let volume = self.current_volume(symbol);
+
+        let (orders, mut context) = self.fill_context();
+        let receipt =
+            orders.submit_market_order(&mut context, symbol, side, quantity, price, volume);
+        self.report_order_events();
+
+        Ok(receipt?)
+    }
+
+    /// Fills the queued market orders at the open of the candle after the
+    /// one they were submitted in, once it was entered
+    fn fill_queued_market_orders(&mut self) {
+        let mut fills = HashMap::new();
+        for queued in self.orders.queued_market_orders(self.market_time) {
+            if let Some(executes_at) = queued.executes_at {
+                let candle = self
+                    .candle_at(&queued.symbol, executes_at)
+                    .ok()
+                    .filter(|_| executes_at <= self.time);
+                let Some(candle) = candle else {
+                    continue;
+                };
+
+                let price = self
+                    .fill_model
+                    .as_deref()
+                    .unwrap_or(&RandomInRange)
+                    .fill_price(&candle, queued.side);
+                let volume = self
+                    .volumes
+                    .contains_key(&queued.symbol)
+                    .then_some(candle.volume);
+                let fill = MarketOrderFill {
+                    price,
+                    volume,
+                    bar_time: candle.start,
+                };
+                fills.insert(queued.id, fill);
+                continue;
+            }
+
+            let next_candle = self.candle_index(queued.submitted_at) + 1;
+            let open = self
+                .price_histories
+                .get(&queued.symbol)
+                .and_then(|history| history.get(next_candle.max(0) as usize))
+                .map(|candle| candle.start)
+                .filter(|_| self.candle_index(self.time) >= next_candle);
+            let Some(open) = open else {
+                continue;
+            };
+
+            let fill = MarketOrderFill {
+                price: open,
+                volume: self
+                    .volumes
+                    .get(&queued.symbol)
+                    .and_then(|volumes| volumes.get(next_candle as usize))
+                    .copied(),
+                bar_time: self.price_history_start
+                    + self.price_history_interval * next_candle as i32,
+            };
+            fills.insert(queued.id, fill);
+        }
+
+        let (orders, mut context) = self.fill_context();
+        orders.fill_market_orders(&mut context, &fills);
+        self.report_order_events();
+    }
+
+    async fn prepare_order(
+        &mut self,
+        id: OrderId,
+        order: Order,
+    ) -> Result<Option<PreparedOrder>, Error> {
+        self.ensure_tradeable(&order.symbol, order.allow_extended_hours)?;
+        check_quantity(&order.symbol, order.quantity)?;
+        self.mark_holdings().await;
+
+        let current_price = self.current_price(&order.symbol).await?;
+        self.account.check(
+            &order.symbol,
+            order.side,
+            order.quantity,
+            order.reference_price().unwrap_or(current_price),
+            self.time,
+        )?;
+
+        let volume = self.current_volume(&order.symbol);
+        Ok(Some((PendingOrder::new(id, order), current_price, volume)))
+    }
+
+    /// The order engine, with what it fills orders into as of now
+    fn fill_context(&mut self) -> (&mut OrderEngine, FillContext<'_>) {
+        (
+            &mut self.orders,
+            FillContext {
+                account: &mut self.account,
+                instruments: &self.instruments,
+                currency: &self.currency,
+                time: self.time,
+                market_time: self.market_time,
+            },
+        )
+    }
+
+    fn report_order_events(&mut self) {
+        for event in self.orders.take_events() {
+            self.report(event);
+        }
+    }
+
+    /// Marks the held equities at their current prices, valuing the account
+    /// for its day trade limit. Equities without a price keep their marks.
+    async fn mark_holdings(&mut self) {
+        if self.account.day_trade_limit().is_none() {
+            return;
+        }
+
+        let symbols: Vec<String> = self
+            .account
+            .holdings()
+            .map(|(symbol, _)| symbol.clone())
+            .collect();
+        for symbol in symbols {
+            if let Ok(price) = self.current_price(&symbol).await {
+                self.account.mark(&symbol, price);
+            }
+        }
+    }
+
+    /// Settles the funding of a perpetual at its current price
+    async fn settle_funding(&mut self, event: Event) -> Result<Event, Error> {
+        let Event::FundingPayment { symbol, rate, .. } = event else {
+            return Ok(event);
+        };
+
+        let quantity = self.account.shares_of(&symbol);
+        let amount = if quantity == 0.0 {
+            Money::ZERO
+        } else {
+            -Money::worth(quantity, self.current_price(&symbol).await? * rate)
+        };
+        self.account.credit(amount);
+
+        Ok(Event::FundingPayment {
+            symbol,
+            rate,
+            amount,
+        })
+    }
+
+    fn charge_interest(&mut self, event: &Event) {
+        if *event == Event::PostMarketEnd {
+            self.account.charge_interest(self.time);
+        }
+    }
+
+    fn scan(&mut self) {
+        let Some(scanner) = &self.scanner else {
+            return;
+        };
+        let interval = scanner.interval();
+        let end = self.time.duration_trunc(interval).unwrap();
+        let mut start = self.scanned_until.unwrap_or(end - interval);
+
+        let mut bars = Vec::new();
+        while start < end {
+            if start >= self.price_history_start {
+                for symbol in scanner.universe() {
+                    if let Ok(candle) = self.candle_at(symbol, start) {
+                        bars.push((symbol.clone(), candle));
+                    }
+                }
+            }
+            start += interval;
+        }
+        self.scanned_until = Some(end);
+
+        let scanner = self.scanner.as_mut().unwrap();
+        let hits: Vec<_> = bars
+            .into_iter()
+            .flat_map(|(symbol, candle)| scanner.on_bar(&symbol, candle))
+            .collect();
+        for hit in hits {
+            self.report(hit);
+        }
+    }
+
+    /// Reports the candles of the `with_bar_events` universe that closed
+    /// since the last ones
+    fn close_bars(&mut self) {
+        let Some(bar_events) = &self.bar_events else {
+            return;
+        };
+        let interval = bar_events.interval;
+        let end = self.time.duration_trunc(interval).unwrap();
+        let mut start = self.bars_closed_until.unwrap_or(end - interval);
+
+        let mut bars = Vec::new();
+        while start < end {
+            if start >= self.price_history_start {
+                for symbol in &bar_events.universe {
+                    if let Ok(candle) = self.candle_at(symbol, start) {
+                        bars.push(Event::BarClosed {
+                            symbol: symbol.clone(),
+                            interval,
+                            candle,
+                        });
+                    }
+                }
+            }
+            start += interval;
+        }
+        self.bars_closed_until = Some(end);
+
+        for bar in bars {
+            self.report(bar);
+        }
+    }
+
+    fn report(&mut self, event: Event) {
+        let event = (self.time, event);
+
+        // After the events already due, so events are reported in order
+        let due = self
+            .events
+            .iter()
+            .take_while(|(time, _)| time <= &self.time)
+            .count();
+        self.events.insert(due, event);
+    }
+
+    fn advance(&mut self, tick: TimeDelta) -> Result<(DateTime<Utc>, Event), Error> {
         let current_tick = self.next_time.duration_trunc(tick).unwrap();
         let next_tick = current_tick + tick;
 
         if self.next_time == current_tick {
+            // Events may be pending from before this tick if the tick
+            // duration changed since the previous call
             if let Some((event_time, event)) = self.events.front() {
-                if event_time == &self.next_time {
-                    self.market_time.update(event).unwrap();
+                if event_time <= &self.next_time {
+                    self.market_time.update(event)?;
                     self.time = *event_time;
                     return Ok(self.events.pop_front().unwrap());
                 }
@@ -63,7 +557,7 @@ impl Market for TestMarket {
 
         if let Some((event_time, event)) = self.events.front() {
             if event_time <= &next_tick {
-                self.market_time.update(event).unwrap();
+                self.market_time.update(event)?;
                 self.next_time = *event_time;
                 self.time = *event_time;
                 return Ok(self.events.pop_front().unwrap());
@@ -74,104 +568,268 @@ impl Market for TestMarket {
         self.time = next_tick;
         Ok((next_tick, Event::Tick))
     }
+}
+
+impl Market for TestMarket {
+    type Error = Error;
+
+    async fn next_event(&mut self) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        let Some((time, event)) = self.events.pop_front() else {
+            return Ok(None);
+        };
+
+        self.market_time.update(&event)?;
+        let since = self.time;
+        self.next_time = time;
+        self.time = time;
+        let event = self.settle_funding(event).await?;
+        self.mark_holdings().await;
+        self.fill_queued_market_orders();
+        self.match_orders(since);
+        self.orders.expire_orders(&event);
+        self.report_order_events();
+        self.charge_interest(&event);
+        self.close_bars();
+        self.scan();
+
+        Ok(Some((time, event)))
+    }
+
+    async fn next_event_or_tick(
+        &mut self,
+        tick: chrono::TimeDelta,
+    ) -> Result<(DateTime<Utc>, Event), Error> {
+        let since = self.time;
+        let (time, event) = self.advance(tick)?;
+        let event = self.settle_funding(event).await?;
+        self.mark_holdings().await;
+        self.fill_queued_market_orders();
+        self.match_orders(since);
+        self.orders.expire_orders(&event);
+        self.report_order_events();
+        self.charge_interest(&event);
+        self.close_bars();
+        self.scan();
+
+        Ok((time, event))
+    }
+
+    async fn next_event_until(
+        &mut self,
+        until: DateTime<Utc>,
+    ) -> Result<Option<(DateTime<Utc>, Event)>, Error> {
+        if self.events.front().is_some_and(|(time, _)| time <= &until) {
+            return self.next_event().await;
+        }
+
+        if until > self.time {
+            let since = self.time;
+            self.next_time = until;
+            self.time = until;
+            self.mark_holdings().await;
+            self.fill_queued_market_orders();
+            self.match_orders(since);
+            self.close_bars();
+            self.scan();
+        }
+
+        Ok(None)
+    }
 
     fn time(&self) -> DateTime<Utc> {
         self.time
     }
 
-    async fn price_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<f64, ()> {
+    async fn quote_at(&self, symbol: &str, time: DateTime<Utc>) -> Result<PriceQuote, Error> {
         if time > self.time {
-            panic!("tried to access a price from the future without the DeLorian")
+            return Err(Error::FutureQuery {
+                future_time: time,
+                current_time: self.time,
+            });
         }
 
-        let price_history = self
-            .price_histories
-            .get(symbol)
-            .expect("symbol does not exist");
-        let candle_index = (time - self.price_history_start).num_nanoseconds().unwrap()
-            / self.price_history_interval.num_nanoseconds().unwrap();
+        let price_history = self.price_history(symbol)?;
+        let candle_index = self.candle_index(time);
 
         // NOTE in the actual implementation, consider returning the latest
         // price instead of `None`
-        let current_candle = price_history.get(candle_index as usize).ok_or(())?;
-        if float_eq!(current_candle.start, current_candle.end, ulps <= 5) {
-            Ok(current_candle.start)
+        let current_candle = price_history
+            .get(candle_index as usize)
+            .ok_or(Error::UnknownPrice(symbol.to_string()))?;
+        let price = if float_eq!(current_candle.start, current_candle.end, ulps <= 5) {
+            current_candle.start
         } else {
             let mut rng = rand::thread_rng();
-            Ok(rng.gen_range(current_candle.clone()))
-        }
+            rng.gen_range(current_candle.clone())
+        };
+
+        Ok(PriceQuote {
+            price,
+            as_of: self.price_history_start + self.price_history_interval * candle_index as i32,
+        })
     }
 
-    async fn buy_at_market(&mut self, symbol: &str, quantity: u32) -> Result<(), ()> {
-        // TODO Avoid trading when the markets are closed
+    async fn buy_at_market(&mut self, symbol: &str, quantity: f64) -> Result<TradeReceipt, Error> {
+        self.ensure_tradeable(symbol, false)?;
+        check_quantity(symbol, quantity)?;
+
+        self.execute_market_order(symbol, Side::Buy, quantity).await
+    }
 
-        let price_per_share = self.current_price(symbol).await.unwrap();
-        let total_price = price_per_share * quantity as f64;
+    async fn sell_at_market(&mut self, symbol: &str, quantity: f64) -> Result<TradeReceipt, Error> {
+        self.ensure_tradeable(symbol, false)?;
+        check_quantity(symbol, quantity)?;
 
-        if total_price > self.cash {
-            panic!(
-                "Not enough cash: tried to buy {} shares of {} at {} with {} in cash",
-                quantity, symbol, price_per_share, self.cash
-            );
+        let owned = self.shares_of(symbol);
+        if quantity > owned {
+            return Err(Error::Account(AccountError::InsufficientShares {
+                quantity,
+                symbol: symbol.to_string(),
+                owned,
+            }));
         }
 
-        self.cash -= total_price;
-
-        let cool = self.holdings.get_mut(symbol);
+        self.execute_market_order(symbol, Side::Sell, quantity)
+            .await
+    }
 
-        if let Some(v) = cool {
-            *v += quantity;
-        } else {
-            self.holdings.insert(symbol.to_string(), quantity);
+    async fn submit_order(&mut self, order: Order) -> Result<OrderId, Error> {
+        let id = self.orders.new_order_id();
+        if let Some(prepared) = self.prepare_order(id, order).await? {
+            let (orders, mut context) = self.fill_context();
+            let submitted = orders.submit(&mut context, prepared);
+            self.report_order_events();
+            submitted?;
         }
 
+        Ok(id)
+    }
+
+    async fn submit_oco(
+        &mut self,
+        first: Order,
+        second: Order,
+    ) -> Result<(OcoGroupId, [OrderId; 2]), Error> {
+        let ids = [self.orders.new_order_id(), self.orders.new_order_id()];
+        let first = self.prepare_order(ids[0], first).await?;
+        let second = self.prepare_order(ids[1], second).await?;
+
+        let (orders, mut context) = self.fill_context();
+        let group = orders.submit_oco(&mut context, first.into_iter().chain(second));
+        self.report_order_events();
+
+        Ok((group?, ids))
+    }
+
+    async fn amend_order(&mut self, id: OrderId, amendment: Amendment) -> Result<(), Error> {
+        let amended = self
+            .orders
+            .pending_order(id)
+            .ok_or(Error::OrderNotOpen(id))?
+            .order
+            .amended(&amendment)
+            .ok_or(Error::InvalidAmendment(id))?;
+
+        // Validated like a new order, but keeping its place in its life cycle
+        let prepared = self.prepare_order(id, amended).await?;
+        let (orders, mut context) = self.fill_context();
+        let amended = orders.amend(&mut context, id, prepared);
+        self.report_order_events();
+        amended?;
+
         Ok(())
     }
 
-    async fn sell_at_market(&mut self, symbol: &str, quantity: u32) -> Result<(), ()> {
-        if &quantity > self.holdings.get(symbol).unwrap() {
-            panic!(
-                "Not enough shares: tried to sell {} shares of {} whilst holding {} shares",
-                quantity,
-                symbol,
-                self.holdings.get(symbol).unwrap()
-            );
+    async fn cancel_order(&mut self, id: OrderId) -> Result<(), Error> {
+        if !self.orders.cancel(id) {
+            return Err(Error::OrderNotOpen(id));
         }
+        self.report_order_events();
 
-        let price_per_share = self.current_price(symbol).await.unwrap();
-        let total_price = price_per_share * quantity as f64;
+        Ok(())
+    }
 
-        self.cash += total_price;
+    fn order_status(&self, id: OrderId) -> Option<OrderStatus> {
+        self.orders.order_status(id)
+    }
 
-        let cool = self.holdings.get_mut(symbol);
+    fn open_orders(&self) -> impl IntoIterator<Item = &PendingOrder> {
+        self.orders.pending_orders()
+    }
 
-        if let Some(v) = cool {
-            *v -= quantity;
+    fn set_tradeable(&mut self, symbol: &str, tradeable: bool) {
+        if tradeable {
+            self.untradeable.remove(symbol);
         } else {
-            unreachable!()
+            self.untradeable.insert(symbol.to_string());
         }
+    }
 
-        Ok(())
+    fn is_tradeable(&self, symbol: &str) -> bool {
+        !self.untradeable.contains(symbol)
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.instruments.get(symbol).lot_size
     }
 
     fn market_time(&self) -> MarketTime {
         self.market_time
     }
 
-    fn cash(&self) -> f64 {
-        self.cash
+    fn cash(&self) -> Money {
+        self.account.cash()
     }
 
-    fn shares_of(&self, symbol: &str) -> u32 {
-        if let Some(q) = self.holdings.get(symbol) {
-            *q
-        } else {
-            0
-        }
+    fn settled_cash(&self) -> Money {
+        self.account.settled_cash(self.time)
+    }
+
+    fn shares_of(&self, symbol: &str) -> f64 {
+        self.account.shares_of(symbol)
+    }
+
+    fn holdings(&self) -> impl IntoIterator<Item = (&String, &f64)> {
+        self.account.holdings()
+    }
+
+    fn position(&self, symbol: &str) -> Option<Position> {
+        self.account.position(symbol).copied()
+    }
+
+    fn realized_pnl(&self) -> Money {
+        self.account.realized_pnl()
+    }
+
+    fn transactions(&self) -> &[Transaction] {
+        self.account.transactions()
+    }
+
+    fn lots(&self, symbol: &str) -> &[Lot] {
+        self.account.lots(symbol)
     }
 
-    fn holdings(&self) -> impl IntoIterator<Item = (&String, &u32)> {
-        &self.holdings
+    fn closed_lots(&self) -> &[ClosedLot] {
+        self.account.closed_lots()
+    }
+
+    fn designate_lots(&mut self, symbol: &str, lots: &[LotId]) {
+        self.account.designate_lots(symbol, lots);
+    }
+
+    async fn position_high_water_mark(&self, symbol: &str) -> Result<Option<f64>, Error> {
+        let Some(&Position { opened_at, .. }) = self.account.position(symbol) else {
+            return Ok(None);
+        };
+
+        let price_history = self.price_history(symbol)?;
+        let first_candle = self.candle_index(opened_at) as usize;
+        let current_candle = self.candle_index(self.time) as usize;
+
+        Ok(price_history[first_candle..=current_candle]
+            .iter()
+            .map(|candle| candle.start.max(candle.end))
+            .reduce(f64::max))
     }
 }
 
@@ -202,8 +860,8 @@ async fn test_ticks() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 0.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
     };
 
     assert!(market.next_event().await.unwrap().is_none());
@@ -247,8 +905,8 @@ async fn test_market_hours() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 0.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
     };
 
     assert_event(
@@ -291,8 +949,8 @@ async fn test_invalid_market_hours() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 0.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
     };
 
     market.next_event().await.unwrap();
@@ -316,8 +974,8 @@ async fn test_prices() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 0.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
     };
 
     let (mut time, _) = market
@@ -337,6 +995,52 @@ async fn test_prices() {
     assert_in_range(12.0, 13.0, market.current_price("STOCK").await.unwrap());
 }
 
+#[tokio::test]
+async fn test_quote_timestamps() {
+    let mut market = TestMarket {
+        events: VecDeque::new(),
+        time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        next_time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        market_time: MarketTime::Regular,
+
+        price_histories: [("STOCK".to_string(), vec![10.0..10.0, 12.0..12.0])].into(),
+        price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        price_history_interval: TimeDelta::minutes(1),
+
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
+    };
+
+    for _ in 0..2 {
+        let _ = market
+            .next_event_or_tick(TimeDelta::seconds(30))
+            .await
+            .unwrap();
+    }
+
+    // Half way through the first candle
+    assert_eq!(
+        PriceQuote {
+            price: 10.0,
+            as_of: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        },
+        market.current_quote("STOCK").await.unwrap()
+    );
+
+    let _ = market
+        .next_event_or_tick(TimeDelta::seconds(30))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        PriceQuote {
+            price: 12.0,
+            as_of: Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap(),
+        },
+        market.current_quote("STOCK").await.unwrap()
+    );
+}
+
 #[tokio::test]
 async fn test_consistant_prices() {
     let mut market = TestMarket {
@@ -349,8 +1053,8 @@ async fn test_consistant_prices() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 0.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
     };
 
     let _ = market
@@ -378,8 +1082,8 @@ async fn test_inverted_lows_and_highs() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 0.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
     };
 
     let _ = market
@@ -391,7 +1095,6 @@ async fn test_inverted_lows_and_highs() {
 }
 
 #[tokio::test]
-#[should_panic]
 async fn test_future_prices() {
     let mut market = TestMarket {
         events: VecDeque::new(),
@@ -403,8 +1106,8 @@ async fn test_future_prices() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 0.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(0.0)),
+        ..Default::default()
     };
 
     let _ = market
@@ -412,9 +1115,12 @@ async fn test_future_prices() {
         .await
         .unwrap();
 
-    let _ = market
-        .price_at("STOCK", Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap())
-        .await;
+    assert!(matches!(
+        market
+            .price_at("STOCK", Utc.with_ymd_and_hms(1970, 1, 1, 0, 1, 0).unwrap())
+            .await,
+        Err(Error::FutureQuery { .. })
+    ));
 }
 
 #[tokio::test]
@@ -429,8 +1135,8 @@ async fn test_buy_and_sell() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 100.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(100.0)),
+        ..Default::default()
     };
 
     let _ = market
@@ -438,19 +1144,19 @@ async fn test_buy_and_sell() {
         .await
         .unwrap();
 
-    market.buy_at_market("STOCK", 100).await.unwrap();
+    market.buy_at_market("STOCK", 100.0).await.unwrap();
 
-    assert_float_eq!(0.0, market.cash, ulps <= 5);
-    assert_eq!(100, *market.holdings.get("STOCK").unwrap());
+    assert_float_eq!(0.0, market.cash().to_f64(), ulps <= 5);
+    assert_eq!(100.0, market.shares_of("STOCK"));
 
     let _ = market
         .next_event_or_tick(TimeDelta::minutes(1))
         .await
         .unwrap();
 
-    market.sell_at_market("STOCK", 100).await.unwrap();
+    market.sell_at_market("STOCK", 100.0).await.unwrap();
 
-    assert_float_eq!(200.0, market.cash, ulps <= 5);
+    assert_float_eq!(200.0, market.cash().to_f64(), ulps <= 5);
 }
 
 #[tokio::test]
@@ -466,8 +1172,8 @@ async fn test_buy_more_than_cash() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 100.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(100.0)),
+        ..Default::default()
     };
 
     let _ = market
@@ -475,7 +1181,7 @@ async fn test_buy_more_than_cash() {
         .await
         .unwrap();
 
-    market.buy_at_market("STOCK", 101).await.unwrap();
+    market.buy_at_market("STOCK", 101.0).await.unwrap();
 }
 
 #[tokio::test]
@@ -491,8 +1197,8 @@ async fn test_sell_more_than_holdings() {
         price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
         price_history_interval: TimeDelta::minutes(1),
 
-        cash: 100.0,
-        holdings: HashMap::new(),
+        account: SimulatedAccount::new(Money::from_f64(100.0)),
+        ..Default::default()
     };
 
     let _ = market
@@ -500,12 +1206,1828 @@ async fn test_sell_more_than_holdings() {
         .await
         .unwrap();
 
-    market.buy_at_market("STOCK", 100).await.unwrap();
+    market.buy_at_market("STOCK", 100.0).await.unwrap();
+
+    let _ = market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+
+    market.sell_at_market("STOCK", 101.0).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_invalid_quantities() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    market.buy_at_market("STOCK", 5.0).await.unwrap();
+
+    for quantity in [0.0, -10.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(
+            market.buy_at_market("STOCK", quantity).await,
+            Err(Error::InvalidQuantity { .. })
+        ));
+        assert!(matches!(
+            market.sell_at_market("STOCK", quantity).await,
+            Err(Error::InvalidQuantity { .. })
+        ));
+        assert!(matches!(
+            market.buy_limit("STOCK", quantity, 9.0).await,
+            Err(Error::InvalidQuantity { .. })
+        ));
+    }
+
+    // Nothing was traded or left open
+    assert_eq!(Money::from_f64(50.0), market.cash());
+    assert_eq!(5.0, market.shares_of("STOCK"));
+    assert_eq!(0, market.open_orders().into_iter().count());
+}
+
+#[tokio::test]
+async fn test_untradeable_symbol() {
+    let mut market = TestMarket {
+        events: VecDeque::new(),
+        time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        next_time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        market_time: MarketTime::Regular,
+
+        price_histories: [("STOCK".to_string(), vec![1.0..1.0, 2.0..2.0])].into(),
+        price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        price_history_interval: TimeDelta::minutes(1),
+
+        account: SimulatedAccount::new(Money::from_f64(100.0)),
+        ..Default::default()
+    };
 
     let _ = market
         .next_event_or_tick(TimeDelta::minutes(1))
         .await
         .unwrap();
 
-    market.sell_at_market("STOCK", 101).await.unwrap();
+    market.buy_at_market("STOCK", 10.0).await.unwrap();
+
+    market.set_tradeable("STOCK", false);
+    assert!(!market.is_tradeable("STOCK"));
+    assert!(market.buy_at_market("STOCK", 10.0).await.is_err());
+    assert!(market.sell_at_market("STOCK", 10.0).await.is_err());
+    assert_eq!(10.0, market.shares_of("STOCK"));
+
+    market.set_tradeable("STOCK", true);
+    market.sell_at_market("STOCK", 10.0).await.unwrap();
+    assert_eq!(0.0, market.shares_of("STOCK"));
+}
+
+#[tokio::test]
+async fn test_position_drawdown() {
+    let mut market = TestMarket {
+        events: VecDeque::new(),
+        time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        next_time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        market_time: MarketTime::Regular,
+
+        price_histories: [(
+            "STOCK".to_string(),
+            vec![20.0..20.0, 10.0..10.0, 12.0..12.0, 9.0..9.0],
+        )]
+        .into(),
+        price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        price_history_interval: TimeDelta::minutes(1),
+
+        account: SimulatedAccount::new(Money::from_f64(100.0)),
+        ..Default::default()
+    };
+
+    for _ in 0..2 {
+        let _ = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(None, market.position_drawdown("STOCK").await.unwrap());
+
+    // Prices from before the position was opened are not taken into account
+    market.buy_at_market("STOCK", 1.0).await.unwrap();
+    assert_float_eq!(
+        0.0,
+        market.position_drawdown("STOCK").await.unwrap().unwrap(),
+        abs <= 1e-9
+    );
+
+    for _ in 0..2 {
+        let _ = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+
+    assert_float_eq!(
+        12.0,
+        market
+            .position_high_water_mark("STOCK")
+            .await
+            .unwrap()
+            .unwrap(),
+        ulps <= 5
+    );
+    assert_float_eq!(
+        0.25,
+        market.position_drawdown("STOCK").await.unwrap().unwrap(),
+        abs <= 1e-9
+    );
+
+    market.sell_at_market("STOCK", 1.0).await.unwrap();
+    assert_eq!(None, market.position_drawdown("STOCK").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_trading_errors() {
+    let mut market = TestMarket {
+        events: VecDeque::new(),
+        time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        next_time: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        market_time: MarketTime::Regular,
+
+        price_histories: [("STOCK".to_string(), vec![1.0..1.0])].into(),
+        price_history_start: Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        price_history_interval: TimeDelta::minutes(1),
+
+        account: SimulatedAccount::new(Money::from_f64(100.0)),
+        ..Default::default()
+    };
+
+    let _ = market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        market.buy_at_market("STOCK", 101.0).await,
+        Err(Error::Account(AccountError::InsufficientCash {
+            quantity: 101.0,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        market.sell_at_market("STOCK", 1.0).await,
+        Err(Error::Account(AccountError::InsufficientShares {
+            owned: 0.0,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        market.buy_at_market("OTHER", 1.0).await,
+        Err(Error::UnknownPrice(symbol)) if symbol == "OTHER"
+    ));
+
+    market.market_time = MarketTime::NotTrading;
+    assert!(matches!(
+        market.buy_at_market("STOCK", 1.0).await,
+        Err(Error::UntimelyTrade(..))
+    ));
+
+    // Failed orders leave the account untouched
+    assert_float_eq!(100.0, market.cash().to_f64(), ulps <= 5);
+    assert_eq!(0.0, market.shares_of("STOCK"));
+}
+
+#[tokio::test]
+async fn test_limit_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 10.0..10.0, 9.0..9.0, 8.0..8.0, 12.0..12.0],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let buy = market.buy_limit("STOCK", 5.0, 8.5).await.unwrap();
+    assert!(matches!(
+        market.buy_limit("STOCK", 20.0, 8.5).await,
+        Err(Error::Account(AccountError::InsufficientCash {
+            quantity: 20.0,
+            ..
+        }))
+    ));
+
+    // The order rests until the price falls to its limit
+    for minute in 0..4 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_event(
+        Event::OrderFilled {
+            id: buy,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 5.0,
+            price: 8.5,
+        },
+        start + TimeDelta::minutes(3),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_float_eq!(57.5, market.cash().to_f64(), ulps <= 5);
+    assert_eq!(5.0, market.shares_of("STOCK"));
+
+    let sell = market.sell_limit("STOCK", 5.0, 11.0).await.unwrap();
+    assert_event(
+        Event::Tick,
+        start + TimeDelta::minutes(4),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_event(
+        Event::OrderFilled {
+            id: sell,
+            symbol: "STOCK".to_string(),
+            side: Side::Sell,
+            quantity: 5.0,
+            price: 11.0,
+        },
+        start + TimeDelta::minutes(4),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_float_eq!(112.5, market.cash().to_f64(), ulps <= 5);
+
+    // Orders that are already marketable fill right away, at the current price
+    let marketable = market.buy_limit("STOCK", 1.0, 20.0).await.unwrap();
+    assert_float_eq!(100.5, market.cash().to_f64(), ulps <= 5);
+    assert_event(
+        Event::OrderFilled {
+            id: marketable,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 1.0,
+            price: 12.0,
+        },
+        start + TimeDelta::minutes(4),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+}
+
+#[tokio::test]
+async fn test_stop_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 10.0..10.0, 9.2..9.6, 10.0..10.0, 12.0..12.0],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    market.buy_at_market("STOCK", 5.0).await.unwrap();
+    let stop_loss = market.sell_stop("STOCK", 5.0, 9.5).await.unwrap();
+    assert_eq!(5.0, market.shares_of("STOCK"));
+
+    for minute in 0..3 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_event(
+        Event::OrderFilled {
+            id: stop_loss,
+            symbol: "STOCK".to_string(),
+            side: Side::Sell,
+            quantity: 5.0,
+            price: 9.5,
+        },
+        start + TimeDelta::minutes(2),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_float_eq!(97.5, market.cash().to_f64(), ulps <= 5);
+
+    // A price gapping past the stop fills at the worse price
+    let stop = market.buy_stop("STOCK", 5.0, 11.0).await.unwrap();
+    for minute in 3..5 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_event(
+        Event::OrderFilled {
+            id: stop,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 5.0,
+            price: 12.0,
+        },
+        start + TimeDelta::minutes(4),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_float_eq!(37.5, market.cash().to_f64(), ulps <= 5);
+}
+
+#[tokio::test]
+async fn test_stop_limit_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 10.0..10.0, 11.0..12.0, 13.0..13.0, 11.2..11.0],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let id = market
+        .buy_stop_limit("STOCK", 5.0, 11.0, 11.5)
+        .await
+        .unwrap();
+    assert_eq!(
+        OrderState::Untriggered,
+        market.orders.pending_orders()[0].state
+    );
+
+    // Triggered, but the price closed above the limit
+    for minute in 0..3 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_eq!(OrderState::Resting, market.orders.pending_orders()[0].state);
+
+    for minute in 3..5 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_event(
+        Event::OrderFilled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 5.0,
+            price: 11.5,
+        },
+        start + TimeDelta::minutes(4),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert!(market.orders.pending_orders().is_empty());
+    assert_float_eq!(42.5, market.cash().to_f64(), ulps <= 5);
+}
+
+#[tokio::test]
+async fn test_trailing_stop_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 10.0..10.0, 10.0..12.0, 11.5..11.5, 11.5..10.5],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    market.buy_at_market("STOCK", 5.0).await.unwrap();
+    let id = market
+        .sell_trailing_stop("STOCK", 5.0, Trail::Percent(10.0))
+        .await
+        .unwrap();
+    assert_float_eq!(
+        9.0,
+        market.orders.pending_orders()[0].stop_price().unwrap(),
+        ulps <= 5
+    );
+
+    // The stop follows the price up
+    for minute in 0..3 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_float_eq!(
+        10.8,
+        market.orders.pending_orders()[0].stop_price().unwrap(),
+        ulps <= 5
+    );
+
+    for minute in 3..5 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_event(
+        Event::OrderFilled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Sell,
+            quantity: 5.0,
+            price: 10.5,
+        },
+        start + TimeDelta::minutes(4),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_float_eq!(102.5, market.cash().to_f64(), ulps <= 5);
+}
+
+#[tokio::test]
+async fn test_oco_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 10.0..10.0, 10.0..10.0, 12.0..12.0],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    // A take-profit and a stop-loss for the same position
+    market.buy_at_market("STOCK", 5.0).await.unwrap();
+    let take_profit = Order::new(
+        "STOCK",
+        Side::Sell,
+        5.0,
+        OrderKind::Limit { limit_price: 12.0 },
+    );
+    let stop_loss = Order {
+        kind: OrderKind::Stop { stop_price: 9.0 },
+        ..take_profit.clone()
+    };
+    let (group, [take_profit, stop_loss]) =
+        market.submit_oco(take_profit, stop_loss).await.unwrap();
+    assert_eq!(2, market.orders.pending_orders().len());
+
+    for minute in 0..4 {
+        assert_event(
+            Event::Tick,
+            start + TimeDelta::minutes(minute),
+            market.next_event_or_tick(TimeDelta::minutes(1)).await,
+        );
+    }
+    assert_event(
+        Event::OrderFilled {
+            id: take_profit,
+            symbol: "STOCK".to_string(),
+            side: Side::Sell,
+            quantity: 5.0,
+            price: 12.0,
+        },
+        start + TimeDelta::minutes(3),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_event(
+        Event::OrderCanceled {
+            id: stop_loss,
+            symbol: "STOCK".to_string(),
+            side: Side::Sell,
+            quantity: 5.0,
+            reason: CancelReason::OneCancelsOther(group),
+        },
+        start + TimeDelta::minutes(3),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert!(market.orders.pending_orders().is_empty());
+    assert_float_eq!(110.0, market.cash().to_f64(), ulps <= 5);
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 12.0 }),
+        market.order_status(take_profit)
+    );
+    assert_eq!(
+        Some(OrderStatus::Canceled(CancelReason::OneCancelsOther(group))),
+        market.order_status(stop_loss)
+    );
+
+    // An order filling right away cancels the other one before it is placed
+    market.buy_at_market("STOCK", 5.0).await.unwrap();
+    let limit = Order::new(
+        "STOCK",
+        Side::Sell,
+        5.0,
+        OrderKind::Limit { limit_price: 11.0 },
+    );
+    let stop = Order {
+        kind: OrderKind::Stop { stop_price: 9.0 },
+        ..limit.clone()
+    };
+    let (second_group, _) = market.submit_oco(limit, stop).await.unwrap();
+    assert_ne!(group, second_group);
+    assert!(market.orders.pending_orders().is_empty());
+    assert_eq!(0.0, market.shares_of("STOCK"));
+}
+
+#[tokio::test]
+async fn test_time_in_force() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0; 4])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_events([(start + TimeDelta::minutes(2), Event::RegularMarketEnd)].into());
+
+    let order = Order::new(
+        "STOCK",
+        Side::Buy,
+        5.0,
+        OrderKind::Limit { limit_price: 8.0 },
+    );
+    let mut ids = Vec::new();
+    for time_in_force in [
+        TimeInForce::Day,
+        TimeInForce::GoodTillCanceled,
+        TimeInForce::ImmediateOrCancel,
+    ] {
+        let id = market
+            .submit_order(order.clone().with_time_in_force(time_in_force))
+            .await
+            .unwrap();
+        ids.push(id);
+    }
+    assert_eq!(2, market.orders.pending_orders().len());
+
+    let mut events = Vec::new();
+    while market.time() < start + TimeDelta::minutes(3) {
+        let (_, event) = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        if event != Event::Tick {
+            events.push(event);
+        }
+    }
+
+    let canceled = |id, reason| Event::OrderCanceled {
+        id,
+        symbol: "STOCK".to_string(),
+        side: Side::Buy,
+        quantity: 5.0,
+        reason,
+    };
+    assert_eq!(
+        vec![
+            canceled(ids[2], CancelReason::NotFilledImmediately),
+            Event::RegularMarketEnd,
+            canceled(ids[0], CancelReason::Expired),
+        ],
+        events
+    );
+    assert_eq!(1, market.orders.pending_orders().len());
+    assert_eq!(
+        TimeInForce::GoodTillCanceled,
+        market.orders.pending_orders()[0].order.time_in_force
+    );
+}
+
+#[tokio::test]
+async fn test_end_of_day_cancellation() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0; 4])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_events(
+        [
+            (start + TimeDelta::minutes(1), Event::RegularMarketEnd),
+            (start + TimeDelta::minutes(2), Event::PostMarketEnd),
+        ]
+        .into(),
+    )
+    .with_end_of_day_cancellation(true);
+
+    let order = Order::new(
+        "STOCK",
+        Side::Buy,
+        5.0,
+        OrderKind::Limit { limit_price: 8.0 },
+    )
+    .with_extended_hours(true);
+    let day = market
+        .submit_order(order.clone().with_time_in_force(TimeInForce::Day))
+        .await
+        .unwrap();
+    let good_till_canceled = market.submit_order(order).await.unwrap();
+
+    let mut events = Vec::new();
+    while market.time() < start + TimeDelta::minutes(3) {
+        let (_, event) = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        if event != Event::Tick {
+            events.push(event);
+        }
+    }
+
+    let canceled = |id, reason| Event::OrderCanceled {
+        id,
+        symbol: "STOCK".to_string(),
+        side: Side::Buy,
+        quantity: 5.0,
+        reason,
+    };
+    assert_eq!(
+        vec![
+            Event::RegularMarketEnd,
+            canceled(day, CancelReason::Expired),
+            Event::PostMarketEnd,
+            canceled(good_till_canceled, CancelReason::EndOfDay),
+        ],
+        events
+    );
+    assert!(market.orders.pending_orders().is_empty());
+    assert_eq!(
+        Some(OrderStatus::Canceled(CancelReason::EndOfDay)),
+        market.order_status(good_till_canceled)
+    );
+}
+
+#[tokio::test]
+async fn test_order_book_queue() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let depth = SyntheticDepth {
+        tick_size: 0.5,
+        top: 100.0,
+        growth: 0.0,
+    };
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 9.5..9.5, 9.5..9.5, 9.0..9.0],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        1000.0,
+    )
+    .with_order_book(
+        depth,
+        [("STOCK".to_string(), vec![0.0, 60.0, 60.0, 10.0])].into(),
+    );
+
+    // Behind 100 shares at 9.5, of which the first touch trades 60
+    let id = market.buy_limit("STOCK", 30.0, 9.5).await.unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(0.0, market.shares_of("STOCK"));
+
+    // The second works through the rest of the queue, and 20 of the order's
+    // shares
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(20.0, market.shares_of("STOCK"));
+
+    // Trades through the price fill the rest
+    while market.time() < start + TimeDelta::minutes(3) {
+        market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+    assert_eq!(30.0, market.shares_of("STOCK"));
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 9.5 }),
+        market.order_status(id)
+    );
+}
+
+#[tokio::test]
+async fn test_order_status() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 10.0..10.0, 12.0..12.0],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let buy = market.buy_limit("STOCK", 5.0, 11.0).await.unwrap();
+    let stop = market.buy_stop("STOCK", 1.0, 11.5).await.unwrap();
+    assert_ne!(buy, stop);
+
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 10.0 }),
+        market.order_status(buy)
+    );
+    assert_eq!(
+        Some(OrderStatus::Open(OrderState::Untriggered)),
+        market.order_status(stop)
+    );
+    assert_eq!(None, market.order_status(OrderId(100)));
+    assert_eq!(
+        vec![stop],
+        market
+            .open_orders()
+            .into_iter()
+            .map(|pending| pending.id)
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_order() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 8.0..8.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let id = market.buy_limit("STOCK", 5.0, 9.0).await.unwrap();
+    market.cancel_order(id).await.unwrap();
+    assert_eq!(
+        Some(OrderStatus::Canceled(CancelReason::Requested)),
+        market.order_status(id)
+    );
+    assert!(matches!(
+        market.cancel_order(id).await,
+        Err(Error::OrderNotOpen(canceled)) if canceled == id
+    ));
+
+    assert_event(
+        Event::OrderCanceled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 5.0,
+            reason: CancelReason::Requested,
+        },
+        start,
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+
+    // The canceled order does not fill once its price is reached
+    for _ in 0..3 {
+        let (_, event) = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        assert_eq!(Event::Tick, event);
+    }
+    assert_float_eq!(100.0, market.cash().to_f64(), ulps <= 5);
+}
+
+#[tokio::test]
+async fn test_amend_order() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 8.0..8.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let id = market.buy_limit("STOCK", 5.0, 9.0).await.unwrap();
+    assert!(matches!(
+        market
+            .amend_order(
+                id,
+                Amendment {
+                    stop_price: Some(9.5),
+                    ..Default::default()
+                }
+            )
+            .await,
+        Err(Error::InvalidAmendment(invalid)) if invalid == id
+    ));
+
+    // Raising the limit to the current price fills the order right away
+    market
+        .amend_order(
+            id,
+            Amendment {
+                quantity: Some(4.0),
+                limit_price: Some(10.0),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 10.0 }),
+        market.order_status(id)
+    );
+    assert_float_eq!(60.0, market.cash().to_f64(), ulps <= 5);
+
+    assert_event(
+        Event::OrderAmended {
+            id,
+            quantity: 4.0,
+            kind: OrderKind::Limit { limit_price: 10.0 },
+        },
+        start,
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_event(
+        Event::OrderFilled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 4.0,
+            price: 10.0,
+        },
+        start,
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert!(matches!(
+        market.amend_order(id, Amendment::default()).await,
+        Err(Error::OrderNotOpen(filled)) if filled == id
+    ));
+}
+
+#[tokio::test]
+async fn test_rejected_amendment() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let id = market.buy_stop("STOCK", 6.0, 15.0).await.unwrap();
+    let pending = market.orders.pending_orders().to_vec();
+
+    // Affordable at the new stop price, but triggered right away at the
+    // current price, which is not
+    assert!(matches!(
+        market
+            .amend_order(
+                id,
+                Amendment {
+                    quantity: Some(11.0),
+                    stop_price: Some(9.0),
+                    ..Default::default()
+                }
+            )
+            .await,
+        Err(Error::Account(AccountError::InsufficientCash { .. }))
+    ));
+
+    // The order is left as it was, without reporting the amendment
+    assert_eq!(pending, market.orders.pending_orders());
+    assert_eq!(
+        Some(OrderStatus::Open(OrderState::Untriggered)),
+        market.order_status(id)
+    );
+    assert_float_eq!(100.0, market.cash().to_f64(), ulps <= 5);
+    assert_event(
+        Event::Tick,
+        start,
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+}
+
+#[tokio::test]
+async fn test_trade_receipts() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let bought = market.buy_at_market("STOCK", 5.0).await.unwrap();
+    assert_eq!(
+        TradeReceipt {
+            order_id: bought.order_id,
+            symbol: "STOCK".to_string(),
+            quantity: 5.0,
+            fill_price: Some(10.0),
+            fees: Money::ZERO,
+            timestamp: start,
+        },
+        bought
+    );
+
+    // Orders that fill later have no price yet
+    let mut market = market.with_market_fill(MarketFill::NextBarOpen);
+    let queued = market.sell_at_market("STOCK", 5.0).await.unwrap();
+    assert_eq!(5.0, queued.quantity);
+    assert_eq!(None, queued.fill_price);
+}
+
+#[tokio::test]
+async fn test_notional_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        105.0,
+    );
+
+    // Whole shares by default
+    let bought = market
+        .buy_notional("STOCK", Money::from_f64(105.0))
+        .await
+        .unwrap();
+    assert_eq!(10.0, bought.quantity);
+    assert_float_eq!(5.0, market.cash().to_f64(), abs <= 1e-9);
+    let sold = market
+        .sell_notional("STOCK", Money::from_f64(55.0))
+        .await
+        .unwrap();
+    assert_eq!(5.0, sold.quantity);
+    assert_float_eq!(55.0, market.cash().to_f64(), abs <= 1e-9);
+
+    // Fractional shares invest all of the cash
+    let mut market = market.with_lot_size(0.001);
+    market
+        .buy_notional("STOCK", Money::from_f64(55.0))
+        .await
+        .unwrap();
+    assert_float_eq!(10.5, market.shares_of("STOCK"), abs <= 1e-9);
+    assert_float_eq!(0.0, market.cash().to_f64(), abs <= 1e-9);
+
+    let sold = market
+        .sell_notional("STOCK", Money::from_f64(52.5))
+        .await
+        .unwrap();
+    assert_float_eq!(5.25, sold.quantity, abs <= 1e-9);
+    assert_float_eq!(52.5, market.cash().to_f64(), abs <= 1e-9);
+}
+
+#[tokio::test]
+async fn test_margin_interest() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_margin(Margin {
+        limit: Money::from_f64(100.0),
+        annual_rate: 0.36,
+    })
+    .with_events(
+        [
+            (start + TimeDelta::hours(1), Event::RegularMarketEnd),
+            (start + TimeDelta::hours(2), Event::PostMarketEnd),
+        ]
+        .into(),
+    );
+
+    market.buy_at_market("STOCK", 15.0).await.unwrap();
+    assert_float_eq!(-50.0, market.cash().to_f64(), abs <= 1e-9);
+
+    // Charged once the day ended
+    market.next_event().await.unwrap();
+    assert_float_eq!(-50.0, market.cash().to_f64(), abs <= 1e-9);
+    market.next_event().await.unwrap();
+    assert_float_eq!(-50.05, market.cash().to_f64(), abs <= 1e-9);
+}
+
+#[tokio::test]
+async fn test_funding_payments() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let funding = |symbol: &str, rate: f64| Event::FundingPayment {
+        symbol: symbol.to_string(),
+        rate,
+        amount: Money::ZERO,
+    };
+    let mut market = TestMarket::new(
+        start,
+        [
+            ("BTC-PERP".to_string(), vec![100.0..100.0, 120.0..120.0]),
+            ("ETH-PERP".to_string(), vec![10.0..10.0, 10.0..10.0]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        1000.0,
+    )
+    .with_events(
+        [
+            (start + TimeDelta::minutes(1), funding("BTC-PERP", 0.001)),
+            (start + TimeDelta::minutes(1), funding("ETH-PERP", 0.001)),
+            (start + TimeDelta::minutes(1), funding("BTC-PERP", -0.002)),
+        ]
+        .into(),
+    );
+    market.buy_at_market("BTC-PERP", 5.0).await.unwrap();
+
+    // Paid on the value of the position at the funding time
+    assert_eq!(
+        Some((
+            start + TimeDelta::minutes(1),
+            Event::FundingPayment {
+                symbol: "BTC-PERP".to_string(),
+                rate: 0.001,
+                amount: Money::from_f64(-0.6),
+            }
+        )),
+        market.next_event().await.unwrap()
+    );
+    assert_float_eq!(499.4, market.cash().to_f64(), abs <= 1e-9);
+
+    // Nothing is paid without a position, and negative rates are received
+    market.next_event().await.unwrap();
+    assert_float_eq!(499.4, market.cash().to_f64(), abs <= 1e-9);
+    market.next_event().await.unwrap();
+    assert_float_eq!(500.6, market.cash().to_f64(), abs <= 1e-9);
+}
+
+#[tokio::test]
+async fn test_positions() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 12.0..12.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    market.buy_at_market("STOCK", 2.0).await.unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    market.buy_at_market("STOCK", 2.0).await.unwrap();
+    market.sell_at_market("STOCK", 1.0).await.unwrap();
+
+    let position = market.position("STOCK").unwrap();
+    assert_eq!(3.0, position.quantity);
+    assert_float_eq!(11.0, position.avg_cost(), ulps <= 5);
+    assert_eq!(start, position.opened_at);
+    assert_float_eq!(3.0, position.unrealized_pnl(12.0).to_f64(), ulps <= 5);
+    assert_float_eq!(
+        3.0,
+        market.unrealized_pnl("STOCK").await.unwrap().to_f64(),
+        ulps <= 5
+    );
+    assert_float_eq!(
+        3.0,
+        market.total_unrealized_pnl().await.unwrap().to_f64(),
+        ulps <= 5
+    );
+    assert_float_eq!(1.0, market.realized_pnl().to_f64(), ulps <= 5);
+
+    market.sell_at_market("STOCK", 3.0).await.unwrap();
+    assert_eq!(None, market.position("STOCK"));
+    assert_eq!(
+        Money::from_f64(0.0),
+        market.total_unrealized_pnl().await.unwrap()
+    );
+    assert_float_eq!(4.0, market.realized_pnl().to_f64(), ulps <= 5);
+
+    let transactions = market.transactions();
+    assert_eq!(4, transactions.len());
+    assert_eq!(
+        vec![
+            (Side::Buy, 2.0, 10.0),
+            (Side::Buy, 2.0, 12.0),
+            (Side::Sell, 1.0, 12.0),
+            (Side::Sell, 3.0, 12.0)
+        ],
+        transactions
+            .iter()
+            .map(|transaction| (transaction.side, transaction.quantity, transaction.price))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(start, transactions[0].time);
+    assert!(transactions
+        .iter()
+        .all(|transaction| transaction.symbol == "STOCK" && transaction.fees == Money::ZERO));
+}
+
+#[tokio::test]
+async fn test_advance_to() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let earnings = Event::Earnings {
+        symbol: "STOCK".to_string(),
+    };
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 8.0..8.0, 8.0..8.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_events([(start + TimeDelta::seconds(30), earnings.clone())].into());
+    let id = market
+        .submit_order(Order::new(
+            "STOCK",
+            Side::Buy,
+            5.0,
+            OrderKind::Limit { limit_price: 9.0 },
+        ))
+        .await
+        .unwrap();
+
+    let arrival = start + TimeDelta::seconds(90);
+    assert_eq!(
+        vec![(start + TimeDelta::seconds(30), earnings)],
+        market.advance_to(arrival).await.unwrap()
+    );
+    assert_eq!(arrival, market.time());
+
+    // Filled upon arrival, and reported without advancing any further
+    assert!(matches!(
+        market.order_status(id),
+        Some(OrderStatus::Filled { .. })
+    ));
+    assert_eq!(
+        vec![(
+            arrival,
+            Event::OrderFilled {
+                id,
+                symbol: "STOCK".to_string(),
+                side: Side::Buy,
+                quantity: 5.0,
+                price: 9.0,
+            }
+        )],
+        market.advance_to(arrival).await.unwrap()
+    );
+    assert!(market.advance_to(start).await.unwrap().is_empty());
+    assert_eq!(arrival, market.time());
+}
+
+#[tokio::test]
+async fn test_event_masks() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0; 2])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_events(
+        [
+            (
+                start + TimeDelta::seconds(10),
+                Event::Earnings {
+                    symbol: "STOCK".to_string(),
+                },
+            ),
+            (start + TimeDelta::seconds(20), Event::RegularMarketEnd),
+            (start + TimeDelta::seconds(30), Event::PostMarketEnd),
+        ]
+        .into(),
+    );
+
+    // Past the earnings report
+    assert_eq!(
+        Some((start + TimeDelta::seconds(20), Event::RegularMarketEnd)),
+        market
+            .next_event_matching(EventMask::SESSIONS)
+            .await
+            .unwrap()
+    );
+
+    // Skipped events still take effect
+    assert_eq!(
+        None,
+        market.next_event_matching(EventMask::FILLS).await.unwrap()
+    );
+    assert_eq!(MarketTime::NotTrading, market.market_time());
+    assert_eq!(start + TimeDelta::seconds(30), market.time());
+}
+
+#[tokio::test]
+async fn test_next_bar_open_fills() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 12.0..11.0, 9.0..9.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_market_fill(MarketFill::NextBarOpen);
+
+    // Queued instead of filled at the current candle's price
+    let id = market.buy_at_market("STOCK", 5.0).await.unwrap().order_id;
+    assert_eq!(0.0, market.shares_of("STOCK"));
+    assert_float_eq!(100.0, market.cash().to_f64(), ulps <= 5);
+    assert_eq!(
+        Some(OrderStatus::Open(OrderState::Resting)),
+        market.order_status(id)
+    );
+
+    assert_event(
+        Event::Tick,
+        start,
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_eq!(0.0, market.shares_of("STOCK"));
+
+    assert_event(
+        Event::Tick,
+        start + TimeDelta::minutes(1),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+    assert_eq!(5.0, market.shares_of("STOCK"));
+    assert_float_eq!(40.0, market.cash().to_f64(), ulps <= 5);
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 12.0 }),
+        market.order_status(id)
+    );
+    assert_event(
+        Event::OrderFilled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 5.0,
+            price: 12.0,
+        },
+        start + TimeDelta::minutes(1),
+        market.next_event_or_tick(TimeDelta::minutes(1)).await,
+    );
+}
+
+#[tokio::test]
+async fn test_volume_limited_market_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 12.0..12.0, 9.0..9.0])].into(),
+        TimeDelta::minutes(1),
+        200.0,
+    )
+    .with_volume_limit(
+        VolumeLimit {
+            fraction: 0.5,
+            remainder: Remainder::RollOver,
+        },
+        [("STOCK".to_string(), vec![10.0, 10.0, 100.0])].into(),
+    );
+
+    let id = market.buy_at_market("STOCK", 12.0).await.unwrap().order_id;
+    assert_eq!(5.0, market.shares_of("STOCK"));
+
+    let partial_fill = |quantity, remaining, price| Event::OrderPartiallyFilled {
+        id,
+        symbol: "STOCK".to_string(),
+        side: Side::Buy,
+        quantity,
+        remaining,
+        price,
+    };
+    let tick = TimeDelta::minutes(1);
+    assert_event(
+        partial_fill(5.0, 7.0, 10.0),
+        start,
+        market.next_event_or_tick(tick).await,
+    );
+    assert_event(Event::Tick, start, market.next_event_or_tick(tick).await);
+
+    // The rest rolls over to the following candles, filling at their opens
+    assert_event(
+        Event::Tick,
+        start + tick,
+        market.next_event_or_tick(tick).await,
+    );
+    assert_event(
+        partial_fill(5.0, 2.0, 12.0),
+        start + tick,
+        market.next_event_or_tick(tick).await,
+    );
+    assert_event(
+        Event::Tick,
+        start + tick * 2,
+        market.next_event_or_tick(tick).await,
+    );
+    assert_event(
+        Event::OrderFilled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 2.0,
+            price: 9.0,
+        },
+        start + tick * 2,
+        market.next_event_or_tick(tick).await,
+    );
+
+    assert_eq!(12.0, market.shares_of("STOCK"));
+    assert_float_eq!(72.0, market.cash().to_f64(), ulps <= 5);
+}
+
+#[tokio::test]
+async fn test_volume_limit_in_fractional_lots() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_volume_limit(
+        VolumeLimit {
+            fraction: 0.5,
+            remainder: Remainder::Cancel,
+        },
+        [("STOCK".to_string(), vec![3.0, 3.0])].into(),
+    )
+    .with_lot_size(0.001);
+
+    // Half of the volume, rather than the whole shares in it
+    market.buy_at_market("STOCK", 2.0).await.unwrap();
+    assert_float_eq!(1.5, market.shares_of("STOCK"), abs <= 1e-9);
+}
+
+#[tokio::test]
+async fn test_volume_limited_fills_in_fractional_lots() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let tick = TimeDelta::minutes(1);
+    // A tenth of a share a bar
+    let market = || {
+        TestMarket::new(
+            start,
+            [("STOCK".to_string(), vec![10.0..10.0; 12])].into(),
+            tick,
+            100.0,
+        )
+        .with_volume_limit(
+            VolumeLimit {
+                fraction: 0.1,
+                remainder: Remainder::RollOver,
+            },
+            [("STOCK".to_string(), vec![1.0; 12])].into(),
+        )
+        .with_lot_size(0.1)
+    };
+    // The fills of an order over the bars, until no more are reported
+    let fills = |mut market: TestMarket| async move {
+        let mut fills = Vec::new();
+        while market.time() < start + tick * 11 {
+            match market.next_event_or_tick(tick).await.unwrap().1 {
+                Event::OrderPartiallyFilled { quantity, .. } => fills.push((quantity, false)),
+                Event::OrderFilled { quantity, .. } => fills.push((quantity, true)),
+                _ => {}
+            }
+        }
+        (fills, market)
+    };
+
+    // The rounding error of adding up ten tenths is not left to fill
+    let mut queued = market();
+    let queued_id = queued.buy_at_market("STOCK", 1.0).await.unwrap().order_id;
+    let mut resting = market();
+    let resting_id = resting.buy_limit("STOCK", 1.0, 10.0).await.unwrap();
+    for (market, id) in [(queued, queued_id), (resting, resting_id)] {
+        let (fills, market) = fills(market).await;
+        let mut expected = vec![(0.1, false); 9];
+        expected.push((0.1, true));
+        assert_eq!(expected.len(), fills.len());
+        for ((quantity, done), (expected_quantity, expected_done)) in fills.iter().zip(expected) {
+            assert_float_eq!(expected_quantity, *quantity, abs <= 1e-9);
+            assert_eq!(expected_done, *done);
+        }
+        assert_eq!(
+            Some(OrderStatus::Filled { price: 10.0 }),
+            market.order_status(id)
+        );
+        assert_float_eq!(1.0, market.shares_of("STOCK"), abs <= 1e-9);
+    }
+}
+
+#[tokio::test]
+async fn test_unaffordable_queued_market_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 20.0..20.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_market_fill(MarketFill::NextBarOpen);
+
+    // Affordable at the current price, but not at the next bar's open
+    let id = market.buy_at_market("STOCK", 10.0).await.unwrap().order_id;
+
+    let tick = TimeDelta::minutes(1);
+    assert_event(Event::Tick, start, market.next_event_or_tick(tick).await);
+    assert_event(
+        Event::Tick,
+        start + tick,
+        market.next_event_or_tick(tick).await,
+    );
+    assert_event(
+        Event::OrderCanceled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 10.0,
+            reason: CancelReason::Rejected,
+        },
+        start + tick,
+        market.next_event_or_tick(tick).await,
+    );
+
+    assert_eq!(
+        Some(OrderStatus::Canceled(CancelReason::Rejected)),
+        market.order_status(id)
+    );
+    assert_eq!(0.0, market.shares_of("STOCK"));
+    assert_float_eq!(100.0, market.cash().to_f64(), ulps <= 5);
+}
+
+#[tokio::test]
+async fn test_volume_limited_resting_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 8.0..8.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_volume_limit(
+        VolumeLimit {
+            fraction: 0.5,
+            remainder: Remainder::Cancel,
+        },
+        [("STOCK".to_string(), vec![100.0, 8.0])].into(),
+    );
+
+    let id = market.buy_limit("STOCK", 10.0, 9.0).await.unwrap();
+
+    let tick = TimeDelta::minutes(1);
+    assert_event(Event::Tick, start, market.next_event_or_tick(tick).await);
+    assert_event(
+        Event::Tick,
+        start + tick,
+        market.next_event_or_tick(tick).await,
+    );
+    assert_event(
+        Event::OrderPartiallyFilled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 4.0,
+            remaining: 6.0,
+            price: 9.0,
+        },
+        start + tick,
+        market.next_event_or_tick(tick).await,
+    );
+    assert_event(
+        Event::OrderCanceled {
+            id,
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 6.0,
+            reason: CancelReason::VolumeLimit,
+        },
+        start + tick,
+        market.next_event_or_tick(tick).await,
+    );
+
+    assert_eq!(4.0, market.shares_of("STOCK"));
+    assert_eq!(
+        Some(OrderStatus::Canceled(CancelReason::VolumeLimit)),
+        market.order_status(id)
+    );
+}
+
+#[tokio::test]
+async fn test_price_impact() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        1000.0,
+    )
+    .with_price_impact(
+        PriceImpact {
+            threshold: 0.1,
+            coefficient: 0.5,
+            curve: ImpactCurve::Linear,
+        },
+        [("STOCK".to_string(), vec![100.0])].into(),
+    );
+
+    // Fills up to the threshold are not impacted
+    market.buy_at_market("STOCK", 10.0).await.unwrap();
+    assert_float_eq!(900.0, market.cash().to_f64(), ulps <= 5);
+
+    // 40% of the volume moves the price by 20%, against the order
+    let id = market.buy_at_market("STOCK", 40.0).await.unwrap().order_id;
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 12.0 }),
+        market.order_status(id)
+    );
+    market.sell_at_market("STOCK", 40.0).await.unwrap();
+    assert_float_eq!(740.0, market.cash().to_f64(), ulps <= 5);
+
+    // Limit orders never fill beyond their limit price
+    let id = market.buy_limit("STOCK", 40.0, 11.0).await.unwrap();
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 11.0 }),
+        market.order_status(id)
+    );
+
+    let square_root = PriceImpact {
+        threshold: 0.1,
+        coefficient: 0.5,
+        curve: ImpactCurve::SquareRoot,
+    };
+    assert_float_eq!(
+        12.0,
+        square_root.apply(10.0, Side::Buy, 16.0, Some(100.0)),
+        ulps <= 5
+    );
+    assert_float_eq!(
+        10.0,
+        square_root.apply(10.0, Side::Sell, 16.0, None),
+        ulps <= 5
+    );
+}
+
+#[tokio::test]
+async fn test_session_policy() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let new_market = || {
+        let mut market = TestMarket::new(
+            start,
+            [("STOCK".to_string(), vec![10.0..10.0])].into(),
+            TimeDelta::minutes(1),
+            100.0,
+        );
+        market.market_time = MarketTime::PreMarket;
+        market
+    };
+    let extended_order = || {
+        Order::new(
+            "STOCK",
+            Side::Buy,
+            1.0,
+            OrderKind::Limit { limit_price: 11.0 },
+        )
+        .with_extended_hours(true)
+    };
+
+    // By default, only orders that allow extended hours trade outside of
+    // the regular session
+    let mut market = new_market();
+    assert!(matches!(
+        market.buy_at_market("STOCK", 1.0).await,
+        Err(Error::OutsideSession(_, MarketTime::PreMarket))
+    ));
+    assert!(matches!(
+        market.buy_limit("STOCK", 1.0, 11.0).await,
+        Err(Error::OutsideSession(..))
+    ));
+    let id = market.submit_order(extended_order()).await.unwrap();
+    assert_eq!(
+        Some(OrderStatus::Filled { price: 10.0 }),
+        market.order_status(id)
+    );
+
+    let mut market = new_market().with_session_policy(SessionPolicy::RegularOnly);
+    assert!(matches!(
+        market.submit_order(extended_order()).await,
+        Err(Error::OutsideSession(..))
+    ));
+
+    let mut market = new_market().with_session_policy(SessionPolicy::All);
+    market.buy_at_market("STOCK", 1.0).await.unwrap();
+    assert_eq!(1.0, market.shares_of("STOCK"));
+}
+
+#[tokio::test]
+async fn test_execution_latency() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let tick = TimeDelta::minutes(1);
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 12.0..12.0, 14.0..14.0],
+        )]
+        .into(),
+        tick,
+        100.0,
+    )
+    .with_latency(Latency::Fixed(TimeDelta::seconds(90)), 0);
+
+    let id = market.buy_at_market("STOCK", 5.0).await.unwrap().order_id;
+    assert_eq!(0.0, market.shares_of("STOCK"));
+    assert_eq!(
+        Some(OrderStatus::Open(OrderState::Resting)),
+        market.order_status(id)
+    );
+
+    // The order fills once its latency passed, at the price of the time it
+    // executed at
+    let mut fill = None;
+    while fill.is_none() {
+        let (time, event) = market.next_event_or_tick(tick).await.unwrap();
+        if let Event::OrderFilled { price, .. } = event {
+            fill = Some((time, price));
+        }
+    }
+    assert_eq!(Some((start + tick * 2, 12.0)), fill);
+    assert_float_eq!(40.0, market.cash().to_f64(), ulps <= 5);
+
+    let latency = Latency::Random {
+        min: TimeDelta::seconds(1),
+        max: TimeDelta::seconds(2),
+    };
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100 {
+        let delay = latency.sample(&mut rng);
+        assert!(delay >= TimeDelta::seconds(1) && delay <= TimeDelta::seconds(2));
+    }
+
+    // Random delays are reproducible from their seed
+    let latency = Latency::Random {
+        min: TimeDelta::zero(),
+        max: tick * 2,
+    };
+    let executions = |seed| async move {
+        let mut market = TestMarket::new(
+            start,
+            [("STOCK".to_string(), vec![10.0..10.0; 20])].into(),
+            tick,
+            100.0,
+        )
+        .with_latency(latency, seed);
+        let mut executions = Vec::new();
+        for minute in 0..5 {
+            market.advance_to(start + tick * minute).await.unwrap();
+            let id = market.buy_at_market("STOCK", 1.0).await.unwrap().order_id;
+            executions.push(
+                market
+                    .orders
+                    .queued_market_orders(market.market_time)
+                    .iter()
+                    .find(|queued| queued.id == id)
+                    .and_then(|queued| queued.executes_at),
+            );
+        }
+        executions
+    };
+    assert_eq!(executions(7).await, executions(7).await);
+    assert_ne!(executions(7).await, executions(8).await);
+}
+
+#[tokio::test]
+async fn test_tax_lots() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 12.0..12.0, 14.0..14.0, 15.0..15.0],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_lot_selection(LotSelection::Lifo);
+    for minute in 1..=3 {
+        market.buy_at_market("STOCK", 1.0).await.unwrap();
+        market
+            .advance_to(start + TimeDelta::minutes(minute))
+            .await
+            .unwrap();
+    }
+
+    let lots = market.lots("STOCK").to_vec();
+    assert_eq!(
+        vec![10.0, 12.0, 14.0],
+        lots.iter().map(|lot| lot.cost).collect::<Vec<_>>()
+    );
+    market.designate_lots("STOCK", &[lots[0].id]);
+    market.sell_at_market("STOCK", 2.0).await.unwrap();
+
+    // The designated lot, then the newest
+    let gains: Vec<_> = market
+        .closed_lots()
+        .iter()
+        .map(|lot| (lot.lot, lot.realized_gain().to_f64()))
+        .collect();
+    assert_eq!(vec![(lots[0].id, 5.0), (lots[2].id, 1.0)], gains);
+    assert_eq!(vec![lots[1]], market.lots("STOCK"));
+}
+
+#[tokio::test]
+async fn test_day_trade_limit() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_day_trade_limit(DayTradeLimit {
+        max_day_trades: 1,
+        ..Default::default()
+    });
+
+    market.buy_at_market("STOCK", 2.0).await.unwrap();
+    market.sell_at_market("STOCK", 1.0).await.unwrap();
+    assert!(matches!(
+        market.sell_at_market("STOCK", 1.0).await,
+        Err(Error::Account(AccountError::DayTradeLimit { .. }))
+    ));
+    assert_eq!(1.0, market.shares_of("STOCK"));
+}
+
+#[tokio::test]
+async fn test_day_trade_limit_at_current_prices() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [
+            ("OTHER".to_string(), vec![10.0..10.0, 4000.0..4000.0]),
+            ("STOCK".to_string(), vec![10.0..10.0, 10.0..10.0]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_day_trade_limit(DayTradeLimit {
+        max_day_trades: 1,
+        ..Default::default()
+    });
+
+    market.buy_at_market("OTHER", 8.0).await.unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    market
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+
+    // Day trades are not limited while the account is worth enough at the
+    // current prices, even if not at cost
+    market.buy_at_market("STOCK", 2.0).await.unwrap();
+    market.sell_at_market("STOCK", 1.0).await.unwrap();
+    market.sell_at_market("STOCK", 1.0).await.unwrap();
+    assert_eq!(0.0, market.shares_of("STOCK"));
+}
+
+#[tokio::test]
+async fn test_settlement_delay() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 15, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 10.0..10.0])].into(),
+        TimeDelta::days(1),
+        100.0,
+    )
+    .with_settlement_delay(1);
+
+    market.buy_at_market("STOCK", 10.0).await.unwrap();
+    market.sell_at_market("STOCK", 10.0).await.unwrap();
+    assert_eq!(Money::from_f64(100.0), market.cash());
+    assert_eq!(Money::from_f64(0.0), market.settled_cash());
+    assert!(matches!(
+        market.buy_at_market("STOCK", 1.0).await,
+        Err(Error::Account(AccountError::UnsettledFunds { .. }))
+    ));
+
+    market.advance_to(start + TimeDelta::days(1)).await.unwrap();
+    assert_eq!(Money::from_f64(100.0), market.settled_cash());
+    market.buy_at_market("STOCK", 1.0).await.unwrap();
 }
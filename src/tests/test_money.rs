@@ -0,0 +1,42 @@
+use chrono::{TimeZone, Utc};
+
+use crate::{account::SimulatedAccount, money::Money, order::Side};
+
+#[test]
+fn test_exact_sums() {
+    let floats: f64 = std::iter::repeat_n(0.1, 10_000).sum();
+    assert_ne!(1000.0, floats);
+
+    let money: Money = std::iter::repeat_n(Money::from_f64(0.1), 10_000).sum();
+    assert_eq!(1000.0, money.to_f64());
+    assert_eq!(Money::ZERO, money - Money::from_f64(1000.0));
+    assert_eq!(Money::from_f64(-1000.0), -money);
+}
+
+#[test]
+fn test_rounding() {
+    assert_eq!(Money::from_f64(12.35), Money::from_f64(12.345).round_to(2));
+    assert_eq!(
+        Money::from_f64(-12.35),
+        Money::from_f64(-12.345).round_to(2)
+    );
+    assert_eq!(Money::from_f64(12.0), Money::from_f64(12.345).round_to(0));
+    assert_eq!("12.5", Money::from_f64(12.5).to_string());
+}
+
+#[test]
+fn test_account_does_not_drift() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut account = SimulatedAccount::new(Money::from_f64(5000.0));
+
+    for _ in 0..10_000 {
+        account.fill("STOCK", Side::Buy, 3.0, 0.1, start).unwrap();
+        account.credit(Money::from_f64(0.01));
+    }
+    account
+        .fill("STOCK", Side::Sell, 30_000.0, 0.1, start)
+        .unwrap();
+
+    assert_eq!(Money::from_f64(5100.0), account.cash());
+    assert_eq!(Money::ZERO, account.realized_pnl());
+}
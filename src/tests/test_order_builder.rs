@@ -0,0 +1,89 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{
+    ext::MarketExt,
+    market::Market,
+    order::{Order, OrderKind, Side, TimeInForce, Trail},
+    order_builder::{MarketOrder, OrderBuilder},
+};
+
+#[test]
+fn test_resting_orders() {
+    assert_eq!(
+        Order::new(
+            "STOCK",
+            Side::Buy,
+            5.0,
+            OrderKind::Limit { limit_price: 10.0 }
+        )
+        .with_time_in_force(TimeInForce::Day),
+        OrderBuilder::limit(10.0)
+            .buy("STOCK", 5.0)
+            .tif(TimeInForce::Day)
+            .build()
+    );
+
+    // The options may come before the side
+    assert_eq!(
+        Order::new(
+            "STOCK",
+            Side::Sell,
+            2.5,
+            OrderKind::StopLimit {
+                stop_price: 9.0,
+                limit_price: 8.5,
+            }
+        )
+        .with_extended_hours(true),
+        OrderBuilder::stop_limit(9.0, 8.5)
+            .extended_hours()
+            .sell("STOCK", 2.5)
+            .build()
+    );
+
+    assert_eq!(
+        Order::new(
+            "STOCK",
+            Side::Sell,
+            1.0,
+            OrderKind::TrailingStop {
+                trail: Trail::Percent(10.0)
+            }
+        ),
+        OrderBuilder::trailing_stop(Trail::Percent(10.0))
+            .sell("STOCK", 1.0)
+            .build()
+    );
+}
+
+#[tokio::test]
+async fn test_market_orders() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+
+    let order = OrderBuilder::market().buy("STOCK", 3.0).build();
+    assert_eq!(
+        MarketOrder {
+            symbol: "STOCK".to_string(),
+            side: Side::Buy,
+            quantity: 3.0,
+        },
+        order
+    );
+
+    let receipt = market.submit_market_order(order).await.unwrap();
+    assert_eq!(3.0, receipt.quantity);
+    assert_eq!(3.0, market.shares_of("STOCK"));
+
+    market
+        .submit_market_order(OrderBuilder::market().sell("STOCK", 1.0).build())
+        .await
+        .unwrap();
+    assert_eq!(2.0, market.shares_of("STOCK"));
+}
@@ -0,0 +1,81 @@
+use chrono::TimeDelta;
+use float_eq::assert_float_eq;
+
+use crate::pricing::{years, ExerciseStyle, OptionKind, OptionParameters};
+
+fn at_the_money(kind: OptionKind) -> OptionParameters {
+    OptionParameters {
+        kind,
+        spot: 100.0,
+        strike: 100.0,
+        time_to_expiry: 1.0,
+        rate: 0.05,
+        volatility: 0.2,
+    }
+}
+
+#[test]
+fn test_black_scholes() {
+    assert_float_eq!(
+        10.4506,
+        at_the_money(OptionKind::Call).black_scholes(),
+        abs <= 1e-4
+    );
+    assert_float_eq!(
+        5.5735,
+        at_the_money(OptionKind::Put).black_scholes(),
+        abs <= 1e-4
+    );
+
+    // At expiry, options are worth their intrinsic value
+    let expired = OptionParameters {
+        spot: 110.0,
+        time_to_expiry: 0.0,
+        ..at_the_money(OptionKind::Call)
+    };
+    assert_eq!(10.0, expired.black_scholes());
+}
+
+#[test]
+fn test_binomial() {
+    let call = at_the_money(OptionKind::Call);
+    let put = at_the_money(OptionKind::Put);
+
+    // The tree converges to Black-Scholes for European options
+    assert_float_eq!(
+        call.black_scholes(),
+        call.binomial(ExerciseStyle::European, 1000),
+        abs <= 1e-2
+    );
+    assert_float_eq!(
+        put.black_scholes(),
+        put.binomial(ExerciseStyle::European, 1000),
+        abs <= 1e-2
+    );
+
+    // Without dividends, early exercise is only worth something for puts
+    assert_float_eq!(
+        call.binomial(ExerciseStyle::European, 200),
+        call.binomial(ExerciseStyle::American, 200),
+        abs <= 1e-9
+    );
+    assert!(
+        put.binomial(ExerciseStyle::American, 200)
+            > put.binomial(ExerciseStyle::European, 200) + 0.1
+    );
+}
+
+#[test]
+fn test_implied_volatility() {
+    let call = at_the_money(OptionKind::Call);
+
+    assert_float_eq!(
+        0.2,
+        call.implied_volatility(call.black_scholes()).unwrap(),
+        abs <= 1e-6
+    );
+    // Below the discounted intrinsic value
+    assert_eq!(None, call.implied_volatility(1.0));
+
+    assert_float_eq!(0.5, years(TimeDelta::days(365) / 2), abs <= 1e-12);
+}
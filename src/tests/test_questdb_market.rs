@@ -0,0 +1,126 @@
+use crate::questdb_market::{asof_query, bound_tables, Error};
+
+/// A table bounded to the time parameter `$2`, as `bound_tables` writes it
+fn bounded(table: &str) -> String {
+    format!("(SELECT * FROM {table} WHERE timestamp <= $2::TIMESTAMP)")
+}
+
+#[test]
+fn test_bound_aggregates() {
+    // Without bounding `prices` itself, the maximum would include the
+    // highs after the current time
+    assert_eq!(
+        format!(
+            "SELECT symbol, max(high) high FROM {} prices WHERE symbol = $1 SAMPLE BY 1d",
+            bounded("prices")
+        ),
+        bound_tables(
+            "SELECT symbol, max(high) high FROM prices WHERE symbol = $1 SAMPLE BY 1d;",
+            2
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        format!(
+            "SELECT * FROM {} prices LATEST ON timestamp PARTITION BY symbol",
+            bounded("prices")
+        ),
+        bound_tables(
+            "SELECT * FROM prices LATEST ON timestamp PARTITION BY symbol",
+            2
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_bound_joins_and_subqueries() {
+    assert_eq!(
+        format!(
+            "SELECT * FROM {} p ASOF JOIN {} AS s ON (symbol)",
+            bounded("prices"),
+            bounded("signals")
+        ),
+        bound_tables(
+            "SELECT * FROM prices p ASOF JOIN signals AS s ON (symbol)",
+            2
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        format!(
+            "SELECT * FROM {} a, {} \"b\"",
+            bounded("prices"),
+            bounded("\"signals\"")
+        ),
+        bound_tables("SELECT * FROM prices a, \"signals\" \"b\"", 2).unwrap()
+    );
+    assert_eq!(
+        format!(
+            "SELECT avg(close) OVER (ORDER BY timestamp) FROM (SELECT * FROM {} prices)",
+            bounded("prices")
+        ),
+        bound_tables(
+            "SELECT avg(close) OVER (ORDER BY timestamp) FROM (SELECT * FROM prices)",
+            2
+        )
+        .unwrap()
+    );
+
+    // Common table expressions are bounded by the tables they read
+    assert_eq!(
+        format!(
+            "WITH daily AS (SELECT * FROM {} prices) SELECT * FROM daily",
+            bounded("prices")
+        ),
+        bound_tables(
+            "WITH daily AS (SELECT * FROM prices) SELECT * FROM daily",
+            2
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_bound_ignores_literals_and_functions() {
+    assert_eq!(
+        format!(
+            "SELECT extract(year FROM timestamp), 'FROM later' FROM {} prices -- FROM later",
+            bounded("prices")
+        ),
+        bound_tables(
+            "SELECT extract(year FROM timestamp), 'FROM later' FROM prices -- FROM later",
+            2
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_unbounded_queries() {
+    for sql in [
+        "SELECT * FROM long_sequence(10)",
+        "SELECT * FROM",
+        "SELECT * FROM 'prices.csv'",
+    ] {
+        assert!(
+            matches!(bound_tables(sql, 1), Err(Error::UnboundedQuery(_))),
+            "{sql}"
+        );
+    }
+}
+
+#[test]
+fn test_asof_query() {
+    assert_eq!(
+        "SELECT * FROM signals WHERE timestamp <= $1::TIMESTAMP AND symbol = $2::TEXT \
+         LATEST ON timestamp PARTITION BY symbol;",
+        asof_query("signals").unwrap()
+    );
+    for table in ["", "signals; DROP TABLE prices", "\"signals\""] {
+        assert!(matches!(
+            asof_query(table),
+            Err(Error::InvalidTableName(invalid)) if invalid == table
+        ));
+    }
+}
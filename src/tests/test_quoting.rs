@@ -0,0 +1,72 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    market::{Event, Market},
+    money::Money,
+    order::OrderStatus,
+    quoting::Quoter,
+};
+
+#[test]
+fn test_skewed_prices() {
+    let quoter = Quoter::new("STOCK", 5.0, 0.1).with_inventory_skew(0.01);
+    let (bid, ask) = quoter.prices(10.0);
+    assert_float_eq!(9.9, bid, abs <= 1e-9);
+    assert_float_eq!(10.1, ask, abs <= 1e-9);
+}
+
+#[tokio::test]
+async fn test_market_making() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![10.0..10.0, 9.85..9.85, 9.85..9.85, 10.3..10.3],
+        )]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    let mut quoter = Quoter::new("STOCK", 5.0, 0.05).with_max_inventory(5.0);
+
+    // Only a bid without inventory to offer
+    quoter.refresh(&mut market).await.unwrap();
+    let (bid, ask) = quoter.quotes();
+    assert!(bid.is_some());
+    assert_eq!(None, ask);
+
+    while market.time() < start + TimeDelta::minutes(3) {
+        let (_, event) = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        quoter.on_event(&event);
+        if event == Event::Tick {
+            quoter.refresh(&mut market).await.unwrap();
+        }
+    }
+    // Reported after the tick that filled it
+    while let Some((_, event)) = market.next_event().await.unwrap() {
+        quoter.on_event(&event);
+    }
+
+    // Bought at 9.95 and sold at 9.9 once the price recovered, without
+    // bidding beyond the limit in between
+    let inventory = quoter.inventory();
+    assert_eq!(5.0, inventory.bought);
+    assert_eq!(5.0, inventory.sold);
+    assert_eq!(0.0, inventory.position);
+    assert_eq!(market.cash() - Money::from_f64(100.0), inventory.pnl(10.3));
+
+    quoter.refresh(&mut market).await.unwrap();
+    let (bid, _) = quoter.quotes();
+    quoter.withdraw(&mut market).await.unwrap();
+    assert!(matches!(
+        market.order_status(bid.unwrap()),
+        Some(OrderStatus::Canceled(_))
+    ));
+    assert_eq!((None, None), quoter.quotes());
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    market::Market,
+    ranking::{combine, long_short, momentum, percentile_ranks, value, z_scores},
+};
+
+fn scores(scores: &[(&str, f64)]) -> HashMap<String, f64> {
+    scores
+        .iter()
+        .map(|(symbol, score)| (symbol.to_string(), *score))
+        .collect()
+}
+
+#[test]
+fn test_normalization() {
+    let raw = scores(&[
+        ("A", 1.0),
+        ("B", 3.0),
+        ("C", 3.0),
+        ("D", 5.0),
+        ("E", f64::NAN),
+    ]);
+
+    let ranks = percentile_ranks(&raw);
+    assert_eq!(4, ranks.len());
+    assert_float_eq!(0.0, ranks["A"], abs <= 1e-9);
+    // Ties share the mean of ranks 1 and 2, of 0 to 3
+    assert_float_eq!(0.5, ranks["B"], abs <= 1e-9);
+    assert_float_eq!(0.5, ranks["C"], abs <= 1e-9);
+    assert_float_eq!(1.0, ranks["D"], abs <= 1e-9);
+    assert_eq!(
+        scores(&[("A", 0.5)]),
+        percentile_ranks(&scores(&[("A", 2.0)]))
+    );
+
+    let z = z_scores(&raw);
+    assert_eq!(4, z.len());
+    // A mean of 3 and a standard deviation of sqrt(2)
+    assert_float_eq!(-2f64.sqrt(), z["A"], abs <= 1e-9);
+    assert_float_eq!(0.0, z["B"], abs <= 1e-9);
+    assert_float_eq!(2f64.sqrt(), z["D"], abs <= 1e-9);
+    assert_eq!(
+        scores(&[("A", 0.0), ("B", 0.0)]),
+        z_scores(&scores(&[("A", 1.0), ("B", 1.0)]))
+    );
+}
+
+#[test]
+fn test_long_short() {
+    let momentum = scores(&[("A", 0.0), ("B", 1.0), ("C", 0.5)]);
+    let value = scores(&[("A", 1.0), ("B", 0.0), ("C", 0.5), ("D", 1.0)]);
+
+    // Only equities scored by both factors are combined
+    let combined = combine(&[(&momentum, 0.5), (&value, 0.5)]);
+    assert_eq!(scores(&[("A", 0.5), ("B", 0.5), ("C", 0.5)]), combined);
+
+    let combined = combine(&[(&momentum, 0.75), (&value, 0.25)]);
+    assert_eq!(
+        (vec!["B".to_string()], vec!["A".to_string()]),
+        long_short(&combined, 1)
+    );
+    // The legs never overlap
+    assert_eq!(
+        (vec!["B".to_string()], vec!["A".to_string()]),
+        long_short(&combined, 2)
+    );
+}
+
+#[tokio::test]
+async fn test_market_factors() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [
+            ("A".to_string(), vec![10.0..10.0, 12.0..12.0, 15.0..15.0]),
+            ("B".to_string(), vec![10.0..10.0, 9.0..9.0, 20.0..20.0]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    while market.time() < start + TimeDelta::minutes(2) {
+        market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+    let universe = ["A".to_string(), "B".to_string()];
+
+    // Leaving out the last minute's reversal
+    let returns = momentum(
+        &market,
+        &universe,
+        TimeDelta::minutes(2),
+        TimeDelta::minutes(1),
+    )
+    .await
+    .unwrap();
+    assert_float_eq!(0.2, returns["A"], abs <= 1e-9);
+    assert_float_eq!(-0.1, returns["B"], abs <= 1e-9);
+
+    let ratios = value(&market, &scores(&[("A", 3.0), ("B", 2.0)]))
+        .await
+        .unwrap();
+    assert_float_eq!(0.2, ratios["A"], abs <= 1e-9);
+    assert_float_eq!(0.1, ratios["B"], abs <= 1e-9);
+}
@@ -0,0 +1,56 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{
+    market::Market,
+    money::Money,
+    reconcile::{reconcile, AccountSnapshot, Discrepancy},
+};
+
+#[tokio::test]
+async fn test_reconcile() {
+    let mut market = TestMarket::new(
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        [
+            ("A".to_string(), vec![10.0..10.0]),
+            ("B".to_string(), vec![5.0..5.0]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    market.buy_at_market("A", 2.0).await.unwrap();
+    market.buy_at_market("B", 4.0).await.unwrap();
+    market.sell_at_market("B", 4.0).await.unwrap();
+
+    let internal = AccountSnapshot::of(&market);
+    assert_eq!(Some(&2.0), internal.holdings.get("A"));
+    assert!(!internal.holdings.contains_key("B"));
+
+    let mut reported = internal.clone();
+    reported.cash += Money::from_f64(0.001);
+    assert!(reconcile(&internal, &reported, Money::from_f64(0.01)).is_empty());
+
+    reported.cash = Money::from_f64(70.0);
+    reported.holdings.insert("A".to_string(), 3.0);
+    reported.holdings.insert("C".to_string(), 1.0);
+    assert_eq!(
+        vec![
+            Discrepancy::Cash {
+                expected: Money::from_f64(80.0),
+                actual: Money::from_f64(70.0)
+            },
+            Discrepancy::Position {
+                symbol: "A".to_string(),
+                expected: 2.0,
+                actual: 3.0
+            },
+            Discrepancy::Position {
+                symbol: "C".to_string(),
+                expected: 0.0,
+                actual: 1.0
+            },
+        ],
+        reconcile(&internal, &reported, Money::from_f64(0.01))
+    );
+}
@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{market::Market, replay::Replay};
+
+#[tokio::test]
+async fn test_replay_controls() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0; 10])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    );
+    let (mut replay, control) = Replay::new(market);
+    let wait = Duration::from_millis(50);
+
+    control.pause();
+    control.step();
+    let (time, _) = replay
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(start, time);
+
+    // The single step was taken
+    assert!(
+        tokio::time::timeout(wait, replay.next_event_or_tick(TimeDelta::minutes(1)))
+            .await
+            .is_err()
+    );
+
+    // A paused wait that was given up on did not consume an event
+    control.step();
+    let (time, _) = replay
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(start + TimeDelta::minutes(1), time);
+
+    // A minute per 20 ms
+    control.resume();
+    control.set_speed(3000.0);
+    let begin = Instant::now();
+    for _ in 0..2 {
+        replay
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+    }
+    assert!(begin.elapsed() >= Duration::from_millis(40));
+
+    // Dropping the control resumes the replay
+    control.pause();
+    drop(control);
+    let (time, _) = replay
+        .next_event_or_tick(TimeDelta::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(start + TimeDelta::minutes(4), time);
+}
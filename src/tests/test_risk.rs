@@ -0,0 +1,99 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    market::Market,
+    risk::{historical, parametric, RiskEstimate, RiskLimit, RiskLimitExceeded, RiskMethod},
+};
+
+#[test]
+fn test_historical() {
+    let returns = [
+        0.02, -0.05, 0.01, -0.01, 0.03, -0.02, 0.0, 0.04, -0.03, 0.01,
+    ];
+
+    let estimate = historical(&returns, 0.9).unwrap();
+    assert_float_eq!(0.05, estimate.value_at_risk, abs <= 1e-12);
+    assert_float_eq!(0.05, estimate.expected_shortfall, abs <= 1e-12);
+
+    let estimate = historical(&returns, 0.8).unwrap();
+    assert_float_eq!(0.03, estimate.value_at_risk, abs <= 1e-12);
+    assert_float_eq!(0.04, estimate.expected_shortfall, abs <= 1e-12);
+
+    assert_eq!(None, historical(&[0.01], 0.9));
+}
+
+#[test]
+fn test_parametric() {
+    // A mean of 0, so the estimates scale with the standard deviation
+    let returns = [-1.0, 1.0, -1.0, 1.0, 0.0, 0.0];
+    let deviation = (4.0f64 / 5.0).sqrt();
+
+    let estimate = parametric(&returns, 0.95).unwrap();
+    assert_float_eq!(1.644854 * deviation, estimate.value_at_risk, abs <= 1e-5);
+    assert_float_eq!(
+        2.062713 * deviation,
+        estimate.expected_shortfall,
+        abs <= 1e-5
+    );
+    assert!(estimate.expected_shortfall > estimate.value_at_risk);
+}
+
+#[test]
+fn test_risk_limit() {
+    let limit = RiskLimit {
+        max_value_at_risk: 0.05,
+        max_expected_shortfall: Some(0.08),
+    };
+    let estimate = |value_at_risk, expected_shortfall| RiskEstimate {
+        value_at_risk,
+        expected_shortfall,
+    };
+
+    assert_eq!(Ok(()), limit.check(&estimate(0.04, 0.07)));
+    assert!(matches!(
+        limit.check(&estimate(0.06, 0.07)),
+        Err(RiskLimitExceeded::ValueAtRisk { .. })
+    ));
+    assert!(matches!(
+        limit.check(&estimate(0.04, 0.09)),
+        Err(RiskLimitExceeded::ExpectedShortfall { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_portfolio_risk() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let interval = TimeDelta::minutes(1);
+    let mut market = TestMarket::new(
+        start,
+        [(
+            "STOCK".to_string(),
+            vec![100.0..100.0, 110.0..110.0, 99.0..99.0, 99.0..99.0],
+        )]
+        .into(),
+        interval,
+        150.0,
+    );
+    market.buy_at_market("STOCK", 1.0).await.unwrap();
+    while market.time() < start + interval * 3 {
+        market.next_event_or_tick(interval).await.unwrap();
+    }
+
+    // With 50 in cash, the book returned 6.67%, -6.88% and 0
+    let estimate = market
+        .portfolio_risk(interval, 3, 0.9, RiskMethod::Historical)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_float_eq!(11.0 / 160.0, estimate.value_at_risk, abs <= 1e-12);
+
+    assert_eq!(
+        None,
+        market
+            .portfolio_risk(interval, 1, 0.9, RiskMethod::Historical)
+            .await
+            .unwrap()
+    );
+}
@@ -0,0 +1,84 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{
+    market::{Event, Market},
+    money::Money,
+    order::{Order, OrderKind, OrderStatus, Side},
+    routing::{RoutedOrder, Router, RoutingError},
+};
+
+#[tokio::test]
+async fn test_routing() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let market = |symbol: &str, price: f64| {
+        TestMarket::new(
+            start,
+            [(symbol.to_string(), vec![price..price, price..price])].into(),
+            TimeDelta::minutes(1),
+            1000.0,
+        )
+    };
+    let earnings = Event::Earnings {
+        symbol: "STOCK".to_string(),
+    };
+    let mut router = Router::new(
+        "broker",
+        market("STOCK", 10.0)
+            .with_events([(start + TimeDelta::seconds(30), earnings.clone())].into()),
+    )
+    .with_venue("exchange", market("BTCUSDT", 100.0))
+    .with_route(|symbol| symbol.ends_with("USDT"), "exchange");
+
+    assert_eq!("broker", router.venue_for("STOCK"));
+    assert_eq!("exchange", router.venue_for("BTCUSDT"));
+
+    let (venue, _) = router.buy_at_market("STOCK", 5.0).await.unwrap();
+    assert_eq!("broker", venue);
+    let (venue, _) = router.buy_at_market("BTCUSDT", 2.0).await.unwrap();
+    assert_eq!("exchange", venue);
+    assert_eq!(5.0, router.venue("broker").unwrap().shares_of("STOCK"));
+    assert_eq!(2.0, router.shares_of("BTCUSDT"));
+    assert_eq!(Money::from_f64(2000.0 - 50.0 - 200.0), router.cash());
+    assert_eq!(Money::from_f64(2000.0), router.net_worth().await.unwrap());
+
+    // An explicit venue overrides the routes
+    let order = Order::new(
+        "STOCK",
+        Side::Buy,
+        1.0,
+        OrderKind::Limit { limit_price: 5.0 },
+    );
+    let routed = router.submit_order_to("exchange", order.clone()).await;
+    assert!(routed.is_err());
+    let routed = router.submit_order(order.clone()).await.unwrap();
+    assert_eq!("broker", routed.venue);
+    assert!(matches!(
+        router.order_status(&routed),
+        Some(OrderStatus::Open(_))
+    ));
+    router.cancel_order(&routed).await.unwrap();
+    assert!(matches!(
+        router.submit_order_to("elsewhere", order).await,
+        Err(RoutingError::UnknownVenue(_))
+    ));
+    assert_eq!(
+        None,
+        router.order_status(&RoutedOrder {
+            venue: "elsewhere".to_string(),
+            id: routed.id,
+        })
+    );
+
+    let events = router
+        .advance_to(start + TimeDelta::seconds(90))
+        .await
+        .unwrap();
+    assert!(events
+        .iter()
+        .any(|(venue, _, event)| venue == "broker" && *event == earnings));
+    assert!(events.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    for (_, market) in router.venues() {
+        assert_eq!(start + TimeDelta::seconds(90), market.time());
+    }
+}
@@ -0,0 +1,144 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+use float_eq::assert_float_eq;
+
+use super::test_market::TestMarket;
+use crate::{
+    market::{Candle, Event, Market},
+    scanner::{gap_up, rsi, rsi_below, volume_surge, Scanner},
+};
+
+fn candle(minute: u32, open: f64, close: f64, volume: f64) -> Candle {
+    Candle {
+        start: Utc.with_ymd_and_hms(1970, 1, 1, 0, minute, 0).unwrap(),
+        open,
+        high: open.max(close),
+        low: open.min(close),
+        close,
+        volume,
+    }
+}
+
+fn hit(symbol: &str, rule: &str) -> Event {
+    Event::ScannerHit {
+        symbol: symbol.to_string(),
+        rule: rule.to_string(),
+    }
+}
+
+#[test]
+fn test_rsi() {
+    let closes = |closes: &[f64]| -> Vec<Candle> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(minute, close)| candle(minute as u32, *close, *close, 100.0))
+            .collect()
+    };
+
+    assert_eq!(None, rsi(&closes(&[1.0, 2.0]), 2));
+    assert_eq!(Some(100.0), rsi(&closes(&[1.0, 2.0, 3.0]), 2));
+    assert_eq!(Some(0.0), rsi(&closes(&[3.0, 2.0, 1.0]), 2));
+    assert_float_eq!(
+        50.0,
+        rsi(&closes(&[1.0, 2.0, 1.0]), 2).unwrap(),
+        abs <= 1e-9
+    );
+    // Wilder's smoothing: the average gain is (0.5 + 0) / 2 and the average
+    // loss (0.5 + 1) / 2
+    assert_float_eq!(
+        25.0,
+        rsi(&closes(&[1.0, 2.0, 1.0, 0.0]), 2).unwrap(),
+        abs <= 1e-9
+    );
+}
+
+#[test]
+fn test_scanner_rules() {
+    let mut scanner = Scanner::new(["STOCK"], TimeDelta::minutes(1))
+        .with_rule("gap", gap_up(0.03))
+        .with_rule("surge", volume_surge(2.0, 2))
+        .with_rule("oversold", rsi_below(2, 30.0));
+
+    assert!(scanner
+        .on_bar("STOCK", candle(0, 10.0, 10.0, 100.0))
+        .is_empty());
+    assert!(scanner
+        .on_bar("STOCK", candle(1, 10.0, 10.0, 100.0))
+        .is_empty());
+    assert_eq!(
+        vec![hit("STOCK", "gap")],
+        scanner.on_bar("STOCK", candle(2, 10.5, 10.5, 100.0))
+    );
+    assert_eq!(
+        vec![hit("STOCK", "surge"), hit("STOCK", "oversold")],
+        scanner.on_bar("STOCK", candle(3, 10.5, 9.0, 300.0))
+    );
+
+    // Outside the universe, or of another interval
+    assert!(scanner
+        .on_bar("OTHER", candle(4, 20.0, 20.0, 1000.0))
+        .is_empty());
+    let bar_closed = |interval| Event::BarClosed {
+        symbol: "STOCK".to_string(),
+        interval,
+        candle: candle(4, 12.0, 12.0, 100.0),
+    };
+    assert!(scanner
+        .on_event(&bar_closed(TimeDelta::minutes(5)))
+        .is_empty());
+    assert_eq!(
+        vec![hit("STOCK", "gap")],
+        scanner.on_event(&bar_closed(TimeDelta::minutes(1)))
+    );
+}
+
+#[test]
+fn test_restore_scanner_history() {
+    let mut scanner =
+        Scanner::new(["STOCK"], TimeDelta::minutes(1)).with_rule("surge", volume_surge(2.0, 2));
+    scanner.on_bar("STOCK", candle(0, 10.0, 10.0, 100.0));
+    scanner.on_bar("STOCK", candle(1, 10.0, 10.0, 100.0));
+    let history = scanner.history();
+
+    let surge = candle(2, 10.0, 10.0, 300.0);
+    assert_eq!(vec![hit("STOCK", "surge")], scanner.on_bar("STOCK", surge));
+    scanner.on_bar("STOCK", candle(3, 10.0, 10.0, 1000.0));
+
+    // Resumed from the history, the same bar is a surge again
+    scanner.restore_history(history.clone());
+    assert_eq!(history, scanner.history());
+    assert_eq!(vec![hit("STOCK", "surge")], scanner.on_bar("STOCK", surge));
+
+    // Not without the bars before it
+    scanner.restore_history(Default::default());
+    assert!(scanner.on_bar("STOCK", surge).is_empty());
+}
+
+#[tokio::test]
+async fn test_market_scanner() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [
+            ("A".to_string(), vec![10.0..10.0, 10.0..10.0, 11.0..11.0]),
+            ("B".to_string(), vec![10.0..10.0; 3]),
+        ]
+        .into(),
+        TimeDelta::minutes(1),
+        100.0,
+    )
+    .with_scanner(Scanner::new(["A", "B"], TimeDelta::minutes(1)).with_rule("gap", gap_up(0.03)));
+
+    // Reported once the bar that gapped closed
+    let (time, event) = loop {
+        let (time, event) = market
+            .next_event_or_tick(TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        if event != Event::Tick {
+            break (time, event);
+        }
+    };
+    assert_eq!(start + TimeDelta::minutes(3), time);
+    assert_eq!(hit("A", "gap"), event);
+}
@@ -0,0 +1,74 @@
+use float_eq::assert_float_eq;
+
+use crate::{
+    money::Money,
+    sizing::{
+        beta, equal_risk_weights, risk_parity_quantities, volatility, RiskMeasure, RiskProfile,
+    },
+};
+
+fn profile(symbol: &str, price: f64, volatility: f64, beta: Option<f64>) -> RiskProfile {
+    RiskProfile {
+        symbol: symbol.to_string(),
+        price,
+        volatility,
+        beta,
+        lot_size: 1.0,
+    }
+}
+
+#[test]
+fn test_estimates() {
+    assert_float_eq!(
+        1.0,
+        volatility(&[-1.0, 1.0, -1.0, 1.0, 0.0, 0.0]).unwrap() / 0.8f64.sqrt(),
+        ulps <= 5
+    );
+    assert_eq!(None, volatility(&[0.1]));
+
+    let benchmark = [0.01, -0.02, 0.03, 0.0];
+    let doubled: Vec<_> = benchmark.iter().map(|b| 2.0 * b).collect();
+    assert_float_eq!(2.0, beta(&doubled, &benchmark).unwrap(), ulps <= 5);
+    assert_eq!(None, beta(&doubled, &[0.01; 4]));
+}
+
+#[test]
+fn test_risk_parity() {
+    let profiles = [
+        profile("CALM", 10.0, 0.1, Some(0.5)),
+        profile("WILD", 20.0, 0.3, Some(1.5)),
+        profile("NEW", 5.0, 0.2, None),
+    ];
+
+    // Half the volatility gets twice the weight
+    let weights = equal_risk_weights(&profiles, RiskMeasure::Volatility);
+    assert_float_eq!(6.0 / 11.0, weights["CALM"], ulps <= 5);
+    assert_float_eq!(2.0 / 11.0, weights["WILD"], ulps <= 5);
+    assert_float_eq!(3.0 / 11.0, weights["NEW"], ulps <= 5);
+
+    // Equities without a beta are left out of beta-adjusted sizing
+    let quantities = risk_parity_quantities(Money::from_f64(1000.0), &profiles, RiskMeasure::Beta);
+    assert_eq!(2, quantities.len());
+    assert_eq!(75.0, quantities["CALM"]);
+    assert_eq!(12.0, quantities["WILD"]);
+}
+
+#[test]
+fn test_risk_parity_in_lots() {
+    let profiles = [
+        RiskProfile {
+            lot_size: 10.0,
+            ..profile("CALM", 10.0, 0.1, None)
+        },
+        RiskProfile {
+            lot_size: 0.5,
+            ..profile("WILD", 20.0, 0.3, None)
+        },
+    ];
+
+    // 750 buys 75 shares of CALM, or 7 whole lots, and 250 buys 25 lots of WILD
+    let quantities =
+        risk_parity_quantities(Money::from_f64(1000.0), &profiles, RiskMeasure::Volatility);
+    assert_eq!(70.0, quantities["CALM"]);
+    assert_eq!(12.5, quantities["WILD"]);
+}
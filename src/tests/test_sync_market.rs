@@ -0,0 +1,30 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{market::Event, money::Money, sync_market::SyncMarket};
+
+#[test]
+fn test_sync_market() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = SyncMarket::new(TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0, 20.0..20.0])].into(),
+        TimeDelta::minutes(1),
+        100.0,
+    ))
+    .unwrap();
+
+    assert_eq!(10.0, market.current_price("STOCK").unwrap());
+    market.buy_at_market("STOCK", 5.0).unwrap();
+    assert_eq!(vec![("STOCK".to_string(), 5.0)], market.holdings());
+
+    assert_eq!(
+        (start, Event::Tick),
+        market.next_event_or_tick(TimeDelta::minutes(1)).unwrap()
+    );
+    assert_eq!(
+        (start + TimeDelta::minutes(1), Event::Tick),
+        market.next_event_or_tick(TimeDelta::minutes(1)).unwrap()
+    );
+    assert_eq!(Money::from_f64(150.0), market.net_worth().unwrap());
+}
@@ -0,0 +1,42 @@
+use chrono::{TimeDelta, TimeZone, Utc};
+
+use super::test_market::TestMarket;
+use crate::{
+    market::{Event, Market},
+    tick::AdaptiveTick,
+};
+
+#[tokio::test]
+async fn test_adaptive_tick() {
+    let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
+    let mut market = TestMarket::new(
+        start,
+        [("STOCK".to_string(), vec![10.0..10.0; 180])].into(),
+        TimeDelta::minutes(1),
+        1000.0,
+    );
+    let tick = AdaptiveTick::new(TimeDelta::hours(1), TimeDelta::minutes(1));
+
+    // Coarse while flat, from the first tick at the start on
+    assert_eq!(TimeDelta::hours(1), tick.tick(&market));
+    market.next_event_or_adaptive_tick(tick).await.unwrap();
+    let (time, _) = market.next_event_or_adaptive_tick(tick).await.unwrap();
+    assert_eq!(start + TimeDelta::hours(1), time);
+
+    // Fine while waiting for a resting order
+    let id = market.buy_limit("STOCK", 1.0, 5.0).await.unwrap();
+    assert_eq!(TimeDelta::minutes(1), tick.tick(&market));
+    market.cancel_order(id).await.unwrap();
+    assert_eq!(TimeDelta::hours(1), tick.tick(&market));
+
+    // And while holding a position
+    market.buy_at_market("STOCK", 1.0).await.unwrap();
+    let mut ticks = Vec::new();
+    while ticks.len() < 2 {
+        let (time, event) = market.next_event_or_adaptive_tick(tick).await.unwrap();
+        if event == Event::Tick {
+            ticks.push(time);
+        }
+    }
+    assert_eq!(TimeDelta::minutes(1), ticks[1] - ticks[0]);
+}
@@ -0,0 +1,36 @@
+//! Tick resolutions that follow the state of a strategy, so a selective
+//! strategy is simulated coarsely while it waits for a setup and finely
+//! while it has something at stake.
+
+use chrono::TimeDelta;
+
+use crate::market::Market;
+
+/// Ticks every `flat` while nothing is held or resting, and every `exposed`
+/// otherwise (e.g. 1h and 1m, so stops are watched closely)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptiveTick {
+    pub flat: TimeDelta,
+    pub exposed: TimeDelta,
+}
+
+impl AdaptiveTick {
+    pub fn new(flat: TimeDelta, exposed: TimeDelta) -> Self {
+        AdaptiveTick { flat, exposed }
+    }
+
+    /// The tick for the current state of a market
+    pub fn tick<M: Market + ?Sized>(&self, market: &M) -> TimeDelta {
+        let holds = market
+            .holdings()
+            .into_iter()
+            .any(|(_, quantity)| *quantity > 0.0);
+        let has_open_orders = market.open_orders().into_iter().next().is_some();
+
+        if holds || has_open_orders {
+            self.exposed
+        } else {
+            self.flat
+        }
+    }
+}